@@ -6,8 +6,10 @@ import config from "../config";
 import { APIDeploymentStack } from "../lib/api/api-deployment-stack";
 import { APIProjectsStack } from "../lib/api/api-projects-stack";
 import { APIProjectDeploymentsStack } from "../lib/api/api-project-deployments-stack";
+import { APIRouterStack } from "../lib/api/api-router-stack";
 import { APIStack } from "../lib/api/api-stack";
 import { APIUsersStack } from "../lib/api/api-users-stack";
+import { APIWebhooksStack } from "../lib/api/api-webhooks-stack";
 import { DeployStack } from "../lib/deploy-stack";
 import { TablesStack } from "../lib/tables-stack";
 
@@ -31,22 +33,37 @@ const tablesStack = new TablesStack(app, config.app.name("TablesStack"), {
 const apiStack = new APIStack(app, config.app.name("APIStack"), {
   env,
 });
-const apiUsersStack = new APIUsersStack(app, config.app.name("APIUsersStack"), {
-  env,
-});
-const apiProjectsStack = new APIProjectsStack(
-  app,
-  config.app.name("APIProjectsStack"),
-  { env }
-);
-const apiProjectDeploymentsStack = new APIProjectDeploymentsStack(
-  app,
-  config.app.name("APIProjectDeploymentsStack"),
-  { env },
-);
-
 const apiDeploymentStack = new APIDeploymentStack(
   app,
   config.app.name("APIDeploymentStack"),
   { env }
 );
+
+// Routes are either served by a single lambda or by one lambda each
+if (config.api.router.singleLambda) {
+  const apiRouterStack = new APIRouterStack(
+    app,
+    config.app.name("APIRouterStack"),
+    { env }
+  );
+} else {
+  const apiUsersStack = new APIUsersStack(app, config.app.name("APIUsersStack"), {
+    env,
+  });
+  const apiProjectsStack = new APIProjectsStack(
+    app,
+    config.app.name("APIProjectsStack"),
+    { env }
+  );
+  const apiProjectDeploymentsStack = new APIProjectDeploymentsStack(
+    app,
+    config.app.name("APIProjectDeploymentsStack"),
+    { env },
+  );
+
+  const apiWebhooksStack = new APIWebhooksStack(
+    app,
+    config.app.name("APIWebhooksStack"),
+    { env }
+  );
+}
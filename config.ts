@@ -21,13 +21,28 @@ const config = {
   api: {
     version: "v1",
     logging: "INFO", // OFF, ERROR, INFO
+    // Comma separated keys expected in the X-Api-Key header, every request is rejected when empty
+    keys: process.env.API_KEYS ? process.env.API_KEYS : "",
+    // "disabled" lets every request through without a key
+    auth: process.env.API_AUTH ? process.env.API_AUTH : "enabled",
+    router: {
+      // Serve every route from a single lambda instead of one lambda per route
+      singleLambda: process.env.API_SINGLE_LAMBDA == "true",
+      logging: process.env.LOGS_LEVEL_API_ROUTER ? process.env.LOGS_LEVEL_API_ROUTER : LOGS_LEVEL_LAMBDAS_DEFAULT,
+    },
     resources: {
       // logging: any value for RUST_LOG env var (error,warning,info,debug)
       projectDeployments: {
         deployment: {
           get: {
             logging: process.env.LOGS_LEVEL_API_PROJECT_DEPLOYMENTS_DEPLOYMENT_GET ? process.env.LOGS_LEVEL_API_PROJECT_DEPLOYMENTS_DEPLOYMENT_GET : LOGS_LEVEL_LAMBDAS_DEFAULT,
-          }
+          },
+          patch: {
+            logging: process.env.LOGS_LEVEL_API_PROJECT_DEPLOYMENTS_DEPLOYMENT_PATCH ? process.env.LOGS_LEVEL_API_PROJECT_DEPLOYMENTS_DEPLOYMENT_PATCH : LOGS_LEVEL_LAMBDAS_DEFAULT,
+          },
+          wait: {
+            logging: process.env.LOGS_LEVEL_API_PROJECT_DEPLOYMENTS_DEPLOYMENT_WAIT ? process.env.LOGS_LEVEL_API_PROJECT_DEPLOYMENTS_DEPLOYMENT_WAIT : LOGS_LEVEL_LAMBDAS_DEFAULT,
+          },
         },
         post: {
           logging: process.env.LOGS_LEVEL_API_PROJECT_DEPLOYMENTS_POST ? process.env.LOGS_LEVEL_API_PROJECT_DEPLOYMENTS_POST : LOGS_LEVEL_LAMBDAS_DEFAULT,
@@ -40,6 +55,20 @@ const config = {
         post: {
           logging: process.env.LOGS_LEVEL_API_PROJECTS_POST ? process.env.LOGS_LEVEL_API_PROJECTS_POST : LOGS_LEVEL_LAMBDAS_DEFAULT,
         },
+        project: {
+          get: {
+            logging: process.env.LOGS_LEVEL_API_PROJECTS_PROJECT_GET ? process.env.LOGS_LEVEL_API_PROJECTS_PROJECT_GET : LOGS_LEVEL_LAMBDAS_DEFAULT,
+          },
+          patch: {
+            logging: process.env.LOGS_LEVEL_API_PROJECTS_PROJECT_PATCH ? process.env.LOGS_LEVEL_API_PROJECTS_PROJECT_PATCH : LOGS_LEVEL_LAMBDAS_DEFAULT,
+          },
+          delete: {
+            logging: process.env.LOGS_LEVEL_API_PROJECTS_PROJECT_DELETE ? process.env.LOGS_LEVEL_API_PROJECTS_PROJECT_DELETE : LOGS_LEVEL_LAMBDAS_DEFAULT,
+          },
+          stats: {
+            logging: process.env.LOGS_LEVEL_API_PROJECTS_PROJECT_STATS ? process.env.LOGS_LEVEL_API_PROJECTS_PROJECT_STATS : LOGS_LEVEL_LAMBDAS_DEFAULT,
+          },
+        },
       },
       root: {
         any: {
@@ -53,7 +82,89 @@ const config = {
         post: {
           logging: process.env.LOGS_LEVEL_API_USERS_POST ? process.env.LOGS_LEVEL_API_USERS_POST : LOGS_LEVEL_LAMBDAS_DEFAULT,
         },
+        user: {
+          get: {
+            logging: process.env.LOGS_LEVEL_API_USERS_USER_GET ? process.env.LOGS_LEVEL_API_USERS_USER_GET : LOGS_LEVEL_LAMBDAS_DEFAULT,
+          },
+          patch: {
+            logging: process.env.LOGS_LEVEL_API_USERS_USER_PATCH ? process.env.LOGS_LEVEL_API_USERS_USER_PATCH : LOGS_LEVEL_LAMBDAS_DEFAULT,
+          },
+          delete: {
+            logging: process.env.LOGS_LEVEL_API_USERS_USER_DELETE ? process.env.LOGS_LEVEL_API_USERS_USER_DELETE : LOGS_LEVEL_LAMBDAS_DEFAULT,
+          },
+        },
       },
+      webhooks: {
+        list: {
+          logging: process.env.LOGS_LEVEL_API_WEBHOOKS_LIST ? process.env.LOGS_LEVEL_API_WEBHOOKS_LIST : LOGS_LEVEL_LAMBDAS_DEFAULT,
+        },
+        post: {
+          logging: process.env.LOGS_LEVEL_API_WEBHOOKS_POST ? process.env.LOGS_LEVEL_API_WEBHOOKS_POST : LOGS_LEVEL_LAMBDAS_DEFAULT,
+        },
+        webhook: {
+          deliveries: {
+            list: {
+              logging: process.env.LOGS_LEVEL_API_WEBHOOKS_WEBHOOK_DELIVERIES_LIST ? process.env.LOGS_LEVEL_API_WEBHOOKS_WEBHOOK_DELIVERIES_LIST : LOGS_LEVEL_LAMBDAS_DEFAULT,
+            },
+          },
+        },
+      },
+    },
+  },
+  deployments: {
+    concurrency: {
+      // Max builds in flight per project, extra deployments are queued
+      project: process.env.DEPLOYMENTS_PROJECT_CONCURRENCY_LIMIT ? process.env.DEPLOYMENTS_PROJECT_CONCURRENCY_LIMIT : "1",
+      // Max builds in flight for the whole account, keep it below the CodeBuild quota
+      account: process.env.DEPLOYMENTS_ACCOUNT_CONCURRENCY_LIMIT ? process.env.DEPLOYMENTS_ACCOUNT_CONCURRENCY_LIMIT : "10",
+    },
+    build: {
+      // Upper bounds for the per-project build settings
+      maxTimeoutMinutes: process.env.DEPLOYMENTS_BUILD_TIMEOUT_MAX_MINUTES ? process.env.DEPLOYMENTS_BUILD_TIMEOUT_MAX_MINUTES : "60",
+      maxComputeSize: process.env.DEPLOYMENTS_BUILD_COMPUTE_SIZE_MAX ? process.env.DEPLOYMENTS_BUILD_COMPUTE_SIZE_MAX : "medium", // small, medium, large
+    },
+  },
+  retention: {
+    gc: {
+      logging: process.env.LOGS_LEVEL_RETENTION_GC ? process.env.LOGS_LEVEL_RETENTION_GC : LOGS_LEVEL_LAMBDAS_DEFAULT,
+      // EventBridge schedule expression
+      schedule: process.env.RETENTION_GC_SCHEDULE ? process.env.RETENTION_GC_SCHEDULE : "rate(1 day)",
+      // "true" only reports what would be expired
+      dryRun: process.env.RETENTION_GC_DRY_RUN ? process.env.RETENTION_GC_DRY_RUN : "false",
+    },
+  },
+  notifications: {
+    // Email notifications are disabled unless a host and sender are set
+    smtp: {
+      host: process.env.SMTP_HOST ? process.env.SMTP_HOST : "",
+      port: process.env.SMTP_PORT ? process.env.SMTP_PORT : "587",
+      security: process.env.SMTP_SECURITY ? process.env.SMTP_SECURITY : "starttls", // starttls, tls, none
+      username: process.env.SMTP_USERNAME ? process.env.SMTP_USERNAME : "",
+      password: process.env.SMTP_PASSWORD ? process.env.SMTP_PASSWORD : "",
+      from: process.env.SMTP_FROM ? process.env.SMTP_FROM : "",
+    },
+  },
+  webhooks: {
+    retry: {
+      logging: process.env.LOGS_LEVEL_WEBHOOKS_RETRY ? process.env.LOGS_LEVEL_WEBHOOKS_RETRY : LOGS_LEVEL_LAMBDAS_DEFAULT,
+      // EventBridge schedule expression, failed deliveries are retried with exponential backoff
+      schedule: process.env.WEBHOOKS_RETRY_SCHEDULE ? process.env.WEBHOOKS_RETRY_SCHEDULE : "rate(1 minute)",
+    },
+  },
+  outbox: {
+    relay: {
+      logging: process.env.LOGS_LEVEL_OUTBOX_RELAY ? process.env.LOGS_LEVEL_OUTBOX_RELAY : LOGS_LEVEL_LAMBDAS_DEFAULT,
+      // EventBridge schedule expression, entries the stream could not publish are retried with exponential backoff
+      schedule: process.env.OUTBOX_RELAY_SCHEDULE ? process.env.OUTBOX_RELAY_SCHEDULE : "rate(1 minute)",
+      // Records sent to the lambda per invocation
+      batchSize: process.env.OUTBOX_RELAY_BATCH_SIZE ? Number(process.env.OUTBOX_RELAY_BATCH_SIZE) : 10,
+    },
+  },
+  streams: {
+    domainEvents: {
+      logging: process.env.LOGS_LEVEL_STREAMS_DOMAIN_EVENTS ? process.env.LOGS_LEVEL_STREAMS_DOMAIN_EVENTS : LOGS_LEVEL_LAMBDAS_DEFAULT,
+      // Records sent to the lambda per invocation
+      batchSize: process.env.STREAMS_DOMAIN_EVENTS_BATCH_SIZE ? Number(process.env.STREAMS_DOMAIN_EVENTS_BATCH_SIZE) : 100,
     },
   },
   codebuild: {
@@ -85,6 +196,42 @@ const config = {
         tableArn: `/${APP_PREFIX}/tables/projectDeployments/tableArn`,
         streamArn: `/${APP_PREFIX}/tables/projectDeployments/streamArn`,
       },
+      webhooks: {
+        tableArn: `/${APP_PREFIX}/tables/webhooks/tableArn`,
+        streamArn: `/${APP_PREFIX}/tables/webhooks/streamArn`,
+      },
+      webhookDeliveries: {
+        tableArn: `/${APP_PREFIX}/tables/webhookDeliveries/tableArn`,
+        streamArn: `/${APP_PREFIX}/tables/webhookDeliveries/streamArn`,
+      },
+      processedEvents: {
+        tableArn: `/${APP_PREFIX}/tables/processedEvents/tableArn`,
+        streamArn: `/${APP_PREFIX}/tables/processedEvents/streamArn`,
+      },
+      deadLetters: {
+        tableArn: `/${APP_PREFIX}/tables/deadLetters/tableArn`,
+        streamArn: `/${APP_PREFIX}/tables/deadLetters/streamArn`,
+      },
+      outbox: {
+        tableArn: `/${APP_PREFIX}/tables/outbox/tableArn`,
+        streamArn: `/${APP_PREFIX}/tables/outbox/streamArn`,
+      },
+      projectStats: {
+        tableArn: `/${APP_PREFIX}/tables/projectStats/tableArn`,
+        streamArn: `/${APP_PREFIX}/tables/projectStats/streamArn`,
+      },
+      userEmails: {
+        tableArn: `/${APP_PREFIX}/tables/userEmails/tableArn`,
+        streamArn: `/${APP_PREFIX}/tables/userEmails/streamArn`,
+      },
+      idempotencyKeys: {
+        tableArn: `/${APP_PREFIX}/tables/idempotencyKeys/tableArn`,
+        streamArn: `/${APP_PREFIX}/tables/idempotencyKeys/streamArn`,
+      },
+      deploymentSlots: {
+        tableArn: `/${APP_PREFIX}/tables/deploymentSlots/tableArn`,
+        streamArn: `/${APP_PREFIX}/tables/deploymentSlots/streamArn`,
+      },
     },
     codebuild: {
       project: {
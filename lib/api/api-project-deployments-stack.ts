@@ -21,6 +21,8 @@ export class APIProjectDeploymentsStack extends Stack {
 
   public readonly post: Function;
   public readonly get: Function;
+  public readonly patch: Function;
+  public readonly wait: Function;
 
   constructor(scope: Construct, id: string, props: StackProps) {
     super(scope, id, props);
@@ -34,6 +36,15 @@ export class APIProjectDeploymentsStack extends Stack {
       this,
       Tables.Projects
     );
+    const outboxTable = TablesStack.getInstance(this, Tables.Outbox);
+    const idempotencyKeysTable = TablesStack.getInstance(
+      this,
+      Tables.IdempotencyKeys
+    );
+    const deploymentSlotsTable = TablesStack.getInstance(
+      this,
+      Tables.DeploymentSlots
+    );
     const codeBuildProjectName = StringParameter.fromStringParameterName(
       this,
       "CodebuildProjectNameValue",
@@ -56,16 +67,29 @@ export class APIProjectDeploymentsStack extends Stack {
       environment: {
         RUST_BACKTRACE: "1",
         RUST_LOG: config.api.resources.projectDeployments.post.logging,
-        TABLE_NAME: deploymentsTable.tableName,
+        TABLE_NAME_DEPLOYMENTS: deploymentsTable.tableName,
         TABLE_REGION: props.env!.region!,
+        API_KEYS: config.api.keys,
+        API_AUTH: config.api.auth,
         TABLE_NAME_PROJECTS: projectsTable.tableName,
+        TABLE_NAME_OUTBOX: outboxTable.tableName,
+        TABLE_NAME_IDEMPOTENCY_KEYS: idempotencyKeysTable.tableName,
+        TABLE_NAME_DEPLOYMENT_SLOTS: deploymentSlotsTable.tableName,
         CODEBUILD_PROJECT_NAME_BUILDING: codeBuildProjectName,
         CODEBUILD_PROJECT_NAME_DEPLOYMENT: "CODEBUILD_PROJECT_NAME_DEPLOYMENT", // TODO: replace with deployment project name once it is implemented
+        PROJECT_CONCURRENCY_LIMIT: config.deployments.concurrency.project,
+        ACCOUNT_CONCURRENCY_LIMIT: config.deployments.concurrency.account,
+        BUILD_TIMEOUT_MAX_MINUTES: config.deployments.build.maxTimeoutMinutes,
+        BUILD_COMPUTE_SIZE_MAX: config.deployments.build.maxComputeSize,
       },
-      timeout: Duration.seconds(5),
+      timeout: Duration.seconds(10),
     });
     deploymentsTable.grantReadWriteData(this.post);
+    TablesStack.grantReadIndex(deploymentsTable, this.post);
     projectsTable.grantReadWriteData(this.post);
+    outboxTable.grantWriteData(this.post);
+    idempotencyKeysTable.grantReadWriteData(this.post);
+    deploymentSlotsTable.grantReadWriteData(this.post);
     this.post.grantInvoke(APIStack.principal);
     this.post.addToRolePolicy(
       new PolicyStatement({
@@ -87,14 +111,61 @@ export class APIProjectDeploymentsStack extends Stack {
       environment: {
         RUST_BACKTRACE: "1",
         RUST_LOG: config.api.resources.projectDeployments.deployment.get.logging,
-        TABLE_NAME: deploymentsTable.tableName,
+        TABLE_NAME_DEPLOYMENTS: deploymentsTable.tableName,
         TABLE_REGION: props.env!.region!,
+        API_KEYS: config.api.keys,
+        API_AUTH: config.api.auth,
       },
       timeout: Duration.seconds(5),
     });
     this.get.grantInvoke(APIStack.principal);
     deploymentsTable.grantReadData(this.get);
 
+    // Pin or unpin a deployment
+    this.patch = new Function(this, "patch", {
+      description: "Pin or unpin a project deployment",
+      runtime: Runtime.PROVIDED_AL2,
+      code: AssetCode.fromAsset(
+        `${this.srcPath}/api-project-deployments-patch/bootstrap.zip`
+      ),
+      architecture: Architecture.X86_64,
+      handler: "bootstrap",
+      environment: {
+        RUST_BACKTRACE: "1",
+        RUST_LOG: config.api.resources.projectDeployments.deployment.patch.logging,
+        TABLE_NAME_DEPLOYMENTS: deploymentsTable.tableName,
+        TABLE_REGION: props.env!.region!,
+        API_KEYS: config.api.keys,
+        API_AUTH: config.api.auth,
+      },
+      timeout: Duration.seconds(5),
+    });
+    this.patch.grantInvoke(APIStack.principal);
+    deploymentsTable.grantReadWriteData(this.patch);
+
+    // Wait for a deployment status change, held below the API Gateway
+    // integration timeout of 29 seconds
+    this.wait = new Function(this, "wait", {
+      description: "Wait for a project deployment status change",
+      runtime: Runtime.PROVIDED_AL2,
+      code: AssetCode.fromAsset(
+        `${this.srcPath}/api-project-deployments-wait/bootstrap.zip`
+      ),
+      architecture: Architecture.X86_64,
+      handler: "bootstrap",
+      environment: {
+        RUST_BACKTRACE: "1",
+        RUST_LOG: config.api.resources.projectDeployments.deployment.wait.logging,
+        TABLE_NAME_DEPLOYMENTS: deploymentsTable.tableName,
+        TABLE_REGION: props.env!.region!,
+        API_KEYS: config.api.keys,
+        API_AUTH: config.api.auth,
+      },
+      timeout: Duration.seconds(29),
+    });
+    this.wait.grantInvoke(APIStack.principal);
+    deploymentsTable.grantReadData(this.wait);
+
     // API Endpoints
     const api = APIStack.getInstance(this);
     const rootResource = APIStack.getRootResource(this, api);
@@ -108,5 +179,9 @@ export class APIProjectDeploymentsStack extends Stack {
       APIProjectDeploymentsStack.pathDeployment
     );
     deployment.addMethod("GET", new LambdaIntegration(this.get));
+    deployment.addMethod("PATCH", new LambdaIntegration(this.patch));
+    deployment
+      .addResource("wait")
+      .addMethod("GET", new LambdaIntegration(this.wait));
   }
 }
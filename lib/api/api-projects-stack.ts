@@ -19,6 +19,10 @@ export class APIProjectsStack extends Stack {
 
   public readonly post: Function;
   public readonly list: Function;
+  public readonly get: Function;
+  public readonly patch: Function;
+  public readonly delete: Function;
+  public readonly stats: Function;
 
   constructor(scope: Construct, id: string, props: StackProps) {
     super(scope, id, props);
@@ -28,6 +32,14 @@ export class APIProjectsStack extends Stack {
       this,
       Tables.Projects
     );
+    const deploymentsTable = TablesStack.getInstance(
+      this,
+      Tables.ProjectDeployments
+    );
+    const projectStatsTable = TablesStack.getInstance(
+      this,
+      Tables.ProjectStats
+    );
 
     // Create new project
     this.post = new Function(this, "post", {
@@ -40,8 +52,10 @@ export class APIProjectsStack extends Stack {
       environment: {
         RUST_BACKTRACE: "1",
         RUST_LOG: config.api.resources.projects.post.logging,
-        TABLE_NAME: projectsTable.tableName,
+        TABLE_NAME_PROJECTS: projectsTable.tableName,
         TABLE_REGION: props.env!.region!,
+        API_KEYS: config.api.keys,
+        API_AUTH: config.api.auth,
       },
       timeout: Duration.seconds(5),
     });
@@ -60,14 +74,109 @@ export class APIProjectsStack extends Stack {
       environment: {
         RUST_BACKTRACE: "1",
         RUST_LOG: config.api.resources.projects.list.logging,
-        TABLE_NAME: projectsTable.tableName,
+        TABLE_NAME_PROJECTS: projectsTable.tableName,
         TABLE_REGION: props.env!.region!,
+        API_KEYS: config.api.keys,
+        API_AUTH: config.api.auth,
       },
       timeout: Duration.seconds(5),
     });
     projectsTable.grantReadData(this.list);
     this.list.grantInvoke(APIStack.principal);
 
+    // Get Project
+    this.get = new Function(this, "get", {
+      description: "Get project",
+      runtime: Runtime.PROVIDED_AL2,
+      code: AssetCode.fromAsset(
+        `${this.srcPath}/api-projects-get/bootstrap.zip`
+      ),
+      architecture: Architecture.X86_64,
+      handler: "bootstrap",
+      environment: {
+        RUST_BACKTRACE: "1",
+        RUST_LOG: config.api.resources.projects.project.get.logging,
+        TABLE_NAME_PROJECTS: projectsTable.tableName,
+        TABLE_REGION: props.env!.region!,
+        API_KEYS: config.api.keys,
+        API_AUTH: config.api.auth,
+      },
+      timeout: Duration.seconds(5),
+    });
+    projectsTable.grantReadData(this.get);
+    this.get.grantInvoke(APIStack.principal);
+
+    // Update Project
+    this.patch = new Function(this, "patch", {
+      description: "Update project",
+      runtime: Runtime.PROVIDED_AL2,
+      code: AssetCode.fromAsset(
+        `${this.srcPath}/api-projects-patch/bootstrap.zip`
+      ),
+      architecture: Architecture.X86_64,
+      handler: "bootstrap",
+      environment: {
+        RUST_BACKTRACE: "1",
+        RUST_LOG: config.api.resources.projects.project.patch.logging,
+        TABLE_NAME_PROJECTS: projectsTable.tableName,
+        TABLE_REGION: props.env!.region!,
+        API_KEYS: config.api.keys,
+        API_AUTH: config.api.auth,
+      },
+      timeout: Duration.seconds(5),
+    });
+    projectsTable.grantReadWriteData(this.patch);
+    this.patch.grantInvoke(APIStack.principal);
+
+    // Delete Project, blocked while deployments are queued or in progress
+    this.delete = new Function(this, "delete", {
+      description: "Delete project",
+      runtime: Runtime.PROVIDED_AL2,
+      code: AssetCode.fromAsset(
+        `${this.srcPath}/api-projects-delete/bootstrap.zip`
+      ),
+      architecture: Architecture.X86_64,
+      handler: "bootstrap",
+      environment: {
+        RUST_BACKTRACE: "1",
+        RUST_LOG: config.api.resources.projects.project.delete.logging,
+        TABLE_NAME_PROJECTS: projectsTable.tableName,
+        TABLE_NAME_DEPLOYMENTS: deploymentsTable.tableName,
+        TABLE_REGION: props.env!.region!,
+        API_KEYS: config.api.keys,
+        API_AUTH: config.api.auth,
+      },
+      timeout: Duration.seconds(5),
+    });
+    projectsTable.grantReadWriteData(this.delete);
+    deploymentsTable.grantReadData(this.delete);
+    TablesStack.grantReadIndex(deploymentsTable, this.delete);
+    this.delete.grantInvoke(APIStack.principal);
+
+    // Project Stats
+    this.stats = new Function(this, "stats", {
+      description: "Get project deployment statistics",
+      runtime: Runtime.PROVIDED_AL2,
+      code: AssetCode.fromAsset(
+        `${this.srcPath}/api-projects-stats/bootstrap.zip`
+      ),
+      architecture: Architecture.X86_64,
+      handler: "bootstrap",
+      environment: {
+        RUST_BACKTRACE: "1",
+        RUST_LOG: config.api.resources.projects.project.stats.logging,
+        TABLE_NAME_STATS: projectStatsTable.tableName,
+        TABLE_NAME_PROJECTS: projectsTable.tableName,
+        TABLE_REGION: props.env!.region!,
+        API_KEYS: config.api.keys,
+        API_AUTH: config.api.auth,
+      },
+      timeout: Duration.seconds(5),
+    });
+    projectStatsTable.grantReadData(this.stats);
+    projectsTable.grantReadData(this.stats);
+    this.stats.grantInvoke(APIStack.principal);
+
     // API Endpoints
     const api = APIStack.getInstance(this);
     const rootResource = APIStack.getRootResource(this, api);
@@ -75,5 +184,13 @@ export class APIProjectsStack extends Stack {
     const projects = rootResource.addResource(APIProjectsStack.pathProjects);
     projects.addMethod("POST", new LambdaIntegration(this.post));
     projects.addMethod("GET", new LambdaIntegration(this.list));
+
+    const project = rootResource.resourceForPath(APIProjectsStack.pathProject);
+    project.addMethod("GET", new LambdaIntegration(this.get));
+    project.addMethod("PATCH", new LambdaIntegration(this.patch));
+    project.addMethod("DELETE", new LambdaIntegration(this.delete));
+    project
+      .addResource("stats")
+      .addMethod("GET", new LambdaIntegration(this.stats));
   }
 }
@@ -0,0 +1,125 @@
+import {
+  Architecture,
+  AssetCode,
+  Function,
+  Runtime,
+} from "aws-cdk-lib/aws-lambda";
+import {Duration, Stack, StackProps} from "aws-cdk-lib";
+import {Effect, PolicyStatement} from "aws-cdk-lib/aws-iam";
+import {Tables, TablesStack} from "../tables-stack";
+
+import {APIStack} from "./api-stack";
+import {Construct} from "constructs";
+import {LambdaIntegration} from "aws-cdk-lib/aws-apigateway";
+import {StringParameter} from "aws-cdk-lib/aws-ssm";
+import config from "../../config";
+
+// Serves every route of the API from a single lambda, replaces the per route stacks
+export class APIRouterStack extends Stack {
+  private readonly srcPath = "target/lambda";
+
+  public readonly router: Function;
+
+  constructor(scope: Construct, id: string, props: StackProps) {
+    super(scope, id, props);
+
+    // Share dependencies
+    const usersTable = TablesStack.getInstance(this, Tables.Users);
+    const userEmailsTable = TablesStack.getInstance(this, Tables.UserEmails);
+    const projectsTable = TablesStack.getInstance(this, Tables.Projects);
+    const deploymentsTable = TablesStack.getInstance(
+      this,
+      Tables.ProjectDeployments
+    );
+    const outboxTable = TablesStack.getInstance(this, Tables.Outbox);
+    const idempotencyKeysTable = TablesStack.getInstance(
+      this,
+      Tables.IdempotencyKeys
+    );
+    const deploymentSlotsTable = TablesStack.getInstance(
+      this,
+      Tables.DeploymentSlots
+    );
+    const projectStatsTable = TablesStack.getInstance(
+      this,
+      Tables.ProjectStats
+    );
+    const webhooksTable = TablesStack.getInstance(this, Tables.Webhooks);
+    const webhookDeliveriesTable = TablesStack.getInstance(
+      this,
+      Tables.WebhookDeliveries
+    );
+    const codeBuildProjectName = StringParameter.fromStringParameterName(
+      this,
+      "CodebuildProjectNameValue",
+      config.ssm.codebuild.project.name
+    ).stringValue;
+    const codeBuildProjectARN = StringParameter.fromStringParameterName(
+      this,
+      "CodebuildProjectARNValue",
+      config.ssm.codebuild.project.arn,
+    ).stringValue;
+
+    this.router = new Function(this, "router", {
+      description: "Serve every route of the API",
+      runtime: Runtime.PROVIDED_AL2,
+      code: AssetCode.fromAsset(`${this.srcPath}/api-router/bootstrap.zip`),
+      architecture: Architecture.X86_64,
+      handler: "bootstrap",
+      environment: {
+        RUST_BACKTRACE: "1",
+        RUST_LOG: config.api.router.logging,
+        API_KEYS: config.api.keys,
+        API_AUTH: config.api.auth,
+        TABLE_NAME_USERS: usersTable.tableName,
+        TABLE_NAME_USER_EMAILS: userEmailsTable.tableName,
+        TABLE_NAME_PROJECTS: projectsTable.tableName,
+        TABLE_NAME_DEPLOYMENTS: deploymentsTable.tableName,
+        TABLE_NAME_OUTBOX: outboxTable.tableName,
+        TABLE_NAME_IDEMPOTENCY_KEYS: idempotencyKeysTable.tableName,
+        TABLE_NAME_DEPLOYMENT_SLOTS: deploymentSlotsTable.tableName,
+        TABLE_NAME_STATS: projectStatsTable.tableName,
+        TABLE_NAME_WEBHOOKS: webhooksTable.tableName,
+        TABLE_NAME_WEBHOOK_DELIVERIES: webhookDeliveriesTable.tableName,
+        CODEBUILD_PROJECT_NAME_BUILDING: codeBuildProjectName,
+        CODEBUILD_PROJECT_NAME_DEPLOYMENT: "CODEBUILD_PROJECT_NAME_DEPLOYMENT", // TODO: replace with deployment project name once it is implemented
+        PROJECT_CONCURRENCY_LIMIT: config.deployments.concurrency.project,
+        ACCOUNT_CONCURRENCY_LIMIT: config.deployments.concurrency.account,
+        BUILD_TIMEOUT_MAX_MINUTES: config.deployments.build.maxTimeoutMinutes,
+        BUILD_COMPUTE_SIZE_MAX: config.deployments.build.maxComputeSize,
+      },
+      // Long polling deployments waits up to 25 seconds
+      timeout: Duration.seconds(29),
+    });
+    usersTable.grantReadWriteData(this.router);
+    userEmailsTable.grantReadWriteData(this.router);
+    projectsTable.grantReadWriteData(this.router);
+    deploymentsTable.grantReadWriteData(this.router);
+    TablesStack.grantReadIndex(deploymentsTable, this.router);
+    outboxTable.grantWriteData(this.router);
+    idempotencyKeysTable.grantReadWriteData(this.router);
+    deploymentSlotsTable.grantReadWriteData(this.router);
+    projectStatsTable.grantReadData(this.router);
+    webhooksTable.grantReadWriteData(this.router);
+    TablesStack.grantReadIndex(webhooksTable, this.router);
+    webhookDeliveriesTable.grantReadData(this.router);
+    TablesStack.grantReadIndex(webhookDeliveriesTable, this.router);
+    this.router.grantInvoke(APIStack.principal);
+    this.router.addToRolePolicy(
+      new PolicyStatement({
+        effect: Effect.ALLOW,
+        actions: ["codebuild:StartBuild"],
+        resources: [codeBuildProjectARN],
+      })
+    );
+
+    // API Endpoints, the router answers unknown routes itself
+    const api = APIStack.getInstance(this);
+    const rootResource = APIStack.getRootResource(this, api);
+
+    rootResource.addProxy({
+      defaultIntegration: new LambdaIntegration(this.router),
+      anyMethod: true,
+    });
+  }
+}
@@ -69,6 +69,26 @@ export class APIStack extends OutputStack {
     );
 
     api.root.addMethod("ANY", anyIntegration);
+    // OpenAPI document, served by the root handler
+    api.root.addResource("openapi.json").addMethod("GET", anyIntegration);
+
+    // Error catalog, public like the root resource
+    const errorsIntegration = new LambdaIntegration(
+      new Function(this, "errors", {
+        description: "List the error catalog of the API",
+        runtime: Runtime.PROVIDED_AL2,
+        code: AssetCode.fromAsset(
+          "target/lambda/api-errors-list/bootstrap.zip"
+        ),
+        architecture: Architecture.X86_64,
+        handler: "bootstrap",
+        environment: {
+          RUST_BACKTRACE: "1",
+        },
+      })
+    );
+
+    api.root.addResource("errors").addMethod("GET", errorsIntegration);
   }
 
   public static getInstance(scope: Construct): IRestApi {
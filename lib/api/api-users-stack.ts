@@ -15,15 +15,20 @@ import config from "../../config";
 export class APIUsersStack extends Stack {
   private readonly srcPath = "target/lambda";
   public static readonly pathUsers = "users";
+  public static readonly pathUser = `${APIUsersStack.pathUsers}/{user}`;
 
   public readonly post: Function;
   public readonly list: Function;
+  public readonly get: Function;
+  public readonly patch: Function;
+  public readonly delete: Function;
 
   constructor(scope: Construct, id: string, props: StackProps) {
     super(scope, id, props);
 
     // dependencies
     const usersTable = TablesStack.getStreamingInstance(this, Tables.Users);
+    const userEmailsTable = TablesStack.getInstance(this, Tables.UserEmails);
 
     // Create new users
     this.post = new Function(this, "post", {
@@ -35,12 +40,17 @@ export class APIUsersStack extends Stack {
       environment: {
         RUST_BACKTRACE: "1",
         RUST_LOG: config.api.resources.users.post.logging,
-        TABLE_NAME: usersTable.tableName,
+        TABLE_NAME_USERS: usersTable.tableName,
+        TABLE_NAME_USER_EMAILS: userEmailsTable.tableName,
         TABLE_REGION: props.env!.region!,
+        API_KEYS: config.api.keys,
+        API_AUTH: config.api.auth,
       },
       timeout: Duration.seconds(5),
     });
-    usersTable.grantWriteData(this.post);
+    // Legacy users are scanned for emails nobody claimed yet
+    usersTable.grantReadWriteData(this.post);
+    userEmailsTable.grantReadWriteData(this.post);
     this.post.grantInvoke(APIStack.principal);
 
     // List users
@@ -53,14 +63,83 @@ export class APIUsersStack extends Stack {
       environment: {
         RUST_BACKTRACE: "1",
         RUST_LOG: config.api.resources.users.list.logging,
-        TABLE_NAME: usersTable.tableName,
+        TABLE_NAME_USERS: usersTable.tableName,
+        TABLE_NAME_USER_EMAILS: userEmailsTable.tableName,
         TABLE_REGION: props.env!.region!,
+        API_KEYS: config.api.keys,
+        API_AUTH: config.api.auth,
       },
       timeout: Duration.seconds(5),
     });
     usersTable.grantReadData(this.list);
+    // Claims the emails of legacy users found by email
+    userEmailsTable.grantReadWriteData(this.list);
     this.list.grantInvoke(APIStack.principal);
 
+    // Get User
+    this.get = new Function(this, "get", {
+      description: "Get user",
+      runtime: Runtime.PROVIDED_AL2,
+      code: AssetCode.fromAsset(`${this.srcPath}/api-users-get/bootstrap.zip`),
+      architecture: Architecture.X86_64,
+      handler: "bootstrap",
+      environment: {
+        RUST_BACKTRACE: "1",
+        RUST_LOG: config.api.resources.users.user.get.logging,
+        TABLE_NAME_USERS: usersTable.tableName,
+        TABLE_REGION: props.env!.region!,
+        API_KEYS: config.api.keys,
+        API_AUTH: config.api.auth,
+      },
+      timeout: Duration.seconds(5),
+    });
+    usersTable.grantReadData(this.get);
+    this.get.grantInvoke(APIStack.principal);
+
+    // Update User
+    this.patch = new Function(this, "patch", {
+      description: "Update user",
+      runtime: Runtime.PROVIDED_AL2,
+      code: AssetCode.fromAsset(`${this.srcPath}/api-users-patch/bootstrap.zip`),
+      architecture: Architecture.X86_64,
+      handler: "bootstrap",
+      environment: {
+        RUST_BACKTRACE: "1",
+        RUST_LOG: config.api.resources.users.user.patch.logging,
+        TABLE_NAME_USERS: usersTable.tableName,
+        TABLE_NAME_USER_EMAILS: userEmailsTable.tableName,
+        TABLE_REGION: props.env!.region!,
+        API_KEYS: config.api.keys,
+        API_AUTH: config.api.auth,
+      },
+      timeout: Duration.seconds(5),
+    });
+    usersTable.grantReadWriteData(this.patch);
+    userEmailsTable.grantReadWriteData(this.patch);
+    this.patch.grantInvoke(APIStack.principal);
+
+    // Delete User
+    this.delete = new Function(this, "delete", {
+      description: "Delete user",
+      runtime: Runtime.PROVIDED_AL2,
+      code: AssetCode.fromAsset(`${this.srcPath}/api-users-delete/bootstrap.zip`),
+      architecture: Architecture.X86_64,
+      handler: "bootstrap",
+      environment: {
+        RUST_BACKTRACE: "1",
+        RUST_LOG: config.api.resources.users.user.delete.logging,
+        TABLE_NAME_USERS: usersTable.tableName,
+        TABLE_NAME_USER_EMAILS: userEmailsTable.tableName,
+        TABLE_REGION: props.env!.region!,
+        API_KEYS: config.api.keys,
+        API_AUTH: config.api.auth,
+      },
+      timeout: Duration.seconds(5),
+    });
+    usersTable.grantReadWriteData(this.delete);
+    userEmailsTable.grantReadWriteData(this.delete);
+    this.delete.grantInvoke(APIStack.principal);
+
     // API Endpoints
     const api = APIStack.getInstance(this);
     const rootResource = APIStack.getRootResource(this, api);
@@ -68,5 +147,10 @@ export class APIUsersStack extends Stack {
     const users = rootResource.addResource(APIUsersStack.pathUsers);
     users.addMethod("POST", new LambdaIntegration(this.post));
     users.addMethod("GET", new LambdaIntegration(this.list));
+
+    const user = rootResource.resourceForPath(APIUsersStack.pathUser);
+    user.addMethod("GET", new LambdaIntegration(this.get));
+    user.addMethod("PATCH", new LambdaIntegration(this.patch));
+    user.addMethod("DELETE", new LambdaIntegration(this.delete));
   }
 }
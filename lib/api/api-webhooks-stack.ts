@@ -0,0 +1,119 @@
+import {Duration, Stack, StackProps} from "aws-cdk-lib";
+import {LambdaIntegration} from "aws-cdk-lib/aws-apigateway";
+import {
+  Architecture,
+  AssetCode,
+  Function,
+  Runtime
+} from "aws-cdk-lib/aws-lambda";
+import {Construct} from "constructs";
+import {Tables, TablesStack} from "../tables-stack";
+import {APIStack} from "./api-stack";
+import config from "../../config";
+
+
+export class APIWebhooksStack extends Stack {
+  private readonly srcPath = "target/lambda";
+  public static readonly pathWebhooks = "webhooks";
+  public static readonly pathWebhook = "{webhook}";
+  public static readonly pathDeliveries = "deliveries";
+
+  public readonly post: Function;
+  public readonly list: Function;
+  public readonly deliveriesList: Function;
+
+  constructor(scope: Construct, id: string, props: StackProps) {
+    super(scope, id, props);
+
+    // dependencies
+    const webhooksTable = TablesStack.getInstance(this, Tables.Webhooks);
+    const webhookDeliveriesTable = TablesStack.getInstance(
+      this,
+      Tables.WebhookDeliveries
+    );
+    const projectsTable = TablesStack.getInstance(this, Tables.Projects);
+    const usersTable = TablesStack.getInstance(this, Tables.Users);
+
+    // Create new webhooks
+    this.post = new Function(this, "post", {
+      description: "Create new webhooks",
+      runtime: Runtime.PROVIDED_AL2,
+      code: AssetCode.fromAsset(`${this.srcPath}/api-webhooks-post/bootstrap.zip`),
+      architecture: Architecture.X86_64,
+      handler: "bootstrap",
+      environment: {
+        RUST_BACKTRACE: "1",
+        RUST_LOG: config.api.resources.webhooks.post.logging,
+        TABLE_NAME_WEBHOOKS: webhooksTable.tableName,
+        TABLE_REGION: props.env!.region!,
+        API_KEYS: config.api.keys,
+        API_AUTH: config.api.auth,
+        TABLE_NAME_PROJECTS: projectsTable.tableName,
+        TABLE_NAME_USERS: usersTable.tableName,
+      },
+      timeout: Duration.seconds(5),
+    });
+    webhooksTable.grantWriteData(this.post);
+    projectsTable.grantReadData(this.post);
+    usersTable.grantReadData(this.post);
+    this.post.grantInvoke(APIStack.principal);
+
+    // List webhooks of a project or user
+    this.list = new Function(this, "list", {
+      description: "List webhooks of a project or user",
+      runtime: Runtime.PROVIDED_AL2,
+      code: AssetCode.fromAsset(`${this.srcPath}/api-webhooks-list/bootstrap.zip`),
+      architecture: Architecture.X86_64,
+      handler: "bootstrap",
+      environment: {
+        RUST_BACKTRACE: "1",
+        RUST_LOG: config.api.resources.webhooks.list.logging,
+        TABLE_NAME_WEBHOOKS: webhooksTable.tableName,
+        TABLE_REGION: props.env!.region!,
+        API_KEYS: config.api.keys,
+        API_AUTH: config.api.auth,
+      },
+      timeout: Duration.seconds(5),
+    });
+    webhooksTable.grantReadData(this.list);
+    TablesStack.grantReadIndex(webhooksTable, this.list);
+    this.list.grantInvoke(APIStack.principal);
+
+    // List deliveries of a webhook
+    this.deliveriesList = new Function(this, "deliveries-list", {
+      description: "List deliveries of a webhook",
+      runtime: Runtime.PROVIDED_AL2,
+      code: AssetCode.fromAsset(
+        `${this.srcPath}/api-webhooks-deliveries-list/bootstrap.zip`
+      ),
+      architecture: Architecture.X86_64,
+      handler: "bootstrap",
+      environment: {
+        RUST_BACKTRACE: "1",
+        RUST_LOG: config.api.resources.webhooks.webhook.deliveries.list.logging,
+        TABLE_NAME_WEBHOOK_DELIVERIES: webhookDeliveriesTable.tableName,
+        TABLE_REGION: props.env!.region!,
+        API_KEYS: config.api.keys,
+        API_AUTH: config.api.auth,
+        TABLE_NAME_WEBHOOKS: webhooksTable.tableName,
+      },
+      timeout: Duration.seconds(5),
+    });
+    webhooksTable.grantReadData(this.deliveriesList);
+    webhookDeliveriesTable.grantReadData(this.deliveriesList);
+    TablesStack.grantReadIndex(webhookDeliveriesTable, this.deliveriesList);
+    this.deliveriesList.grantInvoke(APIStack.principal);
+
+    // API Endpoints
+    const api = APIStack.getInstance(this);
+    const rootResource = APIStack.getRootResource(this, api);
+
+    const webhooks = rootResource.addResource(APIWebhooksStack.pathWebhooks);
+    webhooks.addMethod("POST", new LambdaIntegration(this.post));
+    webhooks.addMethod("GET", new LambdaIntegration(this.list));
+
+    const webhook = webhooks.addResource(APIWebhooksStack.pathWebhook);
+    const deliveries = webhook.addResource(APIWebhooksStack.pathDeliveries);
+    deliveries.addMethod("GET", new LambdaIntegration(this.deliveriesList));
+  }
+}
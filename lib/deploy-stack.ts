@@ -1,12 +1,16 @@
 import {
+  ArnFormat,
   Duration,
   RemovalPolicy,
   StackProps,
   aws_codebuild as build,
+  aws_events as events,
   aws_lambda as lambdas,
   aws_logs as logs,
   aws_s3 as s3,
   aws_events_targets as targets,
+  aws_iam as iam,
+  aws_lambda_event_sources as sources,
 } from "aws-cdk-lib";
 import { Tables, TablesStack } from "./tables-stack";
 
@@ -25,7 +29,30 @@ export class DeployStack extends OutputStack {
       this,
       Tables.ProjectDeployments
     );
-    const projectsTable = TablesStack.getInstance(this, Tables.Projects);
+    const projectsTable = TablesStack.getStreamingInstance(
+      this,
+      Tables.Projects
+    );
+    const usersTable = TablesStack.getStreamingInstance(this, Tables.Users);
+    const processedEventsTable = TablesStack.getInstance(
+      this,
+      Tables.ProcessedEvents
+    );
+    const deadLettersTable = TablesStack.getInstance(this, Tables.DeadLetters);
+    const webhooksTable = TablesStack.getInstance(this, Tables.Webhooks);
+    const webhookDeliveriesTable = TablesStack.getInstance(
+      this,
+      Tables.WebhookDeliveries
+    );
+    const outboxTable = TablesStack.getStreamingInstance(this, Tables.Outbox);
+    const projectStatsTable = TablesStack.getInstance(
+      this,
+      Tables.ProjectStats
+    );
+    const deploymentSlotsTable = TablesStack.getInstance(
+      this,
+      Tables.DeploymentSlots
+    );
 
     const CODEBUILD_PROJECT_NAME_BUILDING = config.app.name("-Building-SPAs");
 
@@ -33,11 +60,11 @@ export class DeployStack extends OutputStack {
       bucketName: config.app.name("-deploy-spas-artifacts").toLowerCase(),
       autoDeleteObjects: true,
       blockPublicAccess: s3.BlockPublicAccess.BLOCK_ALL,
+      // Artifacts are expired by the retention gc lambda so deployments can be rolled back
       lifecycleRules: [
         {
-          id: "auto-delete",
+          id: "abort-incomplete-uploads",
           enabled: true,
-          expiration: Duration.days(1),
           abortIncompleteMultipartUploadAfter: Duration.days(1),
         },
       ],
@@ -63,12 +90,27 @@ export class DeployStack extends OutputStack {
           CODEBUILD_PROJECT_NAME_BUILDING: CODEBUILD_PROJECT_NAME_BUILDING,
           CODEBUILD_PROJECT_NAME_DEPLOYMENT:
             "CODEBUILD_PROJECT_NAME_DEPLOYMENT", // TODO: replace with deployment project name once it is implemented
+          PROJECT_CONCURRENCY_LIMIT: config.deployments.concurrency.project,
+          ACCOUNT_CONCURRENCY_LIMIT: config.deployments.concurrency.account,
+          BUILD_TIMEOUT_MAX_MINUTES: config.deployments.build.maxTimeoutMinutes,
+          BUILD_COMPUTE_SIZE_MAX: config.deployments.build.maxComputeSize,
+          TABLE_NAME_EVENTS: processedEventsTable.tableName,
+          TABLE_NAME_DEAD_LETTERS: deadLettersTable.tableName,
+          TABLE_NAME_OUTBOX: outboxTable.tableName,
+          TABLE_NAME_DEPLOYMENT_SLOTS: deploymentSlotsTable.tableName,
         },
-        timeout: Duration.seconds(5),
+        timeout: Duration.seconds(30),
       }
     );
+    // Manifests are read from the artifact central directory and stored next to it
+    artifactsBucket.grantReadWrite(buildEventsProcessingFn);
     projectDeploymentsTable.grantReadWriteData(buildEventsProcessingFn);
+    TablesStack.grantReadIndex(projectDeploymentsTable, buildEventsProcessingFn);
     projectsTable.grantReadWriteData(buildEventsProcessingFn);
+    processedEventsTable.grantReadWriteData(buildEventsProcessingFn);
+    deadLettersTable.grantWriteData(buildEventsProcessingFn);
+    outboxTable.grantWriteData(buildEventsProcessingFn);
+    deploymentSlotsTable.grantReadWriteData(buildEventsProcessingFn);
 
     const buildingProject = new build.Project(this, "building", {
       projectName: CODEBUILD_PROJECT_NAME_BUILDING,
@@ -121,6 +163,43 @@ export class DeployStack extends OutputStack {
         },
       },
     });
+    // Environment secrets are resolved by CodeBuild, only the ones under the
+    // projects prefix can be read by builds
+    buildingProject.addToRolePolicy(
+      new iam.PolicyStatement({
+        effect: iam.Effect.ALLOW,
+        actions: ["ssm:GetParameters"],
+        resources: [
+          this.formatArn({
+            service: "ssm",
+            resource: "parameter",
+            resourceName: `${config.app.prefix}/projects/*`,
+          }),
+        ],
+      })
+    );
+    buildingProject.addToRolePolicy(
+      new iam.PolicyStatement({
+        effect: iam.Effect.ALLOW,
+        actions: ["secretsmanager:GetSecretValue"],
+        resources: [
+          this.formatArn({
+            service: "secretsmanager",
+            resource: "secret",
+            resourceName: `${config.app.prefix}/projects/*`,
+            arnFormat: ArnFormat.COLON_RESOURCE_NAME,
+          }),
+        ],
+      })
+    );
+    // Queued deployments are started by the processing lambda
+    buildEventsProcessingFn.addToRolePolicy(
+      new iam.PolicyStatement({
+        effect: iam.Effect.ALLOW,
+        actions: ["codebuild:StartBuild"],
+        resources: [buildingProject.projectArn],
+      })
+    );
     buildingProject.onEvent(config.app.name("codebuild-events"), {
       description: "Send codebuild events to processing lambda",
       target: new targets.LambdaFunction(buildEventsProcessingFn, {
@@ -150,6 +229,173 @@ export class DeployStack extends OutputStack {
         },
       },
     });
+    // State changes carry the final build status, logs and environment
+    buildingProject.onStateChange(config.app.name("codebuild-state-events"), {
+      description: "Send codebuild state events to processing lambda",
+      target: new targets.LambdaFunction(buildEventsProcessingFn, {
+        retryAttempts: 3,
+      }),
+      eventPattern: {
+        detail: {
+          "build-status": [
+            "IN_PROGRESS",
+            "SUCCEEDED",
+            "FAILED",
+            "STOPPED",
+            "FAULT",
+            "TIMED_OUT",
+          ],
+        },
+      },
+    });
+
+    const retentionGcFn = new lambdas.Function(this, "retention-gc", {
+      description: "Expire project deployments and artifacts per retention policy, and those of deleted projects",
+      runtime: lambdas.Runtime.PROVIDED_AL2,
+      code: lambdas.AssetCode.fromAsset(
+        `${this.srcPath}/retention-gc/bootstrap.zip`
+      ),
+      architecture: lambdas.Architecture.X86_64,
+      handler: "bootstrap",
+      environment: {
+        RUST_BACKTRACE: "1",
+        RUST_LOG: config.retention.gc.logging,
+        TABLE_NAME: projectDeploymentsTable.tableName,
+        TABLE_NAME_PROJECTS: projectsTable.tableName,
+        TABLE_NAME_OUTBOX: outboxTable.tableName,
+        TABLE_NAME_WEBHOOKS: webhooksTable.tableName,
+        TABLE_NAME_STATS: projectStatsTable.tableName,
+        RETENTION_DRY_RUN: config.retention.gc.dryRun,
+      },
+      timeout: Duration.minutes(5),
+    });
+    projectDeploymentsTable.grantReadWriteData(retentionGcFn);
+    TablesStack.grantReadIndex(projectDeploymentsTable, retentionGcFn);
+    projectsTable.grantReadData(retentionGcFn);
+    outboxTable.grantWriteData(retentionGcFn);
+    artifactsBucket.grantDelete(retentionGcFn);
+    // Webhooks and statistics of deleted projects are deleted with their deployments
+    webhooksTable.grantReadWriteData(retentionGcFn);
+    TablesStack.grantReadIndex(webhooksTable, retentionGcFn);
+    projectStatsTable.grantReadWriteData(retentionGcFn);
+    new events.Rule(this, config.app.name("retention-gc-schedule"), {
+      description: "Apply project deployments retention policy",
+      schedule: events.Schedule.expression(config.retention.gc.schedule),
+      targets: [new targets.LambdaFunction(retentionGcFn)],
+    });
+
+    const webhooksRetryFn = new lambdas.Function(this, "webhooks-retry", {
+      description: "Retry failed webhook deliveries",
+      runtime: lambdas.Runtime.PROVIDED_AL2,
+      code: lambdas.AssetCode.fromAsset(
+        `${this.srcPath}/webhooks-retry/bootstrap.zip`
+      ),
+      architecture: lambdas.Architecture.X86_64,
+      handler: "bootstrap",
+      environment: {
+        RUST_BACKTRACE: "1",
+        RUST_LOG: config.webhooks.retry.logging,
+        TABLE_NAME_WEBHOOKS: webhooksTable.tableName,
+        TABLE_NAME_WEBHOOK_DELIVERIES: webhookDeliveriesTable.tableName,
+      },
+      timeout: Duration.seconds(55),
+    });
+    webhooksTable.grantReadData(webhooksRetryFn);
+    webhookDeliveriesTable.grantReadWriteData(webhooksRetryFn);
+    TablesStack.grantReadIndex(webhookDeliveriesTable, webhooksRetryFn);
+    new events.Rule(this, config.app.name("webhooks-retry-schedule"), {
+      description: "Retry failed webhook deliveries",
+      schedule: events.Schedule.expression(config.webhooks.retry.schedule),
+      targets: [new targets.LambdaFunction(webhooksRetryFn)],
+    });
+
+    const outboxRelayFn = new lambdas.Function(this, "outbox-relay", {
+      description: "Publish outbox entries to webhooks, notifications and stats",
+      runtime: lambdas.Runtime.PROVIDED_AL2,
+      code: lambdas.AssetCode.fromAsset(
+        `${this.srcPath}/outbox-relay/bootstrap.zip`
+      ),
+      architecture: lambdas.Architecture.X86_64,
+      handler: "bootstrap",
+      environment: {
+        RUST_BACKTRACE: "1",
+        RUST_LOG: config.outbox.relay.logging,
+        TABLE_NAME: outboxTable.tableName,
+        TABLE_NAME_DEPLOYMENTS: projectDeploymentsTable.tableName,
+        TABLE_NAME_PROJECTS: projectsTable.tableName,
+        TABLE_NAME_USERS: usersTable.tableName,
+        TABLE_NAME_WEBHOOKS: webhooksTable.tableName,
+        TABLE_NAME_WEBHOOK_DELIVERIES: webhookDeliveriesTable.tableName,
+        TABLE_NAME_STATS: projectStatsTable.tableName,
+        // Emails are skipped while SMTP_HOST or SMTP_FROM are empty
+        SMTP_HOST: config.notifications.smtp.host,
+        SMTP_PORT: config.notifications.smtp.port,
+        SMTP_SECURITY: config.notifications.smtp.security,
+        SMTP_USERNAME: config.notifications.smtp.username,
+        SMTP_PASSWORD: config.notifications.smtp.password,
+        SMTP_FROM: config.notifications.smtp.from,
+      },
+      timeout: Duration.seconds(55),
+    });
+    outboxTable.grantReadWriteData(outboxRelayFn);
+    TablesStack.grantReadIndex(outboxTable, outboxRelayFn);
+    projectDeploymentsTable.grantReadData(outboxRelayFn);
+    TablesStack.grantReadIndex(projectDeploymentsTable, outboxRelayFn);
+    projectsTable.grantReadData(outboxRelayFn);
+    usersTable.grantReadData(outboxRelayFn);
+    webhooksTable.grantReadData(outboxRelayFn);
+    TablesStack.grantReadIndex(webhooksTable, outboxRelayFn);
+    webhookDeliveriesTable.grantWriteData(outboxRelayFn);
+    projectStatsTable.grantReadWriteData(outboxRelayFn);
+    // New entries are published from the stream, the schedule retries the rest
+    outboxRelayFn.addEventSource(
+      new sources.DynamoEventSource(outboxTable, {
+        startingPosition: lambdas.StartingPosition.TRIM_HORIZON,
+        batchSize: config.outbox.relay.batchSize,
+        reportBatchItemFailures: true,
+        retryAttempts: 3,
+      })
+    );
+    new events.Rule(this, config.app.name("outbox-relay-schedule"), {
+      description: "Publish due outbox entries",
+      schedule: events.Schedule.expression(config.outbox.relay.schedule),
+      targets: [new targets.LambdaFunction(outboxRelayFn)],
+    });
+
+    const streamsDomainEventsFn = new lambdas.Function(
+      this,
+      "streams-domain-events",
+      {
+        description: "Publish domain events of the users, projects and deployments streams",
+        runtime: lambdas.Runtime.PROVIDED_AL2,
+        code: lambdas.AssetCode.fromAsset(
+          `${this.srcPath}/streams-domain-events/bootstrap.zip`
+        ),
+        architecture: lambdas.Architecture.X86_64,
+        handler: "bootstrap",
+        environment: {
+          RUST_BACKTRACE: "1",
+          RUST_LOG: config.streams.domainEvents.logging,
+          TABLE_NAME: projectDeploymentsTable.tableName,
+          TABLE_NAME_PROJECTS: projectsTable.tableName,
+          TABLE_NAME_USERS: usersTable.tableName,
+          TABLE_NAME_DEAD_LETTERS: deadLettersTable.tableName,
+        },
+        timeout: Duration.seconds(30),
+      }
+    );
+    deadLettersTable.grantWriteData(streamsDomainEventsFn);
+    // Records rejected by a sink are retried from the failed one on
+    for (const table of [usersTable, projectsTable, projectDeploymentsTable]) {
+      streamsDomainEventsFn.addEventSource(
+        new sources.DynamoEventSource(table, {
+          startingPosition: lambdas.StartingPosition.TRIM_HORIZON,
+          batchSize: config.streams.domainEvents.batchSize,
+          reportBatchItemFailures: true,
+          retryAttempts: 10,
+        })
+      );
+    }
 
     this.outputSSM(
       config.app.name("CodebuildProjectNameSSM"),
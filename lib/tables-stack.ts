@@ -18,6 +18,38 @@ export enum Tables {
   Users = "Users",
   Projects = "Projects",
   ProjectDeployments = "ProjectDeployments",
+  Webhooks = "Webhooks",
+  WebhookDeliveries = "WebhookDeliveries",
+  ProcessedEvents = "ProcessedEvents",
+  DeadLetters = "DeadLetters",
+  Outbox = "Outbox",
+  ProjectStats = "ProjectStats",
+  UserEmails = "UserEmails",
+  IdempotencyKeys = "IdempotencyKeys",
+  DeploymentSlots = "DeploymentSlots",
+}
+
+// Keep in sync with the index constants in src/layers/buildor/src/handlers/project_deployments.rs
+export enum ProjectDeploymentsIndexes {
+  Project = "project_uuid-created_at",
+  Status = "status-created_at",
+  Build = "build_uuid",
+}
+
+// Keep in sync with the index constants in src/layers/buildor/src/handlers/webhooks.rs
+export enum WebhooksIndexes {
+  Project = "project_uuid",
+  User = "user_uuid",
+}
+
+export enum WebhookDeliveriesIndexes {
+  Webhook = "webhook_uuid-created_at",
+  Status = "status-next_attempt_at",
+}
+
+// Keep in sync with the index constants in src/layers/buildor/src/handlers/outbox.rs
+export enum OutboxIndexes {
+  Status = "status-next_attempt_at",
 }
 
 export class TablesStack extends OutputStack {
@@ -66,6 +98,20 @@ export class TablesStack extends OutputStack {
       billingMode: BillingMode.PAY_PER_REQUEST,
       stream: StreamViewType.NEW_AND_OLD_IMAGES,
     });
+    projectDeployments.addGlobalSecondaryIndex({
+      indexName: ProjectDeploymentsIndexes.Project,
+      partitionKey: { name: "project_uuid", type: AttributeType.STRING },
+      sortKey: { name: "created_at", type: AttributeType.STRING },
+    });
+    projectDeployments.addGlobalSecondaryIndex({
+      indexName: ProjectDeploymentsIndexes.Status,
+      partitionKey: { name: "status", type: AttributeType.STRING },
+      sortKey: { name: "created_at", type: AttributeType.STRING },
+    });
+    projectDeployments.addGlobalSecondaryIndex({
+      indexName: ProjectDeploymentsIndexes.Build,
+      partitionKey: { name: "build_uuid", type: AttributeType.STRING },
+    });
 
     this.outputSSM(
       config.app.name(`${Tables.ProjectDeployments}SSM`),
@@ -77,6 +123,195 @@ export class TablesStack extends OutputStack {
       config.ssm.tables.projectDeployments.streamArn,
       projectDeployments.tableStreamArn!
     );
+
+    // Webhooks
+    const webhooks = new Table(this, Tables.Webhooks, {
+      partitionKey: { name: "uuid", type: AttributeType.STRING },
+      billingMode: BillingMode.PAY_PER_REQUEST,
+      stream: StreamViewType.NEW_AND_OLD_IMAGES,
+    });
+    webhooks.addGlobalSecondaryIndex({
+      indexName: WebhooksIndexes.Project,
+      partitionKey: { name: "project_uuid", type: AttributeType.STRING },
+    });
+    webhooks.addGlobalSecondaryIndex({
+      indexName: WebhooksIndexes.User,
+      partitionKey: { name: "user_uuid", type: AttributeType.STRING },
+    });
+
+    this.outputSSM(
+      config.app.name(`${Tables.Webhooks}SSM`),
+      config.ssm.tables.webhooks.tableArn,
+      webhooks.tableArn
+    );
+    this.outputSSM(
+      config.app.name(`${Tables.Webhooks}StreamSSM`),
+      config.ssm.tables.webhooks.streamArn,
+      webhooks.tableStreamArn!
+    );
+
+    // Webhook Deliveries
+    const webhookDeliveries = new Table(this, Tables.WebhookDeliveries, {
+      partitionKey: { name: "uuid", type: AttributeType.STRING },
+      billingMode: BillingMode.PAY_PER_REQUEST,
+      stream: StreamViewType.NEW_AND_OLD_IMAGES,
+    });
+    webhookDeliveries.addGlobalSecondaryIndex({
+      indexName: WebhookDeliveriesIndexes.Webhook,
+      partitionKey: { name: "webhook_uuid", type: AttributeType.STRING },
+      sortKey: { name: "created_at", type: AttributeType.STRING },
+    });
+    webhookDeliveries.addGlobalSecondaryIndex({
+      indexName: WebhookDeliveriesIndexes.Status,
+      partitionKey: { name: "status", type: AttributeType.STRING },
+      sortKey: { name: "next_attempt_at", type: AttributeType.STRING },
+    });
+
+    this.outputSSM(
+      config.app.name(`${Tables.WebhookDeliveries}SSM`),
+      config.ssm.tables.webhookDeliveries.tableArn,
+      webhookDeliveries.tableArn
+    );
+    this.outputSSM(
+      config.app.name(`${Tables.WebhookDeliveries}StreamSSM`),
+      config.ssm.tables.webhookDeliveries.streamArn,
+      webhookDeliveries.tableStreamArn!
+    );
+
+    // Processed Events, ids of handled events expired through the table TTL
+    const processedEvents = new Table(this, Tables.ProcessedEvents, {
+      partitionKey: { name: "id", type: AttributeType.STRING },
+      billingMode: BillingMode.PAY_PER_REQUEST,
+      stream: StreamViewType.NEW_AND_OLD_IMAGES,
+      timeToLiveAttribute: "expires_at",
+    });
+
+    this.outputSSM(
+      config.app.name(`${Tables.ProcessedEvents}SSM`),
+      config.ssm.tables.processedEvents.tableArn,
+      processedEvents.tableArn
+    );
+    this.outputSSM(
+      config.app.name(`${Tables.ProcessedEvents}StreamSSM`),
+      config.ssm.tables.processedEvents.streamArn,
+      processedEvents.tableStreamArn!
+    );
+
+    // Dead Letters, events that could not be processed kept for inspection
+    const deadLetters = new Table(this, Tables.DeadLetters, {
+      partitionKey: { name: "uuid", type: AttributeType.STRING },
+      billingMode: BillingMode.PAY_PER_REQUEST,
+      stream: StreamViewType.NEW_AND_OLD_IMAGES,
+    });
+
+    this.outputSSM(
+      config.app.name(`${Tables.DeadLetters}SSM`),
+      config.ssm.tables.deadLetters.tableArn,
+      deadLetters.tableArn
+    );
+    this.outputSSM(
+      config.app.name(`${Tables.DeadLetters}StreamSSM`),
+      config.ssm.tables.deadLetters.streamArn,
+      deadLetters.tableStreamArn!
+    );
+
+    // Outbox, side effects of deployment changes written in the same transaction
+    const outbox = new Table(this, Tables.Outbox, {
+      partitionKey: { name: "uuid", type: AttributeType.STRING },
+      billingMode: BillingMode.PAY_PER_REQUEST,
+      stream: StreamViewType.NEW_AND_OLD_IMAGES,
+    });
+    outbox.addGlobalSecondaryIndex({
+      indexName: OutboxIndexes.Status,
+      partitionKey: { name: "status", type: AttributeType.STRING },
+      sortKey: { name: "next_attempt_at", type: AttributeType.STRING },
+    });
+
+    this.outputSSM(
+      config.app.name(`${Tables.Outbox}SSM`),
+      config.ssm.tables.outbox.tableArn,
+      outbox.tableArn
+    );
+    this.outputSSM(
+      config.app.name(`${Tables.Outbox}StreamSSM`),
+      config.ssm.tables.outbox.streamArn,
+      outbox.tableStreamArn!
+    );
+
+    // Project Stats, build counters per project and day incremented by the outbox relay
+    const projectStats = new Table(this, Tables.ProjectStats, {
+      partitionKey: { name: "project_uuid", type: AttributeType.STRING },
+      sortKey: { name: "day", type: AttributeType.STRING },
+      billingMode: BillingMode.PAY_PER_REQUEST,
+      stream: StreamViewType.NEW_AND_OLD_IMAGES,
+    });
+
+    this.outputSSM(
+      config.app.name(`${Tables.ProjectStats}SSM`),
+      config.ssm.tables.projectStats.tableArn,
+      projectStats.tableArn
+    );
+    this.outputSSM(
+      config.app.name(`${Tables.ProjectStats}StreamSSM`),
+      config.ssm.tables.projectStats.streamArn,
+      projectStats.tableStreamArn!
+    );
+
+    // User Emails, one item per normalized email so that it belongs to a single user
+    const userEmails = new Table(this, Tables.UserEmails, {
+      partitionKey: { name: "email", type: AttributeType.STRING },
+      billingMode: BillingMode.PAY_PER_REQUEST,
+      stream: StreamViewType.NEW_AND_OLD_IMAGES,
+    });
+
+    this.outputSSM(
+      config.app.name(`${Tables.UserEmails}SSM`),
+      config.ssm.tables.userEmails.tableArn,
+      userEmails.tableArn
+    );
+    this.outputSSM(
+      config.app.name(`${Tables.UserEmails}StreamSSM`),
+      config.ssm.tables.userEmails.streamArn,
+      userEmails.tableStreamArn!
+    );
+
+    // Idempotency Keys, responses of requests sent with an Idempotency-Key
+    // header expired through the table TTL
+    const idempotencyKeys = new Table(this, Tables.IdempotencyKeys, {
+      partitionKey: { name: "key", type: AttributeType.STRING },
+      billingMode: BillingMode.PAY_PER_REQUEST,
+      stream: StreamViewType.NEW_AND_OLD_IMAGES,
+      timeToLiveAttribute: "expires_at",
+    });
+
+    this.outputSSM(
+      config.app.name(`${Tables.IdempotencyKeys}SSM`),
+      config.ssm.tables.idempotencyKeys.tableArn,
+      idempotencyKeys.tableArn
+    );
+    this.outputSSM(
+      config.app.name(`${Tables.IdempotencyKeys}StreamSSM`),
+      config.ssm.tables.idempotencyKeys.streamArn,
+      idempotencyKeys.tableStreamArn!
+    );
+
+    // Deployment Slots, builds in flight per project and for the account
+    const deploymentSlots = new Table(this, Tables.DeploymentSlots, {
+      partitionKey: { name: "key", type: AttributeType.STRING },
+      billingMode: BillingMode.PAY_PER_REQUEST,
+      stream: StreamViewType.NEW_AND_OLD_IMAGES,
+    });
+
+    this.outputSSM(
+      config.app.name(`${Tables.DeploymentSlots}SSM`),
+      config.ssm.tables.deploymentSlots.tableArn,
+      deploymentSlots.tableArn
+    );
+    this.outputSSM(
+      config.app.name(`${Tables.DeploymentSlots}StreamSSM`),
+      config.ssm.tables.deploymentSlots.streamArn,
+      deploymentSlots.tableStreamArn!
+    );
   }
 
   public static getInstance(scope: Construct, table: Tables): ITable {
@@ -0,0 +1,19 @@
+use log::{self, info};
+use serde_json::Value;
+
+use buildor::{
+    handlers::router::Router,
+    models::router::HttpMethod,
+    routes::errors::{ErrorsList, PATH_ERRORS},
+};
+
+#[tokio::main]
+async fn main() -> Result<(), Value> {
+    env_logger::init();
+
+    info!("Creating router");
+    Router::api()
+        .route(HttpMethod::Get, PATH_ERRORS, ErrorsList)
+        .serve()
+        .await
+}
@@ -0,0 +1,19 @@
+use log::{self, info};
+use serde_json::Value;
+
+use buildor::{
+    handlers::router::Router,
+    models::router::HttpMethod,
+    routes::project_deployments::{ProjectDeploymentPatch, PATH_DEPLOYMENT},
+};
+
+#[tokio::main]
+async fn main() -> Result<(), Value> {
+    env_logger::init();
+
+    info!("Creating router");
+    Router::api()
+        .route(HttpMethod::Patch, PATH_DEPLOYMENT, ProjectDeploymentPatch)
+        .serve()
+        .await
+}
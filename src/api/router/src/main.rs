@@ -0,0 +1,12 @@
+use log::{self, info};
+use serde_json::Value;
+
+use buildor::routes;
+
+#[tokio::main]
+async fn main() -> Result<(), Value> {
+    env_logger::init();
+
+    info!("Creating router for the whole API");
+    routes::api().serve().await
+}
@@ -1,67 +1,19 @@
-use error_stack::{Report, ResultExt};
-use lambda_runtime::{service_fn, LambdaEvent};
-use log::{self, error, info};
-use serde_json::{json, Value};
+use log::{self, info};
+use serde_json::Value;
 
 use buildor::{
-    handlers::users::UsersHandler,
-    models::{
-        common::{ExecutionError, ResponseGenericList},
-        handlers::HandlerList,
-        request::RequestError,
-        response::Response,
-    },
-    utils::{load_env_var, Clients},
+    handlers::router::Router,
+    models::router::HttpMethod,
+    routes::users::{UsersList, PATH_USERS},
 };
 
 #[tokio::main]
 async fn main() -> Result<(), Value> {
     env_logger::init();
 
-    info!("Creating service fn for handler");
-    let func = service_fn(handler);
-    info!("Executing handler from runtime");
-    let result = lambda_runtime::run(func).await;
-    info!("Evaluating handler result");
-    match result {
-        Ok(res) => {
-            info!("Success");
-            Ok(res)
-        }
-        Err(err) => {
-            error!("Handler exception: {}", err);
-            Err(json!(RequestError::internal()))
-        }
-    }
-}
-
-async fn handler(event: LambdaEvent<Value>) -> Result<Value, Report<ExecutionError>> {
-    info!("Start handler execution");
-
-    info!("Load env vars");
-    #[allow(non_snake_case)]
-    let TABLE_NAME = load_env_var("TABLE_NAME", None).change_context(ExecutionError)?;
-    info!("TABLE_NAME: {}", TABLE_NAME);
-    #[allow(non_snake_case)]
-    let TABLE_REGION = load_env_var("TABLE_REGION", None).change_context(ExecutionError)?;
-    info!("TABLE_REGION: {}", TABLE_REGION);
-
-    info!("Parse event and context objects");
-    let (event, context) = event.into_parts();
-    info!("event: {:?}", event);
-    info!("context: {:?}", context);
-
-    let table = Clients::dynamodb().await;
-    let uh = UsersHandler::new(table, TABLE_NAME);
-
-    match uh.list().await {
-        Err(error) => {
-            error!(
-                "An error happened when listing projects from db: {}",
-                error.to_string()
-            );
-            Err(error.change_context(ExecutionError))
-        }
-        Ok(users) => Ok(Response::new(ResponseGenericList::new(users), 200)),
-    }
+    info!("Creating router");
+    Router::api()
+        .route(HttpMethod::Get, PATH_USERS, UsersList)
+        .serve()
+        .await
 }
@@ -0,0 +1,23 @@
+use log::{self, info};
+use serde_json::Value;
+
+use buildor::{
+    handlers::router::Router,
+    models::router::HttpMethod,
+    routes::webhooks::{WebhookDeliveriesList, PATH_WEBHOOK_DELIVERIES},
+};
+
+#[tokio::main]
+async fn main() -> Result<(), Value> {
+    env_logger::init();
+
+    info!("Creating router");
+    Router::api()
+        .route(
+            HttpMethod::Get,
+            PATH_WEBHOOK_DELIVERIES,
+            WebhookDeliveriesList,
+        )
+        .serve()
+        .await
+}
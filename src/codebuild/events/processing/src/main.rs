@@ -1,21 +1,39 @@
-use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono::{Duration, Utc};
 use error_stack::{Report, ResultExt};
 use lambda_runtime::{service_fn, LambdaEvent};
 use log::{self, error, info};
 use serde_json::{json, Value};
-use std::str::FromStr;
 
 use buildor::{
-    handlers::codebuild::BuildInfoParser,
+    handlers::{
+        artifacts::{parse_s3_location, ArtifactsHandler},
+        codebuild::{BuildInfoParser, BuildLimits, CodeBuildHandler},
+        dead_letters::DeadLettersHandler,
+        deployment_queue::{ConcurrencyLimits, DeploymentQueue},
+        processed_events::ProcessedEventsHandler,
+        project_deployments::ProjectDeploymentsHandler,
+        projects::ProjectsHandler,
+    },
     models::{
-        codebuild::{BuildInfo, BuildPhase, BuildPhaseStatus},
+        codebuild::{BuildInfo, BuildPhaseStatus},
+        codebuild_event::{
+            BuildPhaseChangeDetail, BuildStateChangeDetail, CodeBuildEvent, CodeBuildEventDetail,
+        },
         common::ExecutionError,
+        dead_letter::DeadLetter,
+        handlers::{HandlerGet, HandlerUpdate},
+        outbox::{BuildOutcome, PhaseDuration},
+        processed_event::{ProcessedEvent, DEFAULT_PROCESSED_EVENT_TTL_HOURS},
+        project_deployment::{ProjectDeploymentStatus, ProjectDeploymentUpdatePayload},
         request::RequestError,
         response::Response,
     },
-    utils::load_env_var,
+    utils::{load_env_var, Clients},
 };
 
+/// Source of the dead letters of this lambda
+const DEAD_LETTER_SOURCE: &str = "codebuild-events-processing";
+
 #[tokio::main]
 async fn main() -> Result<(), Value> {
     env_logger::init();
@@ -40,6 +58,80 @@ async fn main() -> Result<(), Value> {
 async fn handler(event: LambdaEvent<Value>) -> Result<Value, Report<ExecutionError>> {
     info!("Start handler execution");
 
+    info!("Load env vars");
+    #[allow(non_snake_case)]
+    let TABLE_NAME_EVENTS =
+        load_env_var("TABLE_NAME_EVENTS", None).change_context(ExecutionError)?;
+    info!("TABLE_NAME_EVENTS: {}", TABLE_NAME_EVENTS);
+
+    info!("Parse event and context objects");
+    let (event, context) = event.into_parts();
+    info!("event: {}", event);
+    info!("context: {:?}", context);
+
+    // EventBridge retries and duplicate CodeBuild events share the same id.
+    // The id is claimed before the event is applied and marked done once every
+    // side effect succeeded. A failed event is released so that its retry
+    // applies it again, side effects are idempotent.
+    let event_id = event
+        .get("id")
+        .and_then(|value| value.as_str())
+        .map(|value| value.to_string());
+    let source = event
+        .get("source")
+        .and_then(|value| value.as_str())
+        .unwrap_or("unknown")
+        .to_string();
+    let peh = ProcessedEventsHandler::new(Clients::dynamodb().await, TABLE_NAME_EVENTS);
+    let now = Utc::now();
+    let claim = event_id.map(|id| {
+        ProcessedEvent::new(
+            id,
+            source,
+            now,
+            Duration::hours(DEFAULT_PROCESSED_EVENT_TTL_HOURS),
+        )
+    });
+    match claim.as_ref() {
+        Some(claim) => match peh.claim(claim, now).await.change_context(ExecutionError)? {
+            None => (),
+            Some(holder) if holder.done => {
+                info!("Event already processed: {}", holder.id);
+                return Ok(Response::new(json!({ "data": "duplicate event" }), 200));
+            }
+            // Failing lets EventBridge retry the event once the lambda that
+            // holds it completed or released it
+            Some(holder) => {
+                error!("Event in progress in another invocation: {}", holder.id);
+                return Err(Report::new(ExecutionError));
+            }
+        },
+        None => error!("Missing event id, processing without deduplication"),
+    }
+
+    let result = match process(event).await {
+        Ok(result) => result,
+        Err(error) => {
+            if let Some(claim) = claim.as_ref() {
+                if let Err(release_error) = peh.release(claim).await {
+                    error!("Failed to release event {}: {}", claim.id, release_error);
+                }
+            }
+            return Err(error);
+        }
+    };
+
+    if let Some(claim) = claim.as_ref() {
+        let completed = peh.complete(claim).await.change_context(ExecutionError)?;
+        if !completed {
+            info!("Event taken over by another invocation: {}", claim.id);
+        }
+    }
+
+    Ok(result)
+}
+
+async fn process(event: Value) -> Result<Value, Report<ExecutionError>> {
     info!("Load env vars");
     #[allow(non_snake_case)]
     let TABLE_NAME = load_env_var("TABLE_NAME", None).change_context(ExecutionError)?;
@@ -51,6 +143,21 @@ async fn handler(event: LambdaEvent<Value>) -> Result<Value, Report<ExecutionErr
     let TABLE_NAME_PROJECTS =
         load_env_var("TABLE_NAME_PROJECTS", None).change_context(ExecutionError)?;
     info!("TABLE_NAME_PROJECTS: {}", TABLE_NAME_PROJECTS);
+    #[allow(non_snake_case)]
+    let TABLE_NAME_DEAD_LETTERS =
+        load_env_var("TABLE_NAME_DEAD_LETTERS", None).change_context(ExecutionError)?;
+    info!("TABLE_NAME_DEAD_LETTERS: {}", TABLE_NAME_DEAD_LETTERS);
+    #[allow(non_snake_case)]
+    let TABLE_NAME_OUTBOX =
+        load_env_var("TABLE_NAME_OUTBOX", None).change_context(ExecutionError)?;
+    info!("TABLE_NAME_OUTBOX: {}", TABLE_NAME_OUTBOX);
+    #[allow(non_snake_case)]
+    let TABLE_NAME_DEPLOYMENT_SLOTS =
+        load_env_var("TABLE_NAME_DEPLOYMENT_SLOTS", None).change_context(ExecutionError)?;
+    info!(
+        "TABLE_NAME_DEPLOYMENT_SLOTS: {}",
+        TABLE_NAME_DEPLOYMENT_SLOTS
+    );
 
     #[allow(non_snake_case)]
     let CODEBUILD_PROJECT_NAME_BUILDING =
@@ -68,129 +175,313 @@ async fn handler(event: LambdaEvent<Value>) -> Result<Value, Report<ExecutionErr
         CODEBUILD_PROJECT_NAME_DEPLOYMENT
     );
 
-    info!("Parse event and context objects");
-    let (event, context) = event.into_parts();
-    info!("event: {}", event);
-    info!("context: {:?}", context);
-
-    // Get Build Details
-    let details = match event.get("detail") {
-        Some(details) => details.to_owned(),
-        None => todo!(),
-    };
-    info!("Build details: {}", details);
-
-    // Get Build ID
-    let uuid = match details.get("build-id") {
-        Some(value) => {
-            info!("Build raw id: {}", value);
-            match value.as_str() {
-                Some(value_str) => {
-                    let values: Vec<&str> = value_str.split(":").into_iter().collect();
-                    String::from(values[values.len() - 1])
-                }
-                None => todo!(),
-            }
+    // Events that can not be parsed would fail again on every retry, they are
+    // kept as dead letters and acknowledged
+    let build_event = match CodeBuildEventDetail::parse(event.clone()) {
+        Ok(CodeBuildEventDetail::BuildPhaseChange(event)) => BuildEvent::from_phase_change(&event),
+        Ok(CodeBuildEventDetail::BuildStateChange(event)) => BuildEvent::from_state_change(&event),
+        Err(error) => {
+            error!("Failed to parse event: {}", error);
+            let dlh = DeadLettersHandler::new(Clients::dynamodb().await, TABLE_NAME_DEAD_LETTERS);
+            dlh.save(&DeadLetter::new(
+                DEAD_LETTER_SOURCE,
+                error.to_string(),
+                event.to_string(),
+            ))
+            .await
+            .change_context(ExecutionError)?;
+            return Ok(Response::new(json!({ "data": "dead letter" }), 200));
         }
-        None => todo!(),
     };
-    info!("Build uuid: {}", uuid);
-
-    let additional_info = match details.get("additional-information") {
-        Some(value) => value,
-        None => {
-            info!("Missing additional-information property, stop execution");
-            return Err(Report::new(ExecutionError));
-        }
-    };
-    info!("Additional Information: {}", additional_info);
-
-    let build_number = match additional_info.get("build-number") {
-        Some(value) => match value.as_i64() {
-            Some(value) => Some(value),
-            None => None,
-        },
-        None => None,
-    };
-    info!("Build Number: {:?}", build_number);
-
-    let start_time = match additional_info.get("build-start-time") {
-        Some(value) => match value.as_str() {
-            Some(time) => Some(match NaiveDateTime::parse_from_str(time, "%h %d, %Y %r") {
-                Ok(timestamp) => timestamp.timestamp(),
-                Err(error) => {
-                    error!("Failed to parse start_time value: {:?}", error);
-                    return Err(Report::new(ExecutionError));
-                }
-            }),
-            None => None,
-        },
-        None => None,
-    };
-    info!("Start Time: {:?}", start_time);
-
-    let end_time = match event.get("time") {
-        Some(value) => match value.as_str() {
-            Some(time) => Some(match DateTime::<Utc>::from_str(time) {
-                Ok(timestamp) => timestamp.timestamp(),
-                Err(error) => {
-                    error!("Failed to parse end_time value: {:?}", error);
-                    return Err(Report::new(ExecutionError));
-                }
-            }),
-            None => None,
-        },
-        None => None,
-    };
-    info!("End Time: {:?}", end_time);
-
-    // Get Phase Information
-    let completed_phase = match details.get("completed-phase") {
-        Some(value) => match value.as_str() {
-            Some(phase) => BuildPhase::from_str(phase).unwrap(),
-            None => BuildPhase::Unknown,
-        },
-        None => BuildPhase::Unknown,
-    };
-    info!("Build completed phase: {}", completed_phase);
-
-    let completed_phase_status = match details.get("completed-phase-status") {
-        Some(value) => match value.as_str() {
-            Some(status) => BuildPhaseStatus::from_str(status).unwrap(),
-            None => BuildPhaseStatus::Unknown,
-        },
-        None => BuildPhaseStatus::Unknown,
-    };
-    info!("Completed phase status: {:?}", completed_phase_status);
-
-    // Get Codebuild Project Name
-    let codebuild_project_name = match details.get("project-name") {
-        Some(value) => match value.as_str() {
-            Some(parsed) => Some(parsed.to_string()),
-            None => None,
-        },
-        None => None,
-    };
-    info!("Codebuild Project Name: {:?}", codebuild_project_name);
+    info!("Build event: {:?}", build_event);
 
     // Parse Project Deployment Phase
     let project_deployment_phase = BuildInfoParser::deployment_phase(
-        codebuild_project_name,
+        Some(build_event.project_name.clone()),
         CODEBUILD_PROJECT_NAME_BUILDING.clone(),
         CODEBUILD_PROJECT_NAME_DEPLOYMENT.clone(),
     );
     info!("Project Deployment Phase: {}", project_deployment_phase);
 
-    let build = BuildInfo {
-        uuid,
-        build_number,
-        start_time,
-        end_time,
-        deployment_phase: Some(project_deployment_phase.to_string()),
-        current_phase: Some(completed_phase.to_string()),
-        build_status: Some(completed_phase_status.to_string()),
+    let mut build = build_event.build.clone();
+    build.deployment_phase = Some(project_deployment_phase.to_string());
+
+    let pdh = ProjectDeploymentsHandler::new(Clients::dynamodb().await, TABLE_NAME)
+        .with_outbox(TABLE_NAME_OUTBOX)
+        .with_slots(TABLE_NAME_DEPLOYMENT_SLOTS);
+    // Builds carry the uuid of their deployment, in case recording the build
+    // id failed once it was started
+    info!("Find project deployment by build");
+    let found = match build_event.deployment_uuid.clone() {
+        Some(uuid) => pdh.get(uuid).await,
+        None => Ok(None),
+    };
+    let found = match found {
+        Ok(None) => pdh.find_by_build(build.uuid.clone()).await,
+        found => found,
     };
+    let deployment = match found {
+        Ok(Some(deployment)) => deployment,
+        Ok(None) => {
+            error!("Project deployment not found for build: {}", build.uuid);
+            return Err(Report::new(ExecutionError));
+        }
+        Err(error) => {
+            error!("Failed to find project deployment: {}", error);
+            return Err(error.change_context(ExecutionError));
+        }
+    };
+    info!("Project deployment: {:?}", deployment);
+
+    // Phase events carry no logs nor environment details, keep the ones
+    // recorded by previous state events
+    if let Some(previous) = deployment.build.as_ref() {
+        build.logs_url = build.logs_url.or_else(|| previous.logs_url.clone());
+        build.compute_type = build.compute_type.or_else(|| previous.compute_type.clone());
+        build.image = build.image.or_else(|| previous.image.clone());
+    }
     info!("Build Info: {:?}", build);
 
+    // Builds that ended keep their details, late phase events must not
+    // overwrite them. Build state events may still correct a status derived
+    // from phase events, their details are recorded along.
+    info!("Update project deployment build info");
+    let mut updatable = vec![ProjectDeploymentStatus::InProgress];
+    if build_event.authoritative {
+        updatable.push(ProjectDeploymentStatus::Succeeded);
+        updatable.push(ProjectDeploymentStatus::Failed);
+    }
+    let recorded = pdh
+        .update_build(deployment.uuid.clone(), &updatable, build.clone())
+        .await
+        .change_context(ExecutionError)?;
+    if !recorded {
+        info!(
+            "Project deployment {} is {}, build info kept",
+            deployment.uuid,
+            deployment.status()
+        );
+    }
+
+    let status = build_event.status;
+    info!("Project deployment status: {:?}", status);
+
+    if let Some(status) = status {
+        // Build state events are authoritative, they also correct a final
+        // status derived from phase events
+        let mut from = vec![ProjectDeploymentStatus::InProgress];
+        if build_event.authoritative {
+            match status {
+                ProjectDeploymentStatus::Succeeded => from.push(ProjectDeploymentStatus::Failed),
+                ProjectDeploymentStatus::Failed => from.push(ProjectDeploymentStatus::Succeeded),
+                _ => (),
+            }
+        }
+        // Webhooks and notifications are published by the outbox relay
+        let updated = pdh
+            .transition_with_outcome(
+                deployment.uuid.clone(),
+                &from,
+                status,
+                None,
+                build_event.outcome(status, &build),
+            )
+            .await
+            .change_context(ExecutionError)?;
+
+        // Success side effects wait for the build state event, a success
+        // derived from phase events may still be corrected to a failure. They
+        // are idempotent and also run when the deployment already succeeded, so
+        // a retry finishes what a failed attempt left.
+        let succeeded = build_event.authoritative
+            && status == ProjectDeploymentStatus::Succeeded
+            && (updated || deployment.status() == ProjectDeploymentStatus::Succeeded);
+        if succeeded {
+            let mut failures = Vec::new();
+
+            info!("Record project deployment artifact");
+            let location = build_event
+                .artifact_location
+                .as_deref()
+                .and_then(parse_s3_location);
+            match location {
+                Some((bucket, key)) => {
+                    let ah = ArtifactsHandler::new(Clients::s3().await);
+                    match ah.describe(bucket, key).await {
+                        Ok(artifact) => {
+                            info!(
+                                "Artifact: {}, files: {}",
+                                artifact.location(),
+                                artifact.file_count
+                            );
+                            if let Err(error) = pdh
+                                .update(
+                                    deployment.uuid.clone(),
+                                    ProjectDeploymentUpdatePayload {
+                                        project: None,
+                                        status: None,
+                                        build: None,
+                                        artifact: Some(artifact),
+                                        pinned: None,
+                                    },
+                                )
+                                .await
+                            {
+                                error!("Failed to record artifact: {}", error);
+                                failures.push(error.change_context(ExecutionError));
+                            }
+                        }
+                        Err(error) => {
+                            error!("Failed to describe artifact: {}", error);
+                            failures.push(error.change_context(ExecutionError));
+                        }
+                    }
+                }
+                None => error!("Missing artifact location for build: {}", deployment.uuid),
+            }
+
+            info!("Update environment current deployment");
+            let ph = ProjectsHandler::new(Clients::dynamodb().await, TABLE_NAME_PROJECTS.clone());
+            if let Err(error) = ph
+                .set_current_deployment(
+                    deployment.project.uuid.clone(),
+                    deployment.environment.clone(),
+                    deployment.uuid.clone(),
+                )
+                .await
+            {
+                error!("Failed to update current deployment: {}", error);
+                failures.push(error.change_context(ExecutionError));
+            }
+
+            if let Some(failure) = failures.pop() {
+                return Err(failure);
+            }
+        }
+
+        // Also drained on retries, in case the previous attempt failed after
+        // the build gave its slot back
+        if status.is_terminal() {
+            info!("Start queued project deployments");
+            let cbh = CodeBuildHandler::new(
+                Clients::codebuild().await,
+                CODEBUILD_PROJECT_NAME_BUILDING.clone(),
+                CODEBUILD_PROJECT_NAME_DEPLOYMENT.clone(),
+                BuildLimits::from_env(),
+            );
+            let ph = ProjectsHandler::new(Clients::dynamodb().await, TABLE_NAME_PROJECTS.clone());
+            let queue = DeploymentQueue::new(pdh, ph, cbh, ConcurrencyLimits::from_env());
+            let started = queue.drain_all().await.change_context(ExecutionError)?;
+            info!("Started project deployments: {:?}", started);
+        }
+    }
+
     Ok(Response::new(json!({ "data": "static output"}), 200))
 }
+
+/// What the processing needs from phase and state change events
+#[derive(Debug)]
+struct BuildEvent {
+    build: BuildInfo,
+    project_name: String,
+    /// Deployment that started the build, from its build variables
+    deployment_uuid: Option<String>,
+    status: Option<ProjectDeploymentStatus>,
+    /// Whether the status is the final one reported by CodeBuild
+    authoritative: bool,
+    stopped: bool,
+    failed_phase: Option<String>,
+    commit: Option<String>,
+    artifact_location: Option<String>,
+    phases: Vec<PhaseDuration>,
+}
+impl BuildEvent {
+    fn from_phase_change(event: &CodeBuildEvent<BuildPhaseChangeDetail>) -> Self {
+        let detail = &event.detail;
+        let additional_info = &detail.additional_information;
+        let completed_phase = detail.completed_phase();
+        let completed_phase_status = detail.completed_phase_status();
+
+        Self {
+            build: BuildInfo {
+                uuid: detail.build_uuid(),
+                build_number: additional_info.build_number(),
+                start_time: additional_info
+                    .build_start_time()
+                    .map(|timestamp| timestamp.timestamp()),
+                end_time: Some(event.time.timestamp()),
+                deployment_phase: None,
+                current_phase: Some(completed_phase.to_string()),
+                build_status: Some(completed_phase_status.to_string()),
+                logs_url: additional_info.logs_url(),
+                compute_type: None,
+                image: None,
+            },
+            project_name: detail.project_name.clone(),
+            deployment_uuid: additional_info.deployment_uuid(),
+            status: detail.deployment_status(),
+            authoritative: false,
+            stopped: completed_phase_status == BuildPhaseStatus::Stopped,
+            failed_phase: Some(format!("{} ({})", completed_phase, completed_phase_status)),
+            commit: additional_info.commit(),
+            artifact_location: additional_info.artifact_location().map(String::from),
+            phases: additional_info.phase_durations(),
+        }
+    }
+
+    fn from_state_change(event: &CodeBuildEvent<BuildStateChangeDetail>) -> Self {
+        let detail = &event.detail;
+        let additional_info = &detail.additional_information;
+        let build_status = detail.build_status();
+        let environment = additional_info.environment.as_ref();
+
+        Self {
+            build: BuildInfo {
+                uuid: detail.build_uuid(),
+                build_number: additional_info.build_number(),
+                start_time: additional_info
+                    .build_start_time()
+                    .map(|timestamp| timestamp.timestamp()),
+                end_time: match additional_info.build_complete {
+                    true => Some(event.time.timestamp()),
+                    false => None,
+                },
+                deployment_phase: None,
+                current_phase: detail.current_phase.clone(),
+                build_status: Some(build_status.to_string()),
+                logs_url: additional_info.logs_url(),
+                compute_type: environment.and_then(|env| env.compute_type.clone()),
+                image: environment.and_then(|env| env.image.clone()),
+            },
+            project_name: detail.project_name.clone(),
+            deployment_uuid: additional_info.deployment_uuid(),
+            status: detail.deployment_status(),
+            authoritative: true,
+            stopped: build_status == BuildPhaseStatus::Stopped,
+            failed_phase: additional_info
+                .failed_phase()
+                .or_else(|| Some(build_status.to_string())),
+            commit: additional_info.commit(),
+            artifact_location: additional_info.artifact_location().map(String::from),
+            phases: additional_info.phase_durations(),
+        }
+    }
+
+    /// Build details reported with the final `status`, the failed phase is
+    /// only set for failed deployments
+    fn outcome(&self, status: ProjectDeploymentStatus, build: &BuildInfo) -> BuildOutcome {
+        BuildOutcome {
+            stopped: self.stopped,
+            failed_phase: match status {
+                ProjectDeploymentStatus::Failed => self.failed_phase.clone(),
+                _ => None,
+            },
+            duration_seconds: match (build.start_time, build.end_time) {
+                (Some(start), Some(end)) => Some(end - start),
+                _ => None,
+            },
+            commit: self.commit.clone(),
+            phases: self.phases.clone(),
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use crate::models::{
+    project::{Project, ProjectCreatePayload},
+    project_deployment::{
+        ProjectDeployment, ProjectDeploymentCreatePayload, ProjectDeploymentStatus,
+    },
+};
+
+/// Creation date of the deployments whose order does not matter
+pub const CREATED_AT: &str = "2022-01-01T00:00:00+00:00";
+
+/// Project with the default environments
+pub fn project() -> Project {
+    Project::new(ProjectCreatePayload::default(
+        "name".to_string(),
+        "https://example.com/repo.git".to_string(),
+    ))
+}
+
+/// Deployment of a project environment in a given status
+pub fn deployment(
+    project: &Project,
+    environment: &str,
+    status: ProjectDeploymentStatus,
+    created_at: &str,
+) -> ProjectDeployment {
+    let mut deployment = ProjectDeployment::new(ProjectDeploymentCreatePayload {
+        project: project.clone(),
+        environment: environment.to_string(),
+        build: None,
+    });
+    deployment.status = status.to_string();
+    deployment.created_at = created_at.to_string();
+    deployment
+}
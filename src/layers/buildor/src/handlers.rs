@@ -1,5 +1,22 @@
+pub mod artifacts;
 pub mod codebuild;
 pub mod commands;
+pub mod dead_letters;
+pub mod deployment_queue;
+pub mod deployment_slots;
+pub mod deployment_wait;
+pub mod domain_events;
+pub mod email;
+pub mod environments;
+pub mod http;
+pub mod idempotency_keys;
+pub mod notifications;
+pub mod outbox;
+pub mod processed_events;
 pub mod project_deployments;
 pub mod projects;
+pub mod retention;
+pub mod router;
+pub mod stats;
 pub mod users;
+pub mod webhooks;
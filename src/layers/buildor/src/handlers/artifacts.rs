@@ -0,0 +1,685 @@
+use aws_sdk_dynamodb::model::AttributeValue;
+use aws_sdk_s3::{types::ByteStream, Client};
+use error_stack::Report;
+use flate2::read::DeflateDecoder;
+use log::{self, error, info};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io;
+
+use crate::{
+    models::{
+        artifact::{ArtifactError, ArtifactFile, ArtifactManifest, ArtifactSummary},
+        common::MissingModelPropertyError,
+    },
+    utils::slugify,
+};
+
+/// Bytes read from the end of an artifact: the end of central directory record
+/// with the longest comment, preceded by the zip64 record and locator
+const ARCHIVE_TAIL_SIZE: u64 = 22 + 65_535 + 20 + 56;
+
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY: u32 = 0x06064b50;
+const ZIP64_LOCATOR: u32 = 0x07064b50;
+const CENTRAL_DIRECTORY_HEADER: u32 = 0x02014b50;
+const LOCAL_FILE_HEADER: u32 = 0x04034b50;
+const ZIP64_EXTRA_FIELD: u16 = 0x0001;
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+/// Most bytes of neighbouring files read at once to hash them, larger files
+/// are read alone
+const ARCHIVE_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Name of the zip file uploaded by codebuild for a deployment
+pub fn artifact_name(deployment_uuid: &str, project_name: &str) -> String {
+    match slugify(project_name) {
+        slug if slug.is_empty() => format!("{}.zip", deployment_uuid),
+        slug => format!("{}-{}.zip", deployment_uuid, slug),
+    }
+}
+
+/// Splits a codebuild artifact location (`arn:aws:s3:::bucket/key`) or an
+/// `s3://bucket/key` url into its bucket and key
+pub fn parse_s3_location(location: &str) -> Option<(String, String)> {
+    let path = location
+        .strip_prefix("arn:aws:s3:::")
+        .or_else(|| location.strip_prefix("s3://"))?;
+    let (bucket, key) = path.split_once('/')?;
+    if bucket.is_empty() || key.is_empty() {
+        return None;
+    }
+
+    Some((bucket.to_string(), key.to_string()))
+}
+
+/// Key of the manifest object of an artifact
+pub fn manifest_key(artifact_key: &str) -> String {
+    format!("{}.manifest.json", artifact_key)
+}
+
+/// Where the central directory of a zip archive starts and how long it is
+#[derive(Debug, PartialEq)]
+pub struct CentralDirectory {
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// File of a zip archive as listed by the central directory
+#[derive(Debug, PartialEq)]
+pub struct ArchiveEntry {
+    pub path: String,
+    pub size: u64,
+    pub compressed_size: u64,
+    pub method: u16,
+    /// Offset of the local file header
+    pub offset: u64,
+    /// Where the next entry or the central directory starts
+    pub end: u64,
+}
+
+fn read_u16(buffer: &[u8], at: usize) -> Option<u16> {
+    let bytes = buffer.get(at..at + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(buffer: &[u8], at: usize) -> Option<u32> {
+    let bytes = buffer.get(at..at + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_u64(buffer: &[u8], at: usize) -> Option<u64> {
+    let bytes = buffer.get(at..at + 8)?;
+    let mut value = [0; 8];
+    value.copy_from_slice(bytes);
+    Some(u64::from_le_bytes(value))
+}
+
+fn invalid(message: &str) -> Report<ArtifactError> {
+    Report::new(ArtifactError::new(message))
+}
+
+pub struct ArtifactSummaryParser;
+impl ArtifactSummaryParser {
+    pub fn parse(
+        item: HashMap<String, AttributeValue>,
+    ) -> Result<ArtifactSummary, Report<MissingModelPropertyError>> {
+        let bucket = match item.get("bucket") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("bucket"))),
+        };
+
+        let key = match item.get("key") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("key"))),
+        };
+
+        let file_count = match item.get("file_count") {
+            Some(value) => value.as_n().unwrap().parse().unwrap_or(0),
+            None => return Err(Report::new(MissingModelPropertyError::new("file_count"))),
+        };
+
+        let total_size = match item.get("total_size") {
+            Some(value) => value.as_n().unwrap().parse().unwrap_or(0),
+            None => return Err(Report::new(MissingModelPropertyError::new("total_size"))),
+        };
+
+        // Artifacts described before manifests were moved to S3 listed their
+        // files in the item, the list is not read anymore
+        let manifest_key = item
+            .get("manifest_key")
+            .and_then(|value| value.as_s().ok())
+            .map(|value| value.to_string());
+        let checksum = item
+            .get("checksum")
+            .and_then(|value| value.as_s().ok())
+            .map(|value| value.to_string());
+
+        Ok(ArtifactSummary {
+            bucket,
+            key,
+            file_count,
+            total_size,
+            manifest_key,
+            checksum,
+        })
+    }
+}
+
+pub struct ArtifactsHandler {
+    client: Client,
+}
+impl ArtifactsHandler {
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    /// Locates the central directory from the last bytes of an archive of
+    /// `length` bytes
+    pub fn central_directory(
+        tail: &[u8],
+        length: u64,
+    ) -> Result<CentralDirectory, Report<ArtifactError>> {
+        let end = match (0..tail.len().saturating_sub(21))
+            .rev()
+            .find(|at| read_u32(tail, *at) == Some(END_OF_CENTRAL_DIRECTORY))
+        {
+            Some(end) => end,
+            None => return Err(invalid("missing end of central directory")),
+        };
+        let size = read_u32(tail, end + 12).ok_or_else(|| invalid("truncated archive"))?;
+        let offset = read_u32(tail, end + 16).ok_or_else(|| invalid("truncated archive"))?;
+        if size != u32::MAX && offset != u32::MAX {
+            return Ok(CentralDirectory {
+                offset: offset as u64,
+                size: size as u64,
+            });
+        }
+
+        // Archives over 4GB keep the central directory location in the zip64
+        // record, written right before its locator
+        let locator = end
+            .checked_sub(20)
+            .filter(|at| read_u32(tail, *at) == Some(ZIP64_LOCATOR))
+            .ok_or_else(|| invalid("missing zip64 locator"))?;
+        let record = read_u64(tail, locator + 8).ok_or_else(|| invalid("truncated archive"))?;
+        let tail_start = length.saturating_sub(tail.len() as u64);
+        let record = record
+            .checked_sub(tail_start)
+            .map(|at| at as usize)
+            .filter(|at| read_u32(tail, *at) == Some(ZIP64_END_OF_CENTRAL_DIRECTORY))
+            .ok_or_else(|| invalid("missing zip64 end of central directory"))?;
+
+        Ok(CentralDirectory {
+            size: read_u64(tail, record + 40).ok_or_else(|| invalid("truncated archive"))?,
+            offset: read_u64(tail, record + 48).ok_or_else(|| invalid("truncated archive"))?,
+        })
+    }
+
+    /// Lists the entries of a central directory by offset, `data_end` is where
+    /// the entries data stops, the offset of the central directory
+    pub fn entries(
+        directory: &[u8],
+        data_end: u64,
+    ) -> Result<Vec<ArchiveEntry>, Report<ArtifactError>> {
+        let mut entries = Vec::new();
+        let mut at = 0;
+        while at < directory.len() {
+            if read_u32(directory, at) != Some(CENTRAL_DIRECTORY_HEADER) {
+                return Err(invalid("invalid central directory header"));
+            }
+            let header = || invalid("truncated central directory");
+            let method = read_u16(directory, at + 10).ok_or_else(header)?;
+            let compressed_size = read_u32(directory, at + 20).ok_or_else(header)?;
+            let size = read_u32(directory, at + 24).ok_or_else(header)?;
+            let name_length = read_u16(directory, at + 28).ok_or_else(header)? as usize;
+            let extra_length = read_u16(directory, at + 30).ok_or_else(header)? as usize;
+            let comment_length = read_u16(directory, at + 32).ok_or_else(header)? as usize;
+            let offset = read_u32(directory, at + 42).ok_or_else(header)?;
+
+            let name_start = at + 46;
+            let extra_start = name_start + name_length;
+            let path = directory
+                .get(name_start..extra_start)
+                .map(|name| String::from_utf8_lossy(name).to_string())
+                .ok_or_else(header)?;
+            let extra = directory
+                .get(extra_start..extra_start + extra_length)
+                .ok_or_else(header)?;
+            at = extra_start + extra_length + comment_length;
+
+            // Values over 4GB are moved to the zip64 extra field, in this order
+            let mut zip64 = ArtifactsHandler::zip64_values(extra);
+            let mut value = |value: u32| match value {
+                u32::MAX => zip64
+                    .next()
+                    .ok_or_else(|| invalid("missing zip64 extra field")),
+                value => Ok(value as u64),
+            };
+            entries.push(ArchiveEntry {
+                size: value(size)?,
+                compressed_size: value(compressed_size)?,
+                offset: value(offset)?,
+                end: data_end,
+                method,
+                path,
+            });
+        }
+
+        entries.sort_by_key(|entry| entry.offset);
+        for index in 1..entries.len() {
+            entries[index - 1].end = entries[index].offset;
+        }
+        Ok(entries)
+    }
+
+    /// Values of the zip64 extra field of a file
+    fn zip64_values(extra: &[u8]) -> impl Iterator<Item = u64> + '_ {
+        let mut at = 0;
+        let mut field = &extra[0..0];
+        while at + 4 <= extra.len() {
+            let id = read_u16(extra, at).unwrap_or_default();
+            let length = read_u16(extra, at + 2).unwrap_or_default() as usize;
+            if id == ZIP64_EXTRA_FIELD {
+                field = extra.get(at + 4..at + 4 + length).unwrap_or_default();
+                break;
+            }
+            at += 4 + length;
+        }
+        (0..field.len() / 8).filter_map(move |index| read_u64(field, index * 8))
+    }
+
+    /// SHA-256 of the content of an entry, `data` holds the entry from its
+    /// local header to `entry.end`
+    pub fn sha256(entry: &ArchiveEntry, data: &[u8]) -> Result<String, Report<ArtifactError>> {
+        if read_u32(data, 0) != Some(LOCAL_FILE_HEADER) {
+            return Err(invalid("invalid local file header"));
+        }
+        let header = || invalid("truncated local file header");
+        let name_length = read_u16(data, 26).ok_or_else(header)? as usize;
+        let extra_length = read_u16(data, 28).ok_or_else(header)? as usize;
+        let start = 30 + name_length + extra_length;
+        let content = data
+            .get(start..start + entry.compressed_size as usize)
+            .ok_or_else(|| invalid("truncated file data"))?;
+
+        let mut hasher = Sha256::new();
+        let copied = match entry.method {
+            STORED => io::copy(&mut &content[..], &mut hasher),
+            DEFLATED => io::copy(&mut DeflateDecoder::new(content), &mut hasher),
+            method => {
+                return Err(invalid(&format!(
+                    "unsupported compression method: {}",
+                    method
+                )))
+            }
+        };
+        match copied {
+            Ok(size) if size == entry.size => Ok(format!("{:x}", hasher.finalize())),
+            Ok(_) => Err(invalid("file size mismatch")),
+            Err(error) => Err(invalid(&error.to_string())),
+        }
+    }
+
+    /// Describes every file of an archive, directories are skipped
+    pub fn manifest(bucket: String, key: String, mut files: Vec<ArtifactFile>) -> ArtifactManifest {
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        ArtifactManifest {
+            bucket,
+            key,
+            file_count: files.len() as u64,
+            total_size: files.iter().map(|file| file.size).sum(),
+            files,
+        }
+    }
+
+    /// Hashes the files of an archive, entries next to each other are read
+    /// together in ranges of up to `ARCHIVE_CHUNK_SIZE` bytes
+    async fn files(
+        &self,
+        bucket: &str,
+        key: &str,
+        entries: &[ArchiveEntry],
+    ) -> Result<Vec<ArtifactFile>, Report<ArtifactError>> {
+        let mut files = Vec::new();
+        let mut first = 0;
+        while first < entries.len() {
+            let start = entries[first].offset;
+            let mut last = first;
+            while last + 1 < entries.len() && entries[last + 1].end - start <= ARCHIVE_CHUNK_SIZE {
+                last += 1;
+            }
+            let end = entries[last].end;
+            let (chunk, _) = self
+                .read_range(
+                    bucket,
+                    key,
+                    format!("bytes={}-{}", start, end.saturating_sub(1)),
+                )
+                .await?;
+
+            for entry in entries[first..=last].iter() {
+                if entry.path.ends_with('/') {
+                    continue;
+                }
+                let data = chunk
+                    .get((entry.offset - start) as usize..(entry.end - start) as usize)
+                    .ok_or_else(|| invalid("file data out of range"))?;
+                files.push(ArtifactFile {
+                    content_type: mime_guess::from_path(&entry.path)
+                        .first_or_octet_stream()
+                        .to_string(),
+                    path: entry.path.to_owned(),
+                    size: entry.size,
+                    sha256: ArtifactsHandler::sha256(entry, data)?,
+                });
+            }
+            first = last + 1;
+        }
+
+        Ok(files)
+    }
+
+    /// Reads a byte range of an object, with the object length
+    async fn read_range(
+        &self,
+        bucket: &str,
+        key: &str,
+        range: String,
+    ) -> Result<(Vec<u8>, Option<u64>), Report<ArtifactError>> {
+        info!("ArtifactsHandler::read_range - {}", range);
+        let tx = self
+            .client
+            .get_object()
+            .bucket(bucket)
+            .key(key)
+            .range(range);
+
+        match tx.send().await {
+            Ok(result) => {
+                // Content-Range: bytes <start>-<end>/<length>
+                let length = result
+                    .content_range()
+                    .and_then(|range| range.rsplit_once('/'))
+                    .and_then(|(_, length)| length.parse().ok());
+                match result.body.collect().await {
+                    Ok(data) => Ok((data.into_bytes().to_vec(), length)),
+                    Err(error) => {
+                        error!(
+                            "ArtifactsHandler::read_range - failed to read artifact: {:?}",
+                            error
+                        );
+                        Err(Report::new(ArtifactError::new(&error.to_string())))
+                    }
+                }
+            }
+            Err(error) => {
+                error!(
+                    "ArtifactsHandler::read_range - failed to get artifact: {:?}",
+                    error
+                );
+                Err(Report::new(ArtifactError::new(&error.to_string())))
+            }
+        }
+    }
+
+    /// Lists and hashes the files of an artifact zip from its central directory,
+    /// read with range requests, and stores the manifest next to the artifact
+    pub async fn describe(
+        &self,
+        bucket: String,
+        key: String,
+    ) -> Result<ArtifactSummary, Report<ArtifactError>> {
+        info!("ArtifactsHandler::describe - s3://{}/{}", bucket, key);
+
+        let (tail, length) = self
+            .read_range(&bucket, &key, format!("bytes=-{}", ARCHIVE_TAIL_SIZE))
+            .await?;
+        let length = length.unwrap_or(tail.len() as u64);
+        let location = ArtifactsHandler::central_directory(&tail, length)?;
+        info!(
+            "ArtifactsHandler::describe - central directory: {:?}",
+            location
+        );
+
+        let tail_start = length - tail.len() as u64;
+        let directory = if location.offset >= tail_start {
+            let start = (location.offset - tail_start) as usize;
+            tail.get(start..start + location.size as usize)
+                .ok_or_else(|| invalid("central directory out of range"))?
+                .to_vec()
+        } else {
+            let end = location.offset + location.size;
+            self.read_range(
+                &bucket,
+                &key,
+                format!("bytes={}-{}", location.offset, end.saturating_sub(1)),
+            )
+            .await?
+            .0
+        };
+        let entries = ArtifactsHandler::entries(&directory, location.offset)?;
+        let files = self.files(&bucket, &key, &entries).await?;
+        let manifest = ArtifactsHandler::manifest(bucket, key, files);
+
+        let body = match serde_json::to_vec(&manifest) {
+            Ok(body) => body,
+            Err(error) => return Err(Report::new(ArtifactError::new(&error.to_string()))),
+        };
+        let checksum = format!("{:x}", Sha256::digest(&body));
+        let manifest_key = manifest_key(&manifest.key);
+        info!(
+            "ArtifactsHandler::describe - put manifest: {}, checksum: {}",
+            manifest_key, checksum
+        );
+        let tx = self
+            .client
+            .put_object()
+            .bucket(&manifest.bucket)
+            .key(&manifest_key)
+            .content_type("application/json")
+            .body(ByteStream::from(body));
+        if let Err(error) = tx.send().await {
+            error!(
+                "ArtifactsHandler::describe - failed to put manifest: {:?}",
+                error
+            );
+            return Err(Report::new(ArtifactError::new(&error.to_string())));
+        }
+
+        Ok(ArtifactSummary {
+            bucket: manifest.bucket,
+            key: manifest.key,
+            file_count: manifest.file_count,
+            total_size: manifest.total_size,
+            manifest_key: Some(manifest_key),
+            checksum: Some(checksum),
+        })
+    }
+
+    /// Deletes an artifact zip, deleting a missing artifact succeeds
+    pub async fn delete(&self, bucket: String, key: String) -> Result<(), Report<ArtifactError>> {
+        info!("ArtifactsHandler::delete - s3://{}/{}", bucket, key);
+
+        let tx = self.client.delete_object().bucket(&bucket).key(&key);
+
+        info!("ArtifactsHandler::delete - send tx");
+        match tx.send().await {
+            Ok(_) => Ok(()),
+            Err(error) => {
+                error!(
+                    "ArtifactsHandler::delete - failed to delete artifact: {:?}",
+                    error
+                );
+                Err(Report::new(ArtifactError::new(&error.to_string())))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod artifacts_tests {
+    use super::*;
+    use crate::models::common::AsDynamoDBAttributeValue;
+    use std::io::{Cursor, Write};
+    use zip::{write::FileOptions, ZipWriter};
+
+    fn archive() -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.add_directory("dist/", FileOptions::default()).unwrap();
+        zip.start_file("dist/index.html", FileOptions::default())
+            .unwrap();
+        zip.write_all(b"<html></html>").unwrap();
+        zip.start_file("dist/main.js", FileOptions::default())
+            .unwrap();
+        zip.write_all(b"console.log(1)").unwrap();
+        zip.start_file(
+            "dist/favicon.txt",
+            FileOptions::default().compression_method(zip::CompressionMethod::Stored),
+        )
+        .unwrap();
+        zip.write_all(b"favicon\n").unwrap();
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn artifact_name_uses_deployment_uuid_and_slug() {
+        assert_eq!(
+            artifact_name("1234", "My App: Beta"),
+            "1234-my-app-beta.zip"
+        );
+        assert_eq!(artifact_name("1234", "::"), "1234.zip");
+    }
+
+    #[test]
+    fn parses_s3_locations() {
+        assert_eq!(
+            parse_s3_location("arn:aws:s3:::bucket/path/to/file.zip"),
+            Some(("bucket".to_string(), "path/to/file.zip".to_string()))
+        );
+        assert_eq!(
+            parse_s3_location("s3://bucket/file.zip"),
+            Some(("bucket".to_string(), "file.zip".to_string()))
+        );
+        assert_eq!(parse_s3_location("arn:aws:s3:::bucket"), None);
+        assert_eq!(parse_s3_location("https://bucket/file.zip"), None);
+    }
+
+    fn directory(archive: &[u8]) -> Vec<u8> {
+        let location = ArtifactsHandler::central_directory(archive, archive.len() as u64).unwrap();
+        let start = location.offset as usize;
+        archive[start..start + location.size as usize].to_vec()
+    }
+
+    #[test]
+    fn locates_central_directory_from_archive_tail() {
+        let archive = archive();
+        let whole = ArtifactsHandler::central_directory(&archive, archive.len() as u64).unwrap();
+        assert!(whole.size > 0);
+
+        // Only the last bytes are read from S3
+        let tail = &archive[archive.len() - 40..];
+        let located = ArtifactsHandler::central_directory(tail, archive.len() as u64).unwrap();
+        assert_eq!(located, whole);
+
+        match ArtifactsHandler::central_directory(b"not a zip", 9) {
+            Err(error) => assert!(error.to_string().starts_with("Artifact error")),
+            _ => assert_eq!("", "Should have panicked but it did not"),
+        }
+    }
+
+    fn files(archive: &[u8]) -> Vec<ArtifactFile> {
+        let location = ArtifactsHandler::central_directory(archive, archive.len() as u64).unwrap();
+        ArtifactsHandler::entries(&directory(archive), location.offset)
+            .unwrap()
+            .iter()
+            .filter(|entry| !entry.path.ends_with('/'))
+            .map(|entry| ArtifactFile {
+                path: entry.path.to_owned(),
+                size: entry.size,
+                sha256: ArtifactsHandler::sha256(
+                    entry,
+                    &archive[entry.offset as usize..entry.end as usize],
+                )
+                .unwrap(),
+                content_type: String::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn entries_span_up_to_the_next_entry() {
+        let archive = archive();
+        let location = ArtifactsHandler::central_directory(&archive, archive.len() as u64).unwrap();
+        let entries = ArtifactsHandler::entries(&directory(&archive), location.offset).unwrap();
+
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0].path, "dist/");
+        assert_eq!(entries[0].offset, 0);
+        assert_eq!(entries[1].method, DEFLATED);
+        assert_eq!(entries[3].method, STORED);
+        for pair in entries.windows(2) {
+            assert_eq!(pair[0].end, pair[1].offset);
+        }
+        assert_eq!(entries[3].end, location.offset);
+    }
+
+    #[test]
+    fn manifest_describes_archive_files() {
+        let manifest = ArtifactsHandler::manifest(
+            "bucket".to_string(),
+            "key.zip".to_string(),
+            files(&archive()),
+        );
+
+        assert_eq!(manifest.file_count, 3);
+        assert_eq!(manifest.total_size, 35);
+        assert_eq!(manifest.files[0].path, "dist/favicon.txt");
+        assert_eq!(manifest.files[1].path, "dist/index.html");
+        // printf 'favicon\n' | sha256sum
+        assert_eq!(
+            manifest.files[0].sha256,
+            "c44a30e62a0cff0763be4acf657688ce2d2f22abecd738cafc17dd2889d326fc"
+        );
+        // printf '<html></html>' | sha256sum
+        assert_eq!(
+            manifest.files[1].sha256,
+            "b633a587c652d02386c4f16f8c6f6aab7352d97f16367c3c40576214372dd628"
+        );
+    }
+
+    #[test]
+    fn sha256_fails_on_invalid_entry() {
+        let archive = archive();
+        let location = ArtifactsHandler::central_directory(&archive, archive.len() as u64).unwrap();
+        let entries = ArtifactsHandler::entries(&directory(&archive), location.offset).unwrap();
+        let entry = &entries[1];
+
+        let truncated = &archive[entry.offset as usize..entry.end as usize - 2];
+        match ArtifactsHandler::sha256(entry, truncated) {
+            Err(error) => assert!(error.to_string().starts_with("Artifact error")),
+            _ => assert_eq!("", "Should have panicked but it did not"),
+        }
+        match ArtifactsHandler::entries(b"not a zip", 9) {
+            Err(error) => assert!(error.to_string().starts_with("Artifact error")),
+            _ => assert_eq!("", "Should have panicked but it did not"),
+        }
+    }
+
+    #[test]
+    fn parse_round_trips_summary() {
+        let summary = ArtifactSummary {
+            bucket: "bucket".to_string(),
+            key: "key.zip".to_string(),
+            file_count: 2,
+            total_size: 27,
+            manifest_key: Some(manifest_key("key.zip")),
+            checksum: Some("abc".to_string()),
+        };
+        assert_eq!(summary.location(), "s3://bucket/key.zip");
+        assert_eq!(
+            summary.manifest_key.as_deref(),
+            Some("key.zip.manifest.json")
+        );
+        let parsed = ArtifactSummaryParser::parse(summary.as_hashmap()).unwrap();
+        assert_eq!(parsed, summary);
+    }
+
+    #[test]
+    fn parses_legacy_summary_with_files() {
+        let mut item = ArtifactSummary {
+            bucket: "bucket".to_string(),
+            key: "key.zip".to_string(),
+            file_count: 1,
+            total_size: 10,
+            manifest_key: None,
+            checksum: None,
+        }
+        .as_hashmap();
+        item.insert("files".to_string(), AttributeValue::L(vec![]));
+        let parsed = ArtifactSummaryParser::parse(item).unwrap();
+        assert_eq!(parsed.file_count, 1);
+        assert_eq!(parsed.manifest_key, None);
+    }
+}
@@ -3,7 +3,6 @@ use aws_sdk_codebuild::{
     Client,
 };
 use aws_sdk_dynamodb::model::AttributeValue;
-use chrono::Utc;
 use error_stack::Report;
 use log::{self, debug, error, info};
 use serde_json::{json, Value};
@@ -11,35 +10,30 @@ use std::collections::HashMap;
 use std::str::FromStr;
 
 use crate::{
-    handlers::projects::ProjectParser,
+    handlers::{artifacts::artifact_name, projects::ProjectParser},
     models::{
-        codebuild::{BuildInfo, BuildObject, BuildPhase, BuildPhaseStatus, ProjectDeploymentPhase},
+        codebuild::{
+            BuildInfo, BuildObject, BuildPhase, BuildPhaseStatus, ComputeSize,
+            ProjectDeploymentPhase, DEPLOYMENT_UUID_VARIABLE,
+        },
         common::MissingModelPropertyError,
+        environment::Environment,
         handlers::HandlerError,
         project::Project,
     },
+    utils::load_env_var,
 };
 
 fn parse_build_info(build: &Build) -> Option<BuildInfo> {
     let uuid = build.id.to_owned().unwrap().split(":").last()?.to_string();
     let build_number = build.build_number;
-    let start_time = match build.start_time() {
-        Some(value) => Some(value.to_millis().unwrap()),
-        None => None,
-    };
-    let end_time = match build.end_time() {
-        Some(value) => Some(value.to_millis().unwrap()),
-        None => None,
-    };
-    let deployment_phase = match build.project_name() {
-        Some(value) => Some(value.to_string()),
-        None => None,
-    };
+    let start_time = build.start_time().map(|value| value.to_millis().unwrap());
+    let end_time = build.end_time().map(|value| value.to_millis().unwrap());
+    let deployment_phase = build.project_name().map(|value| value.to_string());
     // TODO: change current_phase by build_phase
-    let current_phase = match build.current_phase() {
-        Some(value) => Some(BuildPhase::from_str(value).unwrap().to_string()),
-        None => None,
-    };
+    let current_phase = build
+        .current_phase()
+        .map(|value| BuildPhase::from_str(value).unwrap().to_string());
     // TODO: change build_status by build_phase_status
     let build_status = match build.build_status() {
         Some(value) => Some(
@@ -49,6 +43,18 @@ fn parse_build_info(build: &Build) -> Option<BuildInfo> {
         ),
         None => Some(BuildPhaseStatus::Unknown.to_string()),
     };
+    let logs_url = build
+        .logs()
+        .and_then(|logs| logs.deep_link())
+        .map(|value| value.to_string());
+    let compute_type = build
+        .environment()
+        .and_then(|environment| environment.compute_type())
+        .map(|value| value.as_str().to_string());
+    let image = build
+        .environment()
+        .and_then(|environment| environment.image())
+        .map(|value| value.to_string());
 
     Some(BuildInfo {
         uuid,
@@ -58,6 +64,9 @@ fn parse_build_info(build: &Build) -> Option<BuildInfo> {
         deployment_phase,
         current_phase,
         build_status,
+        logs_url,
+        compute_type,
+        image,
     })
 }
 
@@ -67,7 +76,7 @@ pub fn get_build_info(build: &BuildObject) -> Option<BuildInfo> {
         BuildObject::Builds(builds) => match builds {
             None => None,
             Some(builds) => {
-                if builds.len() >= 1 {
+                if !builds.is_empty() {
                     parse_build_info(&builds[0])
                 } else {
                     None
@@ -137,6 +146,18 @@ impl BuildInfoParser {
             None => return Err(Report::new(MissingModelPropertyError::new("build_status"))),
         };
 
+        // Builds recorded before build state events were handled have no
+        // logs and environment details
+        let logs_url = item
+            .get("logs_url")
+            .map(|value| value.as_s().unwrap().to_string());
+        let compute_type = item
+            .get("compute_type")
+            .map(|value| value.as_s().unwrap().to_string());
+        let image = item
+            .get("image")
+            .map(|value| value.as_s().unwrap().to_string());
+
         Ok(BuildInfo {
             uuid,
             build_number,
@@ -145,6 +166,9 @@ impl BuildInfoParser {
             deployment_phase,
             current_phase,
             build_status,
+            logs_url,
+            compute_type,
+            image,
         })
     }
 
@@ -201,10 +225,72 @@ impl BuildInfoParser {
     }
 }
 
+/// CodeBuild minimum build timeout
+pub const MIN_BUILD_TIMEOUT_MINUTES: i32 = 5;
+/// CodeBuild maximum build timeout
+pub const MAX_BUILD_TIMEOUT_MINUTES: i32 = 480;
+/// Build timeout limit when none is configured, same as the stack default
+pub const DEFAULT_MAX_BUILD_TIMEOUT_MINUTES: i32 = 60;
+
+/// Administrator limits applied to the per-project build settings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BuildLimits {
+    pub max_timeout_minutes: i32,
+    pub max_compute_size: ComputeSize,
+}
+impl BuildLimits {
+    pub fn new(max_timeout_minutes: i32, max_compute_size: ComputeSize) -> Self {
+        Self {
+            max_timeout_minutes: max_timeout_minutes
+                .clamp(MIN_BUILD_TIMEOUT_MINUTES, MAX_BUILD_TIMEOUT_MINUTES),
+            max_compute_size: max_compute_size.capped(ComputeSize::Large),
+        }
+    }
+
+    /// Reads `BUILD_TIMEOUT_MAX_MINUTES` and `BUILD_COMPUTE_SIZE_MAX`, falling back to
+    /// the stack defaults when they are missing or invalid
+    pub fn from_env() -> Self {
+        let max_timeout_minutes = match load_env_var("BUILD_TIMEOUT_MAX_MINUTES", None) {
+            Ok(value) => value.parse().unwrap_or_else(|_| {
+                error!(
+                    "BuildLimits::from_env - invalid max build timeout: {}",
+                    value
+                );
+                DEFAULT_MAX_BUILD_TIMEOUT_MINUTES
+            }),
+            Err(_) => DEFAULT_MAX_BUILD_TIMEOUT_MINUTES,
+        };
+        let max_compute_size = match load_env_var("BUILD_COMPUTE_SIZE_MAX", None) {
+            Ok(value) => ComputeSize::from_str(&value).unwrap_or_else(|_| {
+                error!(
+                    "BuildLimits::from_env - invalid max compute size: {}",
+                    value
+                );
+                ComputeSize::Medium
+            }),
+            Err(_) => ComputeSize::Medium,
+        };
+
+        Self::new(max_timeout_minutes, max_compute_size)
+    }
+
+    pub fn timeout_minutes(&self, requested: i32) -> i32 {
+        requested.clamp(MIN_BUILD_TIMEOUT_MINUTES, self.max_timeout_minutes)
+    }
+
+    /// Requested size capped to the limits, none for unknown sizes
+    pub fn compute_size(&self, requested: &str) -> Option<ComputeSize> {
+        ComputeSize::from_str(requested)
+            .ok()
+            .map(|size| size.capped(self.max_compute_size))
+    }
+}
+
 pub struct CodeBuildHandler {
     client: Client,
     codebuild_project_name_building: String,
     codebuild_project_name_deployment: String,
+    limits: BuildLimits,
 }
 
 impl CodeBuildHandler {
@@ -212,21 +298,43 @@ impl CodeBuildHandler {
         client: Client,
         codebuild_project_name_building: String,
         codebuild_project_name_deployment: String,
+        limits: BuildLimits,
     ) -> Self {
         Self {
             client,
             codebuild_project_name_building,
             codebuild_project_name_deployment,
+            limits,
         }
     }
 
-    pub async fn create(&self, project: &Project) -> Result<BuildInfo, Report<HandlerError>> {
+    pub async fn create(
+        &self,
+        deployment_uuid: &str,
+        project: &Project,
+        environment: &Environment,
+    ) -> Result<BuildInfo, Report<HandlerError>> {
+        info!("CodeBuildHandler::create - deployment: {}", deployment_uuid);
         info!("CodeBuildHandler::create - project: {:?}", project);
-        let timestamp = Utc::now().to_rfc3339().to_string();
+        info!("CodeBuildHandler::create - environment: {:?}", environment);
+
+        let compute_size = match self.limits.compute_size(&project.compute_size) {
+            Some(size) => size,
+            None => {
+                error!(
+                    "CodeBuildHandler::create - invalid compute size: {}",
+                    project.compute_size
+                );
+                return Err(Report::new(HandlerError::new(&format!(
+                    "Invalid compute size: {}",
+                    project.compute_size
+                ))));
+            }
+        };
 
         debug!("CodeBuildHandler::create - create pre-build commands");
         let mut pre_build_commands = Vec::from_iter(project.commands.pre_build.iter());
-        let command_cd_into_project = "cd $PROJECT_NAME".to_string();
+        let command_cd_into_project = "cd \\\"$PROJECT_NAME\\\"".to_string();
         let command_pre_build_title = "####### Install Project Dependencies #######".to_string();
         pre_build_commands.insert(0, &command_cd_into_project);
         pre_build_commands.insert(0, &command_pre_build_title);
@@ -234,7 +342,7 @@ impl CodeBuildHandler {
         debug!("CodeBuildHandler::create - parse pre-build commands as string");
         let pre_build_commands_str = pre_build_commands
             .iter()
-            .map(|s| format!("\"{}\"", s.to_string()))
+            .map(|s| format!("\"{}\"", s))
             .collect::<Vec<String>>()
             .join(",");
 
@@ -248,11 +356,11 @@ impl CodeBuildHandler {
         debug!("CodeBuildHandler::create - parse build commands as string");
         let build_commands_str = build_commands
             .iter()
-            .map(|s| format!("\"{}\"", s.to_string()))
+            .map(|s| format!("\"{}\"", s))
             .collect::<Vec<String>>()
             .join(",");
 
-        let artifacts_output_name = format!("{}-dist-{}.zip", project.name, timestamp);
+        let artifacts_output_name = artifact_name(deployment_uuid, &project.name);
         debug!("CodeBuildHandler::create - parse buildspec");
         let build_spec = format!(
             r###"
@@ -268,7 +376,7 @@ impl CodeBuildHandler {
                   "commands": [
                     "echo Download project",
                     "node -v",
-                    "git clone $REPO_URL $PROJECT_NAME"
+                    "git clone --branch \"$REPO_BRANCH\" -- \"$REPO_URL\" \"$PROJECT_NAME\""
                   ]
                 }},
                 "pre_build": {{
@@ -291,7 +399,7 @@ impl CodeBuildHandler {
         );
 
         debug!("CodeBuildHandler::create - tx preparation");
-        let tx = self
+        let mut tx = self
             .client
             .start_build()
             .project_name(self.codebuild_project_name_building.to_string())
@@ -309,8 +417,55 @@ impl CodeBuildHandler {
                     .set_type(Some(EnvironmentVariableType::Plaintext))
                     .build(),
             )
+            .environment_variables_override(
+                EnvironmentVariable::builder()
+                    .set_name(Some("REPO_BRANCH".to_string()))
+                    .set_value(Some(environment.branch.to_string()))
+                    .set_type(Some(EnvironmentVariableType::Plaintext))
+                    .build(),
+            )
+            .environment_variables_override(
+                EnvironmentVariable::builder()
+                    .set_name(Some(DEPLOYMENT_UUID_VARIABLE.to_string()))
+                    .set_value(Some(deployment_uuid.to_string()))
+                    .set_type(Some(EnvironmentVariableType::Plaintext))
+                    .build(),
+            )
+            .environment_variables_override(
+                EnvironmentVariable::builder()
+                    .set_name(Some("ENVIRONMENT_NAME".to_string()))
+                    .set_value(Some(environment.name.to_string()))
+                    .set_type(Some(EnvironmentVariableType::Plaintext))
+                    .build(),
+            )
+            .timeout_in_minutes_override(self.limits.timeout_minutes(project.build_timeout_minutes))
+            .compute_type_override(compute_size.compute_type())
             .buildspec_override(build_spec);
 
+        debug!("CodeBuildHandler::create - environment variables");
+        for (name, value) in environment.variables.iter() {
+            tx = tx.environment_variables_override(
+                EnvironmentVariable::builder()
+                    .set_name(Some(name.to_string()))
+                    .set_value(Some(value.to_string()))
+                    .set_type(Some(EnvironmentVariableType::Plaintext))
+                    .build(),
+            );
+        }
+        // CodeBuild resolves secrets when the build starts, the build details
+        // only show their parameter name or secret id
+        for (name, secret) in environment.secrets.iter() {
+            tx = tx.environment_variables_override(
+                EnvironmentVariable::builder()
+                    .set_name(Some(name.to_string()))
+                    .set_value(Some(secret.value.to_string()))
+                    .set_type(Some(EnvironmentVariableType::from(
+                        secret.secret_type.as_str(),
+                    )))
+                    .build(),
+            );
+        }
+
         match tx.send().await {
             Ok(result) => {
                 debug!("CodeBuildHandler::create - tx result: {:?}", result);
@@ -354,10 +509,7 @@ impl CodeBuildHandler {
 
         debug!("CodeBuildHandler::get - build ids parameter");
         let mut ids: Vec<String> = Vec::new();
-        ids.push(String::from(format!(
-            "{}:{}",
-            self.codebuild_project_name_building, id
-        )));
+        ids.push(format!("{}:{}", self.codebuild_project_name_building, id));
 
         debug!("CodeBuildHandler::get - tx preparation");
         let tx = self.client.batch_get_builds().set_ids(Some(ids));
@@ -374,3 +526,50 @@ impl CodeBuildHandler {
         }
     }
 }
+
+#[cfg(test)]
+mod build_limits_tests {
+    use super::*;
+    use aws_sdk_codebuild::model::ComputeType;
+
+    #[test]
+    fn timeout_is_clamped_to_limits() {
+        let limits = BuildLimits::new(120, ComputeSize::Large);
+        assert_eq!(limits.timeout_minutes(1), MIN_BUILD_TIMEOUT_MINUTES);
+        assert_eq!(limits.timeout_minutes(30), 30);
+        assert_eq!(limits.timeout_minutes(600), 120);
+    }
+
+    #[test]
+    fn compute_size_is_capped_to_limits() {
+        let limits = BuildLimits::new(60, ComputeSize::Medium);
+        assert_eq!(limits.compute_size("small"), Some(ComputeSize::Small));
+        assert_eq!(limits.compute_size("MEDIUM"), Some(ComputeSize::Medium));
+        assert_eq!(limits.compute_size("large"), Some(ComputeSize::Medium));
+        assert_eq!(limits.compute_size("huge"), None);
+        assert_eq!(
+            limits.compute_size("large").unwrap().compute_type(),
+            ComputeType::BuildGeneral1Medium
+        );
+    }
+
+    #[test]
+    fn admin_limits_stay_within_codebuild_limits() {
+        let limits = BuildLimits::new(10000, ComputeSize::Large);
+        assert_eq!(limits.max_timeout_minutes, MAX_BUILD_TIMEOUT_MINUTES);
+        assert_eq!(limits.max_compute_size, ComputeSize::Large);
+    }
+
+    #[test]
+    fn env_limits_fall_back_to_stack_defaults() {
+        std::env::remove_var("BUILD_TIMEOUT_MAX_MINUTES");
+        std::env::set_var("BUILD_COMPUTE_SIZE_MAX", "huge");
+        let limits = BuildLimits::from_env();
+        std::env::remove_var("BUILD_COMPUTE_SIZE_MAX");
+        assert_eq!(
+            limits.max_timeout_minutes,
+            DEFAULT_MAX_BUILD_TIMEOUT_MINUTES
+        );
+        assert_eq!(limits.max_compute_size, ComputeSize::Medium);
+    }
+}
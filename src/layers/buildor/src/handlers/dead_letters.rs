@@ -0,0 +1,106 @@
+use aws_sdk_dynamodb::{model::AttributeValue, Client};
+use error_stack::Report;
+use log::{self, error, info};
+use std::collections::HashMap;
+
+use crate::models::{
+    common::{AsDynamoDBAttributeValue, MissingModelPropertyError},
+    dead_letter::DeadLetter,
+    handlers::HandlerError,
+};
+
+pub struct DeadLetterParser;
+impl DeadLetterParser {
+    pub fn parse(
+        item: HashMap<String, AttributeValue>,
+    ) -> Result<DeadLetter, Report<MissingModelPropertyError>> {
+        let uuid = match item.get("uuid") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("uuid"))),
+        };
+
+        let source = match item.get("source") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("source"))),
+        };
+
+        let reason = match item.get("reason") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("reason"))),
+        };
+
+        let payload = match item.get("payload") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("payload"))),
+        };
+
+        let created_at = match item.get("created_at") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("created_at"))),
+        };
+
+        Ok(DeadLetter {
+            uuid,
+            source,
+            reason,
+            payload,
+            created_at,
+        })
+    }
+}
+
+pub struct DeadLettersHandler {
+    table: Client,
+    table_name: String,
+}
+impl DeadLettersHandler {
+    pub fn new(client: Client, table_name: String) -> Self {
+        Self {
+            table: client,
+            table_name,
+        }
+    }
+
+    pub async fn save(&self, dead_letter: &DeadLetter) -> Result<(), Report<HandlerError>> {
+        info!(
+            "DeadLettersHandler::save - source: {}, reason: {}",
+            dead_letter.source, dead_letter.reason
+        );
+
+        let tx = self
+            .table
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(dead_letter.as_hashmap()));
+
+        info!("DeadLettersHandler::save - send tx");
+        match tx.send().await {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!(
+                    "DeadLettersHandler::save - failed to save dead letter: {:?}",
+                    err
+                );
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod dead_letters_tests {
+    use super::*;
+
+    #[test]
+    fn parse_round_trips_dead_letter() {
+        let dead_letter = DeadLetter::new(
+            "codebuild-events-processing",
+            "missing field `build-id`".to_string(),
+            "{}".to_string(),
+        );
+        assert_eq!(
+            DeadLetterParser::parse(dead_letter.as_hashmap()).unwrap(),
+            dead_letter
+        );
+    }
+}
@@ -0,0 +1,319 @@
+use error_stack::Report;
+use log::{self, error, info};
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::{
+    handlers::{
+        codebuild::CodeBuildHandler,
+        project_deployments::{DeploymentClaim, ProjectDeploymentsHandler},
+        projects::ProjectsHandler,
+    },
+    models::{
+        codebuild::BuildInfo,
+        handlers::{HandlerCreate, HandlerError, HandlerGet, HandlerUpdate},
+        project::Project,
+        project_deployment::{
+            ProjectDeployment, ProjectDeploymentCreatePayload, ProjectDeploymentStatus,
+            ProjectDeploymentUpdatePayload,
+        },
+    },
+    utils::load_env_var,
+};
+
+pub const DEFAULT_PROJECT_CONCURRENCY_LIMIT: usize = 1;
+/// Attempts to record the build of a started deployment
+const RECORD_BUILD_ATTEMPTS: u32 = 3;
+/// Kept below the CodeBuild concurrent running builds quota
+pub const DEFAULT_ACCOUNT_CONCURRENCY_LIMIT: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConcurrencyLimits {
+    pub project: usize,
+    pub account: usize,
+}
+impl ConcurrencyLimits {
+    pub fn new(project: usize, account: usize) -> Self {
+        Self { project, account }
+    }
+
+    /// Reads `PROJECT_CONCURRENCY_LIMIT` and `ACCOUNT_CONCURRENCY_LIMIT`, falling back to defaults
+    pub fn from_env() -> Self {
+        let project = load_env_var("PROJECT_CONCURRENCY_LIMIT", None)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_PROJECT_CONCURRENCY_LIMIT);
+        let account = load_env_var("ACCOUNT_CONCURRENCY_LIMIT", None)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_ACCOUNT_CONCURRENCY_LIMIT);
+
+        Self::new(project, account)
+    }
+}
+
+/// Queued deployments of the same project environment created before `latest`
+pub fn superseded_by<'a>(
+    queued: &'a [ProjectDeployment],
+    latest: &ProjectDeployment,
+) -> Vec<&'a ProjectDeployment> {
+    queued
+        .iter()
+        .filter(|deployment| {
+            deployment.uuid != latest.uuid
+                && deployment.project.uuid == latest.project.uuid
+                && deployment.environment == latest.environment
+                && deployment.status() == ProjectDeploymentStatus::Queued
+                && deployment.created_at <= latest.created_at
+        })
+        .collect()
+}
+
+pub struct DeploymentQueue {
+    deployments: ProjectDeploymentsHandler,
+    projects: ProjectsHandler,
+    codebuild: CodeBuildHandler,
+    limits: ConcurrencyLimits,
+}
+impl DeploymentQueue {
+    pub fn new(
+        deployments: ProjectDeploymentsHandler,
+        projects: ProjectsHandler,
+        codebuild: CodeBuildHandler,
+        limits: ConcurrencyLimits,
+    ) -> Self {
+        Self {
+            deployments,
+            projects,
+            codebuild,
+            limits,
+        }
+    }
+
+    /// Queues a new deployment, supersedes older queued deployments of the same
+    /// environment and starts as many queued builds as the limits allow.
+    pub async fn enqueue(
+        &self,
+        project: Project,
+        environment: String,
+    ) -> Result<ProjectDeployment, Report<HandlerError>> {
+        info!(
+            "DeploymentQueue::enqueue - project: {}, environment: {}",
+            project.uuid, environment
+        );
+        let project_uuid = project.uuid.clone();
+        let deployment = self
+            .deployments
+            .create(ProjectDeploymentCreatePayload {
+                project,
+                environment,
+                build: None,
+            })
+            .await?;
+
+        let queued = self
+            .deployments
+            .list_by_project(project_uuid.clone(), Some(ProjectDeploymentStatus::Queued))
+            .await?;
+        for older in superseded_by(&queued, &deployment) {
+            info!("DeploymentQueue::enqueue - supersede: {}", older.uuid);
+            self.deployments
+                .transition(
+                    older.uuid.clone(),
+                    &[ProjectDeploymentStatus::Queued],
+                    ProjectDeploymentStatus::Superseded,
+                    None,
+                )
+                .await?;
+        }
+
+        self.drain(project_uuid).await?;
+
+        match self.deployments.get(deployment.uuid.clone()).await? {
+            Some(current) => Ok(current),
+            None => Ok(deployment),
+        }
+    }
+
+    /// Starts the oldest queued deployments of a project while there are free slots.
+    /// Returns the uuids of the started deployments.
+    pub async fn drain(&self, project_uuid: String) -> Result<Vec<String>, Report<HandlerError>> {
+        info!("DeploymentQueue::drain - project: {}", project_uuid);
+        let mut started = Vec::new();
+
+        let queued = self
+            .deployments
+            .list_by_project(project_uuid, Some(ProjectDeploymentStatus::Queued))
+            .await?;
+        for deployment in queued.into_iter() {
+            match self.deployments.claim(&deployment, &self.limits).await? {
+                DeploymentClaim::Claimed => {
+                    if self.start(deployment.clone()).await? {
+                        started.push(deployment.uuid);
+                    }
+                }
+                DeploymentClaim::Taken => {
+                    info!(
+                        "DeploymentQueue::drain - already claimed: {}",
+                        deployment.uuid
+                    )
+                }
+                DeploymentClaim::Full => {
+                    info!("DeploymentQueue::drain - no free slot");
+                    break;
+                }
+            }
+        }
+
+        Ok(started)
+    }
+
+    /// Drains the queues of every project with queued deployments, oldest first
+    pub async fn drain_all(&self) -> Result<Vec<String>, Report<HandlerError>> {
+        info!("DeploymentQueue::drain_all");
+        let mut started = Vec::new();
+        let mut projects: Vec<String> = Vec::new();
+
+        for deployment in self
+            .deployments
+            .list_by_status(ProjectDeploymentStatus::Queued)
+            .await?
+        {
+            if !projects.contains(&deployment.project.uuid) {
+                projects.push(deployment.project.uuid);
+            }
+        }
+        for project_uuid in projects {
+            started.append(&mut self.drain(project_uuid).await?);
+        }
+
+        Ok(started)
+    }
+
+    /// Starts the build of a claimed deployment. Returns `false` when the build
+    /// could not be started and the deployment failed.
+    async fn start(&self, deployment: ProjectDeployment) -> Result<bool, Report<HandlerError>> {
+        // The project may have been deleted while the deployment was queued
+        let exists = self
+            .projects
+            .get(deployment.project.uuid.clone())
+            .await?
+            .is_some();
+        let build = match deployment.project.environment(&deployment.environment) {
+            Some(_) if !exists => Err(Report::new(HandlerError::new("Project not found"))),
+            Some(environment) => {
+                self.codebuild
+                    .create(&deployment.uuid, &deployment.project, environment)
+                    .await
+            }
+            None => Err(Report::new(HandlerError::new(&format!(
+                "Project environment \"{}\" not found",
+                deployment.environment
+            )))),
+        };
+
+        match build {
+            Ok(build) => {
+                info!(
+                    "DeploymentQueue::start - deployment: {}, build: {}",
+                    deployment.uuid, build.uuid
+                );
+                self.record_build(deployment.uuid, build).await?;
+                Ok(true)
+            }
+            Err(error) => {
+                error!(
+                    "DeploymentQueue::start - failed to start build for {}: {}",
+                    deployment.uuid, error
+                );
+                self.deployments
+                    .transition(
+                        deployment.uuid,
+                        &[ProjectDeploymentStatus::InProgress],
+                        ProjectDeploymentStatus::Failed,
+                        None,
+                    )
+                    .await?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Events of the build find its deployment by build id, the write is retried
+    /// as the build is already running. Events also carry the deployment uuid
+    /// as a build variable when it is lost anyway.
+    async fn record_build(
+        &self,
+        uuid: String,
+        build: BuildInfo,
+    ) -> Result<(), Report<HandlerError>> {
+        let mut attempt = 1;
+        loop {
+            let result = self
+                .deployments
+                .update(
+                    uuid.clone(),
+                    ProjectDeploymentUpdatePayload {
+                        project: None,
+                        status: None,
+                        build: Some(build.clone()),
+                        artifact: None,
+                        pinned: None,
+                    },
+                )
+                .await;
+            match result {
+                Err(error) if attempt < RECORD_BUILD_ATTEMPTS => {
+                    error!(
+                        "DeploymentQueue::record_build - attempt {} failed for {}: {}",
+                        attempt, uuid, error
+                    );
+                    sleep(Duration::from_millis(100 * attempt as u64)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod deployment_queue_tests {
+    use super::*;
+    use crate::fixtures::{deployment, project};
+
+    #[test]
+    fn newer_request_supersedes_older_queued_of_same_environment() {
+        let project = project();
+        let older = deployment(
+            &project,
+            "production",
+            ProjectDeploymentStatus::Queued,
+            "2022-08-01T00:00:00+00:00",
+        );
+        let mut started = deployment(
+            &project,
+            "production",
+            ProjectDeploymentStatus::Queued,
+            "2022-08-01T00:00:01+00:00",
+        );
+        started.status = ProjectDeploymentStatus::InProgress.to_string();
+        let staging = deployment(
+            &project,
+            "staging",
+            ProjectDeploymentStatus::Queued,
+            "2022-08-01T00:00:02+00:00",
+        );
+        let latest = deployment(
+            &project,
+            "production",
+            ProjectDeploymentStatus::Queued,
+            "2022-08-01T00:00:03+00:00",
+        );
+        let queued = vec![older.clone(), started, staging, latest.clone()];
+
+        let superseded = superseded_by(&queued, &latest);
+        assert_eq!(superseded.len(), 1);
+        assert_eq!(superseded[0].uuid, older.uuid);
+    }
+}
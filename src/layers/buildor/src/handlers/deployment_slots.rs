@@ -0,0 +1,93 @@
+use aws_sdk_dynamodb::model::{AttributeValue, TransactWriteItem, Update};
+
+use crate::handlers::deployment_queue::ConcurrencyLimits;
+
+/// Key of the slot counter shared by every project
+pub const ACCOUNT_SLOTS_KEY: &str = "account";
+
+/// Key of the slot counter of a project
+pub fn project_slots_key(project_uuid: &str) -> String {
+    format!("project#{}", project_uuid)
+}
+
+/// Counters of the builds in flight per project and for the whole account.
+/// Slots are taken and given back in the transaction that moves a deployment in
+/// and out of `IN_PROGRESS`, so concurrent drains can not exceed the limits.
+pub struct DeploymentSlotsHandler {
+    table_name: String,
+}
+impl DeploymentSlotsHandler {
+    pub fn new(table_name: String) -> Self {
+        Self { table_name }
+    }
+
+    /// Writes taking a slot of the project and one of the account, the
+    /// condition of a full counter cancels the transaction
+    pub fn take(&self, project_uuid: &str, limits: &ConcurrencyLimits) -> Vec<TransactWriteItem> {
+        vec![
+            self.add(project_slots_key(project_uuid), 1, Some(limits.project)),
+            self.add(ACCOUNT_SLOTS_KEY.to_string(), 1, Some(limits.account)),
+        ]
+    }
+
+    /// Writes giving back the slots taken for a deployment of the project
+    pub fn give_back(&self, project_uuid: &str) -> Vec<TransactWriteItem> {
+        vec![
+            self.add(project_slots_key(project_uuid), -1, None),
+            self.add(ACCOUNT_SLOTS_KEY.to_string(), -1, None),
+        ]
+    }
+
+    fn add(&self, key: String, delta: i64, max: Option<usize>) -> TransactWriteItem {
+        let mut update = Update::builder()
+            .table_name(&self.table_name)
+            .key("key", AttributeValue::S(key))
+            .update_expression("ADD #in_flight :delta")
+            .expression_attribute_names("#in_flight", "in_flight")
+            .expression_attribute_values(":delta", AttributeValue::N(format!("{}", delta)));
+        if let Some(max) = max {
+            update = update
+                .condition_expression("attribute_not_exists(#in_flight) OR #in_flight < :max")
+                .expression_attribute_values(":max", AttributeValue::N(format!("{}", max)));
+        }
+
+        TransactWriteItem::builder().update(update.build()).build()
+    }
+}
+
+#[cfg(test)]
+mod deployment_slots_tests {
+    use super::*;
+
+    #[test]
+    fn takes_bounded_slots_and_gives_them_back() {
+        let slots = DeploymentSlotsHandler::new("slots".to_string());
+
+        let taken = slots.take("abc", &ConcurrencyLimits::new(1, 10));
+        assert_eq!(taken.len(), 2);
+        let project = taken[0].update().unwrap();
+        assert_eq!(
+            project.key().unwrap().get("key"),
+            Some(&AttributeValue::S("project#abc".to_string()))
+        );
+        assert!(project.condition_expression().is_some());
+        assert_eq!(
+            project.expression_attribute_values().unwrap().get(":max"),
+            Some(&AttributeValue::N("1".to_string()))
+        );
+        let account = taken[1].update().unwrap();
+        assert_eq!(
+            account.expression_attribute_values().unwrap().get(":max"),
+            Some(&AttributeValue::N("10".to_string()))
+        );
+
+        for given in slots.give_back("abc") {
+            let update = given.update().unwrap();
+            assert!(update.condition_expression().is_none());
+            assert_eq!(
+                update.expression_attribute_values().unwrap().get(":delta"),
+                Some(&AttributeValue::N("-1".to_string()))
+            );
+        }
+    }
+}
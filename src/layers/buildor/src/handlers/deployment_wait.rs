@@ -0,0 +1,181 @@
+use error_stack::Report;
+use log::{self, info};
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use crate::models::{
+    handlers::HandlerError,
+    project_deployment::{ProjectDeployment, ProjectDeploymentWait, WaitOptions, WaitUntil},
+};
+
+/// Time between two reads of a deployment while waiting on it
+pub const WAIT_POLL_INTERVAL_MILLIS: u64 = 1000;
+
+/// Reads a deployment with `fetch` until its status satisfies `options` or the
+/// timeout passes. None when the deployment does not exist, or stops existing
+/// while waiting.
+pub async fn wait_for_deployment<F, Fut>(
+    fetch: F,
+    options: WaitOptions,
+    poll_interval: Duration,
+) -> Result<Option<ProjectDeploymentWait>, Report<HandlerError>>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<Option<ProjectDeployment>, Report<HandlerError>>>,
+{
+    info!(
+        "wait_for_deployment - until: {}, timeout: {}s",
+        options.until, options.timeout_seconds
+    );
+    let deadline = Instant::now() + Duration::from_secs(options.timeout_seconds);
+
+    let initial = match fetch().await? {
+        Some(deployment) => deployment,
+        None => return Ok(None),
+    };
+    let mut deployment = initial.clone();
+    loop {
+        let done = match options.until {
+            WaitUntil::Terminal => deployment.status().is_terminal(),
+            _ => deployment.status != initial.status,
+        };
+        let now = Instant::now();
+        if done || now >= deadline {
+            break;
+        }
+
+        tokio::time::sleep(poll_interval.min(deadline - now)).await;
+        deployment = match fetch().await? {
+            Some(deployment) => deployment,
+            None => return Ok(None),
+        };
+    }
+    info!(
+        "wait_for_deployment - status: {} -> {}",
+        initial.status, deployment.status
+    );
+
+    Ok(Some(ProjectDeploymentWait {
+        changed: deployment.status != initial.status,
+        deployment,
+    }))
+}
+
+#[cfg(test)]
+mod deployment_wait_tests {
+    use super::*;
+    use crate::fixtures::{deployment, project, CREATED_AT};
+    use crate::models::project_deployment::{
+        ProjectDeploymentStatus, DEFAULT_WAIT_TIMEOUT_SECONDS,
+    };
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Answers the statuses in order, then keeps answering the last one
+    async fn wait(
+        statuses: &[&str],
+        until: WaitUntil,
+        timeout_seconds: u64,
+    ) -> (Option<ProjectDeploymentWait>, usize) {
+        let reads = AtomicUsize::new(0);
+        let result = wait_for_deployment(
+            || async {
+                let read = reads.fetch_add(1, Ordering::SeqCst);
+                let status = statuses[read.min(statuses.len() - 1)];
+                Ok(Some(deployment(
+                    &project(),
+                    "production",
+                    ProjectDeploymentStatus::from_str(status).unwrap(),
+                    CREATED_AT,
+                )))
+            },
+            WaitOptions {
+                until,
+                timeout_seconds,
+            },
+            Duration::from_millis(10),
+        )
+        .await
+        .unwrap();
+
+        (result, reads.load(Ordering::SeqCst))
+    }
+
+    #[tokio::test]
+    async fn waits_for_a_status_change() {
+        let (result, reads) = wait(
+            &["QUEUED", "QUEUED", "IN_PROGRESS", "SUCCEEDED"],
+            WaitUntil::Change,
+            DEFAULT_WAIT_TIMEOUT_SECONDS,
+        )
+        .await;
+        let result = result.unwrap();
+        assert!(result.changed);
+        assert_eq!(result.deployment.status, "IN_PROGRESS");
+        assert_eq!(reads, 3);
+    }
+
+    #[tokio::test]
+    async fn waits_for_a_terminal_status() {
+        let (result, _) = wait(
+            &["QUEUED", "IN_PROGRESS", "IN_PROGRESS", "FAILED"],
+            WaitUntil::Terminal,
+            DEFAULT_WAIT_TIMEOUT_SECONDS,
+        )
+        .await;
+        let result = result.unwrap();
+        assert!(result.changed);
+        assert_eq!(result.deployment.status, "FAILED");
+
+        // Terminal deployments are returned right away
+        let (result, reads) = wait(&["SUCCEEDED"], WaitUntil::Terminal, 1).await;
+        assert!(!result.unwrap().changed);
+        assert_eq!(reads, 1);
+    }
+
+    #[tokio::test]
+    async fn returns_the_current_record_on_timeout() {
+        let (result, reads) = wait(&["IN_PROGRESS"], WaitUntil::Terminal, 1).await;
+        let result = result.unwrap();
+        assert!(!result.changed);
+        assert_eq!(result.deployment.status, "IN_PROGRESS");
+        assert!(reads > 1);
+    }
+
+    #[tokio::test]
+    async fn missing_deployments_are_none() {
+        let result = wait_for_deployment(
+            || async { Ok(None) },
+            WaitOptions {
+                until: WaitUntil::Change,
+                timeout_seconds: 1,
+            },
+            Duration::from_millis(10),
+        )
+        .await
+        .unwrap();
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn parses_wait_options() {
+        assert_eq!(
+            WaitOptions::parse(None, None).unwrap(),
+            WaitOptions {
+                until: WaitUntil::Change,
+                timeout_seconds: DEFAULT_WAIT_TIMEOUT_SECONDS,
+            }
+        );
+        assert_eq!(
+            WaitOptions::parse(Some("Terminal".to_string()), Some("10".to_string())).unwrap(),
+            WaitOptions {
+                until: WaitUntil::Terminal,
+                timeout_seconds: 10,
+            }
+        );
+        assert!(WaitOptions::parse(Some("forever".to_string()), None).is_err());
+        assert!(WaitOptions::parse(None, Some("0".to_string())).is_err());
+        assert!(WaitOptions::parse(None, Some("26".to_string())).is_err());
+        assert!(WaitOptions::parse(None, Some("soon".to_string())).is_err());
+    }
+}
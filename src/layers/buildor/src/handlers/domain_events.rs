@@ -0,0 +1,492 @@
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::AttributeValue;
+use error_stack::Report;
+use log::{self, error, info};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::{
+    handlers::{
+        project_deployments::ProjectDeploymentParser, projects::ProjectParser, users::UsersParser,
+    },
+    models::{
+        common::MissingModelPropertyError,
+        domain_event::DomainEvent,
+        handlers::HandlerError,
+        stream::{StreamError, StreamEventName, StreamRecord},
+    },
+};
+
+/// Receives the domain events of the Users, Projects and ProjectDeployments
+/// streams, reactions to any record change are added as sinks. Deployment side
+/// effects that are retried one by one, webhooks, notifications and stats, are
+/// published by the outbox relay instead.
+#[async_trait]
+pub trait DomainEventSink: Send + Sync {
+    fn name(&self) -> &str;
+
+    async fn publish(&self, event: &DomainEvent) -> Result<(), Report<HandlerError>>;
+}
+
+/// Logs every event as JSON
+pub struct LogSink;
+#[async_trait]
+impl DomainEventSink for LogSink {
+    fn name(&self) -> &str {
+        "log"
+    }
+
+    async fn publish(&self, event: &DomainEvent) -> Result<(), Report<HandlerError>> {
+        info!("LogSink::publish - {}: {}", event.name(), json!(event));
+        Ok(())
+    }
+}
+
+/// Names of the tables whose streams are decoded
+#[derive(Debug, Clone)]
+pub struct StreamTables {
+    pub users: String,
+    pub projects: String,
+    pub project_deployments: String,
+}
+
+pub struct DomainEventDecoder {
+    tables: StreamTables,
+}
+impl DomainEventDecoder {
+    pub fn new(tables: StreamTables) -> Self {
+        Self { tables }
+    }
+
+    /// Domain event of a record, none for records of other tables
+    pub fn decode(
+        &self,
+        record: &StreamRecord,
+    ) -> Result<Option<DomainEvent>, Report<StreamError>> {
+        let table = record.table_name.as_str();
+        let event = if table == self.tables.users {
+            match images(record, UsersParser::parse)? {
+                (None, Some(new)) => DomainEvent::UserCreated(new),
+                (Some(old), Some(new)) => DomainEvent::UserUpdated { old, new },
+                (Some(old), None) => DomainEvent::UserDeleted(old),
+                (None, None) => return Err(missing_images(record)),
+            }
+        } else if table == self.tables.projects {
+            match images(record, ProjectParser::parse)? {
+                (None, Some(new)) => DomainEvent::ProjectCreated(new),
+                (Some(old), Some(new)) => DomainEvent::ProjectUpdated { old, new },
+                (Some(old), None) => DomainEvent::ProjectDeleted(old),
+                (None, None) => return Err(missing_images(record)),
+            }
+        } else if table == self.tables.project_deployments {
+            match images(record, ProjectDeploymentParser::parse)? {
+                (None, Some(new)) => DomainEvent::DeploymentCreated(new),
+                (Some(old), Some(new)) if old.status != new.status => {
+                    DomainEvent::DeploymentStatusChanged {
+                        from: old.status,
+                        to: new.status.clone(),
+                        deployment: new,
+                    }
+                }
+                (Some(_), Some(new)) => DomainEvent::DeploymentUpdated(new),
+                (Some(old), None) => DomainEvent::DeploymentDeleted(old),
+                (None, None) => return Err(missing_images(record)),
+            }
+        } else {
+            return Ok(None);
+        };
+
+        Ok(Some(event))
+    }
+}
+
+/// Old and new images of a record parsed with a table parser, the old one is
+/// ignored for inserts and the new one for removals
+fn images<T>(
+    record: &StreamRecord,
+    parse: fn(HashMap<String, AttributeValue>) -> Result<T, Report<MissingModelPropertyError>>,
+) -> Result<(Option<T>, Option<T>), Report<StreamError>> {
+    let parse_image = |image: Option<&HashMap<String, AttributeValue>>| match image {
+        Some(image) => match parse(image.to_owned()) {
+            Ok(parsed) => Ok(Some(parsed)),
+            Err(error) => Err(Report::new(StreamError::new(&error.to_string()))),
+        },
+        None => Ok(None),
+    };
+
+    let old = match record.event_name {
+        StreamEventName::Insert => None,
+        _ => parse_image(record.old_image.as_ref())?,
+    };
+    let new = match record.event_name {
+        StreamEventName::Remove => None,
+        _ => parse_image(record.new_image.as_ref())?,
+    };
+
+    Ok((old, new))
+}
+
+fn missing_images(record: &StreamRecord) -> Report<StreamError> {
+    Report::new(StreamError::new(&format!(
+        "record {} has no images",
+        record.event_id
+    )))
+}
+
+/// Record that could not be decoded, retrying it would fail again
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidStreamRecord {
+    pub payload: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StreamBatchReport {
+    pub published: usize,
+    pub ignored: usize,
+    pub invalid: Vec<InvalidStreamRecord>,
+    /// Sequence number of the first record a sink rejected, it and the
+    /// following records are retried
+    pub failure: Option<String>,
+}
+impl StreamBatchReport {
+    /// Partial batch response of stream event sources
+    pub fn batch_item_failures(&self) -> Value {
+        let failures: Vec<Value> = self
+            .failure
+            .iter()
+            .map(|sequence_number| json!({ "itemIdentifier": sequence_number }))
+            .collect();
+
+        json!({ "batchItemFailures": failures })
+    }
+}
+
+pub struct DomainEventsProcessor {
+    decoder: DomainEventDecoder,
+    sinks: Vec<Box<dyn DomainEventSink>>,
+}
+impl DomainEventsProcessor {
+    pub fn new(decoder: DomainEventDecoder, sinks: Vec<Box<dyn DomainEventSink>>) -> Self {
+        Self { decoder, sinks }
+    }
+
+    /// Publishes the events of a batch of stream records to every sink, in
+    /// order. Processing stops at the first record a sink rejects, so that
+    /// record and the following ones are retried, which means sinks may
+    /// receive an event more than once.
+    pub async fn process(&self, records: &[Value]) -> StreamBatchReport {
+        info!(
+            "DomainEventsProcessor::process - records: {}",
+            records.len()
+        );
+
+        let mut report = StreamBatchReport::default();
+        for payload in records {
+            let (record, event) = match StreamRecord::parse(payload).and_then(|record| {
+                let event = self.decoder.decode(&record)?;
+                Ok((record, event))
+            }) {
+                Ok((record, Some(event))) => (record, event),
+                Ok((_, None)) => {
+                    report.ignored += 1;
+                    continue;
+                }
+                Err(error) => {
+                    error!("DomainEventsProcessor::process - invalid record: {}", error);
+                    report.invalid.push(InvalidStreamRecord {
+                        payload: payload.to_string(),
+                        reason: error.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            for sink in &self.sinks {
+                if let Err(error) = sink.publish(&event).await {
+                    error!(
+                        "DomainEventsProcessor::process - {} rejected {}: {}",
+                        sink.name(),
+                        event.name(),
+                        error
+                    );
+                    report.failure = Some(record.sequence_number);
+                    return report;
+                }
+            }
+            report.published += 1;
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod domain_events_tests {
+    use super::*;
+    use crate::fixtures::{self, project, CREATED_AT};
+    use crate::models::{
+        common::AsDynamoDBAttributeValue,
+        project_deployment::{ProjectDeployment, ProjectDeploymentStatus},
+        user::{User, UserCreatePayload},
+    };
+    use std::sync::{Arc, Mutex};
+
+    fn tables() -> StreamTables {
+        StreamTables {
+            users: "App-Users".to_string(),
+            projects: "App-Projects".to_string(),
+            project_deployments: "App-ProjectDeployments".to_string(),
+        }
+    }
+
+    fn attribute_value_to_json(value: &AttributeValue) -> Value {
+        match value {
+            AttributeValue::S(value) => json!({ "S": value }),
+            AttributeValue::N(value) => json!({ "N": value }),
+            AttributeValue::Bool(value) => json!({ "BOOL": value }),
+            AttributeValue::Null(value) => json!({ "NULL": value }),
+            AttributeValue::Ss(values) => json!({ "SS": values }),
+            AttributeValue::Ns(values) => json!({ "NS": values }),
+            AttributeValue::M(values) => json!({ "M": image_to_json(values) }),
+            AttributeValue::L(values) => {
+                json!({ "L": values.iter().map(attribute_value_to_json).collect::<Vec<Value>>() })
+            }
+            _ => panic!("Unsupported attribute value: {:?}", value),
+        }
+    }
+
+    fn image_to_json(image: &HashMap<String, AttributeValue>) -> Value {
+        let mut object = serde_json::Map::new();
+        for (name, value) in image {
+            object.insert(name.to_string(), attribute_value_to_json(value));
+        }
+        Value::Object(object)
+    }
+
+    fn stream_record(
+        table: &str,
+        sequence_number: &str,
+        event_name: &str,
+        old: Option<HashMap<String, AttributeValue>>,
+        new: Option<HashMap<String, AttributeValue>>,
+    ) -> Value {
+        let mut dynamodb = json!({ "SequenceNumber": sequence_number });
+        if let Some(old) = old {
+            dynamodb["OldImage"] = image_to_json(&old);
+        }
+        if let Some(new) = new {
+            dynamodb["NewImage"] = image_to_json(&new);
+        }
+        json!({
+            "eventID": format!("event-{}", sequence_number),
+            "eventName": event_name,
+            "eventSourceARN": format!(
+                "arn:aws:dynamodb:us-east-1:123456789012:table/{}/stream/2022-09-01T16:12:29.000",
+                table
+            ),
+            "dynamodb": dynamodb,
+        })
+    }
+
+    fn deployment() -> ProjectDeployment {
+        fixtures::deployment(
+            &project(),
+            "production",
+            ProjectDeploymentStatus::Queued,
+            CREATED_AT,
+        )
+    }
+
+    #[derive(Clone, Default)]
+    struct MemorySink {
+        events: Arc<Mutex<Vec<String>>>,
+        fail_on: Option<&'static str>,
+    }
+    #[async_trait]
+    impl DomainEventSink for MemorySink {
+        fn name(&self) -> &str {
+            "memory"
+        }
+
+        async fn publish(&self, event: &DomainEvent) -> Result<(), Report<HandlerError>> {
+            if self.fail_on == Some(event.name()) {
+                return Err(Report::new(HandlerError::new("sink unavailable")));
+            }
+            self.events.lock().unwrap().push(event.name().to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn decodes_records_into_domain_events() {
+        let decoder = DomainEventDecoder::new(tables());
+        let decode = |record: Value| {
+            decoder
+                .decode(&StreamRecord::parse(&record).unwrap())
+                .unwrap()
+        };
+
+        let user = User::new(UserCreatePayload {
+            fname: "Ada".to_string(),
+            lname: "Lovelace".to_string(),
+            email: None,
+        });
+        match decode(stream_record(
+            "App-Users",
+            "1",
+            "INSERT",
+            None,
+            Some(user.as_hashmap()),
+        )) {
+            Some(DomainEvent::UserCreated(created)) => assert_eq!(created.uuid, user.uuid),
+            other => panic!("Unexpected event: {:?}", other),
+        }
+
+        let queued = deployment();
+        let mut started = queued.clone();
+        started.status = "IN_PROGRESS".to_string();
+        match decode(stream_record(
+            "App-ProjectDeployments",
+            "2",
+            "MODIFY",
+            Some(queued.as_hashmap()),
+            Some(started.as_hashmap()),
+        )) {
+            Some(DomainEvent::DeploymentStatusChanged {
+                deployment,
+                from,
+                to,
+            }) => {
+                assert_eq!(deployment.uuid, queued.uuid);
+                assert_eq!(from, "QUEUED");
+                assert_eq!(to, "IN_PROGRESS");
+            }
+            other => panic!("Unexpected event: {:?}", other),
+        }
+
+        match decode(stream_record(
+            "App-ProjectDeployments",
+            "3",
+            "MODIFY",
+            Some(started.as_hashmap()),
+            Some(started.as_hashmap()),
+        )) {
+            Some(DomainEvent::DeploymentUpdated(_)) => (),
+            other => panic!("Unexpected event: {:?}", other),
+        }
+
+        match decode(stream_record(
+            "App-Projects",
+            "4",
+            "REMOVE",
+            Some(queued.project.as_hashmap()),
+            None,
+        )) {
+            Some(DomainEvent::ProjectDeleted(project)) => assert_eq!(project.name, "name"),
+            other => panic!("Unexpected event: {:?}", other),
+        }
+
+        assert!(decode(stream_record(
+            "App-Webhooks",
+            "5",
+            "INSERT",
+            None,
+            Some(queued.as_hashmap())
+        ))
+        .is_none());
+    }
+
+    #[tokio::test]
+    async fn process_publishes_to_every_sink_and_skips_invalid_records() {
+        let first = MemorySink::default();
+        let second = MemorySink::default();
+        let processor = DomainEventsProcessor::new(
+            DomainEventDecoder::new(tables()),
+            vec![Box::new(first.clone()), Box::new(second.clone())],
+        );
+
+        let deployment = deployment();
+        let mut invalid = deployment.as_hashmap();
+        invalid.remove("uuid");
+        let records = vec![
+            stream_record(
+                "App-Projects",
+                "1",
+                "INSERT",
+                None,
+                Some(deployment.project.as_hashmap()),
+            ),
+            stream_record("App-ProjectDeployments", "2", "INSERT", None, Some(invalid)),
+            stream_record(
+                "App-ProjectDeployments",
+                "3",
+                "INSERT",
+                None,
+                Some(deployment.as_hashmap()),
+            ),
+        ];
+
+        let report = processor.process(&records).await;
+        assert_eq!(report.published, 2);
+        assert_eq!(report.invalid.len(), 1);
+        assert!(report.invalid[0].reason.contains("uuid"));
+        assert_eq!(report.failure, None);
+        assert_eq!(
+            report.batch_item_failures(),
+            json!({ "batchItemFailures": [] })
+        );
+        for sink in [first, second] {
+            assert_eq!(
+                *sink.events.lock().unwrap(),
+                vec!["ProjectCreated", "DeploymentCreated"]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn process_stops_at_the_first_rejected_record() {
+        let sink = MemorySink {
+            fail_on: Some("DeploymentCreated"),
+            ..MemorySink::default()
+        };
+        let processor = DomainEventsProcessor::new(
+            DomainEventDecoder::new(tables()),
+            vec![Box::new(sink.clone())],
+        );
+
+        let deployment = deployment();
+        let records = vec![
+            stream_record(
+                "App-Projects",
+                "1",
+                "INSERT",
+                None,
+                Some(deployment.project.as_hashmap()),
+            ),
+            stream_record(
+                "App-ProjectDeployments",
+                "2",
+                "INSERT",
+                None,
+                Some(deployment.as_hashmap()),
+            ),
+            stream_record(
+                "App-Projects",
+                "3",
+                "REMOVE",
+                Some(deployment.project.as_hashmap()),
+                None,
+            ),
+        ];
+
+        let report = processor.process(&records).await;
+        assert_eq!(report.published, 1);
+        assert_eq!(report.failure, Some("2".to_string()));
+        assert_eq!(
+            report.batch_item_failures(),
+            json!({ "batchItemFailures": [{ "itemIdentifier": "2" }] })
+        );
+        assert_eq!(*sink.events.lock().unwrap(), vec!["ProjectCreated"]);
+    }
+}
@@ -0,0 +1,481 @@
+use async_trait::async_trait;
+use error_stack::Report;
+use lettre::{
+    message::{Mailbox, MultiPart},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use log::{self, error, info};
+
+use crate::{
+    handlers::{project_deployments::ProjectDeploymentsHandler, users::UsersHandler},
+    models::{
+        email::{EmailError, EmailMessage, SmtpConfig, SmtpSecurity},
+        handlers::{HandlerError, HandlerGet},
+        notification::{DeploymentNotification, NotificationChannel, NotificationProvider},
+        project::Project,
+        project_deployment::{ProjectDeployment, ProjectDeploymentStatus},
+    },
+};
+
+/// Owner address followed by the opt-in addresses of the project email
+/// channels, without duplicates
+pub fn email_recipients(owner: Option<&str>, channels: &[NotificationChannel]) -> Vec<String> {
+    let mut recipients: Vec<String> = Vec::new();
+    let opt_in = channels
+        .iter()
+        .filter(|channel| channel.provider() == NotificationProvider::Email)
+        .flat_map(|channel| channel.addresses());
+    for address in owner
+        .map(|owner| owner.to_string())
+        .into_iter()
+        .chain(opt_in)
+    {
+        let address = address.trim().to_lowercase();
+        if address.contains('@') && !recipients.contains(&address) {
+            recipients.push(address);
+        }
+    }
+
+    recipients
+}
+
+/// Outcome of the deployment of the same environment preceding `deployment`
+pub fn previous_outcome(
+    deployment: &ProjectDeployment,
+    deployments: &[ProjectDeployment],
+) -> Option<ProjectDeploymentStatus> {
+    deployments
+        .iter()
+        .filter(|previous| {
+            previous.uuid != deployment.uuid
+                && previous.environment == deployment.environment
+                && previous.created_at < deployment.created_at
+                && matches!(
+                    previous.status(),
+                    ProjectDeploymentStatus::Succeeded | ProjectDeploymentStatus::Failed
+                )
+        })
+        .max_by(|a, b| a.created_at.cmp(&b.created_at))
+        .map(|previous| previous.status())
+}
+
+/// Emails are only sent on failures and on the first success after one
+pub fn should_email(
+    status: ProjectDeploymentStatus,
+    previous: Option<ProjectDeploymentStatus>,
+) -> bool {
+    match status {
+        ProjectDeploymentStatus::Failed => true,
+        ProjectDeploymentStatus::Succeeded => previous == Some(ProjectDeploymentStatus::Failed),
+        _ => false,
+    }
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Label and value of every line of the email body
+fn email_fields(notification: &DeploymentNotification) -> Vec<(&'static str, String)> {
+    let mut fields = vec![
+        ("Project", notification.project.clone()),
+        ("Environment", notification.environment.clone()),
+    ];
+    if let Some(phase) = notification.failed_phase.as_ref() {
+        fields.push(("Failed phase", phase.clone()));
+    }
+    fields.push(("Duration", notification.duration()));
+    fields.push(("Commit", notification.commit()));
+    fields.push(("Deployment", notification.deployment.clone()));
+
+    fields
+}
+
+pub fn email_text(notification: &DeploymentNotification) -> String {
+    let mut text = format!("{}\n\n", notification.title());
+    for (name, value) in email_fields(notification) {
+        text.push_str(&format!("{}: {}\n", name, value));
+    }
+
+    text
+}
+
+pub fn email_html(notification: &DeploymentNotification) -> String {
+    let rows: String = email_fields(notification)
+        .into_iter()
+        .map(|(name, value)| {
+            format!(
+                "<tr><th align=\"left\">{}</th><td>{}</td></tr>",
+                name,
+                escape_html(&value)
+            )
+        })
+        .collect();
+
+    format!(
+        "<!DOCTYPE html><html><body><h2>{}</h2><table>{}</table></body></html>",
+        escape_html(&notification.title()),
+        rows
+    )
+}
+
+pub fn deployment_email(
+    notification: &DeploymentNotification,
+    recipients: Vec<String>,
+) -> EmailMessage {
+    EmailMessage {
+        recipients,
+        subject: format!("[Buildor] {}", notification.title()),
+        text: email_text(notification),
+        html: email_html(notification),
+    }
+}
+
+/// Outgoing emails go through this trait so they can be pointed at a local
+/// SMTP sink in tests
+#[async_trait]
+pub trait EmailSender: Send + Sync {
+    async fn send(&self, message: EmailMessage) -> Result<(), Report<EmailError>>;
+}
+
+pub struct SmtpSender {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+impl SmtpSender {
+    pub fn new(config: SmtpConfig) -> Result<Self, Report<EmailError>> {
+        let from = match config.from.parse::<Mailbox>() {
+            Ok(from) => from,
+            Err(error) => return Err(Report::new(EmailError::new(&error.to_string()))),
+        };
+        let builder = match config.security {
+            SmtpSecurity::None => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host)
+            }
+            SmtpSecurity::Tls => match AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host) {
+                Ok(builder) => builder,
+                Err(error) => return Err(Report::new(EmailError::new(&error.to_string()))),
+            },
+            _ => match AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host) {
+                Ok(builder) => builder,
+                Err(error) => return Err(Report::new(EmailError::new(&error.to_string()))),
+            },
+        };
+        let builder = match (config.username, config.password) {
+            (Some(username), Some(password)) => {
+                builder.credentials(Credentials::new(username, password))
+            }
+            _ => builder,
+        };
+
+        Ok(Self {
+            transport: builder.port(config.port).build(),
+            from,
+        })
+    }
+}
+
+#[async_trait]
+impl EmailSender for SmtpSender {
+    async fn send(&self, message: EmailMessage) -> Result<(), Report<EmailError>> {
+        info!("SmtpSender::send - recipients: {:?}", message.recipients);
+        let mut builder = Message::builder()
+            .from(self.from.clone())
+            .subject(message.subject);
+        for address in message.recipients.iter() {
+            match address.parse::<Mailbox>() {
+                Ok(mailbox) => builder = builder.bcc(mailbox),
+                Err(error) => {
+                    error!(
+                        "SmtpSender::send - invalid recipient {}: {}",
+                        address, error
+                    );
+                    return Err(Report::new(EmailError::new(&error.to_string())));
+                }
+            }
+        }
+        let email = match builder.multipart(MultiPart::alternative_plain_html(
+            message.text,
+            message.html,
+        )) {
+            Ok(email) => email,
+            Err(error) => return Err(Report::new(EmailError::new(&error.to_string()))),
+        };
+
+        info!("SmtpSender::send - send tx");
+        match self.transport.send(email).await {
+            Ok(response) => {
+                info!("SmtpSender::send - response code: {}", response.code());
+                Ok(())
+            }
+            Err(error) => {
+                error!("SmtpSender::send - failed to send email: {:?}", error);
+                Err(Report::new(EmailError::new(&error.to_string())))
+            }
+        }
+    }
+}
+
+pub struct DeploymentMailer {
+    deployments: ProjectDeploymentsHandler,
+    users: UsersHandler,
+    sender: Box<dyn EmailSender>,
+}
+impl DeploymentMailer {
+    pub fn new(
+        deployments: ProjectDeploymentsHandler,
+        users: UsersHandler,
+        sender: Box<dyn EmailSender>,
+    ) -> Self {
+        Self {
+            deployments,
+            users,
+            sender,
+        }
+    }
+
+    /// Emails the project owner and opt-in recipients when the deployment
+    /// failed or is the first success after a failure. Returns the recipients
+    /// of the email, empty when none was sent.
+    pub async fn notify(
+        &self,
+        project: &Project,
+        deployment: &ProjectDeployment,
+        notification: &DeploymentNotification,
+    ) -> Result<Vec<String>, Report<HandlerError>> {
+        info!(
+            "DeploymentMailer::notify - deployment: {}, status: {}",
+            deployment.uuid, deployment.status
+        );
+        let previous = match deployment.status() {
+            ProjectDeploymentStatus::Succeeded => {
+                let deployments = self
+                    .deployments
+                    .list_by_project(project.uuid.clone(), None)
+                    .await?;
+                previous_outcome(deployment, &deployments)
+            }
+            _ => None,
+        };
+        if !should_email(deployment.status(), previous) {
+            info!("DeploymentMailer::notify - nothing to report");
+            return Ok(Vec::new());
+        }
+
+        let owner = match project.owner.as_str() {
+            "-" => None,
+            owner => self.users.get(owner.to_string()).await?,
+        };
+        let recipients = email_recipients(
+            owner.as_ref().and_then(|owner| owner.email()),
+            &project.notifications,
+        );
+        if recipients.is_empty() {
+            info!("DeploymentMailer::notify - no recipients");
+            return Ok(recipients);
+        }
+
+        self.sender
+            .send(deployment_email(notification, recipients.clone()))
+            .await
+            .map_err(|error| Report::new(HandlerError::new(&error.to_string())))?;
+
+        Ok(recipients)
+    }
+}
+
+#[cfg(test)]
+mod email_tests {
+    use super::*;
+    use crate::fixtures::{deployment, project};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    /// Local SMTP sink accepting a single message, forwards its recipients
+    /// and raw data
+    fn smtp_sink() -> (u16, mpsc::Receiver<(Vec<String>, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut recipients = Vec::new();
+            let mut data = String::new();
+            stream.write_all(b"220 localhost ESMTP sink\r\n").unwrap();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                let command = line.to_uppercase();
+                let reply: &[u8] = if command.starts_with("EHLO") || command.starts_with("HELO") {
+                    b"250 localhost\r\n"
+                } else if command.starts_with("RCPT TO:") {
+                    recipients.push(line[8..].trim().trim_matches(&['<', '>'][..]).to_string());
+                    b"250 OK\r\n"
+                } else if command.starts_with("DATA") {
+                    stream
+                        .write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n")
+                        .unwrap();
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        if line == ".\r\n" {
+                            break;
+                        }
+                        data.push_str(&line);
+                    }
+                    b"250 OK queued\r\n"
+                } else if command.starts_with("QUIT") {
+                    stream.write_all(b"221 Bye\r\n").unwrap();
+                    break;
+                } else {
+                    b"250 OK\r\n"
+                };
+                stream.write_all(reply).unwrap();
+            }
+            tx.send((recipients, data)).unwrap();
+        });
+
+        (port, rx)
+    }
+
+    fn notification() -> DeploymentNotification {
+        DeploymentNotification {
+            project: "<my-app>".to_string(),
+            environment: "production".to_string(),
+            deployment: "1234".to_string(),
+            status: "FAILED".to_string(),
+            failed_phase: Some("BUILD (FAILED)".to_string()),
+            duration_seconds: Some(42),
+            commit: Some("0123456789abcdef".to_string()),
+        }
+    }
+
+    #[test]
+    fn emails_failures_and_recoveries_only() {
+        let project = project();
+        let deployments = vec![
+            deployment(
+                &project,
+                "production",
+                ProjectDeploymentStatus::Failed,
+                "2022-01-01",
+            ),
+            deployment(
+                &project,
+                "staging",
+                ProjectDeploymentStatus::Succeeded,
+                "2022-01-02",
+            ),
+            deployment(
+                &project,
+                "production",
+                ProjectDeploymentStatus::Superseded,
+                "2022-01-03",
+            ),
+            deployment(
+                &project,
+                "production",
+                ProjectDeploymentStatus::Succeeded,
+                "2022-01-04",
+            ),
+            deployment(
+                &project,
+                "production",
+                ProjectDeploymentStatus::Succeeded,
+                "2022-01-05",
+            ),
+        ];
+
+        let recovered = previous_outcome(&deployments[3], &deployments);
+        assert_eq!(recovered, Some(ProjectDeploymentStatus::Failed));
+        assert!(should_email(ProjectDeploymentStatus::Succeeded, recovered));
+
+        let previous = previous_outcome(&deployments[4], &deployments);
+        assert!(!should_email(ProjectDeploymentStatus::Succeeded, previous));
+        assert!(should_email(ProjectDeploymentStatus::Failed, previous));
+        assert!(!should_email(ProjectDeploymentStatus::Succeeded, None));
+    }
+
+    #[test]
+    fn recipients_include_owner_and_opt_in_list() {
+        let channels = vec![
+            NotificationChannel {
+                provider: "email".to_string(),
+                url: "mailto:Ops@Example.com,owner@example.com".to_string(),
+            },
+            NotificationChannel {
+                provider: "slack".to_string(),
+                url: "https://hooks.slack.com/services/x".to_string(),
+            },
+        ];
+
+        assert_eq!(
+            email_recipients(Some("owner@example.com"), &channels),
+            vec!["owner@example.com", "ops@example.com"]
+        );
+        assert_eq!(
+            email_recipients(Some("-"), &channels[1..]),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn templates_escape_html() {
+        let message = deployment_email(&notification(), vec!["a@example.com".to_string()]);
+        assert_eq!(
+            message.subject,
+            "[Buildor] <my-app> deployment to production failed"
+        );
+        assert!(message.text.contains("Project: <my-app>\n"));
+        assert!(message.text.contains("Failed phase: BUILD (FAILED)\n"));
+        assert!(message.text.contains("Duration: 42s\n"));
+        assert!(message.text.contains("Commit: 0123456\n"));
+        assert!(message
+            .html
+            .contains("<h2>&lt;my-app&gt; deployment to production failed</h2>"));
+        assert!(!message.html.contains("<my-app>"));
+    }
+
+    #[tokio::test]
+    async fn smtp_sender_delivers_to_local_sink() {
+        let (port, sink) = smtp_sink();
+        let sender = SmtpSender::new(SmtpConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            username: None,
+            password: None,
+            from: "Buildor <buildor@example.com>".to_string(),
+            security: SmtpSecurity::None,
+        })
+        .unwrap();
+
+        let recipients = vec![
+            "owner@example.com".to_string(),
+            "ops@example.com".to_string(),
+        ];
+        sender
+            .send(deployment_email(&notification(), recipients.clone()))
+            .await
+            .unwrap();
+
+        let (received, data) = sink.recv().unwrap();
+        assert_eq!(received, recipients);
+        // Opt-in recipients must not learn about each other
+        assert!(!data.contains("owner@example.com"));
+        assert!(!data.contains("ops@example.com"));
+        assert!(data.contains("Subject: [Buildor] <my-app> deployment to production failed"));
+        assert!(data.contains("Content-Type: text/plain"));
+        assert!(data.contains("Content-Type: text/html"));
+    }
+}
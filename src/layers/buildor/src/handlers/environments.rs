@@ -0,0 +1,160 @@
+use aws_sdk_dynamodb::model::AttributeValue;
+use error_stack::Report;
+use std::collections::HashMap;
+
+use crate::models::{
+    common::MissingModelPropertyError,
+    environment::{Environment, SecretVariable},
+};
+
+pub struct EnvironmentsParser;
+impl EnvironmentsParser {
+    pub fn parse(
+        item: HashMap<String, AttributeValue>,
+    ) -> Result<Vec<Environment>, Report<MissingModelPropertyError>> {
+        let mut environments = Vec::new();
+        for (name, value) in item.iter() {
+            match value.as_m() {
+                Ok(environment) => environments.push(EnvironmentsParser::parse_environment(
+                    environment.to_owned(),
+                )?),
+                Err(_) => return Err(Report::new(MissingModelPropertyError::new(name))),
+            }
+        }
+        environments.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(environments)
+    }
+
+    pub fn parse_environment(
+        item: HashMap<String, AttributeValue>,
+    ) -> Result<Environment, Report<MissingModelPropertyError>> {
+        let name = match item.get("name") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("name"))),
+        };
+
+        let branch = match item.get("branch") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("branch"))),
+        };
+
+        let variables = match item.get("variables") {
+            Some(value) => value
+                .as_m()
+                .unwrap()
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.as_s().unwrap().to_string()))
+                .collect::<HashMap<String, String>>(),
+            None => return Err(Report::new(MissingModelPropertyError::new("variables"))),
+        };
+
+        // Environments created before secrets were introduced have none
+        let secrets = match item.get("secrets") {
+            Some(value) => {
+                let mut secrets = HashMap::new();
+                for (name, secret) in value.as_m().unwrap().iter() {
+                    let secret = secret.as_m().unwrap();
+                    match (secret.get("type"), secret.get("value")) {
+                        (Some(secret_type), Some(value)) => secrets.insert(
+                            name.to_string(),
+                            SecretVariable {
+                                secret_type: secret_type.as_s().unwrap().to_string(),
+                                value: value.as_s().unwrap().to_string(),
+                            },
+                        ),
+                        _ => return Err(Report::new(MissingModelPropertyError::new("secrets"))),
+                    };
+                }
+                secrets
+            }
+            None => HashMap::new(),
+        };
+
+        let current_deployment = match item.get("current_deployment") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => {
+                return Err(Report::new(MissingModelPropertyError::new(
+                    "current_deployment",
+                )))
+            }
+        };
+
+        Ok(Environment {
+            name,
+            branch,
+            variables,
+            secrets,
+            current_deployment,
+        })
+    }
+}
+
+#[cfg(test)]
+mod environments_parser_tests {
+    use super::*;
+    use crate::models::{
+        common::AsDynamoDBAttributeValue,
+        environment::{environments_as_attr, EnvironmentCreatePayload},
+    };
+
+    #[test]
+    fn parse_fails_on_missing_branch() {
+        let mut input: HashMap<String, AttributeValue> = HashMap::new();
+        input.insert("name".to_string(), AttributeValue::S("staging".to_string()));
+        match EnvironmentsParser::parse_environment(input) {
+            Err(error) => assert_eq!(error.to_string(), "Missing model property: branch"),
+            _ => assert_eq!("", "Should have panicked but it did not"),
+        }
+    }
+
+    #[test]
+    fn parse_round_trips_environments() {
+        let mut staging = EnvironmentCreatePayload::default("staging".to_string());
+        staging.branch = Some("develop".to_string());
+        staging.variables = Some(HashMap::from([(
+            "API_URL".to_string(),
+            "https://staging.example.com".to_string(),
+        )]));
+        staging.secrets = Some(HashMap::from([(
+            "NPM_TOKEN".to_string(),
+            SecretVariable {
+                secret_type: "parameter_store".to_string(),
+                value: "/App/projects/site/NPM_TOKEN".to_string(),
+            },
+        )]));
+        let mut environments = Environment::defaults();
+        environments.push(Environment::new(staging));
+
+        let input = environments_as_attr(&environments)
+            .as_m()
+            .unwrap()
+            .to_owned();
+        let parsed = EnvironmentsParser::parse(input).unwrap();
+
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].name, "production");
+        assert_eq!(parsed[0].branch, "main");
+        assert_eq!(parsed[1].name, "staging");
+        assert_eq!(parsed[1].branch, "develop");
+        assert_eq!(
+            parsed[1].variables.get("API_URL").unwrap(),
+            "https://staging.example.com"
+        );
+        assert_eq!(
+            parsed[1].secrets.get("NPM_TOKEN").unwrap().secret_type,
+            "PARAMETER_STORE"
+        );
+        assert!(parsed[0].secrets.is_empty());
+        assert_eq!(parsed[1].current_deployment, "-");
+        assert_eq!(
+            parsed[1]
+                .as_hashmap()
+                .get("branch")
+                .unwrap()
+                .as_s()
+                .unwrap(),
+            "develop"
+        );
+    }
+}
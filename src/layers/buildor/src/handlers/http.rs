@@ -0,0 +1,60 @@
+use async_trait::async_trait;
+use error_stack::Report;
+use log::{self, error, info};
+use std::time::Duration;
+
+use crate::models::http::{HttpError, HttpRequest, HttpResponse};
+
+/// Seconds to wait for a remote endpoint before giving up on a request
+pub const DEFAULT_HTTP_TIMEOUT_SECONDS: u64 = 5;
+
+/// Outgoing HTTP calls go through this trait so they can be pointed at fake
+/// servers in tests
+#[async_trait]
+pub trait HttpSender: Send + Sync {
+    async fn post(&self, request: HttpRequest) -> Result<HttpResponse, Report<HttpError>>;
+}
+
+pub struct ReqwestSender {
+    client: reqwest::Client,
+}
+impl ReqwestSender {
+    pub fn new(timeout_seconds: u64) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(timeout_seconds))
+                .build()
+                .unwrap(),
+        }
+    }
+}
+impl Default for ReqwestSender {
+    fn default() -> Self {
+        Self::new(DEFAULT_HTTP_TIMEOUT_SECONDS)
+    }
+}
+
+#[async_trait]
+impl HttpSender for ReqwestSender {
+    async fn post(&self, request: HttpRequest) -> Result<HttpResponse, Report<HttpError>> {
+        info!("ReqwestSender::post - url: {}", request.url);
+        let mut tx = self.client.post(&request.url).body(request.body);
+        for (name, value) in request.headers.iter() {
+            tx = tx.header(name, value);
+        }
+
+        info!("ReqwestSender::post - send tx");
+        match tx.send().await {
+            Ok(response) => {
+                let status = response.status().as_u16();
+                let body = response.text().await.unwrap_or_default();
+                info!("ReqwestSender::post - response status: {}", status);
+                Ok(HttpResponse { status, body })
+            }
+            Err(error) => {
+                error!("ReqwestSender::post - request failed: {:?}", error);
+                Err(Report::new(HttpError::new(&error.to_string())))
+            }
+        }
+    }
+}
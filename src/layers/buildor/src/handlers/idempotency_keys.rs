@@ -0,0 +1,303 @@
+use aws_sdk_dynamodb::{model::AttributeValue, types::SdkError, Client};
+use chrono::{DateTime, Utc};
+use error_stack::Report;
+use log::{self, error, info};
+use std::collections::HashMap;
+
+use crate::models::{
+    common::{AsDynamoDBAttributeValue, MissingModelPropertyError},
+    handlers::HandlerError,
+    idempotency_key::IdempotencyKey,
+    response::Response,
+};
+
+pub struct IdempotencyKeyParser;
+impl IdempotencyKeyParser {
+    pub fn parse(
+        item: HashMap<String, AttributeValue>,
+    ) -> Result<IdempotencyKey, Report<MissingModelPropertyError>> {
+        let key = match item.get("key") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("key"))),
+        };
+
+        let fingerprint = match item.get("fingerprint") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("fingerprint"))),
+        };
+
+        let status_code = item
+            .get("status_code")
+            .and_then(|value| value.as_n().ok())
+            .and_then(|value| value.parse().ok());
+
+        let body = item
+            .get("body")
+            .and_then(|value| value.as_s().ok())
+            .map(|value| value.to_string());
+
+        // Keys claimed before locks existed can be taken over
+        let locked_until = item
+            .get("locked_until")
+            .and_then(|value| value.as_n().ok())
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0);
+
+        let expires_at = match item.get("expires_at") {
+            Some(value) => value.as_n().unwrap().parse().unwrap_or(0),
+            None => return Err(Report::new(MissingModelPropertyError::new("expires_at"))),
+        };
+
+        let created_at = match item.get("created_at") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("created_at"))),
+        };
+
+        Ok(IdempotencyKey {
+            key,
+            fingerprint,
+            status_code,
+            body,
+            locked_until,
+            expires_at,
+            created_at,
+        })
+    }
+}
+
+pub struct IdempotencyKeysHandler {
+    table: Client,
+    table_name: String,
+}
+impl IdempotencyKeysHandler {
+    pub fn new(client: Client, table_name: String) -> Self {
+        Self {
+            table: client,
+            table_name,
+        }
+    }
+
+    /// Claims a key for a new request, expired keys not deleted yet and keys
+    /// whose request lost its lock included. Returns the request that holds
+    /// the key when it is already claimed.
+    pub async fn claim(
+        &self,
+        key: &IdempotencyKey,
+        now: DateTime<Utc>,
+    ) -> Result<Option<IdempotencyKey>, Report<HandlerError>> {
+        info!("IdempotencyKeysHandler::claim - key: {}", key.key);
+
+        // The holder may expire or lose its lock between the claim and its
+        // read, claim it again then
+        for _ in 0..2 {
+            let tx = self
+                .table
+                .put_item()
+                .table_name(&self.table_name)
+                .set_item(Some(key.as_hashmap()))
+                .condition_expression(
+                    "attribute_not_exists(#key) OR #expires_at <= :now OR (attribute_not_exists(#status_code) AND (attribute_not_exists(#locked_until) OR #locked_until <= :now))",
+                )
+                .expression_attribute_names("#key", "key")
+                .expression_attribute_names("#expires_at", "expires_at")
+                .expression_attribute_names("#status_code", "status_code")
+                .expression_attribute_names("#locked_until", "locked_until")
+                .expression_attribute_values(
+                    ":now",
+                    AttributeValue::N(format!("{}", now.timestamp())),
+                );
+
+            info!("IdempotencyKeysHandler::claim - send tx");
+            match tx.send().await {
+                Ok(_) => return Ok(None),
+                Err(SdkError::ServiceError { err, .. })
+                    if err.is_conditional_check_failed_exception() =>
+                {
+                    info!("IdempotencyKeysHandler::claim - key already claimed");
+                    if let Some(holder) = self.get(&key.key).await? {
+                        if !holder.is_expired(now)
+                            && (holder.is_locked(now) || holder.response().is_some())
+                        {
+                            return Ok(Some(holder));
+                        }
+                    }
+                }
+                Err(err) => {
+                    error!(
+                        "IdempotencyKeysHandler::claim - failed to claim key: {:?}",
+                        err
+                    );
+                    return Err(Report::new(HandlerError::new(&err.to_string())));
+                }
+            }
+        }
+
+        Err(Report::new(HandlerError::new(
+            "Idempotency key claimed and released concurrently",
+        )))
+    }
+
+    /// Keeps the response of the request holding the key for its replays.
+    /// Returns `false` when a replay took the key over meanwhile.
+    pub async fn complete(
+        &self,
+        claim: &IdempotencyKey,
+        response: &Response,
+    ) -> Result<bool, Report<HandlerError>> {
+        info!(
+            "IdempotencyKeysHandler::complete - key: {}, status: {}",
+            claim.key, response.status_code
+        );
+
+        let tx = self
+            .table
+            .update_item()
+            .table_name(&self.table_name)
+            .key("key", AttributeValue::S(claim.key.to_owned()))
+            .update_expression("SET #status_code = :status_code, #body = :body")
+            .condition_expression("#created_at = :created_at")
+            .expression_attribute_names("#created_at", "created_at")
+            .expression_attribute_values(
+                ":created_at",
+                AttributeValue::S(claim.created_at.to_owned()),
+            )
+            .expression_attribute_names("#status_code", "status_code")
+            .expression_attribute_names("#body", "body")
+            .expression_attribute_values(
+                ":status_code",
+                AttributeValue::N(format!("{}", response.status_code)),
+            )
+            .expression_attribute_values(":body", AttributeValue::S(response.body.to_owned()));
+
+        info!("IdempotencyKeysHandler::complete - send tx");
+        match tx.send().await {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                info!("IdempotencyKeysHandler::complete - key taken over");
+                Ok(false)
+            }
+            Err(err) => {
+                error!(
+                    "IdempotencyKeysHandler::complete - failed to store response: {:?}",
+                    err
+                );
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+
+    /// Forgets a key so that a retry of a failed request is handled again,
+    /// unless a replay took it over meanwhile
+    pub async fn release(&self, claim: &IdempotencyKey) -> Result<(), Report<HandlerError>> {
+        info!("IdempotencyKeysHandler::release - key: {}", claim.key);
+
+        let tx = self
+            .table
+            .delete_item()
+            .table_name(&self.table_name)
+            .key("key", AttributeValue::S(claim.key.to_owned()))
+            .condition_expression("#created_at = :created_at")
+            .expression_attribute_names("#created_at", "created_at")
+            .expression_attribute_values(
+                ":created_at",
+                AttributeValue::S(claim.created_at.to_owned()),
+            );
+
+        info!("IdempotencyKeysHandler::release - send tx");
+        match tx.send().await {
+            Ok(_) => Ok(()),
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                info!("IdempotencyKeysHandler::release - key taken over");
+                Ok(())
+            }
+            Err(err) => {
+                error!(
+                    "IdempotencyKeysHandler::release - failed to release key: {:?}",
+                    err
+                );
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<IdempotencyKey>, Report<HandlerError>> {
+        let tx = self
+            .table
+            .get_item()
+            .table_name(&self.table_name)
+            .key("key", AttributeValue::S(key.to_string()))
+            .consistent_read(true);
+
+        info!("IdempotencyKeysHandler::get - send tx");
+        match tx.send().await {
+            Ok(res) => match res.item {
+                Some(item) => IdempotencyKeyParser::parse(item)
+                    .map(Some)
+                    .map_err(|err| Report::new(HandlerError::new(&err.to_string()))),
+                None => Ok(None),
+            },
+            Err(err) => {
+                error!("IdempotencyKeysHandler::get - failed to get key: {:?}", err);
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod idempotency_keys_tests {
+    use super::*;
+
+    fn item() -> HashMap<String, AttributeValue> {
+        let mut item: HashMap<String, AttributeValue> = HashMap::new();
+        item.insert(
+            "key".to_string(),
+            AttributeValue::S("POST /deployments#retry-1".to_string()),
+        );
+        item.insert(
+            "fingerprint".to_string(),
+            AttributeValue::S("fingerprint".to_string()),
+        );
+        item.insert(
+            "expires_at".to_string(),
+            AttributeValue::N("1641081600".to_string()),
+        );
+        item.insert(
+            "created_at".to_string(),
+            AttributeValue::S("2022-01-01T00:00:00+00:00".to_string()),
+        );
+        item
+    }
+
+    #[test]
+    fn parses_in_progress_and_completed_keys() {
+        let key = IdempotencyKeyParser::parse(item()).unwrap();
+        assert_eq!(key.status_code, None);
+        assert_eq!(key.locked_until, 0);
+        assert_eq!(key.expires_at, 1641081600);
+
+        let mut completed = item();
+        completed.insert(
+            "status_code".to_string(),
+            AttributeValue::N("202".to_string()),
+        );
+        completed.insert("body".to_string(), AttributeValue::S("{}".to_string()));
+        let key = IdempotencyKeyParser::parse(completed).unwrap();
+        assert_eq!(key.response().unwrap().status_code, 202);
+        assert_eq!(IdempotencyKeyParser::parse(key.as_hashmap()).unwrap(), key);
+    }
+
+    #[test]
+    fn parse_fails_on_missing_fingerprint() {
+        let mut input = item();
+        input.remove("fingerprint");
+        match IdempotencyKeyParser::parse(input) {
+            Err(error) => assert_eq!(error.to_string(), "Missing model property: fingerprint"),
+            _ => assert_eq!("", "Should have panicked but it did not"),
+        }
+    }
+}
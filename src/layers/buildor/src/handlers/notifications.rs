@@ -0,0 +1,354 @@
+use aws_sdk_dynamodb::model::AttributeValue;
+use error_stack::Report;
+use log::{self, error, info};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::{
+    handlers::http::HttpSender,
+    models::{
+        common::MissingModelPropertyError,
+        http::HttpRequest,
+        notification::{DeploymentNotification, NotificationChannel, NotificationProvider},
+        project_deployment::ProjectDeploymentStatus,
+    },
+};
+
+const COLOR_SUCCEEDED: u32 = 0x2EB67D;
+const COLOR_FAILED: u32 = 0xE01E5A;
+const COLOR_OTHER: u32 = 0x9E9E9E;
+
+pub struct NotificationChannelsParser;
+impl NotificationChannelsParser {
+    pub fn parse(
+        items: &[AttributeValue],
+    ) -> Result<Vec<NotificationChannel>, Report<MissingModelPropertyError>> {
+        let mut channels = Vec::new();
+        for item in items {
+            channels.push(NotificationChannelsParser::parse_channel(
+                item.as_m().unwrap().to_owned(),
+            )?);
+        }
+
+        Ok(channels)
+    }
+
+    pub fn parse_channel(
+        item: HashMap<String, AttributeValue>,
+    ) -> Result<NotificationChannel, Report<MissingModelPropertyError>> {
+        let provider = match item.get("provider") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("provider"))),
+        };
+
+        let url = match item.get("url") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("url"))),
+        };
+
+        Ok(NotificationChannel { provider, url })
+    }
+}
+
+/// Label and value of every field shown in chat messages
+fn notification_fields(notification: &DeploymentNotification) -> Vec<(&'static str, String)> {
+    let mut fields = vec![
+        ("Project", notification.project.clone()),
+        ("Environment", notification.environment.clone()),
+    ];
+    if let Some(phase) = notification.failed_phase.as_ref() {
+        fields.push(("Failed phase", phase.clone()));
+    }
+    fields.push(("Duration", notification.duration()));
+    fields.push(("Commit", notification.commit()));
+
+    fields
+}
+
+/// Slack incoming-webhook payload, `text` is the fallback for notifications
+pub fn slack_message(notification: &DeploymentNotification) -> Value {
+    let fields: Vec<Value> = notification_fields(notification)
+        .into_iter()
+        .map(|(name, value)| json!({ "type": "mrkdwn", "text": format!("*{}*\n{}", name, value) }))
+        .collect();
+
+    json!({
+        "text": notification.title(),
+        "blocks": [
+            {
+                "type": "header",
+                "text": { "type": "plain_text", "text": notification.title() },
+            },
+            { "type": "section", "fields": fields },
+            {
+                "type": "context",
+                "elements": [
+                    { "type": "mrkdwn", "text": format!("Deployment {}", notification.deployment) },
+                ],
+            },
+        ],
+    })
+}
+
+/// Discord incoming-webhook payload
+pub fn discord_message(notification: &DeploymentNotification) -> Value {
+    let color = match notification.status.parse() {
+        Ok(ProjectDeploymentStatus::Succeeded) => COLOR_SUCCEEDED,
+        Ok(ProjectDeploymentStatus::Failed) => COLOR_FAILED,
+        _ => COLOR_OTHER,
+    };
+    let fields: Vec<Value> = notification_fields(notification)
+        .into_iter()
+        .map(|(name, value)| json!({ "name": name, "value": value, "inline": true }))
+        .collect();
+
+    json!({
+        "embeds": [
+            {
+                "title": notification.title(),
+                "color": color,
+                "fields": fields,
+                "footer": { "text": format!("Deployment {}", notification.deployment) },
+            },
+        ],
+    })
+}
+
+/// Request posting a notification to a chat channel, email and unknown
+/// providers have none
+pub fn notification_request(
+    channel: &NotificationChannel,
+    notification: &DeploymentNotification,
+) -> Option<HttpRequest> {
+    let message = match channel.provider() {
+        NotificationProvider::Slack => slack_message(notification),
+        NotificationProvider::Discord => discord_message(notification),
+        NotificationProvider::Email | NotificationProvider::Unknown => return None,
+    };
+
+    Some(HttpRequest::json(channel.url.clone(), message.to_string()))
+}
+
+pub struct Notifier {
+    sender: Box<dyn HttpSender>,
+}
+impl Notifier {
+    pub fn new(sender: Box<dyn HttpSender>) -> Self {
+        Self { sender }
+    }
+
+    /// Posts a notification to every chat channel and returns how many
+    /// accepted it. Chat messages are best effort, failures are only logged.
+    pub async fn notify(
+        &self,
+        channels: &[NotificationChannel],
+        notification: &DeploymentNotification,
+    ) -> usize {
+        info!(
+            "Notifier::notify - deployment: {}, channels: {}",
+            notification.deployment,
+            channels.len()
+        );
+
+        let mut delivered = 0;
+        for channel in channels {
+            // Email channels are handled by the email sender
+            if channel.provider() == NotificationProvider::Email {
+                continue;
+            }
+            let request = match notification_request(channel, notification) {
+                Some(request) => request,
+                None => {
+                    error!("Notifier::notify - unknown provider: {}", channel.provider);
+                    continue;
+                }
+            };
+            match self.sender.post(request).await {
+                Ok(response) if response.is_success() => delivered += 1,
+                Ok(response) => error!(
+                    "Notifier::notify - {} responded {}: {}",
+                    channel.provider, response.status, response.body
+                ),
+                Err(error) => error!(
+                    "Notifier::notify - {} request failed: {}",
+                    channel.provider, error
+                ),
+            }
+        }
+
+        delivered
+    }
+}
+
+#[cfg(test)]
+mod notifications_tests {
+    use super::*;
+    use crate::handlers::http::ReqwestSender;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    /// Fake incoming-webhook server answering `status` to `requests` requests
+    /// and forwarding every request body
+    fn fake_server(status: u16, requests: usize) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                tx.send(String::from_utf8(body).unwrap()).unwrap();
+                write!(
+                    stream,
+                    "HTTP/1.1 {} OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+                    status
+                )
+                .unwrap();
+            }
+        });
+
+        (url, rx)
+    }
+
+    fn notification() -> DeploymentNotification {
+        DeploymentNotification {
+            project: "my-app".to_string(),
+            environment: "production".to_string(),
+            deployment: "1234".to_string(),
+            status: "FAILED".to_string(),
+            failed_phase: Some("BUILD (FAILED)".to_string()),
+            duration_seconds: Some(125),
+            commit: Some("0123456789abcdef0123456789abcdef01234567".to_string()),
+        }
+    }
+
+    #[test]
+    fn slack_message_uses_block_kit() {
+        let message = slack_message(&notification());
+        assert_eq!(message["text"], "my-app deployment to production failed");
+        assert_eq!(message["blocks"][0]["type"], "header");
+        let fields = message["blocks"][1]["fields"].as_array().unwrap();
+        let texts: Vec<&str> = fields.iter().map(|f| f["text"].as_str().unwrap()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "*Project*\nmy-app",
+                "*Environment*\nproduction",
+                "*Failed phase*\nBUILD (FAILED)",
+                "*Duration*\n2m 05s",
+                "*Commit*\n0123456",
+            ]
+        );
+    }
+
+    #[test]
+    fn discord_message_uses_embeds() {
+        let mut notification = notification();
+        notification.status = "SUCCEEDED".to_string();
+        notification.failed_phase = None;
+        notification.duration_seconds = None;
+        notification.commit = None;
+
+        let message = discord_message(&notification);
+        let embed = &message["embeds"][0];
+        assert_eq!(embed["title"], "my-app deployment to production succeeded");
+        assert_eq!(embed["color"], COLOR_SUCCEEDED);
+        assert_eq!(embed["footer"]["text"], "Deployment 1234");
+        let fields: Vec<(&str, &str)> = embed["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| (f["name"].as_str().unwrap(), f["value"].as_str().unwrap()))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("Project", "my-app"),
+                ("Environment", "production"),
+                ("Duration", "unknown"),
+                ("Commit", "unknown"),
+            ]
+        );
+    }
+
+    #[test]
+    fn validates_channels() {
+        let channel = |provider: &str, url: &str| NotificationChannel {
+            provider: provider.to_string(),
+            url: url.to_string(),
+        };
+        assert!(channel("Slack", "https://hooks.slack.com/services/x")
+            .validate()
+            .is_ok());
+        assert!(channel("discord", "https://discord.com/api/webhooks/x")
+            .validate()
+            .is_ok());
+        assert!(channel("email", "mailto:a@example.com, b@example.com")
+            .validate()
+            .is_ok());
+        assert!(channel("email", "mailto:").validate().is_ok());
+        assert!(channel("email", "https://example.com").validate().is_err());
+        assert!(channel("email", "mailto:nobody").validate().is_err());
+        assert!(channel("teams", "https://example.com").validate().is_err());
+        assert!(channel("slack", "http://example.com").validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn notify_posts_formatted_payloads_to_every_channel() {
+        let (slack_url, slack) = fake_server(200, 1);
+        let (discord_url, discord) = fake_server(200, 1);
+        let channels = vec![
+            NotificationChannel {
+                provider: "slack".to_string(),
+                url: slack_url,
+            },
+            NotificationChannel {
+                provider: "discord".to_string(),
+                url: discord_url,
+            },
+            NotificationChannel {
+                provider: "unknown".to_string(),
+                url: "https://example.com".to_string(),
+            },
+        ];
+
+        let notifier = Notifier::new(Box::new(ReqwestSender::default()));
+        let delivered = notifier.notify(&channels, &notification()).await;
+        assert_eq!(delivered, 2);
+
+        let body: Value = serde_json::from_str(&slack.recv().unwrap()).unwrap();
+        assert_eq!(body, slack_message(&notification()));
+        let body: Value = serde_json::from_str(&discord.recv().unwrap()).unwrap();
+        assert_eq!(body, discord_message(&notification()));
+    }
+
+    #[tokio::test]
+    async fn notify_skips_failed_channels() {
+        let (url, requests) = fake_server(500, 1);
+        let channels = vec![NotificationChannel {
+            provider: "slack".to_string(),
+            url,
+        }];
+
+        let notifier = Notifier::new(Box::new(ReqwestSender::default()));
+        assert_eq!(notifier.notify(&channels, &notification()).await, 0);
+        assert!(requests.recv().is_ok());
+    }
+}
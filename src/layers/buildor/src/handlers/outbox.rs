@@ -0,0 +1,603 @@
+use async_trait::async_trait;
+use aws_sdk_dynamodb::{
+    error::QueryError,
+    model::{AttributeValue, Put, TransactWriteItem},
+    types::SdkError,
+    Client,
+};
+use chrono::{DateTime, Utc};
+use error_stack::Report;
+use log::{self, error, info};
+use std::collections::HashMap;
+use tokio_stream::StreamExt;
+
+use crate::{
+    handlers::{
+        email::DeploymentMailer, notifications::Notifier,
+        project_deployments::ProjectDeploymentsHandler, projects::ProjectsHandler,
+        webhooks::WebhookDispatcher,
+    },
+    models::{
+        common::{AsDynamoDBAttributeValue, MissingModelPropertyError},
+        handlers::{HandlerError, HandlerGet},
+        outbox::{OutboxEntry, OutboxEvent, OutboxStatus},
+        project_deployment::ProjectDeployment,
+        webhook::RetryPolicy,
+    },
+};
+
+/// Index of entries by status, sorted by next attempt date
+pub const INDEX_OUTBOX_STATUS: &str = "status-next_attempt_at";
+
+pub struct OutboxEntryParser {}
+impl OutboxEntryParser {
+    pub fn parse(
+        item: HashMap<String, AttributeValue>,
+    ) -> Result<OutboxEntry, Report<MissingModelPropertyError>> {
+        let uuid = match item.get("uuid") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("uuid"))),
+        };
+
+        let event_type = match item.get("event_type") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("event_type"))),
+        };
+
+        let aggregate = match item.get("aggregate") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("aggregate"))),
+        };
+
+        let payload = match item.get("payload") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("payload"))),
+        };
+
+        let status = match item.get("status") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("status"))),
+        };
+
+        let attempts = match item.get("attempts") {
+            Some(value) => value.as_n().unwrap().parse().unwrap_or(0),
+            None => return Err(Report::new(MissingModelPropertyError::new("attempts"))),
+        };
+
+        let last_error = item
+            .get("last_error")
+            .map(|value| value.as_s().unwrap().to_string());
+
+        let next_attempt_at = match item.get("next_attempt_at") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => {
+                return Err(Report::new(MissingModelPropertyError::new(
+                    "next_attempt_at",
+                )))
+            }
+        };
+
+        let updated_at = match item.get("updated_at") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("updated_at"))),
+        };
+
+        let created_at = match item.get("created_at") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("created_at"))),
+        };
+
+        Ok(OutboxEntry {
+            uuid,
+            event_type,
+            aggregate,
+            payload,
+            status,
+            attempts,
+            last_error,
+            next_attempt_at,
+            updated_at,
+            created_at,
+        })
+    }
+}
+
+pub struct OutboxHandler {
+    table: Client,
+    table_name: String,
+}
+impl OutboxHandler {
+    pub fn new(client: Client, table_name: String) -> Self {
+        Self {
+            table: client,
+            table_name,
+        }
+    }
+
+    /// Write of a new entry, to be sent in the transaction of the change it reports
+    pub fn put(&self, entry: &OutboxEntry) -> TransactWriteItem {
+        TransactWriteItem::builder()
+            .put(
+                Put::builder()
+                    .table_name(&self.table_name)
+                    .set_item(Some(entry.as_hashmap()))
+                    .build(),
+            )
+            .build()
+    }
+
+    pub async fn save(&self, entry: &OutboxEntry) -> Result<(), Report<HandlerError>> {
+        info!(
+            "OutboxHandler::save - uuid: {}, status: {}, attempts: {}",
+            entry.uuid, entry.status, entry.attempts
+        );
+
+        let tx = self
+            .table
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(entry.as_hashmap()));
+
+        info!("OutboxHandler::save - send tx");
+        match tx.send().await {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!("OutboxHandler::save - failed to save entry: {:?}", err);
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+
+    /// Pending entries whose next attempt is due at `now`
+    pub async fn list_due(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<OutboxEntry>, Report<HandlerError>> {
+        info!("OutboxHandler::list_due - now: {}", now);
+        let tx = self
+            .table
+            .query()
+            .table_name(&self.table_name)
+            .index_name(INDEX_OUTBOX_STATUS)
+            .key_condition_expression("#status = :status AND #next_attempt_at <= :now")
+            .expression_attribute_names("#status", "status")
+            .expression_attribute_names("#next_attempt_at", "next_attempt_at")
+            .expression_attribute_values(
+                ":status",
+                AttributeValue::S(OutboxStatus::Pending.to_string()),
+            )
+            .expression_attribute_values(":now", AttributeValue::S(now.to_rfc3339()));
+
+        info!("OutboxHandler::list_due - send tx");
+        let result: Result<Vec<_>, SdkError<QueryError>> =
+            tx.into_paginator().items().send().collect().await;
+        match result {
+            Ok(items) => {
+                let mut data = Vec::new();
+                for item in items {
+                    match OutboxEntryParser::parse(item) {
+                        Ok(parsed) => data.push(parsed),
+                        Err(error) => error!(
+                            "OutboxHandler::list_due - parse error (skip from result): {}",
+                            error
+                        ),
+                    }
+                }
+                Ok(data)
+            }
+            Err(err) => {
+                error!("OutboxHandler::list_due - failed to list entries: {}", err);
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+}
+
+/// Destination of the outbox events, the deployment changes written in the
+/// same transaction as their entry. Record changes of every table are decoded
+/// from the streams by `DomainEventsProcessor`. Events are published at least
+/// once, a sink may see the same event again when a later sink failed.
+#[async_trait]
+pub trait OutboxSink: Send + Sync {
+    fn name(&self) -> &str;
+
+    async fn publish(
+        &self,
+        event: &OutboxEvent,
+        deployment: &ProjectDeployment,
+    ) -> Result<(), Report<HandlerError>>;
+}
+
+/// Records and attempts the webhook deliveries of status changes
+pub struct WebhooksSink {
+    dispatcher: WebhookDispatcher,
+}
+impl WebhooksSink {
+    pub fn new(dispatcher: WebhookDispatcher) -> Self {
+        Self { dispatcher }
+    }
+}
+#[async_trait]
+impl OutboxSink for WebhooksSink {
+    fn name(&self) -> &str {
+        "webhooks"
+    }
+
+    async fn publish(
+        &self,
+        event: &OutboxEvent,
+        deployment: &ProjectDeployment,
+    ) -> Result<(), Report<HandlerError>> {
+        // Failed deliveries are retried on their own, only storage errors fail here
+        if let Some(webhook_event) = event.webhook_event() {
+            let deliveries = self.dispatcher.dispatch(webhook_event, deployment).await?;
+            info!("WebhooksSink::publish - deliveries: {}", deliveries.len());
+        }
+
+        Ok(())
+    }
+}
+
+/// Chat and email notifications of finished deployments
+pub struct NotificationsSink {
+    projects: ProjectsHandler,
+    notifier: Notifier,
+    /// None when no SMTP server is configured
+    mailer: Option<DeploymentMailer>,
+}
+impl NotificationsSink {
+    pub fn new(
+        projects: ProjectsHandler,
+        notifier: Notifier,
+        mailer: Option<DeploymentMailer>,
+    ) -> Self {
+        Self {
+            projects,
+            notifier,
+            mailer,
+        }
+    }
+}
+#[async_trait]
+impl OutboxSink for NotificationsSink {
+    fn name(&self) -> &str {
+        "notifications"
+    }
+
+    async fn publish(
+        &self,
+        event: &OutboxEvent,
+        deployment: &ProjectDeployment,
+    ) -> Result<(), Report<HandlerError>> {
+        let notification = match event.notification(deployment) {
+            Some(notification) => notification,
+            None => return Ok(()),
+        };
+        let project = match self.projects.get(deployment.project.uuid.clone()).await? {
+            Some(project) => project,
+            None => {
+                error!(
+                    "NotificationsSink::publish - project not found: {}",
+                    deployment.project.uuid
+                );
+                return Ok(());
+            }
+        };
+
+        let delivered = self
+            .notifier
+            .notify(&project.notifications, &notification)
+            .await;
+        info!("NotificationsSink::publish - chat delivered: {}", delivered);
+        if let Some(mailer) = self.mailer.as_ref() {
+            let recipients = mailer.notify(&project, deployment, &notification).await?;
+            info!(
+                "NotificationsSink::publish - email recipients: {:?}",
+                recipients
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Publishes outbox entries to every sink and records the outcome on the entry
+pub struct OutboxRelay {
+    outbox: OutboxHandler,
+    deployments: ProjectDeploymentsHandler,
+    sinks: Vec<Box<dyn OutboxSink>>,
+    retry: RetryPolicy,
+}
+impl OutboxRelay {
+    pub fn new(
+        outbox: OutboxHandler,
+        deployments: ProjectDeploymentsHandler,
+        sinks: Vec<Box<dyn OutboxSink>>,
+        retry: RetryPolicy,
+    ) -> Self {
+        Self {
+            outbox,
+            deployments,
+            sinks,
+            retry,
+        }
+    }
+
+    /// Publishes an entry unless it was already handled. Sink failures are
+    /// recorded on the entry for `relay_due`, only storage errors are returned.
+    pub async fn relay(&self, mut entry: OutboxEntry) -> Result<OutboxEntry, Report<HandlerError>> {
+        info!(
+            "OutboxRelay::relay - entry: {}, event: {}, status: {}",
+            entry.uuid, entry.event_type, entry.status
+        );
+        if entry.status() != OutboxStatus::Pending {
+            return Ok(entry);
+        }
+
+        let event = match entry.event() {
+            Ok(event) => event,
+            Err(error) => {
+                error!("OutboxRelay::relay - invalid payload: {}", error);
+                entry.abandon(format!("Invalid payload: {}", error), Utc::now());
+                self.outbox.save(&entry).await?;
+                return Ok(entry);
+            }
+        };
+        let mut deployment = match self.deployments.get(entry.aggregate.clone()).await? {
+            Some(deployment) => deployment,
+            None => {
+                error!(
+                    "OutboxRelay::relay - deployment not found: {}",
+                    entry.aggregate
+                );
+                entry.abandon("Project deployment not found".to_string(), Utc::now());
+                self.outbox.save(&entry).await?;
+                return Ok(entry);
+            }
+        };
+        // Sinks report the deployment as it was when the event happened
+        if let OutboxEvent::DeploymentStatusChanged { status, .. } = &event {
+            deployment.status = status.clone();
+        }
+
+        let mut outcome = Ok(());
+        for sink in self.sinks.iter() {
+            if let Err(error) = sink.publish(&event, &deployment).await {
+                error!(
+                    "OutboxRelay::relay - sink {} failed: {}",
+                    sink.name(),
+                    error
+                );
+                outcome = Err(format!("{}: {}", sink.name(), error));
+                break;
+            }
+        }
+        entry.attempted(outcome, &self.retry, Utc::now());
+        info!(
+            "OutboxRelay::relay - entry: {}, status: {}",
+            entry.uuid, entry.status
+        );
+        self.outbox.save(&entry).await?;
+
+        Ok(entry)
+    }
+
+    /// Publishes again every pending entry whose next attempt is due, including
+    /// the ones the stream relay never picked up
+    pub async fn relay_due(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<OutboxEntry>, Report<HandlerError>> {
+        let mut entries = Vec::new();
+        for entry in self.outbox.list_due(now).await? {
+            entries.push(self.relay(entry).await?);
+        }
+
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod outbox_tests {
+    use super::*;
+    use crate::fixtures::{deployment, project, CREATED_AT};
+    use crate::models::{
+        outbox::BuildOutcome, project_deployment::ProjectDeploymentStatus, webhook::WebhookEvent,
+    };
+    use chrono::Duration;
+    use serde_json::{json, Value};
+
+    fn status_changed(status: ProjectDeploymentStatus, outcome: BuildOutcome) -> OutboxEvent {
+        OutboxEvent::DeploymentStatusChanged {
+            deployment: "deployment".to_string(),
+            status: status.to_string(),
+            outcome,
+        }
+    }
+
+    #[test]
+    fn parse_round_trips_entry() {
+        let now = Utc::now();
+        let mut entry = OutboxEntry::new(
+            &status_changed(ProjectDeploymentStatus::Failed, BuildOutcome::default()),
+            now,
+        );
+        entry.attempted(Err("timeout".to_string()), &RetryPolicy::default(), now);
+
+        let parsed = OutboxEntryParser::parse(entry.as_hashmap()).unwrap();
+        assert_eq!(parsed.uuid, entry.uuid);
+        assert_eq!(parsed.event_type, "DeploymentStatusChanged");
+        assert_eq!(parsed.aggregate, "deployment");
+        assert_eq!(parsed.event().unwrap(), entry.event().unwrap());
+        assert_eq!(parsed.status(), OutboxStatus::Pending);
+        assert_eq!(parsed.attempts, 1);
+        assert_eq!(parsed.last_error, Some("timeout".to_string()));
+        assert_eq!(parsed.next_attempt_at, entry.next_attempt_at);
+
+        let mut item = entry.as_hashmap();
+        item.remove("payload");
+        match OutboxEntryParser::parse(item) {
+            Err(error) => assert!(error.to_string().contains("payload")),
+            _ => assert_eq!("", "Should have panicked but it did not"),
+        }
+    }
+
+    #[test]
+    fn serializes_events_with_their_type() {
+        let event = status_changed(
+            ProjectDeploymentStatus::Failed,
+            BuildOutcome {
+                stopped: false,
+                failed_phase: Some("BUILD (FAILED)".to_string()),
+                duration_seconds: Some(42),
+                commit: None,
+                phases: Vec::new(),
+            },
+        );
+        let value: Value = serde_json::to_value(&event).unwrap();
+        assert_eq!(value["type"], "DeploymentStatusChanged");
+        assert_eq!(value["deployment"], "deployment");
+        assert_eq!(value["status"], "FAILED");
+        assert_eq!(value["outcome"]["failed_phase"], "BUILD (FAILED)");
+
+        // Entries without an outcome are status changes outside of a build
+        let event: OutboxEvent = serde_json::from_value(json!({
+            "type": "DeploymentStatusChanged",
+            "deployment": "deployment",
+            "status": "SUPERSEDED"
+        }))
+        .unwrap();
+        assert_eq!(
+            event,
+            status_changed(ProjectDeploymentStatus::Superseded, BuildOutcome::default())
+        );
+    }
+
+    #[test]
+    fn new_entries_wait_for_the_stream_relay() {
+        let now = Utc::now();
+        let entry = OutboxEntry::new(
+            &OutboxEvent::DeploymentCreated {
+                deployment: "deployment".to_string(),
+            },
+            now,
+        );
+        assert_eq!(entry.status(), OutboxStatus::Pending);
+        assert_eq!(entry.attempts, 0);
+        assert!(entry.next_attempt_at > now.to_rfc3339());
+        assert!(entry.next_attempt_at <= (now + Duration::minutes(1)).to_rfc3339());
+    }
+
+    #[test]
+    fn attempts_back_off_then_fail() {
+        let now = Utc::now();
+        let retry = RetryPolicy::new(2, 60, 3600);
+        let mut entry = OutboxEntry::new(
+            &status_changed(ProjectDeploymentStatus::Succeeded, BuildOutcome::default()),
+            now,
+        );
+
+        entry.attempted(Err("down".to_string()), &retry, now);
+        assert_eq!(entry.status(), OutboxStatus::Pending);
+        assert_eq!(
+            entry.next_attempt_at,
+            (now + Duration::seconds(60)).to_rfc3339()
+        );
+
+        entry.attempted(Err("down".to_string()), &retry, now);
+        assert_eq!(entry.status(), OutboxStatus::Failed);
+        assert_eq!(entry.next_attempt_at, "-");
+        assert_eq!(entry.last_error, Some("down".to_string()));
+
+        let mut entry = OutboxEntry::new(
+            &status_changed(ProjectDeploymentStatus::Succeeded, BuildOutcome::default()),
+            now,
+        );
+        entry.attempted(Ok(()), &retry, now);
+        assert_eq!(entry.status(), OutboxStatus::Published);
+        assert_eq!(entry.next_attempt_at, "-");
+    }
+
+    #[test]
+    fn maps_events_to_webhooks() {
+        let stopped = BuildOutcome {
+            stopped: true,
+            ..BuildOutcome::default()
+        };
+        assert_eq!(
+            status_changed(ProjectDeploymentStatus::InProgress, BuildOutcome::default())
+                .webhook_event(),
+            Some(WebhookEvent::DeploymentStarted)
+        );
+        assert_eq!(
+            status_changed(ProjectDeploymentStatus::Failed, BuildOutcome::default())
+                .webhook_event(),
+            Some(WebhookEvent::DeploymentFailed)
+        );
+        assert_eq!(
+            status_changed(ProjectDeploymentStatus::Failed, stopped).webhook_event(),
+            Some(WebhookEvent::DeploymentCancelled)
+        );
+        assert_eq!(
+            status_changed(ProjectDeploymentStatus::Superseded, BuildOutcome::default())
+                .webhook_event(),
+            None
+        );
+        assert_eq!(
+            OutboxEvent::DeploymentCreated {
+                deployment: "deployment".to_string()
+            }
+            .webhook_event(),
+            None
+        );
+    }
+
+    #[test]
+    fn notifies_finished_deployments_only() {
+        let outcome = BuildOutcome {
+            stopped: false,
+            failed_phase: Some("BUILD (FAILED)".to_string()),
+            duration_seconds: Some(42),
+            commit: Some("abc1234".to_string()),
+            phases: Vec::new(),
+        };
+
+        let failed = deployment(
+            &project(),
+            "production",
+            ProjectDeploymentStatus::Failed,
+            CREATED_AT,
+        );
+        let notification = status_changed(ProjectDeploymentStatus::Failed, outcome.clone())
+            .notification(&failed)
+            .unwrap();
+        assert_eq!(
+            notification.failed_phase,
+            Some("BUILD (FAILED)".to_string())
+        );
+        assert_eq!(notification.duration_seconds, Some(42));
+        assert_eq!(notification.commit, Some("abc1234".to_string()));
+
+        // The failed phase of a previous attempt is never reported on success
+        let succeeded = deployment(
+            &project(),
+            "production",
+            ProjectDeploymentStatus::Succeeded,
+            CREATED_AT,
+        );
+        let notification = status_changed(ProjectDeploymentStatus::Succeeded, outcome.clone())
+            .notification(&succeeded)
+            .unwrap();
+        assert_eq!(notification.failed_phase, None);
+
+        let started = deployment(
+            &project(),
+            "production",
+            ProjectDeploymentStatus::InProgress,
+            CREATED_AT,
+        );
+        assert!(status_changed(ProjectDeploymentStatus::InProgress, outcome)
+            .notification(&started)
+            .is_none());
+    }
+}
@@ -0,0 +1,460 @@
+use aws_sdk_dynamodb::{model::AttributeValue, types::SdkError, Client};
+use chrono::{DateTime, Utc};
+use error_stack::Report;
+use log::{self, error, info};
+use std::collections::HashMap;
+
+use crate::models::{
+    common::{AsDynamoDBAttributeValue, MissingModelPropertyError},
+    handlers::HandlerError,
+    processed_event::ProcessedEvent,
+};
+
+pub struct ProcessedEventParser;
+impl ProcessedEventParser {
+    pub fn parse(
+        item: HashMap<String, AttributeValue>,
+    ) -> Result<ProcessedEvent, Report<MissingModelPropertyError>> {
+        let id = match item.get("id") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("id"))),
+        };
+
+        let source = match item.get("source") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("source"))),
+        };
+
+        // Events recorded before they were claimed were recorded once processed
+        let done = item
+            .get("done")
+            .and_then(|value| value.as_bool().ok())
+            .copied()
+            .unwrap_or(true);
+
+        let locked_until = item
+            .get("locked_until")
+            .and_then(|value| value.as_n().ok())
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0);
+
+        let expires_at = match item.get("expires_at") {
+            Some(value) => value.as_n().unwrap().parse().unwrap_or(0),
+            None => return Err(Report::new(MissingModelPropertyError::new("expires_at"))),
+        };
+
+        let created_at = match item.get("created_at") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("created_at"))),
+        };
+
+        Ok(ProcessedEvent {
+            id,
+            source,
+            done,
+            locked_until,
+            expires_at,
+            created_at,
+        })
+    }
+}
+
+pub struct ProcessedEventsHandler {
+    table: Client,
+    table_name: String,
+}
+impl ProcessedEventsHandler {
+    pub fn new(client: Client, table_name: String) -> Self {
+        Self {
+            table: client,
+            table_name,
+        }
+    }
+
+    /// Claims an event before processing it, expired events not deleted yet
+    /// and events whose lambda lost its lock included. Returns the event that
+    /// holds the id when it is already claimed, done or still in progress.
+    pub async fn claim(
+        &self,
+        event: &ProcessedEvent,
+        now: DateTime<Utc>,
+    ) -> Result<Option<ProcessedEvent>, Report<HandlerError>> {
+        info!(
+            "ProcessedEventsHandler::claim - id: {}, source: {}",
+            event.id, event.source
+        );
+
+        // The holder may expire or be released between the claim and its
+        // read, claim it again then
+        for _ in 0..2 {
+            let tx = self
+                .table
+                .put_item()
+                .table_name(&self.table_name)
+                .set_item(Some(event.as_hashmap()))
+                .condition_expression(
+                    "attribute_not_exists(#id) OR #expires_at <= :now OR (#done = :false AND #locked_until <= :now)",
+                )
+                .expression_attribute_names("#id", "id")
+                .expression_attribute_names("#expires_at", "expires_at")
+                .expression_attribute_names("#done", "done")
+                .expression_attribute_names("#locked_until", "locked_until")
+                .expression_attribute_values(
+                    ":now",
+                    AttributeValue::N(format!("{}", now.timestamp())),
+                )
+                .expression_attribute_values(":false", AttributeValue::Bool(false));
+
+            info!("ProcessedEventsHandler::claim - send tx");
+            match tx.send().await {
+                Ok(_) => return Ok(None),
+                Err(SdkError::ServiceError { err, .. })
+                    if err.is_conditional_check_failed_exception() =>
+                {
+                    info!("ProcessedEventsHandler::claim - event already claimed");
+                    if let Some(holder) = self.get(&event.id).await? {
+                        if holder.expires_at > now.timestamp()
+                            && (holder.done || holder.is_locked(now))
+                        {
+                            return Ok(Some(holder));
+                        }
+                    }
+                }
+                Err(err) => {
+                    error!(
+                        "ProcessedEventsHandler::claim - failed to claim event: {:?}",
+                        err
+                    );
+                    return Err(Report::new(HandlerError::new(&err.to_string())));
+                }
+            }
+        }
+
+        Err(Report::new(HandlerError::new(
+            "Event claimed and released concurrently",
+        )))
+    }
+
+    /// Marks a claimed event done once every side effect was applied, later
+    /// duplicates are acknowledged. Returns `false` when a retry took the event
+    /// over meanwhile.
+    pub async fn complete(&self, claim: &ProcessedEvent) -> Result<bool, Report<HandlerError>> {
+        info!("ProcessedEventsHandler::complete - id: {}", claim.id);
+
+        let tx = self
+            .table
+            .update_item()
+            .table_name(&self.table_name)
+            .key("id", AttributeValue::S(claim.id.to_owned()))
+            .update_expression("SET #done = :true")
+            .condition_expression("#created_at = :created_at")
+            .expression_attribute_names("#done", "done")
+            .expression_attribute_names("#created_at", "created_at")
+            .expression_attribute_values(":true", AttributeValue::Bool(true))
+            .expression_attribute_values(
+                ":created_at",
+                AttributeValue::S(claim.created_at.to_owned()),
+            );
+
+        info!("ProcessedEventsHandler::complete - send tx");
+        match tx.send().await {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                info!("ProcessedEventsHandler::complete - event taken over");
+                Ok(false)
+            }
+            Err(err) => {
+                error!(
+                    "ProcessedEventsHandler::complete - failed to complete event: {:?}",
+                    err
+                );
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+
+    /// Forgets a claimed event whose processing failed so that its retry is
+    /// processed again, unless a retry took it over meanwhile
+    pub async fn release(&self, claim: &ProcessedEvent) -> Result<(), Report<HandlerError>> {
+        info!("ProcessedEventsHandler::release - id: {}", claim.id);
+
+        let tx = self
+            .table
+            .delete_item()
+            .table_name(&self.table_name)
+            .key("id", AttributeValue::S(claim.id.to_owned()))
+            .condition_expression("#created_at = :created_at")
+            .expression_attribute_names("#created_at", "created_at")
+            .expression_attribute_values(
+                ":created_at",
+                AttributeValue::S(claim.created_at.to_owned()),
+            );
+
+        info!("ProcessedEventsHandler::release - send tx");
+        match tx.send().await {
+            Ok(_) => Ok(()),
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                info!("ProcessedEventsHandler::release - event taken over");
+                Ok(())
+            }
+            Err(err) => {
+                error!(
+                    "ProcessedEventsHandler::release - failed to release event: {:?}",
+                    err
+                );
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+
+    /// Reads an event consistently as it is checked right after a claim
+    async fn get(&self, id: &str) -> Result<Option<ProcessedEvent>, Report<HandlerError>> {
+        let tx = self
+            .table
+            .get_item()
+            .table_name(&self.table_name)
+            .key("id", AttributeValue::S(id.to_string()))
+            .consistent_read(true);
+
+        info!("ProcessedEventsHandler::get - send tx");
+        match tx.send().await {
+            Ok(res) => match res.item {
+                Some(item) => ProcessedEventParser::parse(item)
+                    .map(Some)
+                    .map_err(|err| Report::new(HandlerError::new(&err.to_string()))),
+                None => Ok(None),
+            },
+            Err(err) => {
+                error!(
+                    "ProcessedEventsHandler::get - failed to get event: {:?}",
+                    err
+                );
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod processed_events_tests {
+    use super::*;
+    use aws_sdk_dynamodb::{Config, Credentials, Endpoint, Region, RetryConfig};
+    use chrono::{Duration, TimeZone, Utc};
+    use serde_json::json;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    /// Fake DynamoDB answering each request with the next response, forwards
+    /// the operation and body of every request
+    fn fake_dynamodb(responses: Vec<(u16, String)>) -> (Client, mpsc::Receiver<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for (stream, (status, response)) in listener.incoming().zip(responses) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut length = 0;
+                let mut operation = String::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse().unwrap();
+                        }
+                        if name.eq_ignore_ascii_case("x-amz-target") {
+                            operation = value.trim().replace("DynamoDB_20120810.", "");
+                        }
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                tx.send((operation, String::from_utf8(body).unwrap()))
+                    .unwrap();
+                write!(
+                    stream,
+                    "HTTP/1.1 {} OK\r\nContent-Type: application/x-amz-json-1.0\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    response.len(),
+                    response
+                )
+                .unwrap();
+            }
+        });
+
+        let config = Config::builder()
+            .region(Region::new("us-east-1"))
+            .credentials_provider(Credentials::new("key", "secret", None, None, "test"))
+            .endpoint_resolver(Endpoint::immutable(url.parse().unwrap()))
+            .retry_config(RetryConfig::disabled())
+            .build();
+        (Client::from_conf(config), rx)
+    }
+
+    fn condition_failed() -> (u16, String) {
+        (
+            400,
+            json!({
+                "__type": "com.amazonaws.dynamodb.v20120810#ConditionalCheckFailedException",
+                "message": "The conditional request failed",
+            })
+            .to_string(),
+        )
+    }
+
+    fn item(event: &ProcessedEvent) -> (u16, String) {
+        (
+            200,
+            json!({
+                "Item": {
+                    "id": { "S": event.id },
+                    "source": { "S": event.source },
+                    "done": { "BOOL": event.done },
+                    "locked_until": { "N": event.locked_until.to_string() },
+                    "expires_at": { "N": event.expires_at.to_string() },
+                    "created_at": { "S": event.created_at },
+                }
+            })
+            .to_string(),
+        )
+    }
+
+    #[tokio::test]
+    async fn second_claim_loses_to_the_event_in_progress() {
+        let now = Utc::now();
+        let first = ProcessedEvent::new(
+            "id".to_string(),
+            "aws.codebuild".to_string(),
+            now,
+            Duration::hours(48),
+        );
+        let second = ProcessedEvent::new(
+            "id".to_string(),
+            "aws.codebuild".to_string(),
+            now + Duration::seconds(1),
+            Duration::hours(48),
+        );
+        let (client, requests) = fake_dynamodb(vec![
+            (200, "{}".to_string()),
+            condition_failed(),
+            item(&first),
+        ]);
+        let peh = ProcessedEventsHandler::new(client, "events".to_string());
+
+        assert_eq!(peh.claim(&first, now).await.unwrap(), None);
+        let holder = peh
+            .claim(&second, now + Duration::seconds(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!holder.done);
+        assert!(holder.is_locked(now + Duration::seconds(1)));
+        assert_eq!(holder.created_at, first.created_at);
+
+        let (operation, body) = requests.recv().unwrap();
+        assert_eq!(operation, "PutItem");
+        assert!(body.contains("attribute_not_exists(#id)"));
+        assert_eq!(requests.recv().unwrap().0, "PutItem");
+        assert_eq!(requests.recv().unwrap().0, "GetItem");
+    }
+
+    #[tokio::test]
+    async fn claim_takes_over_an_expired_lock() {
+        let now = Utc::now();
+        let mut crashed = ProcessedEvent::new(
+            "id".to_string(),
+            "aws.codebuild".to_string(),
+            now - Duration::minutes(5),
+            Duration::hours(48),
+        );
+        let retry = ProcessedEvent::new(
+            "id".to_string(),
+            "aws.codebuild".to_string(),
+            now,
+            Duration::hours(48),
+        );
+        assert!(!crashed.is_locked(now));
+
+        // The lock of the crashed lambda expired between the claim and its
+        // read, the event is claimed again
+        let (client, _requests) = fake_dynamodb(vec![
+            condition_failed(),
+            item(&crashed),
+            (200, "{}".to_string()),
+        ]);
+        let peh = ProcessedEventsHandler::new(client, "events".to_string());
+        assert_eq!(peh.claim(&retry, now).await.unwrap(), None);
+
+        crashed.done = true;
+        let (client, _requests) = fake_dynamodb(vec![condition_failed(), item(&crashed)]);
+        let peh = ProcessedEventsHandler::new(client, "events".to_string());
+        assert!(peh.claim(&retry, now).await.unwrap().unwrap().done);
+    }
+
+    #[test]
+    fn new_event_expires_after_ttl() {
+        let now = Utc.ymd(2022, 1, 1).and_hms(0, 0, 0);
+        let event = ProcessedEvent::new(
+            "id".to_string(),
+            "aws.codebuild".to_string(),
+            now,
+            Duration::hours(48),
+        );
+        assert_eq!(event.expires_at, now.timestamp() + 48 * 3600);
+        assert_eq!(event.created_at, "2022-01-01T00:00:00+00:00");
+    }
+
+    #[test]
+    fn parse_round_trips_event() {
+        let event = ProcessedEvent::new(
+            "id".to_string(),
+            "aws.codebuild".to_string(),
+            Utc::now(),
+            Duration::hours(1),
+        );
+        assert_eq!(
+            ProcessedEventParser::parse(event.as_hashmap()).unwrap(),
+            event
+        );
+    }
+
+    #[test]
+    fn parses_events_recorded_once_processed_as_done() {
+        let mut item = ProcessedEvent::new(
+            "id".to_string(),
+            "aws.codebuild".to_string(),
+            Utc::now(),
+            Duration::hours(1),
+        )
+        .as_hashmap();
+        item.remove("done");
+        item.remove("locked_until");
+        let event = ProcessedEventParser::parse(item).unwrap();
+        assert!(event.done);
+        assert!(!event.is_locked(Utc::now()));
+    }
+
+    #[test]
+    fn parse_fails_on_missing_expires_at() {
+        let mut item = HashMap::new();
+        item.insert("id".to_string(), AttributeValue::S("id".to_string()));
+        item.insert(
+            "source".to_string(),
+            AttributeValue::S("source".to_string()),
+        );
+        match ProcessedEventParser::parse(item) {
+            Err(error) => assert_eq!(error.to_string(), "Missing model property: expires_at"),
+            _ => assert_eq!("", "Should have panicked but it did not"),
+        }
+    }
+}
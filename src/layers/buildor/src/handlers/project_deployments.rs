@@ -1,20 +1,37 @@
 use async_trait::async_trait;
 use aws_sdk_dynamodb::{
-    model::{AttributeValue, ReturnConsumedCapacity, ReturnItemCollectionMetrics, ReturnValue},
+    error::{QueryError, TransactWriteItemsError, TransactWriteItemsErrorKind},
+    model::{
+        AttributeValue, Put, ReturnConsumedCapacity, ReturnItemCollectionMetrics, ReturnValue,
+        TransactWriteItem, Update,
+    },
+    types::SdkError,
     Client,
 };
+use chrono::Utc;
 use error_stack::Report;
 use log::{self, error, info};
 use serde_json::{json, Value};
 use std::collections::HashMap;
+use tokio_stream::StreamExt;
 
 use crate::{
-    handlers::{codebuild::BuildInfoParser, projects::ProjectParser},
+    handlers::{
+        artifacts::ArtifactSummaryParser, codebuild::BuildInfoParser,
+        deployment_queue::ConcurrencyLimits, deployment_slots::DeploymentSlotsHandler,
+        outbox::OutboxHandler, projects::ProjectParser,
+    },
     models::{
+        codebuild::BuildInfo,
         common::{AsDynamoDBAttributeValue, MissingModelPropertyError},
-        handlers::{HandlerCreate, HandlerError, HandlerGet, HandlerUpdate},
+        environment::DEFAULT_ENVIRONMENT_NAME,
+        handlers::{
+            HandlerCreate, HandlerError, HandlerGet, HandlerUpdate, HandlerUpdateExpressions,
+        },
+        outbox::{BuildOutcome, OutboxEntry, OutboxEvent},
         project_deployment::{
-            ProjectDeployment, ProjectDeploymentCreatePayload, ProjectDeploymentUpdatePayload,
+            ProjectDeployment, ProjectDeploymentCreatePayload, ProjectDeploymentStatus,
+            ProjectDeploymentUpdatePayload,
         },
     },
 };
@@ -39,14 +56,45 @@ impl ProjectDeploymentParser {
             None => return Err(Report::new(MissingModelPropertyError::new("project"))),
         };
 
+        // Deployments created before environments were introduced always
+        // targeted the default environment
+        let environment = match item.get("environment") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => DEFAULT_ENVIRONMENT_NAME.to_string(),
+        };
+
+        // Deployments created before the queue existed have no status
+        let status = match item.get("status") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => ProjectDeploymentStatus::Unknown.to_string(),
+        };
+
+        // Queued deployments have no build yet
         let build = match item.get("build") {
             Some(value) => match BuildInfoParser::parse(value.as_m().unwrap().to_owned()) {
-                Ok(value) => value,
+                Ok(value) => Some(value),
                 Err(_) => {
                     return Err(Report::new(MissingModelPropertyError::new("build")));
                 }
             },
-            None => return Err(Report::new(MissingModelPropertyError::new("build"))),
+            None => None,
+        };
+
+        // Only successful deployments have an artifact
+        let artifact = match item.get("artifact") {
+            Some(value) => match ArtifactSummaryParser::parse(value.as_m().unwrap().to_owned()) {
+                Ok(value) => Some(value),
+                Err(error) => {
+                    return Err(error.change_context(MissingModelPropertyError::new("artifact")))
+                }
+            },
+            None => None,
+        };
+
+        // Deployments created before retention policies were introduced are not pinned
+        let pinned = match item.get("pinned") {
+            Some(value) => *value.as_bool().unwrap(),
+            None => false,
         };
 
         let updated_at = match item.get("updated_at") {
@@ -62,7 +110,11 @@ impl ProjectDeploymentParser {
         Ok(ProjectDeployment {
             uuid,
             project,
+            environment,
+            status,
             build,
+            artifact,
+            pinned,
             updated_at,
             created_at,
         })
@@ -78,17 +130,626 @@ impl ProjectDeploymentParser {
     }
 }
 
+/// Index by project, sorted by creation date
+pub const INDEX_PROJECT: &str = "project_uuid-created_at";
+/// Index by deployment status, sorted by creation date
+pub const INDEX_STATUS: &str = "status-created_at";
+/// Index by codebuild build id
+pub const INDEX_BUILD: &str = "build_uuid";
+
+/// Outcome of claiming a queued deployment
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DeploymentClaim {
+    Claimed,
+    /// The deployment is no longer queued, claimed by someone else first
+    Taken,
+    /// The project or the account has no free slot
+    Full,
+}
+
+/// Outcome of a transaction, cancelled ones tell the indexes of the items
+/// whose condition was not met
+enum Transacted {
+    Committed,
+    Cancelled(Vec<usize>),
+}
+
 pub struct ProjectDeploymentsHandler {
     table: Client,
     table_name: String,
+    outbox: Option<OutboxHandler>,
+    slots: Option<DeploymentSlotsHandler>,
 }
 impl ProjectDeploymentsHandler {
     pub fn new(client: Client, table_name: String) -> Self {
         Self {
             table: client,
             table_name,
+            outbox: None,
+            slots: None,
+        }
+    }
+
+    /// Writes an outbox entry in the same transaction as every created
+    /// deployment and status change
+    pub fn with_outbox(mut self, table_name: String) -> Self {
+        self.outbox = Some(OutboxHandler::new(self.table.clone(), table_name));
+        self
+    }
+
+    /// Takes a build slot of the project and of the account in the transaction
+    /// claiming a deployment, and gives it back when the build ends
+    pub fn with_slots(mut self, table_name: String) -> Self {
+        self.slots = Some(DeploymentSlotsHandler::new(table_name));
+        self
+    }
+
+    /// Sends changes in one transaction, with the outbox entry of `event` when
+    /// the handler has an outbox
+    async fn transact(
+        &self,
+        method: &str,
+        changes: Vec<TransactWriteItem>,
+        event: OutboxEvent,
+    ) -> Result<Transacted, Report<HandlerError>> {
+        let mut tx = self
+            .table
+            .transact_write_items()
+            .set_transact_items(Some(changes));
+        if let Some(outbox) = self.outbox.as_ref() {
+            let entry = OutboxEntry::new(&event, Utc::now());
+            info!(
+                "ProjectDeploymentsHandler::{} - outbox entry: {}, event: {}",
+                method, entry.uuid, entry.event_type
+            );
+            tx = tx.transact_items(outbox.put(&entry));
+        }
+
+        info!("ProjectDeploymentsHandler::{} - send transaction", method);
+        match tx.send().await {
+            Ok(_) => Ok(Transacted::Committed),
+            Err(SdkError::ServiceError { err, .. }) if condition_failed(&err) => {
+                info!(
+                    "ProjectDeploymentsHandler::{} - transaction condition not met",
+                    method
+                );
+                Ok(Transacted::Cancelled(failed_conditions(&err)))
+            }
+            Err(err) => {
+                error!(
+                    "ProjectDeploymentsHandler::{} - transaction failed: {:?}",
+                    method, err
+                );
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
         }
     }
+
+    pub async fn list_by_project(
+        &self,
+        project_uuid: String,
+        status: Option<ProjectDeploymentStatus>,
+    ) -> Result<Vec<ProjectDeployment>, Report<HandlerError>> {
+        info!(
+            "ProjectDeploymentsHandler::list_by_project - project: {}, status: {:?}",
+            project_uuid, status
+        );
+        let mut data: Vec<ProjectDeployment> = Vec::new();
+
+        let mut tx = self
+            .table
+            .query()
+            .table_name(&self.table_name)
+            .index_name(INDEX_PROJECT)
+            .key_condition_expression("#project_uuid = :project_uuid")
+            .expression_attribute_names("#project_uuid", "project_uuid")
+            .expression_attribute_values(":project_uuid", AttributeValue::S(project_uuid))
+            .scan_index_forward(true);
+        if let Some(status) = status {
+            tx = tx
+                .filter_expression("#status = :status")
+                .expression_attribute_names("#status", "status")
+                .expression_attribute_values(":status", AttributeValue::S(status.to_string()));
+        }
+
+        info!("ProjectDeploymentsHandler::list_by_project - send tx");
+        let result: Result<Vec<_>, SdkError<QueryError>> =
+            tx.into_paginator().items().send().collect().await;
+
+        match result {
+            Ok(items) => {
+                for item in items {
+                    match ProjectDeploymentParser::parse(item) {
+                        Ok(parsed) => data.push(parsed),
+                        Err(error) => error!(
+                            "ProjectDeploymentsHandler::list_by_project - parse error (skip from result): {}",
+                            error
+                        ),
+                    }
+                }
+                Ok(data)
+            }
+            Err(err) => {
+                error!(
+                    "ProjectDeploymentsHandler::list_by_project - failed to list project deployments: {}",
+                    err
+                );
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+
+    pub async fn list_by_status(
+        &self,
+        status: ProjectDeploymentStatus,
+    ) -> Result<Vec<ProjectDeployment>, Report<HandlerError>> {
+        info!(
+            "ProjectDeploymentsHandler::list_by_status - status: {}",
+            status
+        );
+        let mut data: Vec<ProjectDeployment> = Vec::new();
+
+        let tx = self
+            .table
+            .query()
+            .table_name(&self.table_name)
+            .index_name(INDEX_STATUS)
+            .key_condition_expression("#status = :status")
+            .expression_attribute_names("#status", "status")
+            .expression_attribute_values(":status", AttributeValue::S(status.to_string()))
+            .scan_index_forward(true);
+
+        info!("ProjectDeploymentsHandler::list_by_status - send tx");
+        let result: Result<Vec<_>, SdkError<QueryError>> =
+            tx.into_paginator().items().send().collect().await;
+
+        match result {
+            Ok(items) => {
+                for item in items {
+                    match ProjectDeploymentParser::parse(item) {
+                        Ok(parsed) => data.push(parsed),
+                        Err(error) => error!(
+                            "ProjectDeploymentsHandler::list_by_status - parse error (skip from result): {}",
+                            error
+                        ),
+                    }
+                }
+                Ok(data)
+            }
+            Err(err) => {
+                error!(
+                    "ProjectDeploymentsHandler::list_by_status - failed to list project deployments: {}",
+                    err
+                );
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+
+    /// Deployment of a build, deployments created before the queue existed
+    /// have no `build_uuid` and use the build id as their uuid
+    pub async fn find_by_build(
+        &self,
+        build_uuid: String,
+    ) -> Result<Option<ProjectDeployment>, Report<HandlerError>> {
+        info!(
+            "ProjectDeploymentsHandler::find_by_build - build: {}",
+            build_uuid
+        );
+
+        let tx = self
+            .table
+            .query()
+            .table_name(&self.table_name)
+            .index_name(INDEX_BUILD)
+            .key_condition_expression("#build_uuid = :build_uuid")
+            .expression_attribute_names("#build_uuid", "build_uuid")
+            .expression_attribute_values(":build_uuid", AttributeValue::S(build_uuid.clone()))
+            .limit(1);
+
+        info!("ProjectDeploymentsHandler::find_by_build - send tx");
+        match tx.send().await {
+            Ok(res) => {
+                match res.items.unwrap_or_default().pop() {
+                    Some(item) => match ProjectDeploymentParser::parse(item) {
+                        Ok(deployment) => Ok(Some(deployment)),
+                        Err(error) => {
+                            error!("ProjectDeploymentsHandler::find_by_build - failed to parse project deployment: {}", error);
+                            Ok(None)
+                        }
+                    },
+                    None => {
+                        info!("ProjectDeploymentsHandler::find_by_build - not indexed, get by build id");
+                        self.get(build_uuid).await
+                    }
+                }
+            }
+            Err(err) => {
+                error!(
+                    "ProjectDeploymentsHandler::find_by_build - failed to find project deployment: {:?}",
+                    err
+                );
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+
+    /// Updates a deployment, only while it was last updated at `updated_at`
+    /// when set. Returns `false` when the deployment is missing or changed
+    /// meanwhile.
+    pub async fn update_if_unmodified(
+        &self,
+        uuid: String,
+        payload: ProjectDeploymentUpdatePayload,
+        updated_at: Option<String>,
+    ) -> Result<bool, Report<HandlerError>> {
+        info!(
+            "ProjectDeploymentsHandler::update_if_unmodified - uuid: {}, updated_at: {:?}",
+            uuid, updated_at
+        );
+        let expressions = self.get_update_expressions(payload);
+
+        // Never recreate a deleted deployment from the updated fields only
+        let mut tx = self
+            .table
+            .update_item()
+            .table_name(&self.table_name)
+            .key("uuid".to_string(), AttributeValue::S(uuid))
+            .set_expression_attribute_names(Some(expressions.attribute_names))
+            .set_expression_attribute_values(Some(expressions.attribute_values))
+            .expression_attribute_names("#uuid", "uuid")
+            .condition_expression("attribute_exists(#uuid)")
+            .update_expression(expressions.update_expression);
+        if let Some(updated_at) = updated_at {
+            tx = tx
+                .condition_expression("attribute_exists(#uuid) AND #updated_at = :read_updated_at")
+                .expression_attribute_values(":read_updated_at", AttributeValue::S(updated_at));
+        }
+
+        info!("ProjectDeploymentsHandler::update_if_unmodified - send tx");
+        match tx.send().await {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                info!("ProjectDeploymentsHandler::update_if_unmodified - deployment missing or modified");
+                Ok(false)
+            }
+            Err(err) => {
+                error!(
+                    "ProjectDeploymentsHandler::update_if_unmodified - failed to update project deployment: {:?}",
+                    err
+                );
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+
+    /// Records the build details of a deployment only if it exists and its
+    /// current status is one of `from`, late events of a build that ended must
+    /// not recreate nor overwrite its deployment. Returns `false` otherwise.
+    pub async fn update_build(
+        &self,
+        uuid: String,
+        from: &[ProjectDeploymentStatus],
+        build: BuildInfo,
+    ) -> Result<bool, Report<HandlerError>> {
+        info!(
+            "ProjectDeploymentsHandler::update_build - uuid: {}, from: {:?}",
+            uuid, from
+        );
+        let mut expressions = self.get_update_expressions(ProjectDeploymentUpdatePayload {
+            project: None,
+            status: None,
+            build: Some(build),
+            artifact: None,
+            pinned: None,
+        });
+        let mut condition = Vec::new();
+        for (index, status) in from.iter().enumerate() {
+            condition.push(format!(":from{}", index));
+            expressions.attribute_values.insert(
+                format!(":from{}", index),
+                AttributeValue::S(status.to_string()),
+            );
+        }
+        expressions
+            .attribute_names
+            .insert("#uuid".to_string(), "uuid".to_string());
+        expressions
+            .attribute_names
+            .insert("#status".to_string(), "status".to_string());
+
+        let tx = self
+            .table
+            .update_item()
+            .table_name(&self.table_name)
+            .key("uuid".to_string(), AttributeValue::S(uuid))
+            .condition_expression(format!(
+                "attribute_exists(#uuid) AND #status IN ({})",
+                condition.join(", ")
+            ))
+            .set_expression_attribute_names(Some(expressions.attribute_names))
+            .set_expression_attribute_values(Some(expressions.attribute_values))
+            .update_expression(expressions.update_expression);
+
+        info!("ProjectDeploymentsHandler::update_build - send tx");
+        match tx.send().await {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                info!("ProjectDeploymentsHandler::update_build - status precondition not met");
+                Ok(false)
+            }
+            Err(err) => {
+                error!(
+                    "ProjectDeploymentsHandler::update_build - failed to update project deployment: {:?}",
+                    err
+                );
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+
+    /// Moves a deployment to `to` only if its current status is one of `from`.
+    /// Returns `false` when the deployment was not in any of the expected statuses.
+    pub async fn transition(
+        &self,
+        uuid: String,
+        from: &[ProjectDeploymentStatus],
+        to: ProjectDeploymentStatus,
+        build: Option<BuildInfo>,
+    ) -> Result<bool, Report<HandlerError>> {
+        self.transition_with_outcome(uuid, from, to, build, BuildOutcome::default())
+            .await
+    }
+
+    /// Same as `transition`, with the build details reported by the outbox event
+    pub async fn transition_with_outcome(
+        &self,
+        uuid: String,
+        from: &[ProjectDeploymentStatus],
+        to: ProjectDeploymentStatus,
+        build: Option<BuildInfo>,
+        outcome: BuildOutcome,
+    ) -> Result<bool, Report<HandlerError>> {
+        info!(
+            "ProjectDeploymentsHandler::transition - uuid: {}, from: {:?}, to: {}",
+            uuid, from, to
+        );
+        let event = OutboxEvent::DeploymentStatusChanged {
+            deployment: uuid.clone(),
+            status: to.to_string(),
+            outcome,
+        };
+
+        // Builds that end give their slot back, deployments started before
+        // slots existed hold none
+        if let Some(slots) = self.slots.as_ref() {
+            if from.contains(&ProjectDeploymentStatus::InProgress) && to.is_terminal() {
+                if let Some(project_uuid) = self.slot_holder(&uuid).await? {
+                    let mut expressions = self.status_expressions(to, build.clone());
+                    expressions
+                        .attribute_names
+                        .insert("#slot".to_string(), "slot".to_string());
+                    expressions.attribute_values.insert(
+                        ":in_progress".to_string(),
+                        AttributeValue::S(ProjectDeploymentStatus::InProgress.to_string()),
+                    );
+                    expressions.update_expression.push_str(" REMOVE #slot");
+                    let mut changes = vec![self.conditional_update(
+                        &uuid,
+                        "#status = :in_progress AND attribute_exists(#slot)".to_string(),
+                        expressions,
+                    )];
+                    changes.append(&mut slots.give_back(&project_uuid));
+                    if let Transacted::Committed =
+                        self.transact("transition", changes, event.clone()).await?
+                    {
+                        return Ok(true);
+                    }
+                }
+            }
+        }
+
+        let mut expressions = self.status_expressions(to, build);
+        let mut condition = Vec::new();
+        for (index, status) in from.iter().enumerate() {
+            condition.push(format!(":from{}", index));
+            expressions.attribute_values.insert(
+                format!(":from{}", index),
+                AttributeValue::S(status.to_string()),
+            );
+        }
+        let condition = format!("#status IN ({})", condition.join(", "));
+
+        if self.outbox.is_some() {
+            let change = self.conditional_update(&uuid, condition, expressions);
+            return match self.transact("transition", vec![change], event).await? {
+                Transacted::Committed => Ok(true),
+                Transacted::Cancelled(_) => Ok(false),
+            };
+        }
+
+        let tx = self
+            .table
+            .update_item()
+            .table_name(&self.table_name)
+            .key("uuid".to_string(), AttributeValue::S(uuid))
+            .condition_expression(condition)
+            .set_expression_attribute_names(Some(expressions.attribute_names))
+            .set_expression_attribute_values(Some(expressions.attribute_values))
+            .update_expression(expressions.update_expression);
+
+        info!("ProjectDeploymentsHandler::transition - send tx");
+        match tx.send().await {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                info!("ProjectDeploymentsHandler::transition - status precondition not met");
+                Ok(false)
+            }
+            Err(err) => {
+                error!(
+                    "ProjectDeploymentsHandler::transition - failed to update project deployment: {:?}",
+                    err
+                );
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+
+    /// Moves a queued deployment to `IN_PROGRESS`, taking a slot of its project
+    /// and of the account in the same transaction when the handler has slots
+    pub async fn claim(
+        &self,
+        deployment: &ProjectDeployment,
+        limits: &ConcurrencyLimits,
+    ) -> Result<DeploymentClaim, Report<HandlerError>> {
+        info!(
+            "ProjectDeploymentsHandler::claim - uuid: {}, limits: {:?}",
+            deployment.uuid, limits
+        );
+        let slots = match self.slots.as_ref() {
+            Some(slots) => slots,
+            None => {
+                return match self
+                    .transition(
+                        deployment.uuid.clone(),
+                        &[ProjectDeploymentStatus::Queued],
+                        ProjectDeploymentStatus::InProgress,
+                        None,
+                    )
+                    .await?
+                {
+                    true => Ok(DeploymentClaim::Claimed),
+                    false => Ok(DeploymentClaim::Taken),
+                }
+            }
+        };
+
+        // The slot is remembered so that only builds holding one give it back
+        let mut expressions = self.status_expressions(ProjectDeploymentStatus::InProgress, None);
+        expressions
+            .attribute_names
+            .insert("#slot".to_string(), "slot".to_string());
+        expressions.attribute_values.insert(
+            ":slot".to_string(),
+            AttributeValue::S(deployment.project.uuid.to_owned()),
+        );
+        expressions.attribute_values.insert(
+            ":queued".to_string(),
+            AttributeValue::S(ProjectDeploymentStatus::Queued.to_string()),
+        );
+        expressions.update_expression.push_str(", #slot = :slot");
+        let mut changes = vec![self.conditional_update(
+            &deployment.uuid,
+            "#status = :queued".to_string(),
+            expressions,
+        )];
+        changes.append(&mut slots.take(&deployment.project.uuid, limits));
+
+        let event = OutboxEvent::DeploymentStatusChanged {
+            deployment: deployment.uuid.clone(),
+            status: ProjectDeploymentStatus::InProgress.to_string(),
+            outcome: BuildOutcome::default(),
+        };
+        match self.transact("claim", changes, event).await? {
+            Transacted::Committed => Ok(DeploymentClaim::Claimed),
+            Transacted::Cancelled(failed) if failed.contains(&0) => Ok(DeploymentClaim::Taken),
+            Transacted::Cancelled(_) => Ok(DeploymentClaim::Full),
+        }
+    }
+
+    /// Project of the slot held by a deployment, none when it holds no slot
+    async fn slot_holder(&self, uuid: &str) -> Result<Option<String>, Report<HandlerError>> {
+        let tx = self
+            .table
+            .get_item()
+            .table_name(&self.table_name)
+            .key("uuid".to_string(), AttributeValue::S(uuid.to_string()))
+            .projection_expression("#slot")
+            .expression_attribute_names("#slot", "slot")
+            .consistent_read(true);
+
+        info!("ProjectDeploymentsHandler::slot_holder - send tx");
+        match tx.send().await {
+            Ok(res) => Ok(res
+                .item
+                .and_then(|item| item.get("slot").cloned())
+                .and_then(|value| value.as_s().ok().cloned())),
+            Err(err) => {
+                error!(
+                    "ProjectDeploymentsHandler::slot_holder - failed to get project deployment: {:?}",
+                    err
+                );
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+
+    fn status_expressions(
+        &self,
+        to: ProjectDeploymentStatus,
+        build: Option<BuildInfo>,
+    ) -> HandlerUpdateExpressions {
+        self.get_update_expressions(ProjectDeploymentUpdatePayload {
+            project: None,
+            status: Some(to.to_string()),
+            build,
+            artifact: None,
+            pinned: None,
+        })
+    }
+
+    fn conditional_update(
+        &self,
+        uuid: &str,
+        condition: String,
+        expressions: HandlerUpdateExpressions,
+    ) -> TransactWriteItem {
+        TransactWriteItem::builder()
+            .update(
+                Update::builder()
+                    .table_name(&self.table_name)
+                    .key("uuid".to_string(), AttributeValue::S(uuid.to_string()))
+                    .condition_expression(condition)
+                    .set_expression_attribute_names(Some(expressions.attribute_names))
+                    .set_expression_attribute_values(Some(expressions.attribute_values))
+                    .update_expression(expressions.update_expression)
+                    .build(),
+            )
+            .build()
+    }
+}
+
+/// Whether a transaction was cancelled by a condition of one of its items
+pub(crate) fn condition_failed(err: &TransactWriteItemsError) -> bool {
+    match &err.kind {
+        TransactWriteItemsErrorKind::TransactionCanceledException(cancelled) => cancelled
+            .cancellation_reasons()
+            .unwrap_or_default()
+            .iter()
+            .any(|reason| reason.code() == Some("ConditionalCheckFailed")),
+        _ => false,
+    }
+}
+
+/// Indexes of the transaction items cancelled by their condition
+fn failed_conditions(err: &TransactWriteItemsError) -> Vec<usize> {
+    match &err.kind {
+        TransactWriteItemsErrorKind::TransactionCanceledException(cancelled) => cancelled
+            .cancellation_reasons()
+            .unwrap_or_default()
+            .iter()
+            .enumerate()
+            .filter(|(_, reason)| reason.code() == Some("ConditionalCheckFailed"))
+            .map(|(index, _)| index)
+            .collect(),
+        _ => Vec::new(),
+    }
 }
 
 #[async_trait]
@@ -100,7 +761,27 @@ impl HandlerCreate<ProjectDeployment, ProjectDeploymentCreatePayload, HandlerErr
         payload: ProjectDeploymentCreatePayload,
     ) -> Result<ProjectDeployment, Report<HandlerError>> {
         info!("ProjectDeploymentsHandler::create - payload: {:?}", payload);
-        let project_deployment = ProjectDeployment::new(payload.project, payload.build);
+        let project_deployment = ProjectDeployment::new(payload);
+
+        if self.outbox.is_some() {
+            let change = TransactWriteItem::builder()
+                .put(
+                    Put::builder()
+                        .table_name(&self.table_name)
+                        .set_item(Some(project_deployment.as_hashmap()))
+                        .build(),
+                )
+                .build();
+            let event = OutboxEvent::DeploymentCreated {
+                deployment: project_deployment.uuid.clone(),
+            };
+            return match self.transact("create", vec![change], event).await? {
+                Transacted::Committed => Ok(project_deployment),
+                Transacted::Cancelled(_) => Err(Report::new(HandlerError::new(
+                    "Project deployment creation cancelled",
+                ))),
+            };
+        }
 
         let tx = self
             .table
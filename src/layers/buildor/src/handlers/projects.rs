@@ -1,15 +1,29 @@
 use async_trait::async_trait;
 use aws_sdk_dynamodb::{error::ScanError, model::AttributeValue, types::SdkError, Client};
+use chrono::Utc;
 use error_stack::Report;
 use log::{self, error, info};
 use serde_json::{json, Value};
-use std::collections::HashMap;
+use std::{collections::HashMap, str::FromStr};
 use tokio_stream::StreamExt;
 
-use crate::handlers::commands::CommandsParser;
+use crate::handlers::{
+    commands::CommandsParser, environments::EnvironmentsParser,
+    notifications::NotificationChannelsParser,
+};
+use crate::models::codebuild::ComputeSize;
 use crate::models::common::{AsDynamoDBAttributeValue, MissingModelPropertyError};
-use crate::models::handlers::{HandlerCreate, HandlerError, HandlerGet, HandlerList};
-use crate::models::project::{Project, ProjectCreatePayload};
+use crate::models::environment::Environment;
+use crate::models::handlers::{
+    HandlerCreate, HandlerDelete, HandlerError, HandlerGet, HandlerList, HandlerUpdate,
+};
+use crate::models::list_query::ListQuery;
+use crate::models::project::{
+    Project, ProjectCreatePayload, ProjectUpdatePayload, DEFAULT_BUILD_TIMEOUT_MINUTES,
+};
+use crate::models::retention::{
+    RetentionPolicy, DEFAULT_RETENTION_EXPIRE_AFTER_DAYS, DEFAULT_RETENTION_KEEP_LAST,
+};
 
 pub struct ProjectParser {}
 impl ProjectParser {
@@ -31,6 +45,12 @@ impl ProjectParser {
             None => return Err(Report::new(MissingModelPropertyError::new("repository"))),
         };
 
+        // Projects created before owners were introduced have none
+        let owner = match item.get("owner") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => "-".to_string(),
+        };
+
         let commands = match item.get("commands") {
             Some(value) => match CommandsParser::parse(value.as_m().unwrap().to_owned()) {
                 Ok(value) => value,
@@ -46,6 +66,72 @@ impl ProjectParser {
             None => return Err(Report::new(MissingModelPropertyError::new("output_folder"))),
         };
 
+        // Projects created before environments were introduced only had the
+        // implicit default environment
+        let environments = match item.get("environments") {
+            Some(value) => match EnvironmentsParser::parse(value.as_m().unwrap().to_owned()) {
+                Ok(value) => value,
+                Err(error) => {
+                    return Err(error.change_context(MissingModelPropertyError::new("environments")))
+                }
+            },
+            None => Environment::defaults(),
+        };
+
+        // Projects created before build settings were introduced used the defaults
+        let build_timeout_minutes = match item.get("build_timeout_minutes") {
+            Some(value) => match value.as_n().ok().and_then(|value| value.parse().ok()) {
+                Some(value) => value,
+                None => {
+                    return Err(Report::new(MissingModelPropertyError::new(
+                        "build_timeout_minutes",
+                    )))
+                }
+            },
+            None => DEFAULT_BUILD_TIMEOUT_MINUTES,
+        };
+
+        let compute_size = match item.get("compute_size") {
+            Some(value) => match value
+                .as_s()
+                .ok()
+                .and_then(|value| ComputeSize::from_str(value).ok())
+            {
+                Some(value) => value.to_string(),
+                None => return Err(Report::new(MissingModelPropertyError::new("compute_size"))),
+            },
+            None => ComputeSize::Small.to_string(),
+        };
+
+        // Projects created before retention policies were introduced use the defaults
+        let retention = match item.get("retention") {
+            Some(value) => {
+                let policy = value.as_m().unwrap();
+                let number = |name: &str, default: i32| match policy.get(name) {
+                    Some(value) => value.as_n().unwrap().parse().unwrap_or(default),
+                    None => default,
+                };
+                RetentionPolicy::new(
+                    number("keep_last", DEFAULT_RETENTION_KEEP_LAST),
+                    number("expire_after_days", DEFAULT_RETENTION_EXPIRE_AFTER_DAYS),
+                )
+            }
+            None => RetentionPolicy::defaults(),
+        };
+
+        // Projects created before notifications were introduced have no channels
+        let notifications = match item.get("notifications") {
+            Some(value) => match NotificationChannelsParser::parse(value.as_l().unwrap()) {
+                Ok(value) => value,
+                Err(error) => {
+                    return Err(
+                        error.change_context(MissingModelPropertyError::new("notifications"))
+                    )
+                }
+            },
+            None => Vec::new(),
+        };
+
         let last_published = match item.get("last_published") {
             Some(value) => value.as_s().unwrap().to_string(),
             None => {
@@ -69,8 +155,14 @@ impl ProjectParser {
             uuid,
             name,
             repository,
+            owner,
             commands,
             output_folder,
+            environments,
+            build_timeout_minutes,
+            compute_size,
+            retention,
+            notifications,
             last_published,
             updated_at,
             created_at,
@@ -87,6 +179,308 @@ impl ProjectParser {
     }
 }
 
+pub struct ProjectsHandler {
+    table: Client,
+    table_name: String,
+}
+impl ProjectsHandler {
+    pub fn new(client: Client, table_name: String) -> Self {
+        Self {
+            table: client,
+            table_name,
+        }
+    }
+}
+
+#[async_trait]
+impl HandlerCreate<Project, ProjectCreatePayload, HandlerError> for ProjectsHandler {
+    async fn create(&self, payload: ProjectCreatePayload) -> Result<Project, Report<HandlerError>> {
+        info!("ProjectsHandler::create - payload: {:?}", payload);
+        let project = Project::new(payload);
+
+        let tx = self
+            .table
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(project.as_hashmap()));
+
+        info!("ProjectsHandler::create - send tx");
+        let result = tx.send().await;
+        info!("ProjectsHandler::create - tx response: {:?}", result);
+
+        match result {
+            Ok(res) => {
+                info!("ProjectsHandler::create - new user created: {:?}", res);
+                Ok(project)
+            }
+            Err(err) => {
+                error!("ProjectsHandler::create - failed to create user: {:?}", err);
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+}
+#[async_trait]
+impl HandlerList<Project, HandlerError> for ProjectsHandler {
+    async fn list(&self) -> Result<Vec<Project>, Report<HandlerError>> {
+        let mut data: Vec<Project> = Vec::new();
+
+        info!("ProjectsHandler::list - preparing query to list projects");
+        let tx = self
+            .table
+            .scan()
+            .table_name(&self.table_name)
+            .into_paginator()
+            .items();
+        info!("ProjectsHandler::list - send tx");
+        let result: Result<Vec<_>, SdkError<ScanError>> = tx.send().collect().await;
+        info!("ProjectsHandler::list - tx response: {:?}", result);
+
+        match result {
+            Ok(res) => {
+                info!("ProjectsHandler::list - parse projects");
+                for item in res {
+                    info!("ProjectParser::list - parse record: {:?}", &item);
+                    match ProjectParser::parse(item) {
+                        Ok(parsed) => {
+                            info!("ProjectsHandler::list - project: {:?}", parsed);
+                            data.push(parsed);
+                        }
+                        Err(error) => {
+                            error!(
+                                "ProjectParser::list - parse error (skip from result): {}",
+                                error
+                            )
+                        }
+                    };
+                }
+            }
+            Err(err) => {
+                error!("ProjectsHandler::list - failed to list projects: {}", err);
+                return Err(Report::new(HandlerError::new(&err.to_string())));
+            }
+        };
+
+        Ok(data)
+    }
+}
+#[async_trait]
+impl HandlerGet<Project, HandlerError> for ProjectsHandler {
+    async fn get(&self, uuid: String) -> Result<Option<Project>, Report<HandlerError>> {
+        info!("ProjectsHandler::get - uuid: {:?}", uuid);
+
+        let tx = self
+            .table
+            .get_item()
+            .table_name(&self.table_name)
+            .key("uuid".to_string(), AttributeValue::S(uuid));
+
+        info!("ProjectsHandler::get - send tx");
+        let result = tx.send().await;
+        info!("ProjectsHandler::get - tx response: {:?}", result);
+
+        match result {
+            Ok(res) => {
+                info!("ProjectsHandler::get - record: {:?}", res);
+                match res.item {
+                    Some(value) => match ProjectParser::parse(value) {
+                        Ok(project) => Ok(Some(project)),
+                        Err(error) => {
+                            error!("ProjectsHandler::get - failed to parse project: {}", error);
+                            Ok(None)
+                        }
+                    },
+                    None => Ok(None),
+                }
+            }
+            Err(err) => {
+                error!("ProjectsHandler::get - failed to get project: {:?}", err);
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+}
+#[async_trait]
+impl HandlerUpdate<Project, ProjectUpdatePayload, HandlerError> for ProjectsHandler {
+    async fn update(
+        &self,
+        uuid: String,
+        payload: ProjectUpdatePayload,
+    ) -> Result<(), Report<HandlerError>> {
+        match self.update_if_unmodified(uuid, payload, None).await? {
+            true => Ok(()),
+            false => Err(Report::new(HandlerError::new("Project not found"))),
+        }
+    }
+}
+#[async_trait]
+impl HandlerDelete<Project, HandlerError> for ProjectsHandler {
+    async fn delete(&self, uuid: String) -> Result<bool, Report<HandlerError>> {
+        self.delete_if_unmodified(uuid, None).await
+    }
+}
+
+impl ProjectsHandler {
+    /// Projects of the list endpoint, only the matching ones and the fields asked
+    pub async fn search(&self, list: &ListQuery) -> Result<Vec<Value>, Report<HandlerError>> {
+        info!("ProjectsHandler::search - list: {:?}", list);
+        let tx = list
+            .scan(self.table.scan().table_name(&self.table_name))
+            .into_paginator()
+            .items();
+
+        info!("ProjectsHandler::search - send tx");
+        let result: Result<Vec<_>, SdkError<ScanError>> = tx.send().collect().await;
+        match result {
+            Ok(items) => Ok(list.render(items, ProjectParser::parse)),
+            Err(err) => {
+                error!("ProjectsHandler::search - failed to list projects: {}", err);
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+
+    /// Updates a project, only while it was last updated at `updated_at` when
+    /// set. Returns `false` when the project is missing or changed meanwhile.
+    pub async fn update_if_unmodified(
+        &self,
+        uuid: String,
+        payload: ProjectUpdatePayload,
+        updated_at: Option<String>,
+    ) -> Result<bool, Report<HandlerError>> {
+        info!("ProjectsHandler::update - uuid: {}", uuid);
+        info!("ProjectsHandler::update - payload: {:?}", payload);
+        let expressions = self.get_update_expressions(payload);
+
+        // Never recreate a deleted project from the updated fields only
+        let mut tx = self
+            .table
+            .update_item()
+            .table_name(&self.table_name)
+            .key("uuid".to_string(), AttributeValue::S(uuid))
+            .set_expression_attribute_names(Some(expressions.attribute_names))
+            .set_expression_attribute_values(Some(expressions.attribute_values))
+            .expression_attribute_names("#uuid", "uuid")
+            .condition_expression("attribute_exists(#uuid)")
+            .update_expression(expressions.update_expression);
+        if let Some(updated_at) = updated_at {
+            tx = tx
+                .condition_expression("attribute_exists(#uuid) AND #updated_at = :read_updated_at")
+                .expression_attribute_values(":read_updated_at", AttributeValue::S(updated_at));
+        }
+
+        info!("ProjectsHandler::update - send tx");
+        let result = tx.send().await;
+        info!("ProjectsHandler::update - tx response: {:?}", result);
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                info!("ProjectsHandler::update - project missing or modified");
+                Ok(false)
+            }
+            Err(err) => {
+                error!(
+                    "ProjectsHandler::update - failed to update project: {:?}",
+                    err
+                );
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+
+    /// Deletes a project, only while it was last updated at `updated_at` when
+    /// set. Returns `false` when the project is missing or changed meanwhile.
+    pub async fn delete_if_unmodified(
+        &self,
+        uuid: String,
+        updated_at: Option<String>,
+    ) -> Result<bool, Report<HandlerError>> {
+        info!("ProjectsHandler::delete - uuid: {}", uuid);
+
+        let mut tx = self
+            .table
+            .delete_item()
+            .table_name(&self.table_name)
+            .key("uuid".to_string(), AttributeValue::S(uuid))
+            .condition_expression("attribute_exists(#uuid)")
+            .expression_attribute_names("#uuid", "uuid");
+        if let Some(updated_at) = updated_at {
+            tx = tx
+                .condition_expression("attribute_exists(#uuid) AND #updated_at = :updated_at")
+                .expression_attribute_names("#updated_at", "updated_at")
+                .expression_attribute_values(":updated_at", AttributeValue::S(updated_at));
+        }
+
+        info!("ProjectsHandler::delete - send tx");
+        match tx.send().await {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                info!("ProjectsHandler::delete - project missing or modified");
+                Ok(false)
+            }
+            Err(err) => {
+                error!(
+                    "ProjectsHandler::delete - failed to delete project: {:?}",
+                    err
+                );
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+
+    pub async fn set_current_deployment(
+        &self,
+        uuid: String,
+        environment: String,
+        deployment: String,
+    ) -> Result<(), Report<HandlerError>> {
+        info!(
+            "ProjectsHandler::set_current_deployment - uuid: {}, environment: {}, deployment: {}",
+            uuid, environment, deployment
+        );
+        let timestamp = Utc::now().to_rfc3339();
+
+        let tx = self
+            .table
+            .update_item()
+            .table_name(&self.table_name)
+            .key("uuid".to_string(), AttributeValue::S(uuid))
+            .condition_expression("attribute_exists(#environments.#environment)")
+            .update_expression(
+                "SET #environments.#environment.#current_deployment = :deployment, #updated_at = :updated_at",
+            )
+            .expression_attribute_names("#environments", "environments")
+            .expression_attribute_names("#environment", environment)
+            .expression_attribute_names("#current_deployment", "current_deployment")
+            .expression_attribute_names("#updated_at", "updated_at")
+            .expression_attribute_values(":deployment", AttributeValue::S(deployment))
+            .expression_attribute_values(":updated_at", AttributeValue::S(timestamp));
+
+        info!("ProjectsHandler::set_current_deployment - send tx");
+        let result = tx.send().await;
+        info!(
+            "ProjectsHandler::set_current_deployment - tx response: {:?}",
+            result
+        );
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!(
+                    "ProjectsHandler::set_current_deployment - failed to update project: {:?}",
+                    err
+                );
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod project_parser_tests {
     use super::*;
@@ -210,126 +604,20 @@ mod project_parser_tests {
             _ => assert_eq!("", "Should have panicked but it did not"),
         }
     }
-}
-
-pub struct ProjectsHandler {
-    table: Client,
-    table_name: String,
-}
-impl ProjectsHandler {
-    pub fn new(client: Client, table_name: String) -> Self {
-        Self {
-            table: client,
-            table_name,
-        }
-    }
-}
-
-#[async_trait]
-impl HandlerCreate<Project, ProjectCreatePayload, HandlerError> for ProjectsHandler {
-    async fn create(&self, payload: ProjectCreatePayload) -> Result<Project, Report<HandlerError>> {
-        info!("ProjectsHandler::create - payload: {:?}", payload);
-        let project = Project::new(payload);
-
-        let tx = self
-            .table
-            .put_item()
-            .table_name(&self.table_name)
-            .set_item(Some(project.as_hashmap()));
-
-        info!("ProjectsHandler::create - send tx");
-        let result = tx.send().await;
-        info!("ProjectsHandler::create - tx response: {:?}", result);
-
-        match result {
-            Ok(res) => {
-                info!("ProjectsHandler::create - new user created: {:?}", res);
-                Ok(project)
-            }
-            Err(err) => {
-                error!("ProjectsHandler::create - failed to create user: {:?}", err);
-                Err(Report::new(HandlerError::new(&err.to_string())))
-            }
-        }
-    }
-}
-#[async_trait]
-impl HandlerList<Project, HandlerError> for ProjectsHandler {
-    async fn list(&self) -> Result<Vec<Project>, Report<HandlerError>> {
-        let mut data: Vec<Project> = Vec::new();
-
-        info!("ProjectsHandler::list - preparing query to list projects");
-        let tx = self
-            .table
-            .scan()
-            .table_name(&self.table_name)
-            .into_paginator()
-            .items();
-        info!("ProjectsHandler::list - send tx");
-        let result: Result<Vec<_>, SdkError<ScanError>> = tx.send().collect().await;
-        info!("ProjectsHandler::list - tx response: {:?}", result);
-
-        match result {
-            Ok(res) => {
-                info!("ProjectsHandler::list - parse projects");
-                for item in res {
-                    info!("ProjectParser::list - parse record: {:?}", &item);
-                    match ProjectParser::parse(item) {
-                        Ok(parsed) => {
-                            info!("ProjectsHandler::list - project: {:?}", parsed);
-                            data.push(parsed);
-                        }
-                        Err(error) => {
-                            error!(
-                                "ProjectParser::list - parse error (skip from result): {}",
-                                error
-                            )
-                        }
-                    };
-                }
-            }
-            Err(err) => {
-                error!("ProjectsHandler::list - failed to list projects: {}", err);
-                return Err(Report::new(HandlerError::new(&err.to_string())));
-            }
-        };
-
-        Ok(data)
-    }
-}
-#[async_trait]
-impl HandlerGet<Project, HandlerError> for ProjectsHandler {
-    async fn get(&self, uuid: String) -> Result<Option<Project>, Report<HandlerError>> {
-        info!("ProjectsHandler::get - uuid: {:?}", uuid);
-
-        let tx = self
-            .table
-            .get_item()
-            .table_name(&self.table_name)
-            .key("uuid".to_string(), AttributeValue::S(uuid));
-
-        info!("ProjectsHandler::get - send tx");
-        let result = tx.send().await;
-        info!("ProjectsHandler::get - tx response: {:?}", result);
 
-        match result {
-            Ok(res) => {
-                info!("ProjectsHandler::get - record: {:?}", res);
-                match res.item {
-                    Some(value) => match ProjectParser::parse(value) {
-                        Ok(project) => Ok(Some(project)),
-                        Err(error) => {
-                            error!("ProjectsHandler::get - failed to parse project: {}", error);
-                            Ok(None)
-                        }
-                    },
-                    None => Ok(None),
-                }
-            }
-            Err(err) => {
-                error!("ProjectsHandler::get - failed to get project: {:?}", err);
-                Err(Report::new(HandlerError::new(&err.to_string())))
-            }
-        }
+    #[test]
+    fn updates_only_the_fields_sent() {
+        let payload: ProjectUpdatePayload = serde_json::from_value(json!({
+            "name": "site",
+            "compute_size": "LARGE",
+            "commands": { "pre_build": ["npm ci"], "build": ["npm run build"] },
+        }))
+        .unwrap();
+        let map = payload.as_hashmap();
+        let mut keys: Vec<&String> = map.keys().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["commands", "compute_size", "name"]);
+        assert_eq!(map["compute_size"], AttributeValue::S("large".to_string()));
+        assert!(ProjectUpdatePayload::default().as_hashmap().is_empty());
     }
 }
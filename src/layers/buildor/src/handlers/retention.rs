@@ -0,0 +1,361 @@
+use chrono::{DateTime, Duration, Utc};
+use error_stack::Report;
+use log::{self, error, info};
+use std::collections::BTreeMap;
+
+use crate::{
+    handlers::{
+        artifacts::ArtifactsHandler, project_deployments::ProjectDeploymentsHandler,
+        projects::ProjectsHandler, stats::ProjectStatsHandler, webhooks::WebhooksHandler,
+    },
+    models::{
+        handlers::{HandlerError, HandlerGet, HandlerList},
+        project::Project,
+        project_deployment::{ProjectDeployment, ProjectDeploymentStatus},
+        retention::{ExpiredDeployment, ProjectRetentionReport, RetentionPolicy, RetentionReport},
+    },
+};
+
+/// Statuses the retention policy is allowed to expire
+pub const EXPIRABLE_STATUSES: [ProjectDeploymentStatus; 3] = [
+    ProjectDeploymentStatus::Succeeded,
+    ProjectDeploymentStatus::Failed,
+    ProjectDeploymentStatus::Superseded,
+];
+
+/// Deployments of a project that its retention policy expires at `now`.
+///
+/// Kept: pinned deployments, the current deployment of every environment, the
+/// last `keep_last` successful deployments and anything younger than
+/// `expire_after_days`. Queued, in progress and already expired deployments
+/// are never touched.
+pub fn expired_deployments<'a>(
+    project: &Project,
+    deployments: &'a [ProjectDeployment],
+    now: DateTime<Utc>,
+) -> Vec<&'a ProjectDeployment> {
+    let cutoff = now - Duration::days(project.retention.expire_after_days as i64);
+    let current: Vec<&String> = project
+        .environments
+        .iter()
+        .map(|environment| &environment.current_deployment)
+        .collect();
+
+    let mut candidates: Vec<&ProjectDeployment> = deployments
+        .iter()
+        .filter(|deployment| EXPIRABLE_STATUSES.contains(&deployment.status()))
+        .collect();
+    // Newest first, created_at is an rfc3339 timestamp
+    candidates.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+
+    let mut succeeded = 0;
+    let mut expired = Vec::new();
+    for deployment in candidates {
+        if deployment.status() == ProjectDeploymentStatus::Succeeded {
+            succeeded += 1;
+            if succeeded <= project.retention.keep_last {
+                continue;
+            }
+        }
+        if deployment.pinned || current.contains(&&deployment.uuid) {
+            continue;
+        }
+        match DateTime::parse_from_rfc3339(&deployment.created_at) {
+            Ok(created_at) if created_at < cutoff => expired.push(deployment),
+            Ok(_) => (),
+            Err(error) => error!(
+                "expired_deployments - invalid created_at for {}: {}",
+                deployment.uuid, error
+            ),
+        }
+    }
+
+    expired
+}
+
+/// Deployments left by deleted projects, grouped by project. All of them are
+/// expired regardless of age or pin, queued and in progress ones are failed by
+/// the queue first.
+pub fn orphaned_deployments<'a>(
+    projects: &[Project],
+    deployments: &'a [ProjectDeployment],
+) -> BTreeMap<String, Vec<&'a ProjectDeployment>> {
+    let mut orphaned: BTreeMap<String, Vec<&ProjectDeployment>> = BTreeMap::new();
+    for deployment in deployments
+        .iter()
+        .filter(|deployment| EXPIRABLE_STATUSES.contains(&deployment.status()))
+        .filter(|deployment| {
+            !projects
+                .iter()
+                .any(|project| project.uuid == deployment.project.uuid)
+        })
+    {
+        orphaned
+            .entry(deployment.project.uuid.clone())
+            .or_default()
+            .push(deployment);
+    }
+
+    orphaned
+}
+
+pub struct GarbageCollector {
+    projects: ProjectsHandler,
+    deployments: ProjectDeploymentsHandler,
+    artifacts: ArtifactsHandler,
+    webhooks: WebhooksHandler,
+    stats: ProjectStatsHandler,
+}
+impl GarbageCollector {
+    pub fn new(
+        projects: ProjectsHandler,
+        deployments: ProjectDeploymentsHandler,
+        artifacts: ArtifactsHandler,
+        webhooks: WebhooksHandler,
+        stats: ProjectStatsHandler,
+    ) -> Self {
+        Self {
+            projects,
+            deployments,
+            artifacts,
+            webhooks,
+            stats,
+        }
+    }
+
+    /// Applies the retention policy of every project, then expires the
+    /// deployments of deleted projects and deletes their webhooks and
+    /// statistics. Artifacts are deleted before their
+    /// deployment is marked `Expired` so a failed run is retried on the next
+    /// schedule. A dry run only reports what would be expired.
+    pub async fn run(
+        &self,
+        dry_run: bool,
+        now: DateTime<Utc>,
+    ) -> Result<RetentionReport, Report<HandlerError>> {
+        info!("GarbageCollector::run - dry run: {}", dry_run);
+        let mut report = RetentionReport {
+            dry_run,
+            projects: Vec::new(),
+        };
+
+        let projects = self.projects.list().await?;
+        for project in projects.iter() {
+            let deployments = self
+                .deployments
+                .list_by_project(project.uuid.clone(), None)
+                .await?;
+            let expired = expired_deployments(project, &deployments, now);
+            info!(
+                "GarbageCollector::run - project: {}, deployments: {}, expired: {}",
+                project.uuid,
+                deployments.len(),
+                expired.len()
+            );
+
+            let mut project_report = ProjectRetentionReport {
+                project: project.uuid.clone(),
+                deleted: false,
+                retention: project.retention.clone(),
+                kept: deployments.len() - expired.len(),
+                expired: Vec::new(),
+            };
+            for deployment in expired {
+                project_report
+                    .expired
+                    .push(self.expire(deployment, dry_run).await?);
+            }
+            report.projects.push(project_report);
+        }
+
+        // Deployments of deleted projects are only found by status
+        let mut deployments = Vec::new();
+        for status in EXPIRABLE_STATUSES {
+            deployments.append(&mut self.deployments.list_by_status(status).await?);
+        }
+        for (project_uuid, orphaned) in orphaned_deployments(&projects, &deployments) {
+            // Projects created since they were listed are not deleted
+            if self.projects.get(project_uuid.clone()).await?.is_some() {
+                continue;
+            }
+            info!(
+                "GarbageCollector::run - deleted project: {}, expired: {}",
+                project_uuid,
+                orphaned.len()
+            );
+
+            let mut project_report = ProjectRetentionReport {
+                project: project_uuid,
+                deleted: true,
+                retention: RetentionPolicy::new(0, 0),
+                kept: 0,
+                expired: Vec::new(),
+            };
+            for deployment in orphaned {
+                project_report
+                    .expired
+                    .push(self.expire(deployment, dry_run).await?);
+            }
+            if !dry_run {
+                let webhooks = self
+                    .webhooks
+                    .delete_by_project(project_report.project.clone())
+                    .await?;
+                let stats = self
+                    .stats
+                    .delete_project(project_report.project.clone())
+                    .await?;
+                info!(
+                    "GarbageCollector::run - deleted project: {}, webhooks: {}, stats: {}",
+                    project_report.project, webhooks, stats
+                );
+            }
+            report.projects.push(project_report);
+        }
+
+        Ok(report)
+    }
+
+    /// Deletes the artifact of a deployment then marks it `Expired`
+    async fn expire(
+        &self,
+        deployment: &ProjectDeployment,
+        dry_run: bool,
+    ) -> Result<ExpiredDeployment, Report<HandlerError>> {
+        if !dry_run {
+            if let Some(artifact) = deployment.artifact.as_ref() {
+                let keys = std::iter::once(&artifact.key).chain(&artifact.manifest_key);
+                for key in keys {
+                    self.artifacts
+                        .delete(artifact.bucket.clone(), key.clone())
+                        .await
+                        .map_err(|error| Report::new(HandlerError::new(&error.to_string())))?;
+                }
+            }
+            self.deployments
+                .transition(
+                    deployment.uuid.clone(),
+                    &EXPIRABLE_STATUSES,
+                    ProjectDeploymentStatus::Expired,
+                    None,
+                )
+                .await?;
+        }
+
+        Ok(ExpiredDeployment {
+            uuid: deployment.uuid.clone(),
+            environment: deployment.environment.clone(),
+            status: deployment.status.clone(),
+            created_at: deployment.created_at.clone(),
+            artifact: deployment
+                .artifact
+                .as_ref()
+                .map(|artifact| artifact.location()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod retention_tests {
+    use super::*;
+    use crate::fixtures::deployment;
+    use crate::models::{project::ProjectCreatePayload, retention::RetentionPolicy};
+
+    fn project() -> Project {
+        let mut payload = ProjectCreatePayload::default(
+            "name".to_string(),
+            "https://example.com/repo.git".to_string(),
+        );
+        payload.retention = Some(RetentionPolicy::new(2, 30));
+        Project::new(payload)
+    }
+
+    fn aged(
+        project: &Project,
+        status: ProjectDeploymentStatus,
+        days_ago: i64,
+        now: DateTime<Utc>,
+    ) -> ProjectDeployment {
+        deployment(
+            project,
+            "production",
+            status,
+            &(now - Duration::days(days_ago)).to_rfc3339(),
+        )
+    }
+
+    #[test]
+    fn keeps_last_successful_deployments_regardless_of_age() {
+        let now = Utc::now();
+        let project = project();
+        let deployments = vec![
+            aged(&project, ProjectDeploymentStatus::Succeeded, 100, now),
+            aged(&project, ProjectDeploymentStatus::Succeeded, 90, now),
+            aged(&project, ProjectDeploymentStatus::Succeeded, 80, now),
+        ];
+
+        let expired = expired_deployments(&project, &deployments, now);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].uuid, deployments[0].uuid);
+    }
+
+    #[test]
+    fn keeps_recent_pinned_and_current_deployments() {
+        let now = Utc::now();
+        let mut project = project();
+        let mut pinned = aged(&project, ProjectDeploymentStatus::Succeeded, 200, now);
+        pinned.pinned = true;
+        let current = aged(&project, ProjectDeploymentStatus::Succeeded, 150, now);
+        project.environments[0].current_deployment = current.uuid.clone();
+        let deployments = vec![
+            pinned,
+            current,
+            aged(&project, ProjectDeploymentStatus::Failed, 40, now),
+            aged(&project, ProjectDeploymentStatus::Superseded, 10, now),
+            aged(&project, ProjectDeploymentStatus::Succeeded, 5, now),
+            aged(&project, ProjectDeploymentStatus::Succeeded, 1, now),
+        ];
+
+        let expired = expired_deployments(&project, &deployments, now);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].uuid, deployments[2].uuid);
+    }
+
+    #[test]
+    fn never_expires_active_or_expired_deployments() {
+        let now = Utc::now();
+        let mut project = project();
+        project.retention = RetentionPolicy::new(0, 0);
+        let deployments = vec![
+            aged(&project, ProjectDeploymentStatus::Queued, 10, now),
+            aged(&project, ProjectDeploymentStatus::InProgress, 10, now),
+            aged(&project, ProjectDeploymentStatus::Expired, 10, now),
+            aged(&project, ProjectDeploymentStatus::Unknown, 10, now),
+        ];
+
+        assert!(expired_deployments(&project, &deployments, now).is_empty());
+    }
+
+    #[test]
+    fn expires_every_deployment_left_by_deleted_projects() {
+        let now = Utc::now();
+        let live = project();
+        let deleted = project();
+        let mut pinned = aged(&deleted, ProjectDeploymentStatus::Succeeded, 1, now);
+        pinned.pinned = true;
+        let deployments = vec![
+            aged(&live, ProjectDeploymentStatus::Succeeded, 100, now),
+            pinned,
+            aged(&deleted, ProjectDeploymentStatus::Failed, 0, now),
+            aged(&deleted, ProjectDeploymentStatus::Expired, 50, now),
+        ];
+
+        let orphaned = orphaned_deployments(&[live], &deployments);
+        assert_eq!(orphaned.len(), 1);
+        let expired: Vec<&String> = orphaned[&deleted.uuid]
+            .iter()
+            .map(|deployment| &deployment.uuid)
+            .collect();
+        assert_eq!(expired, vec![&deployments[1].uuid, &deployments[2].uuid]);
+    }
+}
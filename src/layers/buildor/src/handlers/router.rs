@@ -0,0 +1,764 @@
+use async_trait::async_trait;
+use error_stack::Report;
+use lambda_runtime::{service_fn, LambdaEvent};
+use log::{self, error, info};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::time::Instant;
+
+use crate::{
+    models::{
+        api_error::PROBLEM_CONTENT_TYPE,
+        api_gateway::{ApiGatewayRequest, ApiGatewayResponse},
+        handlers::HandlerError,
+        request::RequestError,
+        response::{etag_matches, Response},
+        router::{HttpMethod, RouteRequest, RouterError},
+    },
+    utils::load_env_var,
+};
+
+pub type RouteResult = Result<Response, Report<HandlerError>>;
+
+/// Endpoint of the API, the response is sent in the payload format of the request
+#[async_trait]
+pub trait Route: Send + Sync {
+    async fn handle(&self, request: &RouteRequest) -> RouteResult;
+}
+
+/// Wraps every route of a router, calling `next` hands the request over to the
+/// following middleware and eventually to the route
+#[async_trait]
+pub trait Middleware: Send + Sync {
+    async fn handle(&self, request: &RouteRequest, next: Next<'_>) -> RouteResult;
+}
+
+pub struct Next<'a> {
+    middlewares: &'a [Box<dyn Middleware>],
+    route: &'a dyn Route,
+}
+impl Next<'_> {
+    pub async fn run(self, request: &RouteRequest) -> RouteResult {
+        match self.middlewares.split_first() {
+            Some((middleware, middlewares)) => {
+                middleware
+                    .handle(
+                        request,
+                        Next {
+                            middlewares,
+                            route: self.route,
+                        },
+                    )
+                    .await
+            }
+            None => self.route.handle(request).await,
+        }
+    }
+}
+
+/// Fixed response, used for the requests no route matches
+struct Reply(Response);
+#[async_trait]
+impl Route for Reply {
+    async fn handle(&self, _: &RouteRequest) -> RouteResult {
+        Ok(self.0.clone())
+    }
+}
+
+struct RouteEntry {
+    method: HttpMethod,
+    template: String,
+    segments: Vec<String>,
+    route: Box<dyn Route>,
+}
+impl RouteEntry {
+    /// Path parameters when `path` matches the template
+    fn matches(&self, path: &[&str]) -> Option<HashMap<String, String>> {
+        if self.segments.len() != path.len() {
+            return None;
+        }
+        let mut parameters = HashMap::new();
+        for (segment, value) in self.segments.iter().zip(path.iter()) {
+            match segment
+                .strip_prefix('{')
+                .and_then(|name| name.strip_suffix('}'))
+            {
+                Some(name) => {
+                    parameters.insert(name.to_string(), value.to_string());
+                }
+                None if segment == value => {}
+                None => return None,
+            }
+        }
+
+        Some(parameters)
+    }
+
+    /// Literal segments win over parameters when several templates match
+    fn literals(&self) -> usize {
+        self.segments
+            .iter()
+            .filter(|segment| !segment.starts_with('{'))
+            .count()
+    }
+}
+
+/// Dispatches API Gateway proxy events on their method and path, the same
+/// router serves one route per lambda or the whole API from a single one
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<RouteEntry>,
+    middlewares: Vec<Box<dyn Middleware>>,
+}
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Router with the middlewares every API lambda uses, outermost first
+    pub fn api() -> Self {
+        Self::new()
+            .middleware(LoggingMiddleware)
+            .middleware(ConditionalGetMiddleware)
+            .middleware(ProblemDetailsMiddleware)
+            .middleware(ErrorMiddleware)
+            .middleware(ApiKeyAuth::from_env())
+    }
+
+    /// Registers a route for a path template, e.g. `/projects/{project}/stats`
+    pub fn route<R: Route + 'static>(
+        mut self,
+        method: HttpMethod,
+        template: &str,
+        route: R,
+    ) -> Self {
+        self.routes.push(RouteEntry {
+            method,
+            template: template.to_string(),
+            segments: split_path(template).into_iter().map(String::from).collect(),
+            route: Box::new(route),
+        });
+        self
+    }
+
+    /// Adds a middleware inside the ones already registered
+    pub fn middleware<M: Middleware + 'static>(mut self, middleware: M) -> Self {
+        self.middlewares.push(Box::new(middleware));
+        self
+    }
+
+    /// Methods and templates of the registered routes
+    pub fn routes(&self) -> Vec<(HttpMethod, &str)> {
+        self.routes
+            .iter()
+            .map(|entry| (entry.method, entry.template.as_str()))
+            .collect()
+    }
+
+    pub async fn dispatch(
+        &self,
+        event: ApiGatewayRequest,
+    ) -> Result<ApiGatewayResponse, Report<HandlerError>> {
+        let mut request = RouteRequest::from(event);
+        let path = request.path.clone();
+        let segments = split_path(&path);
+
+        let mut candidates: Vec<(&RouteEntry, HashMap<String, String>)> = self
+            .routes
+            .iter()
+            .filter_map(|entry| entry.matches(&segments).map(|params| (entry, params)))
+            .collect();
+        candidates.sort_by_key(|(entry, _)| std::cmp::Reverse(entry.literals()));
+
+        let not_found;
+        let route: &dyn Route = match candidates
+            .iter()
+            .find(|(entry, _)| entry.method.serves(request.method))
+        {
+            Some((entry, parameters)) => {
+                request.resource = entry.template.clone();
+                request.path_parameters.extend(parameters.clone());
+                entry.route.as_ref()
+            }
+            None if candidates.is_empty() => {
+                not_found = Reply(Response::error(RouterError::route_not_found(&path)));
+                &not_found
+            }
+            None => {
+                let allowed: Vec<HttpMethod> =
+                    candidates.iter().map(|(entry, _)| entry.method).collect();
+                not_found = Reply(Response::error(RouterError::method_not_allowed(
+                    request.method,
+                    &allowed,
+                )));
+                &not_found
+            }
+        };
+
+        let response = Next {
+            middlewares: &self.middlewares,
+            route,
+        }
+        .run(&request)
+        .await?;
+
+        Ok(ApiGatewayResponse::new(request.format, response))
+    }
+
+    /// Runs the router as the lambda handler
+    pub async fn serve(self) -> Result<(), Value> {
+        info!(
+            "Creating service fn for router, routes: {:?}",
+            self.routes()
+        );
+        let router = &self;
+        let func = service_fn(move |event: LambdaEvent<ApiGatewayRequest>| async move {
+            info!("context: {:?}", event.context);
+            router.dispatch(event.payload).await
+        });
+        info!("Executing router from runtime");
+        let result = lambda_runtime::run(func).await;
+        info!("Evaluating router result");
+        match result {
+            Ok(res) => {
+                info!("Success");
+                Ok(res)
+            }
+            Err(err) => {
+                error!("Handler exception: {}", err);
+                Err(json!(RequestError::internal()))
+            }
+        }
+    }
+}
+
+fn split_path(path: &str) -> Vec<&str> {
+    path.split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect()
+}
+
+/// Logs every request with the status and duration of its response
+pub struct LoggingMiddleware;
+#[async_trait]
+impl Middleware for LoggingMiddleware {
+    async fn handle(&self, request: &RouteRequest, next: Next<'_>) -> RouteResult {
+        info!(
+            "LoggingMiddleware::handle - {} {} ({})",
+            request.method, request.path, request.resource
+        );
+        let started = Instant::now();
+        let result = next.run(request).await;
+        let status = match result.as_ref() {
+            Ok(response) => response.status_code.to_string(),
+            Err(_) => "error".to_string(),
+        };
+        info!(
+            "LoggingMiddleware::handle - {} {} -> {} in {}ms",
+            request.method,
+            request.path,
+            status,
+            started.elapsed().as_millis()
+        );
+
+        result
+    }
+}
+
+/// Tags successful GET responses with the entity tag of their body, and answers
+/// 304 without body when the client already has it (`If-None-Match`)
+pub struct ConditionalGetMiddleware;
+#[async_trait]
+impl Middleware for ConditionalGetMiddleware {
+    async fn handle(&self, request: &RouteRequest, next: Next<'_>) -> RouteResult {
+        let response = next.run(request).await?;
+        if request.method != HttpMethod::Get || response.status_code != 200 {
+            return Ok(response);
+        }
+
+        let response = response.with_etag();
+        let etag = response.headers.etag.clone().unwrap_or_default();
+        match request.header("if-none-match") {
+            Some(header) if etag_matches(header, &etag) => {
+                info!(
+                    "ConditionalGetMiddleware::handle - not modified: {}",
+                    request.path
+                );
+                Ok(Response::not_modified(etag))
+            }
+            _ => Ok(response),
+        }
+    }
+}
+
+/// Turns route errors into internal server error responses
+pub struct ErrorMiddleware;
+#[async_trait]
+impl Middleware for ErrorMiddleware {
+    async fn handle(&self, request: &RouteRequest, next: Next<'_>) -> RouteResult {
+        match next.run(request).await {
+            Ok(response) => Ok(response),
+            Err(err) => {
+                error!(
+                    "ErrorMiddleware::handle - {} {} failed: {:?}",
+                    request.method, request.path, err
+                );
+                Ok(Response::error(RequestError::internal()))
+            }
+        }
+    }
+}
+
+/// Renders error responses as RFC 7807 problem details for the clients
+/// accepting `application/problem+json`
+pub struct ProblemDetailsMiddleware;
+#[async_trait]
+impl Middleware for ProblemDetailsMiddleware {
+    async fn handle(&self, request: &RouteRequest, next: Next<'_>) -> RouteResult {
+        let response = next.run(request).await?;
+        let accepts_problem = request
+            .header("accept")
+            .map(|accept| accept.contains(PROBLEM_CONTENT_TYPE))
+            .unwrap_or(false);
+
+        match (accepts_problem, response.error.clone()) {
+            (true, Some(error)) => Ok(Response::problem(error, &request.path)),
+            _ => Ok(response),
+        }
+    }
+}
+
+/// Requires one of the API keys in the `X-Api-Key` header or as a bearer token,
+/// every request is rejected without keys unless authentication is disabled
+pub struct ApiKeyAuth {
+    /// None when authentication is disabled
+    keys: Option<Vec<String>>,
+}
+impl ApiKeyAuth {
+    pub fn new(keys: Vec<String>) -> Self {
+        Self {
+            keys: Some(keys.into_iter().filter(|key| !key.is_empty()).collect()),
+        }
+    }
+
+    /// Lets every request through
+    pub fn disabled() -> Self {
+        Self { keys: None }
+    }
+
+    /// Keys from the comma separated `API_KEYS` env var, authentication is
+    /// only disabled explicitly with `API_AUTH=disabled`
+    pub fn from_env() -> Self {
+        if load_env_var("API_AUTH", Some("")).unwrap_or_default() == "disabled" {
+            info!("ApiKeyAuth::from_env - API authentication disabled");
+            return Self::disabled();
+        }
+        let keys = load_env_var("API_KEYS", Some("")).unwrap_or_default();
+        if keys.trim().is_empty() {
+            error!("ApiKeyAuth::from_env - missing API_KEYS, every request is rejected");
+        }
+
+        Self::new(keys.split(',').map(|key| key.trim().to_string()).collect())
+    }
+
+    fn authorized(&self, request: &RouteRequest) -> bool {
+        let keys = match self.keys.as_ref() {
+            Some(keys) => keys,
+            None => return true,
+        };
+        let key = request.header("x-api-key").or_else(|| {
+            request
+                .header("authorization")
+                .and_then(|value| value.strip_prefix("Bearer "))
+        });
+
+        match key {
+            Some(key) => keys.iter().any(|valid| valid == key),
+            None => false,
+        }
+    }
+}
+#[async_trait]
+impl Middleware for ApiKeyAuth {
+    async fn handle(&self, request: &RouteRequest, next: Next<'_>) -> RouteResult {
+        match self.authorized(request) {
+            true => next.run(request).await,
+            false => {
+                info!(
+                    "ApiKeyAuth::handle - unauthorized request: {} {}",
+                    request.method, request.path
+                );
+                Ok(Response::error(RouterError::unauthorized()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod router_tests {
+    use super::*;
+    use crate::models::api_gateway::PayloadFormat;
+    use std::sync::{Arc, Mutex};
+
+    /// Echoes the matched resource and parameters
+    struct Echo;
+    #[async_trait]
+    impl Route for Echo {
+        async fn handle(&self, request: &RouteRequest) -> RouteResult {
+            Ok(Response::json(
+                json!({
+                    "resource": request.resource,
+                    "parameters": request.path_parameters,
+                    "window": request.query_parameter("window"),
+                    "body": request.body::<Value>().ok(),
+                }),
+                200,
+            ))
+        }
+    }
+
+    struct Failing;
+    #[async_trait]
+    impl Route for Failing {
+        async fn handle(&self, _: &RouteRequest) -> RouteResult {
+            Err(Report::new(HandlerError::new("storage unavailable")))
+        }
+    }
+
+    struct Recorder {
+        name: &'static str,
+        calls: Arc<Mutex<Vec<&'static str>>>,
+    }
+    #[async_trait]
+    impl Middleware for Recorder {
+        async fn handle(&self, request: &RouteRequest, next: Next<'_>) -> RouteResult {
+            self.calls.lock().unwrap().push(self.name);
+            next.run(request).await
+        }
+    }
+
+    fn event(method: &str, path: &str) -> Value {
+        json!({
+            "httpMethod": method,
+            "path": path,
+            "resource": "/{proxy+}",
+            "pathParameters": { "proxy": path.trim_start_matches('/') },
+            "queryStringParameters": { "window": "7d" },
+            "headers": { "X-Api-Key": "secret" },
+            "body": null,
+        })
+    }
+
+    fn request(event: Value) -> ApiGatewayRequest {
+        serde_json::from_value(event).unwrap()
+    }
+
+    fn status(response: &ApiGatewayResponse) -> u16 {
+        response.status_code()
+    }
+
+    fn body(response: &ApiGatewayResponse) -> Value {
+        serde_json::from_str(response.body()).unwrap()
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .route(HttpMethod::Get, "/projects", Echo)
+            .route(HttpMethod::Get, "/projects/{project}", Echo)
+            .route(HttpMethod::Get, "/projects/{project}/stats", Echo)
+            .route(HttpMethod::Get, "/projects/archived", Echo)
+            .route(HttpMethod::Post, "/projects", Failing)
+    }
+
+    #[tokio::test]
+    async fn dispatches_on_method_and_path() {
+        let response = router()
+            .dispatch(request(event("GET", "/projects/abc/stats/")))
+            .await
+            .unwrap();
+        assert_eq!(status(&response), 200);
+        let body = body(&response);
+        assert_eq!(body["resource"], "/projects/{project}/stats");
+        assert_eq!(body["parameters"]["project"], "abc");
+        assert_eq!(body["parameters"]["proxy"], "projects/abc/stats/");
+        assert_eq!(body["window"], "7d");
+    }
+
+    #[tokio::test]
+    async fn serves_rest_and_http_api_payloads() {
+        let fixtures = [
+            include_str!("../../fixtures/api-gateway/rest-v1-project-stats.json"),
+            include_str!("../../fixtures/api-gateway/http-v2-project-stats.json"),
+        ];
+        for fixture in fixtures {
+            let request: ApiGatewayRequest = serde_json::from_str(fixture).unwrap();
+            let format = request.format();
+            let response = router().dispatch(request).await.unwrap();
+            assert_eq!(status(&response), 200);
+            let body = body(&response);
+            assert_eq!(body["resource"], "/projects/{project}/stats");
+            assert_eq!(
+                body["parameters"]["project"],
+                "0a6a5e2c-3f1b-4c41-9a43-6f7c2c1a9b10"
+            );
+            assert_eq!(body["window"], "7d");
+            // Answered in the payload format of the request
+            match (format, response) {
+                (PayloadFormat::V1, ApiGatewayResponse::V1(_)) => {}
+                (PayloadFormat::V2, ApiGatewayResponse::V2(_)) => {}
+                (format, response) => panic!("{:?} request answered with {:?}", format, response),
+            }
+        }
+
+        let request: ApiGatewayRequest = serde_json::from_str(include_str!(
+            "../../fixtures/api-gateway/http-v2-users-post.json"
+        ))
+        .unwrap();
+        let response = Router::new()
+            .route(HttpMethod::Post, "/users", Echo)
+            .dispatch(request)
+            .await
+            .unwrap();
+        assert_eq!(body(&response)["body"]["fname"], "John");
+    }
+
+    #[tokio::test]
+    async fn literal_segments_win_over_parameters() {
+        let response = router()
+            .dispatch(request(event("GET", "/projects/archived")))
+            .await
+            .unwrap();
+        assert_eq!(body(&response)["resource"], "/projects/archived");
+    }
+
+    #[tokio::test]
+    async fn unknown_routes_and_methods() {
+        let response = router()
+            .dispatch(request(event("GET", "/users")))
+            .await
+            .unwrap();
+        assert_eq!(status(&response), 404);
+        assert_eq!(body(&response)["code"], "GRE101");
+
+        let response = router()
+            .dispatch(request(event("DELETE", "/projects")))
+            .await
+            .unwrap();
+        assert_eq!(status(&response), 405);
+        assert_eq!(body(&response)["code"], "GRE102");
+        assert_eq!(
+            body(&response)["details"],
+            "Method DELETE not allowed, expected one of GET, POST"
+        );
+
+        let response = Router::new()
+            .route(HttpMethod::Any, "/", Echo)
+            .dispatch(request(event("DELETE", "/")))
+            .await
+            .unwrap();
+        assert_eq!(status(&response), 200);
+    }
+
+    #[tokio::test]
+    async fn middlewares_run_outermost_first() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let router = router()
+            .middleware(Recorder {
+                name: "outer",
+                calls: calls.clone(),
+            })
+            .middleware(Recorder {
+                name: "inner",
+                calls: calls.clone(),
+            });
+
+        router
+            .dispatch(request(event("GET", "/projects")))
+            .await
+            .unwrap();
+        // Unmatched requests go through the middlewares too
+        router
+            .dispatch(request(event("GET", "/users")))
+            .await
+            .unwrap();
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec!["outer", "inner", "outer", "inner"]
+        );
+    }
+
+    #[tokio::test]
+    async fn maps_route_errors() {
+        assert!(router()
+            .dispatch(request(event("POST", "/projects")))
+            .await
+            .is_err());
+
+        let response = router()
+            .middleware(ErrorMiddleware)
+            .dispatch(request(event("POST", "/projects")))
+            .await
+            .unwrap();
+        assert_eq!(status(&response), 500);
+        assert_eq!(body(&response)["code"], "ISE00");
+    }
+
+    #[tokio::test]
+    async fn renders_problem_details_on_demand() {
+        let router = router()
+            .middleware(ProblemDetailsMiddleware)
+            .middleware(ErrorMiddleware);
+
+        let mut accepting = event("GET", "/users");
+        accepting["headers"] = json!({ "Accept": "application/problem+json" });
+        let response = router.dispatch(request(accepting)).await.unwrap();
+        assert_eq!(status(&response), 404);
+        let problem = body(&response);
+        assert_eq!(problem["type"], "/errors#GRE101");
+        assert_eq!(problem["status"], 404);
+        assert_eq!(problem["instance"], "/users");
+        match response {
+            ApiGatewayResponse::V1(response) => {
+                assert_eq!(response.headers["Content-Type"], PROBLEM_CONTENT_TYPE)
+            }
+            other => panic!("Unexpected response: {:?}", other),
+        }
+
+        // Plain error bodies otherwise, successful responses are left untouched
+        let response = router
+            .dispatch(request(event("POST", "/projects")))
+            .await
+            .unwrap();
+        assert_eq!(body(&response)["code"], "ISE00");
+        assert_eq!(body(&response)["retryable"], true);
+        let mut accepting = event("GET", "/projects");
+        accepting["headers"] = json!({ "Accept": "application/problem+json" });
+        let response = router.dispatch(request(accepting)).await.unwrap();
+        assert_eq!(body(&response)["resource"], "/projects");
+    }
+
+    #[tokio::test]
+    async fn requires_an_api_key() {
+        let open = router().middleware(ApiKeyAuth::disabled());
+        let response = open
+            .dispatch(request(event("GET", "/projects")))
+            .await
+            .unwrap();
+        assert_eq!(status(&response), 200);
+
+        // Missing keys reject every request
+        let closed = router().middleware(ApiKeyAuth::new(vec!["".to_string()]));
+        let mut empty = event("GET", "/projects");
+        empty["headers"] = json!({ "x-api-key": "" });
+        let response = closed.dispatch(request(empty)).await.unwrap();
+        assert_eq!(status(&response), 401);
+
+        let router = router().middleware(ApiKeyAuth::new(vec!["secret".to_string()]));
+        let response = router
+            .dispatch(request(event("GET", "/projects")))
+            .await
+            .unwrap();
+        assert_eq!(status(&response), 200);
+
+        let mut bearer = event("GET", "/projects");
+        bearer["headers"] = json!({ "Authorization": "Bearer secret" });
+        let response = router.dispatch(request(bearer)).await.unwrap();
+        assert_eq!(status(&response), 200);
+
+        let mut invalid = event("GET", "/projects");
+        invalid["headers"] = json!({ "x-api-key": "guess" });
+        let response = router.dispatch(request(invalid)).await.unwrap();
+        assert_eq!(status(&response), 401);
+        assert_eq!(body(&response)["code"], "GRE103");
+
+        let mut missing = event("GET", "/unknown");
+        missing["headers"] = Value::Null;
+        let response = router.dispatch(request(missing)).await.unwrap();
+        assert_eq!(status(&response), 401);
+    }
+
+    #[tokio::test]
+    async fn answers_conditional_gets() {
+        let router = router()
+            .middleware(ConditionalGetMiddleware)
+            .middleware(ErrorMiddleware);
+        let etag = match router
+            .dispatch(request(event("GET", "/projects/abc")))
+            .await
+            .unwrap()
+        {
+            ApiGatewayResponse::V1(response) => response.headers["ETag"].clone(),
+            other => panic!("Unexpected response: {:?}", other),
+        };
+        assert_eq!(etag.len(), 34);
+
+        let mut cached = event("GET", "/projects/abc");
+        cached["headers"] = json!({ "If-None-Match": format!("W/{}, \"other\"", etag) });
+        let response = router.dispatch(request(cached)).await.unwrap();
+        assert_eq!(status(&response), 304);
+        assert_eq!(response.body(), "");
+
+        let mut stale = event("GET", "/projects/abc");
+        stale["headers"] = json!({ "If-None-Match": "\"other\"" });
+        let response = router.dispatch(request(stale)).await.unwrap();
+        assert_eq!(status(&response), 200);
+
+        // Only successful GET responses are tagged
+        let response = router
+            .dispatch(request(event("POST", "/projects")))
+            .await
+            .unwrap();
+        match response {
+            ApiGatewayResponse::V1(response) => assert!(!response.headers.contains_key("ETag")),
+            other => panic!("Unexpected response: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn checks_if_match() {
+        let current = crate::models::response::etag(json!({ "name": "site" }));
+        let mut conditional = event("PATCH", "/projects/abc");
+        conditional["headers"] = json!({ "If-Match": current });
+        assert!(RouteRequest::from(request(conditional))
+            .if_match(&current)
+            .is_ok());
+
+        let mut stale = event("PATCH", "/projects/abc");
+        stale["headers"] = json!({ "If-Match": "\"other\"" });
+        let error = RouteRequest::from(request(stale))
+            .if_match(&current)
+            .unwrap_err();
+        assert_eq!(error.status, 412);
+
+        // Requests without the header are not conditional
+        assert!(RouteRequest::from(request(event("PATCH", "/projects/abc")))
+            .if_match(&current)
+            .is_ok());
+    }
+
+    #[test]
+    fn reads_idempotency_keys() {
+        let mut keyed = event("POST", "/deployments");
+        keyed["headers"] = json!({ "Idempotency-Key": " retry-1 " });
+        assert_eq!(
+            RouteRequest::from(request(keyed)).idempotency_key(),
+            Ok(Some("retry-1".to_string()))
+        );
+
+        let mut blank = event("POST", "/deployments");
+        blank["headers"] = json!({ "Idempotency-Key": "" });
+        let error = RouteRequest::from(request(blank))
+            .idempotency_key()
+            .unwrap_err();
+        assert_eq!(error.status, 400);
+
+        let mut long = event("POST", "/deployments");
+        long["headers"] = json!({ "Idempotency-Key": "k".repeat(256) });
+        assert!(RouteRequest::from(request(long)).idempotency_key().is_err());
+
+        assert_eq!(
+            RouteRequest::from(request(event("POST", "/deployments"))).idempotency_key(),
+            Ok(None)
+        );
+    }
+}
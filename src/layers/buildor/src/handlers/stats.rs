@@ -0,0 +1,889 @@
+use async_trait::async_trait;
+use aws_sdk_dynamodb::{
+    error::QueryError,
+    model::{AttributeValue, Put, TransactWriteItem, Update},
+    types::SdkError,
+    Client,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use error_stack::Report;
+use log::{self, error, info};
+use std::collections::HashMap;
+use tokio_stream::StreamExt;
+
+use crate::{
+    handlers::{outbox::OutboxSink, project_deployments::condition_failed},
+    models::{
+        common::MissingModelPropertyError,
+        handlers::HandlerError,
+        outbox::OutboxEvent,
+        project_deployment::{ProjectDeployment, ProjectDeploymentStatus},
+        stats::{CountedBuild, DailyStats, IncidentChange, StatsUpdate},
+    },
+};
+
+/// Sort key of the item holding the failing environments of a project, sorts
+/// after every day
+pub const STATS_STATE_KEY: &str = "state";
+/// Sort key prefix of the items holding the status deployments were counted
+/// with, sorts after every day
+pub const STATS_COUNTED_PREFIX: &str = "deployment#";
+
+pub struct DailyStatsParser {}
+impl DailyStatsParser {
+    pub fn parse(
+        item: HashMap<String, AttributeValue>,
+    ) -> Result<DailyStats, Report<MissingModelPropertyError>> {
+        let project_uuid = match item.get("project_uuid") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("project_uuid"))),
+        };
+
+        let day = match item.get("day") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("day"))),
+        };
+
+        let counters: HashMap<String, i64> = item
+            .iter()
+            .filter_map(|(name, value)| match value.as_n() {
+                Ok(value) => value.parse().ok().map(|value| (name.clone(), value)),
+                Err(_) => None,
+            })
+            .collect();
+
+        Ok(DailyStats::from_counters(project_uuid, day, &counters))
+    }
+}
+
+pub struct CountedBuildParser {}
+impl CountedBuildParser {
+    pub fn parse(
+        item: HashMap<String, AttributeValue>,
+    ) -> Result<CountedBuild, Report<MissingModelPropertyError>> {
+        let deployment_uuid = match item.get("deployment_uuid") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => {
+                return Err(Report::new(MissingModelPropertyError::new(
+                    "deployment_uuid",
+                )))
+            }
+        };
+
+        let status = match item.get("status") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("status"))),
+        };
+
+        let day = match item.get("counted_day") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("counted_day"))),
+        };
+
+        let failing_phase = item
+            .get("failing_phase")
+            .map(|value| value.as_s().unwrap().to_string());
+
+        let recovery_seconds = item
+            .get("recovery_seconds")
+            .and_then(|value| value.as_n().unwrap().parse().ok());
+
+        let failing_since = item
+            .get("failing_since")
+            .and_then(|value| value.as_n().unwrap().parse().ok());
+
+        Ok(CountedBuild {
+            deployment_uuid,
+            status,
+            day,
+            failing_phase,
+            recovery_seconds,
+            failing_since,
+        })
+    }
+}
+
+pub struct ProjectStatsHandler {
+    table: Client,
+    table_name: String,
+}
+impl ProjectStatsHandler {
+    pub fn new(client: Client, table_name: String) -> Self {
+        Self {
+            table: client,
+            table_name,
+        }
+    }
+
+    /// Final status a deployment was counted with, None when it was not yet
+    pub async fn counted(
+        &self,
+        project_uuid: String,
+        deployment_uuid: String,
+    ) -> Result<Option<CountedBuild>, Report<HandlerError>> {
+        info!(
+            "ProjectStatsHandler::counted - project: {}, deployment: {}",
+            project_uuid, deployment_uuid
+        );
+        let tx = self
+            .table
+            .get_item()
+            .table_name(&self.table_name)
+            .key("project_uuid", AttributeValue::S(project_uuid))
+            .key("day", AttributeValue::S(counted_key(&deployment_uuid)))
+            .consistent_read(true);
+
+        info!("ProjectStatsHandler::counted - send tx");
+        match tx.send().await {
+            Ok(res) => match res.item {
+                Some(item) => match CountedBuildParser::parse(item) {
+                    Ok(counted) => Ok(Some(counted)),
+                    Err(error) => {
+                        error!(
+                            "ProjectStatsHandler::counted - failed to parse counted build: {}",
+                            error
+                        );
+                        Err(Report::new(HandlerError::new(&error.to_string())))
+                    }
+                },
+                None => Ok(None),
+            },
+            Err(err) => {
+                error!(
+                    "ProjectStatsHandler::counted - failed to get counted build: {:?}",
+                    err
+                );
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+
+    /// When an environment started failing, None when it is not failing
+    pub async fn incident(
+        &self,
+        project_uuid: String,
+        environment: String,
+    ) -> Result<Option<DateTime<Utc>>, Report<HandlerError>> {
+        info!(
+            "ProjectStatsHandler::incident - project: {}, environment: {}",
+            project_uuid, environment
+        );
+        let attribute = incident_attribute(&environment);
+        let tx = self
+            .table
+            .get_item()
+            .table_name(&self.table_name)
+            .key("project_uuid", AttributeValue::S(project_uuid))
+            .key("day", AttributeValue::S(STATS_STATE_KEY.to_string()))
+            .projection_expression("#environment")
+            .expression_attribute_names("#environment", &attribute)
+            .consistent_read(true);
+
+        info!("ProjectStatsHandler::incident - send tx");
+        match tx.send().await {
+            Ok(res) => Ok(res
+                .item
+                .as_ref()
+                .and_then(|item| item.get(&attribute))
+                .and_then(|value| value.as_n().ok())
+                .and_then(|value| value.parse().ok())
+                .map(|timestamp| Utc.timestamp(timestamp, 0))),
+            Err(err) => {
+                error!(
+                    "ProjectStatsHandler::incident - failed to get state: {:?}",
+                    err
+                );
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+
+    /// Applies the counters, the counted status and the incident change of a
+    /// deployment in one transaction. Returns `false` when the deployment or
+    /// the incident changed since they were read.
+    pub async fn apply(
+        &self,
+        project_uuid: String,
+        environment: String,
+        update: &StatsUpdate,
+    ) -> Result<bool, Report<HandlerError>> {
+        info!(
+            "ProjectStatsHandler::apply - project: {}, deployment: {}, status: {}, previous: {:?}",
+            project_uuid,
+            update.counted.deployment_uuid,
+            update.counted.status,
+            update.previous_status
+        );
+        let mut changes = vec![
+            self.counters(&project_uuid, &update.counted.day, &update.increments),
+            self.count(&project_uuid, update),
+        ];
+        if let Some(incident) = update.incident {
+            changes.push(self.incident_change(&project_uuid, &environment, incident));
+        }
+
+        let tx = self
+            .table
+            .transact_write_items()
+            .set_transact_items(Some(changes));
+
+        info!("ProjectStatsHandler::apply - send transaction");
+        match tx.send().await {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError { err, .. }) if condition_failed(&err) => {
+                info!("ProjectStatsHandler::apply - transaction condition not met");
+                Ok(false)
+            }
+            Err(err) => {
+                error!(
+                    "ProjectStatsHandler::apply - failed to update stats: {:?}",
+                    err
+                );
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+
+    fn counters(
+        &self,
+        project_uuid: &str,
+        day: &str,
+        increments: &[(String, i64)],
+    ) -> TransactWriteItem {
+        let mut expression = Vec::new();
+        let mut names = HashMap::new();
+        let mut values = HashMap::new();
+        for (index, (name, increment)) in increments.iter().enumerate() {
+            expression.push(format!("#c{} :c{}", index, index));
+            names.insert(format!("#c{}", index), name.clone());
+            values.insert(
+                format!(":c{}", index),
+                AttributeValue::N(increment.to_string()),
+            );
+        }
+
+        TransactWriteItem::builder()
+            .update(
+                Update::builder()
+                    .table_name(&self.table_name)
+                    .key("project_uuid", AttributeValue::S(project_uuid.to_string()))
+                    .key("day", AttributeValue::S(day.to_string()))
+                    .update_expression(format!("ADD {}", expression.join(", ")))
+                    .set_expression_attribute_names(Some(names))
+                    .set_expression_attribute_values(Some(values))
+                    .build(),
+            )
+            .build()
+    }
+
+    /// Write of the counted status, only if it is still the one it was read with
+    fn count(&self, project_uuid: &str, update: &StatsUpdate) -> TransactWriteItem {
+        let counted = &update.counted;
+        let mut item = HashMap::new();
+        item.insert(
+            "project_uuid".to_string(),
+            AttributeValue::S(project_uuid.to_string()),
+        );
+        item.insert(
+            "day".to_string(),
+            AttributeValue::S(counted_key(&counted.deployment_uuid)),
+        );
+        item.insert(
+            "deployment_uuid".to_string(),
+            AttributeValue::S(counted.deployment_uuid.clone()),
+        );
+        item.insert(
+            "status".to_string(),
+            AttributeValue::S(counted.status.clone()),
+        );
+        item.insert(
+            "counted_day".to_string(),
+            AttributeValue::S(counted.day.clone()),
+        );
+        if let Some(phase) = counted.failing_phase.as_ref() {
+            item.insert(
+                "failing_phase".to_string(),
+                AttributeValue::S(phase.clone()),
+            );
+        }
+        if let Some(recovery) = counted.recovery_seconds {
+            item.insert(
+                "recovery_seconds".to_string(),
+                AttributeValue::N(recovery.to_string()),
+            );
+        }
+        if let Some(since) = counted.failing_since {
+            item.insert(
+                "failing_since".to_string(),
+                AttributeValue::N(since.to_string()),
+            );
+        }
+
+        let put = Put::builder()
+            .table_name(&self.table_name)
+            .set_item(Some(item))
+            .expression_attribute_names("#status", "status");
+        let put = match update.previous_status.as_ref() {
+            Some(previous) => put
+                .condition_expression("#status = :previous")
+                .expression_attribute_values(":previous", AttributeValue::S(previous.clone())),
+            None => put.condition_expression("attribute_not_exists(#status)"),
+        };
+
+        TransactWriteItem::builder().put(put.build()).build()
+    }
+
+    fn incident_change(
+        &self,
+        project_uuid: &str,
+        environment: &str,
+        incident: IncidentChange,
+    ) -> TransactWriteItem {
+        let update = Update::builder()
+            .table_name(&self.table_name)
+            .key("project_uuid", AttributeValue::S(project_uuid.to_string()))
+            .key("day", AttributeValue::S(STATS_STATE_KEY.to_string()))
+            .expression_attribute_names("#environment", incident_attribute(environment));
+        let update = match incident {
+            IncidentChange::Open(since) => update
+                .update_expression("SET #environment = if_not_exists(#environment, :since)")
+                .expression_attribute_values(":since", AttributeValue::N(since.to_string())),
+            // Closed only if no other deployment closed or opened it again since
+            IncidentChange::Close(since) => update
+                .update_expression("REMOVE #environment")
+                .condition_expression("#environment = :since")
+                .expression_attribute_values(":since", AttributeValue::N(since.to_string())),
+        };
+
+        TransactWriteItem::builder().update(update.build()).build()
+    }
+
+    /// Deletes every item of a deleted project, days, counted deployments and
+    /// failing environments. Returns how many were deleted.
+    pub async fn delete_project(
+        &self,
+        project_uuid: String,
+    ) -> Result<usize, Report<HandlerError>> {
+        info!(
+            "ProjectStatsHandler::delete_project - project: {}",
+            project_uuid
+        );
+        let tx = self
+            .table
+            .query()
+            .table_name(&self.table_name)
+            .key_condition_expression("#project_uuid = :project_uuid")
+            .projection_expression("#day")
+            .expression_attribute_names("#project_uuid", "project_uuid")
+            .expression_attribute_names("#day", "day")
+            .expression_attribute_values(":project_uuid", AttributeValue::S(project_uuid.clone()));
+
+        info!("ProjectStatsHandler::delete_project - send tx");
+        let result: Result<Vec<_>, SdkError<QueryError>> =
+            tx.into_paginator().items().send().collect().await;
+        let items = match result {
+            Ok(items) => items,
+            Err(err) => {
+                error!(
+                    "ProjectStatsHandler::delete_project - failed to list stats: {}",
+                    err
+                );
+                return Err(Report::new(HandlerError::new(&err.to_string())));
+            }
+        };
+
+        for day in items.iter().filter_map(|item| item.get("day")) {
+            let tx = self
+                .table
+                .delete_item()
+                .table_name(&self.table_name)
+                .key("project_uuid", AttributeValue::S(project_uuid.clone()))
+                .key("day", day.clone());
+
+            if let Err(err) = tx.send().await {
+                error!(
+                    "ProjectStatsHandler::delete_project - failed to delete stats: {:?}",
+                    err
+                );
+                return Err(Report::new(HandlerError::new(&err.to_string())));
+            }
+        }
+
+        Ok(items.len())
+    }
+
+    /// Daily statistics of a project between two days, both included
+    pub async fn list(
+        &self,
+        project_uuid: String,
+        from: String,
+        to: String,
+    ) -> Result<Vec<DailyStats>, Report<HandlerError>> {
+        info!(
+            "ProjectStatsHandler::list - project: {}, from: {}, to: {}",
+            project_uuid, from, to
+        );
+        let tx = self
+            .table
+            .query()
+            .table_name(&self.table_name)
+            .key_condition_expression(
+                "#project_uuid = :project_uuid AND #day BETWEEN :from AND :to",
+            )
+            .expression_attribute_names("#project_uuid", "project_uuid")
+            .expression_attribute_names("#day", "day")
+            .expression_attribute_values(":project_uuid", AttributeValue::S(project_uuid))
+            .expression_attribute_values(":from", AttributeValue::S(from))
+            .expression_attribute_values(":to", AttributeValue::S(to));
+
+        info!("ProjectStatsHandler::list - send tx");
+        let result: Result<Vec<_>, SdkError<QueryError>> =
+            tx.into_paginator().items().send().collect().await;
+        match result {
+            Ok(items) => {
+                let mut data = Vec::new();
+                for item in items {
+                    match DailyStatsParser::parse(item) {
+                        Ok(parsed) => data.push(parsed),
+                        Err(error) => error!(
+                            "ProjectStatsHandler::list - parse error (skip from result): {}",
+                            error
+                        ),
+                    }
+                }
+                Ok(data)
+            }
+            Err(err) => {
+                error!("ProjectStatsHandler::list - failed to list stats: {}", err);
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+}
+
+/// Attribute of the state item holding when an environment started failing
+fn incident_attribute(environment: &str) -> String {
+    format!("failing_since_{}", environment)
+}
+
+/// Sort key of the item holding the status a deployment was counted with
+fn counted_key(deployment_uuid: &str) -> String {
+    format!("{}{}", STATS_COUNTED_PREFIX, deployment_uuid)
+}
+
+/// Counts finished builds in the daily statistics of their project
+pub struct ProjectStatsSink {
+    stats: ProjectStatsHandler,
+}
+impl ProjectStatsSink {
+    pub fn new(stats: ProjectStatsHandler) -> Self {
+        Self { stats }
+    }
+}
+#[async_trait]
+impl OutboxSink for ProjectStatsSink {
+    fn name(&self) -> &str {
+        "stats"
+    }
+
+    async fn publish(
+        &self,
+        event: &OutboxEvent,
+        deployment: &ProjectDeployment,
+    ) -> Result<(), Report<HandlerError>> {
+        let status = match event.status() {
+            Some(status @ ProjectDeploymentStatus::Succeeded)
+            | Some(status @ ProjectDeploymentStatus::Failed) => status,
+            _ => return Ok(()),
+        };
+        let outcome = match event {
+            OutboxEvent::DeploymentStatusChanged { outcome, .. } => outcome,
+            _ => return Ok(()),
+        };
+        let finished_at = deployment
+            .build
+            .as_ref()
+            .and_then(|build| build.end_time)
+            .map(|timestamp| Utc.timestamp(timestamp, 0))
+            .unwrap_or_else(Utc::now);
+
+        let project_uuid = deployment.project.uuid.clone();
+        let counted = self
+            .stats
+            .counted(project_uuid.clone(), deployment.uuid.clone())
+            .await?;
+        let failing_since = self
+            .stats
+            .incident(project_uuid.clone(), deployment.environment.clone())
+            .await?;
+        let update = match StatsUpdate::new(
+            deployment.uuid.clone(),
+            status,
+            outcome,
+            finished_at,
+            counted.as_ref(),
+            failing_since,
+        ) {
+            Some(update) => update,
+            None => {
+                info!(
+                    "ProjectStatsSink::publish - already counted: {}",
+                    deployment.uuid
+                );
+                return Ok(());
+            }
+        };
+
+        // The relay retries the entry, which reads the new state again
+        match self
+            .stats
+            .apply(project_uuid, deployment.environment.clone(), &update)
+            .await?
+        {
+            true => Ok(()),
+            false => Err(Report::new(HandlerError::new(
+                "Project stats changed concurrently",
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod stats_tests {
+    use super::*;
+    use crate::models::{
+        outbox::{BuildOutcome, PhaseDuration},
+        stats::{
+            BuildSample, DurationCounters, ProjectStats, StatsWindow, DURATION_BUCKETS_SECONDS,
+        },
+    };
+
+    fn phase(phase: &str, status: &str, duration_seconds: i64) -> PhaseDuration {
+        PhaseDuration {
+            phase: phase.to_string(),
+            status: Some(status.to_string()),
+            duration_seconds,
+        }
+    }
+
+    fn outcome(duration_seconds: i64, phases: Vec<PhaseDuration>) -> BuildOutcome {
+        BuildOutcome {
+            duration_seconds: Some(duration_seconds),
+            phases,
+            ..BuildOutcome::default()
+        }
+    }
+
+    /// Daily stats as stored after recording the samples
+    fn day(day: &str, samples: &[BuildSample]) -> DailyStats {
+        let mut item = HashMap::new();
+        item.insert(
+            "project_uuid".to_string(),
+            AttributeValue::S("project".to_string()),
+        );
+        item.insert("day".to_string(), AttributeValue::S(day.to_string()));
+        for sample in samples {
+            for (name, increment) in sample.increments() {
+                let current: i64 = item
+                    .get(&name)
+                    .and_then(|value: &AttributeValue| value.as_n().ok())
+                    .map(|value| value.parse().unwrap())
+                    .unwrap_or(0);
+                item.insert(name, AttributeValue::N((current + increment).to_string()));
+            }
+        }
+
+        DailyStatsParser::parse(item).unwrap()
+    }
+
+    #[test]
+    fn parses_stats_windows() {
+        assert_eq!(StatsWindow::parse(None).unwrap().days, 30);
+        assert_eq!(
+            StatsWindow::parse(Some("7d".to_string())).unwrap(),
+            StatsWindow { days: 7 }
+        );
+        assert!(StatsWindow::parse(Some("7".to_string())).is_err());
+        assert!(StatsWindow::parse(Some("0d".to_string())).is_err());
+        assert!(StatsWindow::parse(Some("366d".to_string())).is_err());
+        assert!(StatsWindow::parse(Some("1w".to_string())).is_err());
+
+        let now = Utc.ymd(2022, 12, 12).and_hms(10, 0, 0);
+        assert_eq!(
+            StatsWindow { days: 30 }.range(now),
+            ("2022-11-13".to_string(), "2022-12-12".to_string())
+        );
+    }
+
+    #[test]
+    fn samples_find_the_failing_phase() {
+        let failed = BuildSample::new(
+            ProjectDeploymentStatus::Failed,
+            &outcome(
+                90,
+                vec![
+                    phase("SUBMITTED", "SUCCEEDED", 1),
+                    phase("PRE_BUILD", "SUCCEEDED", 20),
+                    phase("BUILD", "FAILED", 29),
+                ],
+            ),
+            None,
+        );
+        assert!(!failed.succeeded);
+        assert_eq!(failed.failing_phase, Some("BUILD".to_string()));
+
+        let succeeded = BuildSample::new(
+            ProjectDeploymentStatus::Succeeded,
+            &outcome(90, vec![phase("BUILD", "SUCCEEDED", 29)]),
+            Some(600),
+        );
+        assert_eq!(succeeded.failing_phase, None);
+        assert_eq!(succeeded.recovery_seconds, Some(600));
+    }
+
+    #[test]
+    fn counters_round_trip_through_the_item() {
+        let sample = BuildSample::new(
+            ProjectDeploymentStatus::Failed,
+            &outcome(
+                100,
+                vec![
+                    phase("PRE_BUILD", "SUCCEEDED", 20),
+                    phase("BUILD", "FAILED", 70),
+                ],
+            ),
+            None,
+        );
+        let stats = day("2022-12-12", &[sample.clone(), sample]);
+
+        assert_eq!(stats.project_uuid, "project");
+        assert_eq!(stats.day, "2022-12-12");
+        assert_eq!(stats.builds, 2);
+        assert_eq!(stats.failed, 2);
+        assert_eq!(stats.succeeded, 0);
+        assert_eq!(stats.duration.count, 2);
+        assert_eq!(stats.duration.sum, 200);
+        assert_eq!(stats.duration.histogram[DurationCounters::bucket(100)], 2);
+        assert_eq!(stats.phases["PRE_BUILD"].sum, 40);
+        assert_eq!(
+            stats.phases["PRE_BUILD"].histogram[DurationCounters::bucket(20)],
+            2
+        );
+        assert_eq!(stats.phases["BUILD"].count, 2);
+        assert_eq!(stats.failed_phases["BUILD"], 2);
+        assert_eq!(stats.recovery.count, 0);
+    }
+
+    #[test]
+    fn updates_count_a_deployment_once_per_status() {
+        let finished_at = Utc.ymd(2022, 12, 12).and_hms(10, 0, 0);
+        let failed_outcome = outcome(100, vec![phase("BUILD", "FAILED", 70)]);
+
+        let failed = StatsUpdate::new(
+            "deployment".to_string(),
+            ProjectDeploymentStatus::Failed,
+            &failed_outcome,
+            finished_at,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(failed.previous_status, None);
+        assert_eq!(failed.counted.day, "2022-12-12");
+        assert_eq!(failed.counted.failing_phase, Some("BUILD".to_string()));
+        assert_eq!(
+            failed.incident,
+            Some(IncidentChange::Open(finished_at.timestamp()))
+        );
+        assert!(failed.increments.contains(&("builds".to_string(), 1)));
+
+        // The same status again is a duplicate
+        assert_eq!(
+            StatsUpdate::new(
+                "deployment".to_string(),
+                ProjectDeploymentStatus::Failed,
+                &failed_outcome,
+                finished_at,
+                Some(&failed.counted),
+                Some(finished_at),
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn corrections_move_the_deployment_between_status_counters() {
+        let finished_at = Utc.ymd(2022, 12, 12).and_hms(10, 0, 0);
+        let since = finished_at - chrono::Duration::hours(1);
+        let build = outcome(100, vec![phase("BUILD", "SUCCEEDED", 70)]);
+
+        let succeeded = StatsUpdate::new(
+            "deployment".to_string(),
+            ProjectDeploymentStatus::Succeeded,
+            &build,
+            finished_at,
+            None,
+            Some(since),
+        )
+        .unwrap();
+        assert_eq!(succeeded.counted.recovery_seconds, Some(3600));
+        assert_eq!(succeeded.counted.failing_since, Some(since.timestamp()));
+        assert_eq!(
+            succeeded.incident,
+            Some(IncidentChange::Close(since.timestamp()))
+        );
+
+        // Corrected the next day, the counters of the build day are fixed
+        let failed_build = outcome(100, vec![phase("BUILD", "FAILED", 70)]);
+        let failed = StatsUpdate::new(
+            "deployment".to_string(),
+            ProjectDeploymentStatus::Failed,
+            &failed_build,
+            finished_at + chrono::Duration::days(1),
+            Some(&succeeded.counted),
+            None,
+        )
+        .unwrap();
+        assert_eq!(failed.previous_status, Some("SUCCEEDED".to_string()));
+        assert_eq!(failed.counted.day, "2022-12-12");
+        assert_eq!(
+            failed.incident,
+            Some(IncidentChange::Open(since.timestamp()))
+        );
+
+        let stats = DailyStats::from_counters(
+            "project".to_string(),
+            "2022-12-12".to_string(),
+            &succeeded
+                .increments
+                .iter()
+                .chain(failed.increments.iter())
+                .fold(HashMap::new(), |mut counters, (name, increment)| {
+                    *counters.entry(name.clone()).or_insert(0) += increment;
+                    counters
+                }),
+        );
+        assert_eq!(stats.builds, 1);
+        assert_eq!(stats.succeeded, 0);
+        assert_eq!(stats.failed, 1);
+        assert_eq!(stats.failed_phases["BUILD"], 1);
+        assert_eq!(stats.duration.count, 1);
+        assert_eq!(stats.recovery.count, 0);
+        assert_eq!(stats.recovery.sum, 0);
+    }
+
+    #[test]
+    fn percentiles_use_bucket_bounds() {
+        let mut counters = DurationCounters::default();
+        assert_eq!(counters.percentile(0.95), None);
+
+        for seconds in [
+            10, 20, 50, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100, 100,
+            100, 500,
+        ] {
+            counters.count += 1;
+            counters.sum += seconds;
+            counters.add_to_bucket(DurationCounters::bucket(seconds), 1);
+        }
+        // 19 of the 20 samples are at most 120 seconds
+        assert_eq!(counters.percentile(0.95), Some(120));
+        assert_eq!(counters.percentile(1.0), Some(600));
+        assert_eq!(counters.summary().unwrap().mean_seconds, 109.0);
+
+        counters.add_to_bucket(DurationCounters::bucket(100000), 100);
+        assert_eq!(
+            DurationCounters::bucket(100000),
+            DURATION_BUCKETS_SECONDS.len()
+        );
+        assert_eq!(counters.percentile(0.95), None);
+    }
+
+    #[test]
+    fn aggregates_the_days_of_the_window() {
+        let now = Utc.ymd(2022, 12, 12).and_hms(10, 0, 0);
+        let failed = BuildSample::new(
+            ProjectDeploymentStatus::Failed,
+            &outcome(
+                100,
+                vec![
+                    phase("INSTALL", "SUCCEEDED", 30),
+                    phase("BUILD", "FAILED", 60),
+                ],
+            ),
+            None,
+        );
+        let install_failed = BuildSample::new(
+            ProjectDeploymentStatus::Failed,
+            &outcome(40, vec![phase("INSTALL", "FAILED", 30)]),
+            None,
+        );
+        let recovered = BuildSample::new(
+            ProjectDeploymentStatus::Succeeded,
+            &outcome(
+                200,
+                vec![
+                    phase("INSTALL", "SUCCEEDED", 40),
+                    phase("BUILD", "SUCCEEDED", 150),
+                ],
+            ),
+            Some(3600),
+        );
+        let succeeded = BuildSample::new(
+            ProjectDeploymentStatus::Succeeded,
+            &outcome(
+                300,
+                vec![
+                    phase("INSTALL", "SUCCEEDED", 50),
+                    phase("BUILD", "SUCCEEDED", 240),
+                ],
+            ),
+            None,
+        );
+        let days = vec![
+            // Outside of a 7 days window
+            day(
+                "2022-12-01",
+                &[failed.clone(), failed.clone(), failed.clone()],
+            ),
+            day("2022-12-10", &[failed, install_failed, recovered]),
+            day("2022-12-12", &[succeeded.clone(), succeeded]),
+        ];
+
+        let stats =
+            ProjectStats::aggregate("project".to_string(), StatsWindow { days: 7 }, now, &days);
+        assert_eq!(stats.window, "7d");
+        assert_eq!(stats.from, "2022-12-06");
+        assert_eq!(stats.to, "2022-12-12");
+        assert_eq!(stats.builds, 5);
+        assert_eq!(stats.succeeded, 3);
+        assert_eq!(stats.failed, 2);
+        assert_eq!(stats.success_rate, Some(0.6));
+
+        let duration = stats.duration.unwrap();
+        assert_eq!(duration.count, 5);
+        assert_eq!(duration.mean_seconds, 188.0);
+        assert_eq!(duration.p95_seconds, Some(300));
+
+        // Phases are listed in build order
+        let phases: Vec<&str> = stats
+            .phases
+            .iter()
+            .map(|stats| stats.phase.as_str())
+            .collect();
+        assert_eq!(phases, vec!["INSTALL", "BUILD"]);
+        assert_eq!(stats.phases[0].duration.count, 5);
+        assert_eq!(stats.phases[0].duration.mean_seconds, 40.0);
+
+        // Tied failing phases go to the earliest one
+        assert_eq!(stats.most_common_failing_phase, Some("INSTALL".to_string()));
+        assert_eq!(stats.mean_time_to_recovery_seconds, Some(3600.0));
+        assert_eq!(stats.recoveries, 1);
+
+        let empty =
+            ProjectStats::aggregate("project".to_string(), StatsWindow { days: 1 }, now, &[]);
+        assert_eq!(empty.builds, 0);
+        assert_eq!(empty.success_rate, None);
+        assert_eq!(empty.duration, None);
+        assert_eq!(empty.most_common_failing_phase, None);
+    }
+}
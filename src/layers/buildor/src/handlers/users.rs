@@ -1,9 +1,14 @@
 use std::collections::HashMap;
 
+use crate::handlers::project_deployments::condition_failed;
 use crate::models::common::{AsDynamoDBAttributeValue, MissingModelPropertyError};
-use crate::models::handlers::{HandlerCreate, HandlerError, HandlerList};
-use crate::models::user::{User, UserCreatePayload};
+use crate::models::handlers::{
+    HandlerCreate, HandlerDelete, HandlerError, HandlerGet, HandlerList, HandlerUpdate,
+};
+use crate::models::list_query::ListQuery;
+use crate::models::user::{normalize_email, User, UserCreatePayload, UserEmail, UserUpdatePayload};
 use async_trait::async_trait;
+use aws_sdk_dynamodb::model::{Delete, Put, TransactWriteItem, Update};
 use aws_sdk_dynamodb::types::SdkError;
 use aws_sdk_dynamodb::Client;
 use aws_sdk_dynamodb::{error::ScanError, model::AttributeValue};
@@ -31,6 +36,12 @@ impl UsersParser {
             None => return Err(Report::new(MissingModelPropertyError::new("lname"))),
         };
 
+        // Users created before emails were introduced have none
+        let email = match item.get("email") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => "-".to_string(),
+        };
+
         let updated_at = match item.get("updated_at") {
             Some(value) => value.as_s().unwrap().to_string(),
             None => return Err(Report::new(MissingModelPropertyError::new("updated_at"))),
@@ -45,6 +56,7 @@ impl UsersParser {
             uuid,
             fname,
             lname,
+            email,
             updated_at,
             created_at,
         })
@@ -60,60 +72,347 @@ impl UsersParser {
     }
 }
 
-#[cfg(test)]
-mod project_parser_tests {
-    use super::*;
+pub struct UsersHandler {
+    table: Client,
+    table_name: String,
+    emails_table_name: Option<String>,
+}
 
-    // Validate required properties
-    #[test]
-    fn fails_on_missing_uuid() {
-        let input: HashMap<String, AttributeValue> = HashMap::new();
-        match UsersParser::parse(input) {
-            Err(error) => assert_eq!(error.to_string(), "Missing model property: uuid"),
-            _ => assert_eq!("", "Should have panicked but it did not"),
+impl UsersHandler {
+    pub fn new(table: Client, table_name: String) -> Self {
+        UsersHandler {
+            table,
+            table_name,
+            emails_table_name: None,
         }
     }
 
-    #[test]
-    fn fails_on_missing_fname() {
-        let mut input: HashMap<String, AttributeValue> = HashMap::new();
-        input.insert(
-            "uuid".to_string(),
-            AttributeValue::S("uuid-value".to_string()),
-        );
-        match UsersParser::parse(input) {
-            Err(error) => assert_eq!(error.to_string(), "Missing model property: fname"),
-            _ => assert_eq!("", "Should have panicked but it did not"),
+    /// Keeps one item per email in the user emails table, written in the same
+    /// transaction as the user so that an email can only belong to one user
+    pub fn with_emails(mut self, table_name: String) -> Self {
+        self.emails_table_name = Some(table_name);
+        self
+    }
+
+    fn emails_table_name(&self) -> Result<&String, Report<HandlerError>> {
+        self.emails_table_name
+            .as_ref()
+            .ok_or_else(|| Report::new(HandlerError::new("User emails table not set")))
+    }
+
+    /// Claims an email, only when no user has it yet
+    fn put_email(
+        &self,
+        email: &str,
+        user_uuid: &str,
+    ) -> Result<TransactWriteItem, Report<HandlerError>> {
+        Ok(TransactWriteItem::builder()
+            .put(
+                Put::builder()
+                    .table_name(self.emails_table_name()?)
+                    .set_item(Some(UserEmail::new(email, user_uuid).as_hashmap()))
+                    .condition_expression("attribute_not_exists(#email)")
+                    .expression_attribute_names("#email", "email")
+                    .build(),
+            )
+            .build())
+    }
+
+    /// Releases an email, users created before emails were unique may not have
+    /// claimed theirs
+    fn delete_email(
+        &self,
+        email: &str,
+        user_uuid: &str,
+    ) -> Result<TransactWriteItem, Report<HandlerError>> {
+        Ok(TransactWriteItem::builder()
+            .delete(
+                Delete::builder()
+                    .table_name(self.emails_table_name()?)
+                    .key("email", AttributeValue::S(normalize_email(email)))
+                    .condition_expression("attribute_not_exists(#email) OR #user_uuid = :user_uuid")
+                    .expression_attribute_names("#email", "email")
+                    .expression_attribute_names("#user_uuid", "user_uuid")
+                    .expression_attribute_values(
+                        ":user_uuid",
+                        AttributeValue::S(user_uuid.to_string()),
+                    )
+                    .build(),
+            )
+            .build())
+    }
+
+    /// Sends the writes of a user and its email in a single transaction.
+    /// Returns `false` when a condition was not met.
+    async fn transact(
+        &self,
+        method: &str,
+        items: Vec<TransactWriteItem>,
+    ) -> Result<bool, Report<HandlerError>> {
+        let tx = self
+            .table
+            .transact_write_items()
+            .set_transact_items(Some(items));
+
+        println!("UsersHandler::{} - send transaction", method);
+        match tx.send().await {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError { err, .. }) if condition_failed(&err) => {
+                println!("UsersHandler::{} - transaction condition not met", method);
+                Ok(false)
+            }
+            Err(err) => {
+                println!("UsersHandler::{} - transaction failed: {:?}", method, err);
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
         }
     }
 
-    #[test]
-    fn fails_on_missing_lname() {
-        let mut input: HashMap<String, AttributeValue> = HashMap::new();
-        input.insert(
-            "uuid".to_string(),
-            AttributeValue::S("uuid-value".to_string()),
-        );
-        input.insert(
-            "fname".to_string(),
-            AttributeValue::S("fname-value".to_string()),
-        );
+    /// Creates a user, none when its email already belongs to another user
+    pub async fn create_unique(
+        &self,
+        payload: UserCreatePayload,
+    ) -> Result<Option<User>, Report<HandlerError>> {
+        println!("UsersHandler::create_unique - payload: {:?}", payload);
+        let user = User::new(payload);
 
-        match UsersParser::parse(input) {
-            Err(error) => assert_eq!(error.to_string(), "Missing model property: lname"),
-            _ => assert_eq!("", "Should have panicked but it did not"),
+        let mut items = vec![TransactWriteItem::builder()
+            .put(
+                Put::builder()
+                    .table_name(&self.table_name)
+                    .set_item(Some(user.as_hashmap()))
+                    .condition_expression("attribute_not_exists(#uuid)")
+                    .expression_attribute_names("#uuid", "uuid")
+                    .build(),
+            )
+            .build()];
+        if let Some(email) = user.email() {
+            if self.legacy_holder(email).await?.is_some() {
+                return Ok(None);
+            }
+            items.push(self.put_email(email, &user.uuid)?);
+        }
+
+        match self.transact("create_unique", items).await? {
+            true => Ok(Some(user)),
+            false => Ok(None),
         }
     }
-}
 
-pub struct UsersHandler {
-    table: Client,
-    table_name: String,
-}
+    /// Updates a user, moving its email claim when the email changes. Returns
+    /// `false` when the new email already belongs to another user or the user
+    /// changed meanwhile, any change counts when `unmodified` is set.
+    pub async fn update_unique(
+        &self,
+        user: &User,
+        payload: UserUpdatePayload,
+        unmodified: bool,
+    ) -> Result<bool, Report<HandlerError>> {
+        println!("UsersHandler::update_unique - uuid: {}", user.uuid);
+        println!("UsersHandler::update_unique - payload: {:?}", payload);
+        let current = user.email().map(normalize_email);
+        let new = payload.email.as_deref().map(normalize_email);
+        let email_changed = new.is_some() && new != current;
+        let expressions = self.get_update_expressions(payload);
 
-impl UsersHandler {
-    pub fn new(table: Client, table_name: String) -> Self {
-        UsersHandler { table, table_name }
+        // The email read is the one released, so it must not have changed since
+        let mut update = Update::builder()
+            .table_name(&self.table_name)
+            .key("uuid", AttributeValue::S(user.uuid.to_owned()))
+            .set_expression_attribute_names(Some(expressions.attribute_names))
+            .set_expression_attribute_values(Some(expressions.attribute_values))
+            .expression_attribute_names("#uuid", "uuid")
+            .expression_attribute_names("#current_email", "email")
+            .expression_attribute_values(":current_email", AttributeValue::S(user.email.to_owned()))
+            .condition_expression(
+                "attribute_exists(#uuid) AND (attribute_not_exists(#current_email) OR #current_email = :current_email)",
+            )
+            .update_expression(expressions.update_expression);
+        if unmodified {
+            update = update
+                .condition_expression(
+                    "attribute_exists(#uuid) AND (attribute_not_exists(#current_email) OR #current_email = :current_email) AND #updated_at = :read_updated_at",
+                )
+                .expression_attribute_values(
+                    ":read_updated_at",
+                    AttributeValue::S(user.updated_at.to_owned()),
+                );
+        }
+        let mut items = vec![TransactWriteItem::builder().update(update.build()).build()];
+        if let Some(email) = new.as_deref().filter(|_| email_changed) {
+            if self.legacy_holder(email).await?.is_some() {
+                return Ok(false);
+            }
+        }
+        if email_changed {
+            if let Some(email) = current.as_deref() {
+                items.push(self.delete_email(email, &user.uuid)?);
+            }
+            if let Some(email) = new.as_deref() {
+                items.push(self.put_email(email, &user.uuid)?);
+            }
+        }
+
+        self.transact("update_unique", items).await
+    }
+
+    /// Deletes a user and releases its email. Returns `false` when the user is
+    /// gone, or changed since it was read when `unmodified` is set.
+    pub async fn delete_user(
+        &self,
+        user: &User,
+        unmodified: bool,
+    ) -> Result<bool, Report<HandlerError>> {
+        println!("UsersHandler::delete_user - uuid: {}", user.uuid);
+        let mut delete = Delete::builder()
+            .table_name(&self.table_name)
+            .key("uuid", AttributeValue::S(user.uuid.to_owned()))
+            .condition_expression("attribute_exists(#uuid)")
+            .expression_attribute_names("#uuid", "uuid");
+        if unmodified {
+            delete = delete
+                .condition_expression("attribute_exists(#uuid) AND #updated_at = :updated_at")
+                .expression_attribute_names("#updated_at", "updated_at")
+                .expression_attribute_values(
+                    ":updated_at",
+                    AttributeValue::S(user.updated_at.to_owned()),
+                );
+        }
+
+        let mut items = vec![TransactWriteItem::builder().delete(delete.build()).build()];
+        if let Some(email) = user.email() {
+            items.push(self.delete_email(email, &user.uuid)?);
+        }
+
+        self.transact("delete_user", items).await
+    }
+
+    /// Users of the list endpoint, only the matching ones and the fields asked
+    pub async fn search(&self, list: &ListQuery) -> Result<Vec<Value>, Report<HandlerError>> {
+        println!("UsersHandler::search - list: {:?}", list);
+        let tx = list
+            .scan(self.table.scan().table_name(&self.table_name))
+            .into_paginator()
+            .items();
+
+        println!("UsersHandler::search - send tx");
+        let result: Result<Vec<_>, SdkError<ScanError>> = tx.send().collect().await;
+        match result {
+            Ok(items) => Ok(list.render(items, UsersParser::parse)),
+            Err(err) => {
+                println!("UsersHandler::search - failed to list users: {}", err);
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+
+    /// User an email belongs to, compared once normalized
+    pub async fn find_by_email(&self, email: &str) -> Result<Option<User>, Report<HandlerError>> {
+        let email = normalize_email(email);
+        println!("UsersHandler::find_by_email - email: {}", email);
+
+        match self.claimant(&email).await? {
+            Some(user_uuid) => self.get(user_uuid).await,
+            None => self.legacy_holder(&email).await,
+        }
+    }
+
+    /// Uuid of the user that claimed an email
+    async fn claimant(&self, email: &str) -> Result<Option<String>, Report<HandlerError>> {
+        let tx = self
+            .table
+            .get_item()
+            .table_name(self.emails_table_name()?)
+            .key("email".to_string(), AttributeValue::S(email.to_string()));
+
+        println!("UsersHandler::claimant - send tx");
+        let result = tx.send().await;
+        println!("UsersHandler::claimant - tx response: {:?}", result);
+
+        match result {
+            Ok(res) => Ok(res
+                .item
+                .as_ref()
+                .and_then(|item| item.get("user_uuid"))
+                .and_then(|value| value.as_s().ok())
+                .map(|user_uuid| user_uuid.to_string())),
+            Err(err) => {
+                println!("UsersHandler::claimant - failed to get email: {}", err);
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+
+    /// User holding an unclaimed email. Users created before emails were unique
+    /// only have it on their item, the first lookup scans the users table and
+    /// claims the email for them so that the next ones and the claim
+    /// conditions of other users see it.
+    async fn legacy_holder(&self, email: &str) -> Result<Option<User>, Report<HandlerError>> {
+        let email = normalize_email(email);
+        if self.claimant(&email).await?.is_some() {
+            return Ok(None);
+        }
+
+        let tx = self
+            .table
+            .scan()
+            .table_name(&self.table_name)
+            .filter_expression("attribute_exists(#email)")
+            .expression_attribute_names("#email", "email")
+            .into_paginator()
+            .items();
+
+        println!("UsersHandler::legacy_holder - send tx");
+        let result: Result<Vec<_>, SdkError<ScanError>> = tx.send().collect().await;
+        let holder = match result {
+            Ok(items) => items
+                .into_iter()
+                .filter_map(|item| UsersParser::parse(item).ok())
+                .find(|user| user.email().map(normalize_email).as_deref() == Some(&email)),
+            Err(err) => {
+                println!(
+                    "UsersHandler::legacy_holder - failed to scan users: {}",
+                    err
+                );
+                return Err(Report::new(HandlerError::new(&err.to_string())));
+            }
+        };
+        let holder = match holder {
+            Some(holder) => holder,
+            None => return Ok(None),
+        };
+        println!(
+            "UsersHandler::legacy_holder - claim {} for {}",
+            email, holder.uuid
+        );
+
+        let tx = self
+            .table
+            .put_item()
+            .table_name(self.emails_table_name()?)
+            .set_item(Some(UserEmail::new(&email, &holder.uuid).as_hashmap()))
+            .condition_expression("attribute_not_exists(#email)")
+            .expression_attribute_names("#email", "email");
+        match tx.send().await {
+            Ok(_) => Ok(Some(holder)),
+            // Claimed meanwhile, by the holder or by a user created since
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                println!("UsersHandler::legacy_holder - email claimed meanwhile");
+                match self.claimant(&email).await? {
+                    Some(user_uuid) => self.get(user_uuid).await,
+                    None => Ok(None),
+                }
+            }
+            Err(err) => {
+                println!(
+                    "UsersHandler::legacy_holder - failed to claim email: {}",
+                    err
+                );
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
     }
 }
 
@@ -121,6 +420,14 @@ impl UsersHandler {
 impl HandlerCreate<User, UserCreatePayload, HandlerError> for UsersHandler {
     async fn create(&self, payload: UserCreatePayload) -> Result<User, Report<HandlerError>> {
         println!("UserHandler::create - payload: {:?}", payload);
+        if self.emails_table_name.is_some() {
+            return match self.create_unique(payload).await? {
+                Some(user) => Ok(user),
+                None => Err(Report::new(HandlerError::new(
+                    "User email already registered",
+                ))),
+            };
+        }
         let user = User::new(payload);
 
         let tx = self
@@ -190,3 +497,116 @@ impl HandlerList<User, HandlerError> for UsersHandler {
         Ok(data)
     }
 }
+
+#[async_trait]
+impl HandlerGet<User, HandlerError> for UsersHandler {
+    async fn get(&self, uuid: String) -> Result<Option<User>, Report<HandlerError>> {
+        println!("UsersHandler::get - uuid: {}", uuid);
+
+        let tx = self
+            .table
+            .get_item()
+            .table_name(&self.table_name)
+            .key("uuid".to_string(), AttributeValue::S(uuid));
+
+        println!("UsersHandler::get - send tx");
+        let result = tx.send().await;
+        println!("UsersHandler::get - tx response: {:?}", result);
+
+        match result {
+            Ok(res) => match res.item {
+                Some(value) => match UsersParser::parse(value) {
+                    Ok(user) => Ok(Some(user)),
+                    Err(error) => {
+                        println!("UsersHandler::get - failed to parse user: {}", error);
+                        Ok(None)
+                    }
+                },
+                None => Ok(None),
+            },
+            Err(err) => {
+                println!("UsersHandler::get - failed to get user: {}", err);
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl HandlerUpdate<User, UserUpdatePayload, HandlerError> for UsersHandler {
+    async fn update(
+        &self,
+        uuid: String,
+        payload: UserUpdatePayload,
+    ) -> Result<(), Report<HandlerError>> {
+        let user = match self.get(uuid).await? {
+            Some(user) => user,
+            None => return Err(Report::new(HandlerError::new("User not found"))),
+        };
+
+        match self.update_unique(&user, payload, false).await? {
+            true => Ok(()),
+            false => Err(Report::new(HandlerError::new("User update cancelled"))),
+        }
+    }
+}
+
+#[async_trait]
+impl HandlerDelete<User, HandlerError> for UsersHandler {
+    async fn delete(&self, uuid: String) -> Result<bool, Report<HandlerError>> {
+        println!("UsersHandler::delete - uuid: {}", uuid);
+        match self.get(uuid).await? {
+            Some(user) => self.delete_user(&user, false).await,
+            None => {
+                println!("UsersHandler::delete - user not found");
+                Ok(false)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod users_parser_tests {
+    use super::*;
+
+    // Validate required properties
+    #[test]
+    fn fails_on_missing_uuid() {
+        let input: HashMap<String, AttributeValue> = HashMap::new();
+        match UsersParser::parse(input) {
+            Err(error) => assert_eq!(error.to_string(), "Missing model property: uuid"),
+            _ => assert_eq!("", "Should have panicked but it did not"),
+        }
+    }
+
+    #[test]
+    fn fails_on_missing_fname() {
+        let mut input: HashMap<String, AttributeValue> = HashMap::new();
+        input.insert(
+            "uuid".to_string(),
+            AttributeValue::S("uuid-value".to_string()),
+        );
+        match UsersParser::parse(input) {
+            Err(error) => assert_eq!(error.to_string(), "Missing model property: fname"),
+            _ => assert_eq!("", "Should have panicked but it did not"),
+        }
+    }
+
+    #[test]
+    fn fails_on_missing_lname() {
+        let mut input: HashMap<String, AttributeValue> = HashMap::new();
+        input.insert(
+            "uuid".to_string(),
+            AttributeValue::S("uuid-value".to_string()),
+        );
+        input.insert(
+            "fname".to_string(),
+            AttributeValue::S("fname-value".to_string()),
+        );
+
+        match UsersParser::parse(input) {
+            Err(error) => assert_eq!(error.to_string(), "Missing model property: lname"),
+            _ => assert_eq!("", "Should have panicked but it did not"),
+        }
+    }
+}
@@ -0,0 +1,874 @@
+use async_trait::async_trait;
+use aws_sdk_dynamodb::{error::QueryError, model::AttributeValue, types::SdkError, Client};
+use chrono::{DateTime, Utc};
+use error_stack::Report;
+use hmac::{Hmac, Mac};
+use log::{self, error, info};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::HashMap;
+use tokio_stream::StreamExt;
+
+use crate::{
+    handlers::http::HttpSender,
+    models::{
+        common::{AsDynamoDBAttributeValue, MissingModelPropertyError},
+        handlers::{HandlerCreate, HandlerError, HandlerGet},
+        http::HttpRequest,
+        list_query::ListQuery,
+        project::Project,
+        project_deployment::ProjectDeployment,
+        webhook::{
+            RetryPolicy, Webhook, WebhookCreatePayload, WebhookDelivery, WebhookDeliveryStatus,
+            WebhookEvent,
+        },
+    },
+};
+
+pub const HEADER_EVENT: &str = "X-Buildor-Event";
+pub const HEADER_DELIVERY: &str = "X-Buildor-Delivery";
+pub const HEADER_TIMESTAMP: &str = "X-Buildor-Timestamp";
+pub const HEADER_SIGNATURE: &str = "X-Buildor-Signature";
+
+/// Index of webhooks by project
+pub const INDEX_PROJECT: &str = "project_uuid";
+/// Index of webhooks by owner
+pub const INDEX_USER: &str = "user_uuid";
+/// Index of deliveries by webhook, sorted by creation date
+pub const INDEX_WEBHOOK: &str = "webhook_uuid-created_at";
+/// Index of deliveries by status, sorted by next attempt date
+pub const INDEX_DELIVERY_STATUS: &str = "status-next_attempt_at";
+
+/// HMAC-SHA256 of `{timestamp}.{body}` keyed with the webhook secret, receivers
+/// recompute it from the timestamp and signature headers
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    format!("sha256={:x}", mac.finalize().into_bytes())
+}
+
+/// Body delivered for a deployment event
+pub fn webhook_payload(event: WebhookEvent, deployment: &ProjectDeployment) -> String {
+    json!({
+        "event": event.to_string(),
+        "createdAt": Utc::now().to_rfc3339(),
+        "data": {
+            "deployment": {
+                "uuid": deployment.uuid,
+                "environment": deployment.environment,
+                "status": deployment.status,
+                "build": deployment.build,
+                "createdAt": deployment.created_at,
+            },
+            "project": {
+                "uuid": deployment.project.uuid,
+                "name": deployment.project.name,
+                "repository": deployment.project.repository,
+            },
+        },
+    })
+    .to_string()
+}
+
+/// Signed request for a delivery attempt
+pub fn webhook_request(
+    webhook: &Webhook,
+    delivery: &WebhookDelivery,
+    timestamp: i64,
+) -> HttpRequest {
+    HttpRequest::json(webhook.url.clone(), delivery.payload.clone())
+        .header(HEADER_EVENT, &delivery.event)
+        .header(HEADER_DELIVERY, &delivery.uuid)
+        .header(HEADER_TIMESTAMP, &timestamp.to_string())
+        .header(
+            HEADER_SIGNATURE,
+            &sign(&webhook.secret, timestamp, &delivery.payload),
+        )
+}
+
+pub struct WebhookParser {}
+impl WebhookParser {
+    pub fn parse(
+        item: HashMap<String, AttributeValue>,
+    ) -> Result<Webhook, Report<MissingModelPropertyError>> {
+        let uuid = match item.get("uuid") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("uuid"))),
+        };
+
+        let project_uuid = match item.get("project_uuid") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("project_uuid"))),
+        };
+
+        let user_uuid = match item.get("user_uuid") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("user_uuid"))),
+        };
+
+        let url = match item.get("url") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("url"))),
+        };
+
+        let events = match item.get("events") {
+            Some(value) => value
+                .as_l()
+                .unwrap()
+                .iter()
+                .map(|event| event.as_s().unwrap().to_string())
+                .collect(),
+            None => return Err(Report::new(MissingModelPropertyError::new("events"))),
+        };
+
+        let secret = match item.get("secret") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("secret"))),
+        };
+
+        let updated_at = match item.get("updated_at") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("updated_at"))),
+        };
+
+        let created_at = match item.get("created_at") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("created_at"))),
+        };
+
+        Ok(Webhook {
+            uuid,
+            project_uuid,
+            user_uuid,
+            url,
+            events,
+            secret,
+            updated_at,
+            created_at,
+        })
+    }
+}
+
+pub struct WebhookDeliveryParser {}
+impl WebhookDeliveryParser {
+    pub fn parse(
+        item: HashMap<String, AttributeValue>,
+    ) -> Result<WebhookDelivery, Report<MissingModelPropertyError>> {
+        let uuid = match item.get("uuid") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("uuid"))),
+        };
+
+        let webhook_uuid = match item.get("webhook_uuid") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("webhook_uuid"))),
+        };
+
+        let event = match item.get("event") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("event"))),
+        };
+
+        let payload = match item.get("payload") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("payload"))),
+        };
+
+        let status = match item.get("status") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("status"))),
+        };
+
+        let attempts = match item.get("attempts") {
+            Some(value) => value.as_n().unwrap().parse().unwrap_or(0),
+            None => return Err(Report::new(MissingModelPropertyError::new("attempts"))),
+        };
+
+        let response_status = item
+            .get("response_status")
+            .and_then(|value| value.as_n().unwrap().parse().ok());
+
+        let last_error = item
+            .get("last_error")
+            .map(|value| value.as_s().unwrap().to_string());
+
+        let next_attempt_at = match item.get("next_attempt_at") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => {
+                return Err(Report::new(MissingModelPropertyError::new(
+                    "next_attempt_at",
+                )))
+            }
+        };
+
+        let updated_at = match item.get("updated_at") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("updated_at"))),
+        };
+
+        let created_at = match item.get("created_at") {
+            Some(value) => value.as_s().unwrap().to_string(),
+            None => return Err(Report::new(MissingModelPropertyError::new("created_at"))),
+        };
+
+        Ok(WebhookDelivery {
+            uuid,
+            webhook_uuid,
+            event,
+            payload,
+            status,
+            attempts,
+            response_status,
+            last_error,
+            next_attempt_at,
+            updated_at,
+            created_at,
+        })
+    }
+}
+
+pub struct WebhooksHandler {
+    table: Client,
+    table_name: String,
+}
+impl WebhooksHandler {
+    pub fn new(client: Client, table_name: String) -> Self {
+        Self {
+            table: client,
+            table_name,
+        }
+    }
+
+    pub async fn list_by_project(
+        &self,
+        project_uuid: String,
+    ) -> Result<Vec<Webhook>, Report<HandlerError>> {
+        info!(
+            "WebhooksHandler::list_by_project - project: {}",
+            project_uuid
+        );
+        self.list_by_index(INDEX_PROJECT, "project_uuid", project_uuid)
+            .await
+    }
+
+    /// Deletes the webhooks of a deleted project, returns how many were deleted
+    pub async fn delete_by_project(
+        &self,
+        project_uuid: String,
+    ) -> Result<usize, Report<HandlerError>> {
+        info!(
+            "WebhooksHandler::delete_by_project - project: {}",
+            project_uuid
+        );
+        let webhooks = self.list_by_project(project_uuid).await?;
+        for webhook in webhooks.iter() {
+            let tx = self
+                .table
+                .delete_item()
+                .table_name(&self.table_name)
+                .key("uuid", AttributeValue::S(webhook.uuid.to_owned()));
+
+            info!("WebhooksHandler::delete_by_project - send tx");
+            if let Err(err) = tx.send().await {
+                error!(
+                    "WebhooksHandler::delete_by_project - failed to delete webhook: {:?}",
+                    err
+                );
+                return Err(Report::new(HandlerError::new(&err.to_string())));
+            }
+        }
+
+        Ok(webhooks.len())
+    }
+
+    pub async fn list_by_user(
+        &self,
+        user_uuid: String,
+    ) -> Result<Vec<Webhook>, Report<HandlerError>> {
+        info!("WebhooksHandler::list_by_user - user: {}", user_uuid);
+        self.list_by_index(INDEX_USER, "user_uuid", user_uuid).await
+    }
+
+    /// Webhooks of a project for the list endpoint, only the matching ones
+    /// and the fields asked
+    pub async fn search_by_project(
+        &self,
+        project_uuid: String,
+        list: &ListQuery,
+    ) -> Result<Vec<Value>, Report<HandlerError>> {
+        info!(
+            "WebhooksHandler::search_by_project - project: {}",
+            project_uuid
+        );
+        let items = self
+            .query_index(INDEX_PROJECT, "project_uuid", project_uuid, list)
+            .await?;
+        Ok(list.render(items, WebhookParser::parse))
+    }
+
+    /// Same as `search_by_project`, for the webhooks of a user
+    pub async fn search_by_user(
+        &self,
+        user_uuid: String,
+        list: &ListQuery,
+    ) -> Result<Vec<Value>, Report<HandlerError>> {
+        info!("WebhooksHandler::search_by_user - user: {}", user_uuid);
+        let items = self
+            .query_index(INDEX_USER, "user_uuid", user_uuid, list)
+            .await?;
+        Ok(list.render(items, WebhookParser::parse))
+    }
+
+    async fn list_by_index(
+        &self,
+        index: &str,
+        key: &str,
+        value: String,
+    ) -> Result<Vec<Webhook>, Report<HandlerError>> {
+        let mut data: Vec<Webhook> = Vec::new();
+        for item in self
+            .query_index(index, key, value, &ListQuery::default())
+            .await?
+        {
+            match WebhookParser::parse(item) {
+                Ok(parsed) => data.push(parsed),
+                Err(error) => error!(
+                    "WebhooksHandler::list_by_index - parse error (skip from result): {}",
+                    error
+                ),
+            }
+        }
+        Ok(data)
+    }
+
+    async fn query_index(
+        &self,
+        index: &str,
+        key: &str,
+        value: String,
+        list: &ListQuery,
+    ) -> Result<Vec<HashMap<String, AttributeValue>>, Report<HandlerError>> {
+        let tx = self
+            .table
+            .query()
+            .table_name(&self.table_name)
+            .index_name(index)
+            .key_condition_expression("#key = :value")
+            .expression_attribute_names("#key", key)
+            .expression_attribute_values(":value", AttributeValue::S(value));
+
+        info!("WebhooksHandler::query_index - send tx");
+        let result: Result<Vec<_>, SdkError<QueryError>> = list
+            .query(tx)
+            .into_paginator()
+            .items()
+            .send()
+            .collect()
+            .await;
+        result.map_err(|err| {
+            error!(
+                "WebhooksHandler::query_index - failed to list webhooks: {}",
+                err
+            );
+            Report::new(HandlerError::new(&err.to_string()))
+        })
+    }
+}
+
+#[async_trait]
+impl HandlerCreate<Webhook, WebhookCreatePayload, HandlerError> for WebhooksHandler {
+    async fn create(&self, payload: WebhookCreatePayload) -> Result<Webhook, Report<HandlerError>> {
+        info!("WebhooksHandler::create - url: {}", payload.url);
+        let webhook = Webhook::new(payload);
+
+        let tx = self
+            .table
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(webhook.as_hashmap()));
+
+        info!("WebhooksHandler::create - send tx");
+        match tx.send().await {
+            Ok(_) => Ok(webhook),
+            Err(err) => {
+                error!(
+                    "WebhooksHandler::create - failed to create webhook: {:?}",
+                    err
+                );
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl HandlerGet<Webhook, HandlerError> for WebhooksHandler {
+    async fn get(&self, uuid: String) -> Result<Option<Webhook>, Report<HandlerError>> {
+        info!("WebhooksHandler::get - uuid: {}", uuid);
+
+        let tx = self
+            .table
+            .get_item()
+            .table_name(&self.table_name)
+            .key("uuid".to_string(), AttributeValue::S(uuid));
+
+        info!("WebhooksHandler::get - send tx");
+        match tx.send().await {
+            Ok(res) => match res.item {
+                Some(value) => match WebhookParser::parse(value) {
+                    Ok(webhook) => Ok(Some(webhook)),
+                    Err(error) => {
+                        error!("WebhooksHandler::get - failed to parse webhook: {}", error);
+                        Ok(None)
+                    }
+                },
+                None => Ok(None),
+            },
+            Err(err) => {
+                error!("WebhooksHandler::get - failed to get webhook: {:?}", err);
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+}
+
+pub struct WebhookDeliveriesHandler {
+    table: Client,
+    table_name: String,
+}
+impl WebhookDeliveriesHandler {
+    pub fn new(client: Client, table_name: String) -> Self {
+        Self {
+            table: client,
+            table_name,
+        }
+    }
+
+    /// Writes the whole delivery record, every attempt replaces the previous state
+    pub async fn save(&self, delivery: &WebhookDelivery) -> Result<(), Report<HandlerError>> {
+        info!(
+            "WebhookDeliveriesHandler::save - uuid: {}, status: {}, attempts: {}",
+            delivery.uuid, delivery.status, delivery.attempts
+        );
+
+        let tx = self
+            .table
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(delivery.as_hashmap()));
+
+        info!("WebhookDeliveriesHandler::save - send tx");
+        match tx.send().await {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!(
+                    "WebhookDeliveriesHandler::save - failed to save delivery: {:?}",
+                    err
+                );
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+
+    /// Delivery log of a webhook, newest first, only the matching deliveries
+    /// and the fields asked
+    pub async fn search_by_webhook(
+        &self,
+        webhook_uuid: String,
+        list: &ListQuery,
+    ) -> Result<Vec<Value>, Report<HandlerError>> {
+        info!(
+            "WebhookDeliveriesHandler::search_by_webhook - webhook: {}",
+            webhook_uuid
+        );
+        let tx = self
+            .table
+            .query()
+            .table_name(&self.table_name)
+            .index_name(INDEX_WEBHOOK)
+            .key_condition_expression("#webhook_uuid = :webhook_uuid")
+            .expression_attribute_names("#webhook_uuid", "webhook_uuid")
+            .expression_attribute_values(":webhook_uuid", AttributeValue::S(webhook_uuid))
+            .scan_index_forward(false);
+
+        info!("WebhookDeliveriesHandler::search_by_webhook - send tx");
+        let result: Result<Vec<_>, SdkError<QueryError>> = list
+            .query(tx)
+            .into_paginator()
+            .items()
+            .send()
+            .collect()
+            .await;
+        match result {
+            Ok(items) => Ok(list.render(items, WebhookDeliveryParser::parse)),
+            Err(err) => {
+                error!(
+                    "WebhookDeliveriesHandler::search_by_webhook - failed to list deliveries: {}",
+                    err
+                );
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+
+    /// Deliveries waiting for a retry whose next attempt is due at `now`
+    pub async fn list_due(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<WebhookDelivery>, Report<HandlerError>> {
+        info!("WebhookDeliveriesHandler::list_due - now: {}", now);
+        let tx = self
+            .table
+            .query()
+            .table_name(&self.table_name)
+            .index_name(INDEX_DELIVERY_STATUS)
+            .key_condition_expression("#status = :status AND #next_attempt_at <= :now")
+            .expression_attribute_names("#status", "status")
+            .expression_attribute_names("#next_attempt_at", "next_attempt_at")
+            .expression_attribute_values(
+                ":status",
+                AttributeValue::S(WebhookDeliveryStatus::Retrying.to_string()),
+            )
+            .expression_attribute_values(":now", AttributeValue::S(now.to_rfc3339()));
+
+        info!("WebhookDeliveriesHandler::list_due - send tx");
+        let result: Result<Vec<_>, SdkError<QueryError>> =
+            tx.into_paginator().items().send().collect().await;
+        self.parse_items("list_due", result)
+    }
+
+    fn parse_items(
+        &self,
+        method: &str,
+        result: Result<Vec<HashMap<String, AttributeValue>>, SdkError<QueryError>>,
+    ) -> Result<Vec<WebhookDelivery>, Report<HandlerError>> {
+        match result {
+            Ok(items) => {
+                let mut data = Vec::new();
+                for item in items {
+                    match WebhookDeliveryParser::parse(item) {
+                        Ok(parsed) => data.push(parsed),
+                        Err(error) => error!(
+                            "WebhookDeliveriesHandler::{} - parse error (skip from result): {}",
+                            method, error
+                        ),
+                    }
+                }
+                Ok(data)
+            }
+            Err(err) => {
+                error!(
+                    "WebhookDeliveriesHandler::{} - failed to list deliveries: {}",
+                    method, err
+                );
+                Err(Report::new(HandlerError::new(&err.to_string())))
+            }
+        }
+    }
+}
+
+pub struct WebhookDispatcher {
+    webhooks: WebhooksHandler,
+    deliveries: WebhookDeliveriesHandler,
+    sender: Box<dyn HttpSender>,
+    retry: RetryPolicy,
+}
+impl WebhookDispatcher {
+    pub fn new(
+        webhooks: WebhooksHandler,
+        deliveries: WebhookDeliveriesHandler,
+        sender: Box<dyn HttpSender>,
+        retry: RetryPolicy,
+    ) -> Self {
+        Self {
+            webhooks,
+            deliveries,
+            sender,
+            retry,
+        }
+    }
+
+    /// Webhooks of the project and of its owner subscribed to `event`
+    pub async fn subscribers(
+        &self,
+        project: &Project,
+        event: WebhookEvent,
+    ) -> Result<Vec<Webhook>, Report<HandlerError>> {
+        let mut webhooks = self.webhooks.list_by_project(project.uuid.clone()).await?;
+        if project.owner != "-" {
+            webhooks.append(&mut self.webhooks.list_by_user(project.owner.clone()).await?);
+        }
+
+        Ok(webhooks
+            .into_iter()
+            .filter(|webhook| webhook.subscribes(event))
+            .collect())
+    }
+
+    /// Records and attempts a delivery for every subscribed webhook. Failed
+    /// attempts are left for `retry_due`.
+    pub async fn dispatch(
+        &self,
+        event: WebhookEvent,
+        deployment: &ProjectDeployment,
+    ) -> Result<Vec<WebhookDelivery>, Report<HandlerError>> {
+        info!(
+            "WebhookDispatcher::dispatch - event: {}, deployment: {}",
+            event, deployment.uuid
+        );
+        let payload = webhook_payload(event, deployment);
+        let mut deliveries = Vec::new();
+        for webhook in self.subscribers(&deployment.project, event).await? {
+            let delivery = WebhookDelivery::new(webhook.uuid.clone(), event, payload.clone());
+            deliveries.push(self.attempt(&webhook, delivery).await?);
+        }
+
+        Ok(deliveries)
+    }
+
+    /// Attempts again every delivery whose backoff elapsed
+    pub async fn retry_due(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<WebhookDelivery>, Report<HandlerError>> {
+        let mut deliveries = Vec::new();
+        for mut delivery in self.deliveries.list_due(now).await? {
+            info!("WebhookDispatcher::retry_due - delivery: {}", delivery.uuid);
+            match self.webhooks.get(delivery.webhook_uuid.clone()).await? {
+                Some(webhook) => deliveries.push(self.attempt(&webhook, delivery).await?),
+                None => {
+                    delivery.status = WebhookDeliveryStatus::Failed.to_string();
+                    delivery.last_error = Some("Webhook not found".to_string());
+                    delivery.next_attempt_at = "-".to_string();
+                    self.deliveries.save(&delivery).await?;
+                    deliveries.push(delivery);
+                }
+            }
+        }
+
+        Ok(deliveries)
+    }
+
+    async fn attempt(
+        &self,
+        webhook: &Webhook,
+        mut delivery: WebhookDelivery,
+    ) -> Result<WebhookDelivery, Report<HandlerError>> {
+        let now = Utc::now();
+        let request = webhook_request(webhook, &delivery, now.timestamp());
+        let outcome = match self.sender.post(request).await {
+            Ok(response) => Ok(response.status),
+            Err(error) => Err(error.to_string()),
+        };
+        delivery.attempted(outcome, &self.retry, now);
+        info!(
+            "WebhookDispatcher::attempt - delivery: {}, status: {}",
+            delivery.uuid, delivery.status
+        );
+        self.deliveries.save(&delivery).await?;
+
+        Ok(delivery)
+    }
+}
+
+#[cfg(test)]
+mod webhooks_tests {
+    use super::*;
+    use crate::models::{
+        project::ProjectCreatePayload, project_deployment::ProjectDeploymentCreatePayload,
+        project_deployment::ProjectDeploymentStatus, validation::Validate,
+    };
+    use chrono::Duration;
+    use serde_json::Value;
+
+    fn webhook() -> Webhook {
+        Webhook::new(WebhookCreatePayload {
+            project_uuid: Some("project".to_string()),
+            user_uuid: None,
+            url: "https://example.com/hook".to_string(),
+            events: Some(vec!["deployment.failed".to_string()]),
+            secret: Some("secret".to_string()),
+        })
+    }
+
+    #[test]
+    fn signs_timestamp_and_body() {
+        // echo -n '1660000000.{}' | openssl dgst -sha256 -hmac secret
+        assert_eq!(
+            sign("secret", 1660000000, "{}"),
+            "sha256=adec1f5fdfc32678ba7182519dd3588c5c2f59bc8683b5d276162806740ca6e4"
+        );
+        assert_ne!(
+            sign("other", 1660000000, "{}"),
+            sign("secret", 1660000000, "{}")
+        );
+    }
+
+    #[test]
+    fn request_carries_signature_headers() {
+        let webhook = webhook();
+        let delivery = WebhookDelivery::new(
+            webhook.uuid.clone(),
+            WebhookEvent::DeploymentFailed,
+            "{}".to_string(),
+        );
+        let request = webhook_request(&webhook, &delivery, 1660000000);
+        let header = |name: &str| {
+            request
+                .headers
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+                .unwrap()
+        };
+
+        assert_eq!(request.url, "https://example.com/hook");
+        assert_eq!(header(HEADER_EVENT), "deployment.failed");
+        assert_eq!(header(HEADER_DELIVERY), delivery.uuid);
+        assert_eq!(header(HEADER_TIMESTAMP), "1660000000");
+        assert_eq!(header(HEADER_SIGNATURE), sign("secret", 1660000000, "{}"));
+    }
+
+    #[test]
+    fn payload_describes_deployment() {
+        let project = Project::new(ProjectCreatePayload::default(
+            "name".to_string(),
+            "https://example.com/repo.git".to_string(),
+        ));
+        let deployment = ProjectDeployment::new(ProjectDeploymentCreatePayload {
+            project,
+            environment: "production".to_string(),
+            build: None,
+        });
+        let payload: Value = serde_json::from_str(&webhook_payload(
+            WebhookEvent::DeploymentStarted,
+            &deployment,
+        ))
+        .unwrap();
+
+        assert_eq!(payload["event"], "deployment.started");
+        assert_eq!(
+            payload["data"]["deployment"]["uuid"],
+            deployment.uuid.as_str()
+        );
+        assert_eq!(payload["data"]["project"]["name"], "name");
+    }
+
+    #[test]
+    fn events_follow_deployment_status() {
+        assert_eq!(
+            WebhookEvent::from_status(ProjectDeploymentStatus::Failed, true),
+            Some(WebhookEvent::DeploymentCancelled)
+        );
+        assert_eq!(
+            WebhookEvent::from_status(ProjectDeploymentStatus::Failed, false),
+            Some(WebhookEvent::DeploymentFailed)
+        );
+        assert_eq!(
+            WebhookEvent::from_status(ProjectDeploymentStatus::Queued, false),
+            None
+        );
+        assert!(webhook().subscribes(WebhookEvent::DeploymentFailed));
+        assert!(!webhook().subscribes(WebhookEvent::DeploymentSucceeded));
+    }
+
+    #[test]
+    fn payload_validation_requires_one_owner_and_known_events() {
+        let fields = |payload: &WebhookCreatePayload| match payload.validate() {
+            Ok(_) => vec![],
+            Err(error) => error
+                .errors
+                .into_iter()
+                .map(|error| error.field)
+                .collect::<Vec<String>>(),
+        };
+        let mut payload = WebhookCreatePayload {
+            project_uuid: Some("0a6a5e2c-3f1b-4c41-9a43-6f7c2c1a9b10".to_string()),
+            user_uuid: Some("6c1f2c8e-57b0-4a8e-9d0c-3c1b7f6e2a11".to_string()),
+            url: "https://example.com/hook".to_string(),
+            events: None,
+            secret: None,
+        };
+        assert_eq!(fields(&payload), vec!["project_uuid"]);
+        payload.user_uuid = None;
+        assert!(payload.validate().is_ok());
+        payload.events = Some(vec![
+            "deployment.failed".to_string(),
+            "deployment.exploded".to_string(),
+        ]);
+        assert_eq!(fields(&payload), vec!["events[1]"]);
+    }
+
+    #[test]
+    fn payload_validation_requires_https_outside_localhost() {
+        let payload = |url: &str| WebhookCreatePayload {
+            project_uuid: None,
+            user_uuid: Some("6c1f2c8e-57b0-4a8e-9d0c-3c1b7f6e2a11".to_string()),
+            url: url.to_string(),
+            events: None,
+            secret: None,
+        };
+        assert!(payload("https://example.com/hook").validate().is_ok());
+        assert!(payload("http://localhost:8080/hook").validate().is_ok());
+        assert!(payload("http://127.0.0.1/hook").validate().is_ok());
+        assert!(payload("http://[::1]:3000").validate().is_ok());
+
+        for url in [
+            "http://example.com/hook",
+            "http://localhost.example.com/hook",
+            "http://localhost@example.com/hook",
+            "ftp://localhost/hook",
+        ] {
+            let error = payload(url).validate().unwrap_err();
+            assert_eq!(error.errors[0].field, "url");
+            assert_eq!(error.errors[0].code, "VLE02");
+        }
+    }
+
+    #[test]
+    fn failed_attempts_back_off_exponentially_until_exhausted() {
+        let retry = RetryPolicy::new(3, 60, 90);
+        let now = Utc::now();
+        let mut delivery = WebhookDelivery::new(
+            "webhook".to_string(),
+            WebhookEvent::DeploymentFailed,
+            "{}".to_string(),
+        );
+
+        delivery.attempted(Err("connection refused".to_string()), &retry, now);
+        assert_eq!(delivery.status(), WebhookDeliveryStatus::Retrying);
+        assert_eq!(
+            delivery.next_attempt_at,
+            (now + Duration::seconds(60)).to_rfc3339()
+        );
+
+        delivery.attempted(Ok(500), &retry, now);
+        assert_eq!(delivery.status(), WebhookDeliveryStatus::Retrying);
+        assert_eq!(delivery.response_status, Some(500));
+        assert_eq!(
+            delivery.next_attempt_at,
+            (now + Duration::seconds(90)).to_rfc3339()
+        );
+
+        delivery.attempted(Ok(502), &retry, now);
+        assert_eq!(delivery.status(), WebhookDeliveryStatus::Failed);
+        assert_eq!(delivery.attempts, 3);
+        assert_eq!(delivery.next_attempt_at, "-");
+    }
+
+    #[test]
+    fn successful_attempt_completes_delivery() {
+        let mut delivery = WebhookDelivery::new(
+            "webhook".to_string(),
+            WebhookEvent::DeploymentSucceeded,
+            "{}".to_string(),
+        );
+        delivery.attempted(Ok(204), &RetryPolicy::default(), Utc::now());
+
+        assert_eq!(delivery.status(), WebhookDeliveryStatus::Succeeded);
+        assert_eq!(delivery.last_error, None);
+        let parsed = WebhookDeliveryParser::parse(delivery.as_hashmap()).unwrap();
+        assert_eq!(parsed.status, delivery.status);
+        assert_eq!(parsed.response_status, Some(204));
+    }
+}
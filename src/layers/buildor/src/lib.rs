@@ -1,3 +1,7 @@
 pub mod handlers;
 pub mod models;
+pub mod routes;
 pub mod utils;
+
+#[cfg(test)]
+mod fixtures;
@@ -1,9 +1,30 @@
+pub mod api_error;
+pub mod api_gateway;
+pub mod artifact;
 pub mod codebuild;
+pub mod codebuild_event;
 pub mod commands;
 pub mod common;
+pub mod dead_letter;
+pub mod domain_event;
+pub mod email;
+pub mod environment;
 pub mod handlers;
+pub mod http;
+pub mod idempotency_key;
+pub mod list_query;
+pub mod notification;
+pub mod openapi;
+pub mod outbox;
+pub mod processed_event;
 pub mod project;
 pub mod project_deployment;
 pub mod request;
 pub mod response;
+pub mod retention;
+pub mod router;
+pub mod stats;
+pub mod stream;
 pub mod user;
+pub mod validation;
+pub mod webhook;
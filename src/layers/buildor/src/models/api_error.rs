@@ -0,0 +1,323 @@
+use serde_derive::Serialize;
+use std::fmt;
+use std::str::FromStr;
+
+use super::list_query::ListField;
+use super::request::RequestError;
+use super::validation::FieldError;
+
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// Catalog of the errors the API responds with, each one has a stable code and
+/// the HTTP status it is sent with
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ApiError {
+    Generic,                         // "CME00"
+    SchemaCompliant,                 // "CME01"
+    ItemNotFound,                    // "CME02"
+    Validation,                      // "CME03"
+    IdempotencyKeyInvalid,           // "CME04"
+    IdempotencyKeyMismatch,          // "CME05"
+    IdempotencyKeyInProgress,        // "CME06"
+    InvalidListQuery,                // "CME07"
+    Internal,                        // "ISE00"
+    PathParameter,                   // "GRE100"
+    RouteNotFound,                   // "GRE101"
+    MethodNotAllowed,                // "GRE102"
+    Unauthorized,                    // "GRE103"
+    PreconditionFailed,              // "GRE104"
+    ProjectCreationFailed,           // "PJE00"
+    ProjectInvalidPayload,           // "PJE01"
+    ProjectInvalidStatsWindow,       // "PJE02"
+    ProjectDeleteBlocked,            // "PJE03"
+    ProjectUpdateFailed,             // "PJE04"
+    ProjectDeleteFailed,             // "PJE05"
+    UserCreationFailed,              // "USE00"
+    UserEmailTaken,                  // "USE01"
+    UserUpdateFailed,                // "USE02"
+    UserDeleteFailed,                // "USE03"
+    ProjectDeploymentCreationFailed, // "PDE00"
+    ProjectDeploymentQueueFailed,    // "PDE01"
+    ProjectDeploymentInvalidWait,    // "PDE02"
+    ProjectDeploymentUpdateFailed,   // "PDE03"
+    WebhookCreationFailed,           // "WHE00"
+    WebhookInvalidPayload,           // "WHE01"
+    Unknown, // "UNKNOWN", (custom value used when parsing from/to string/enum)
+}
+impl FromStr for ApiError {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<ApiError, ()> {
+        Ok(ApiError::ALL
+            .iter()
+            .find(|error| error.code().eq_ignore_ascii_case(input))
+            .copied()
+            .unwrap_or(ApiError::Unknown))
+    }
+}
+impl fmt::Display for ApiError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str(self.code())
+    }
+}
+impl ApiError {
+    /// Every error of the catalog, `Unknown` excluded
+    pub const ALL: &'static [ApiError] = &[
+        ApiError::Generic,
+        ApiError::SchemaCompliant,
+        ApiError::ItemNotFound,
+        ApiError::Validation,
+        ApiError::IdempotencyKeyInvalid,
+        ApiError::IdempotencyKeyMismatch,
+        ApiError::IdempotencyKeyInProgress,
+        ApiError::InvalidListQuery,
+        ApiError::Internal,
+        ApiError::PathParameter,
+        ApiError::RouteNotFound,
+        ApiError::MethodNotAllowed,
+        ApiError::Unauthorized,
+        ApiError::PreconditionFailed,
+        ApiError::ProjectCreationFailed,
+        ApiError::ProjectInvalidPayload,
+        ApiError::ProjectInvalidStatsWindow,
+        ApiError::ProjectDeleteBlocked,
+        ApiError::ProjectUpdateFailed,
+        ApiError::ProjectDeleteFailed,
+        ApiError::UserCreationFailed,
+        ApiError::UserEmailTaken,
+        ApiError::UserUpdateFailed,
+        ApiError::UserDeleteFailed,
+        ApiError::ProjectDeploymentCreationFailed,
+        ApiError::ProjectDeploymentQueueFailed,
+        ApiError::ProjectDeploymentInvalidWait,
+        ApiError::ProjectDeploymentUpdateFailed,
+        ApiError::WebhookCreationFailed,
+        ApiError::WebhookInvalidPayload,
+    ];
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::Generic => "CME00",
+            ApiError::SchemaCompliant => "CME01",
+            ApiError::ItemNotFound => "CME02",
+            ApiError::Validation => "CME03",
+            ApiError::IdempotencyKeyInvalid => "CME04",
+            ApiError::IdempotencyKeyMismatch => "CME05",
+            ApiError::IdempotencyKeyInProgress => "CME06",
+            ApiError::InvalidListQuery => "CME07",
+            ApiError::Internal => "ISE00",
+            ApiError::PathParameter => "GRE100",
+            ApiError::RouteNotFound => "GRE101",
+            ApiError::MethodNotAllowed => "GRE102",
+            ApiError::Unauthorized => "GRE103",
+            ApiError::PreconditionFailed => "GRE104",
+            ApiError::ProjectCreationFailed => "PJE00",
+            ApiError::ProjectInvalidPayload => "PJE01",
+            ApiError::ProjectInvalidStatsWindow => "PJE02",
+            ApiError::ProjectDeleteBlocked => "PJE03",
+            ApiError::ProjectUpdateFailed => "PJE04",
+            ApiError::ProjectDeleteFailed => "PJE05",
+            ApiError::UserCreationFailed => "USE00",
+            ApiError::UserEmailTaken => "USE01",
+            ApiError::UserUpdateFailed => "USE02",
+            ApiError::UserDeleteFailed => "USE03",
+            ApiError::ProjectDeploymentCreationFailed => "PDE00",
+            ApiError::ProjectDeploymentQueueFailed => "PDE01",
+            ApiError::ProjectDeploymentInvalidWait => "PDE02",
+            ApiError::ProjectDeploymentUpdateFailed => "PDE03",
+            ApiError::WebhookCreationFailed => "WHE00",
+            ApiError::WebhookInvalidPayload => "WHE01",
+            ApiError::Unknown => "UNKNOWN",
+        }
+    }
+
+    pub fn status(&self) -> u16 {
+        match self {
+            ApiError::Generic
+            | ApiError::SchemaCompliant
+            | ApiError::Validation
+            | ApiError::IdempotencyKeyInvalid
+            | ApiError::InvalidListQuery
+            | ApiError::PathParameter
+            | ApiError::ProjectInvalidPayload
+            | ApiError::ProjectInvalidStatsWindow
+            | ApiError::ProjectDeploymentInvalidWait
+            | ApiError::WebhookInvalidPayload => 400,
+            ApiError::Unauthorized => 401,
+            ApiError::ItemNotFound | ApiError::RouteNotFound => 404,
+            ApiError::MethodNotAllowed => 405,
+            ApiError::ProjectDeleteBlocked
+            | ApiError::UserEmailTaken
+            | ApiError::IdempotencyKeyInProgress => 409,
+            ApiError::PreconditionFailed => 412,
+            ApiError::IdempotencyKeyMismatch => 422,
+            ApiError::Internal
+            | ApiError::ProjectCreationFailed
+            | ApiError::ProjectUpdateFailed
+            | ApiError::ProjectDeleteFailed
+            | ApiError::UserCreationFailed
+            | ApiError::UserUpdateFailed
+            | ApiError::UserDeleteFailed
+            | ApiError::ProjectDeploymentCreationFailed
+            | ApiError::ProjectDeploymentUpdateFailed
+            | ApiError::WebhookCreationFailed
+            | ApiError::Unknown => 500,
+            ApiError::ProjectDeploymentQueueFailed => 503,
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            ApiError::Generic => "Error",
+            ApiError::SchemaCompliant => "Schema Compliant Error",
+            ApiError::ItemNotFound => "Not Found Error",
+            ApiError::Validation => "Validation Error",
+            ApiError::IdempotencyKeyInvalid => "Invalid Idempotency Key",
+            ApiError::IdempotencyKeyMismatch => "Idempotency Key Reused",
+            ApiError::IdempotencyKeyInProgress => "Idempotent Request In Progress",
+            ApiError::InvalidListQuery => "Invalid List Query",
+            ApiError::Internal | ApiError::Unknown => "Internal Server Error",
+            ApiError::PathParameter => "Request Error",
+            ApiError::RouteNotFound => "Route Not Found",
+            ApiError::MethodNotAllowed => "Method Not Allowed",
+            ApiError::Unauthorized => "Unauthorized",
+            ApiError::PreconditionFailed => "Precondition Failed",
+            ApiError::ProjectCreationFailed => "Create Project Error",
+            ApiError::ProjectInvalidPayload => "Invalid Project Error",
+            ApiError::ProjectInvalidStatsWindow => "Invalid Project Stats Window",
+            ApiError::ProjectDeleteBlocked => "Delete Project Conflict",
+            ApiError::ProjectUpdateFailed => "Update Project Error",
+            ApiError::ProjectDeleteFailed => "Delete Project Error",
+            ApiError::UserCreationFailed => "Create User Error",
+            ApiError::UserEmailTaken => "User Email Conflict",
+            ApiError::UserUpdateFailed => "Update User Error",
+            ApiError::UserDeleteFailed => "Delete User Error",
+            ApiError::ProjectDeploymentCreationFailed => "Create Project Deployment Error",
+            ApiError::ProjectDeploymentQueueFailed => "Queue Project Deployment Error",
+            ApiError::ProjectDeploymentInvalidWait => "Invalid Project Deployment Wait",
+            ApiError::ProjectDeploymentUpdateFailed => "Update Project Deployment Error",
+            ApiError::WebhookCreationFailed => "Create Webhook Error",
+            ApiError::WebhookInvalidPayload => "Invalid Webhook Error",
+        }
+    }
+
+    /// Whether sending the same request again may succeed
+    pub fn retryable(&self) -> bool {
+        matches!(
+            self,
+            ApiError::Internal
+                | ApiError::IdempotencyKeyInProgress
+                | ApiError::ProjectCreationFailed
+                | ApiError::ProjectDeleteBlocked
+                | ApiError::ProjectUpdateFailed
+                | ApiError::ProjectDeleteFailed
+                | ApiError::UserCreationFailed
+                | ApiError::UserUpdateFailed
+                | ApiError::UserDeleteFailed
+                | ApiError::ProjectDeploymentCreationFailed
+                | ApiError::ProjectDeploymentQueueFailed
+                | ApiError::ProjectDeploymentUpdateFailed
+                | ApiError::WebhookCreationFailed
+        )
+    }
+
+    pub fn error(&self, details: String) -> RequestError {
+        RequestError::new(*self, details)
+    }
+}
+
+/// Fields the error catalog can be filtered, sorted and listed by
+pub const API_ERROR_LIST_FIELDS: &[ListField] = &[
+    ListField::text("code", "code"),
+    ListField::number("status", "status"),
+    ListField::text("message", "message"),
+    ListField::nested("retryable", "retryable"),
+];
+
+/// Entry of the error catalog, as listed by `GET /errors`
+#[derive(Serialize, Debug)]
+pub struct ApiErrorEntry {
+    pub code: &'static str,
+    pub status: u16,
+    pub message: &'static str,
+    pub retryable: bool,
+}
+impl From<ApiError> for ApiErrorEntry {
+    fn from(error: ApiError) -> Self {
+        Self {
+            code: error.code(),
+            status: error.status(),
+            message: error.message(),
+            retryable: error.retryable(),
+        }
+    }
+}
+
+/// RFC 7807 problem details body, sent to the clients accepting
+/// `application/problem+json`
+#[derive(Serialize, Debug)]
+pub struct ProblemDetails {
+    #[serde(rename(serialize = "type"))]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    pub instance: String,
+    pub code: String,
+    pub retryable: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+impl ProblemDetails {
+    pub fn new(error: &RequestError, instance: &str) -> Self {
+        Self {
+            problem_type: format!("/errors#{}", error.code),
+            title: error.message.clone(),
+            status: error.status,
+            detail: error.details.clone(),
+            instance: instance.to_string(),
+            code: error.code.clone(),
+            retryable: error.retryable,
+            errors: error.errors.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod api_error_tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashSet;
+
+    #[test]
+    fn codes_are_unique_and_parse_back() {
+        let codes: HashSet<&str> = ApiError::ALL.iter().map(|error| error.code()).collect();
+        assert_eq!(codes.len(), ApiError::ALL.len());
+        for error in ApiError::ALL {
+            assert_eq!(ApiError::from_str(error.code()).unwrap(), *error);
+        }
+        assert_eq!(ApiError::from_str("XXX00").unwrap(), ApiError::Unknown);
+    }
+
+    #[test]
+    fn maps_statuses() {
+        assert_eq!(ApiError::ProjectCreationFailed.status(), 500);
+        assert!(ApiError::ProjectCreationFailed.retryable());
+        assert_eq!(ApiError::ItemNotFound.status(), 404);
+        assert_eq!(ApiError::ProjectDeleteBlocked.status(), 409);
+        assert!(!ApiError::Validation.retryable());
+    }
+
+    #[test]
+    fn builds_problem_details() {
+        let error = ApiError::ItemNotFound.error("Project not found".to_string());
+        let problem = json!(ProblemDetails::new(&error, "/projects/abc"));
+        assert_eq!(problem["type"], "/errors#CME02");
+        assert_eq!(problem["title"], "Not Found Error");
+        assert_eq!(problem["status"], 404);
+        assert_eq!(problem["detail"], "Project not found");
+        assert_eq!(problem["instance"], "/projects/abc");
+        assert_eq!(problem["retryable"], false);
+        assert!(problem.get("errors").is_none());
+    }
+}
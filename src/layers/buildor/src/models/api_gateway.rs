@@ -0,0 +1,290 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::response::Response;
+
+/// Payload format of the API Gateway proxy integration, REST APIs send v1 and
+/// HTTP APIs send v2 unless configured otherwise
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PayloadFormat {
+    V1, // "1.0"
+    V2, // "2.0"
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ApiGatewayV1Identity {
+    #[serde(rename = "sourceIp")]
+    pub source_ip: Option<String>,
+    #[serde(rename = "userAgent")]
+    pub user_agent: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ApiGatewayV1RequestContext {
+    #[serde(rename = "requestId")]
+    pub request_id: Option<String>,
+    pub stage: Option<String>,
+    #[serde(default)]
+    pub identity: ApiGatewayV1Identity,
+}
+
+/// REST API proxy request, also sent by HTTP APIs using the 1.0 payload format
+#[derive(Deserialize, Debug, Clone)]
+pub struct ApiGatewayV1Request {
+    #[serde(rename = "httpMethod")]
+    pub http_method: String,
+    pub path: String,
+    /// Resource template, e.g. `/projects/{project}/stats`
+    #[serde(default)]
+    pub resource: Option<String>,
+    #[serde(default)]
+    pub headers: Option<HashMap<String, String>>,
+    #[serde(default, rename = "queryStringParameters")]
+    pub query_string_parameters: Option<HashMap<String, String>>,
+    #[serde(default, rename = "pathParameters")]
+    pub path_parameters: Option<HashMap<String, String>>,
+    #[serde(default, rename = "requestContext")]
+    pub request_context: ApiGatewayV1RequestContext,
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default, rename = "isBase64Encoded")]
+    pub is_base64_encoded: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ApiGatewayV2Http {
+    pub method: String,
+    pub path: String,
+    #[serde(rename = "sourceIp")]
+    pub source_ip: Option<String>,
+    #[serde(rename = "userAgent")]
+    pub user_agent: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ApiGatewayV2RequestContext {
+    pub http: ApiGatewayV2Http,
+    #[serde(rename = "requestId")]
+    pub request_id: Option<String>,
+    pub stage: Option<String>,
+}
+
+/// HTTP API proxy request using the 2.0 payload format
+#[derive(Deserialize, Debug, Clone)]
+pub struct ApiGatewayV2Request {
+    pub version: String,
+    /// Method and resource template, e.g. `GET /projects/{project}/stats`, or `$default`
+    #[serde(rename = "routeKey")]
+    pub route_key: String,
+    /// Path including the stage, unless it is the default one
+    #[serde(rename = "rawPath")]
+    pub raw_path: String,
+    #[serde(default, rename = "rawQueryString")]
+    pub raw_query_string: String,
+    #[serde(default)]
+    pub cookies: Option<Vec<String>>,
+    #[serde(default)]
+    pub headers: Option<HashMap<String, String>>,
+    #[serde(default, rename = "queryStringParameters")]
+    pub query_string_parameters: Option<HashMap<String, String>>,
+    #[serde(default, rename = "pathParameters")]
+    pub path_parameters: Option<HashMap<String, String>>,
+    #[serde(rename = "requestContext")]
+    pub request_context: ApiGatewayV2RequestContext,
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default, rename = "isBase64Encoded")]
+    pub is_base64_encoded: bool,
+}
+impl ApiGatewayV2Request {
+    /// Path without the stage prefix HTTP APIs keep for named stages
+    pub fn path(&self) -> String {
+        match self.request_context.stage.as_deref() {
+            Some(stage) if stage != "$default" => {
+                match self.raw_path.strip_prefix(&format!("/{}", stage)) {
+                    Some("") => "/".to_string(),
+                    Some(path) if path.starts_with('/') => path.to_string(),
+                    _ => self.raw_path.clone(),
+                }
+            }
+            _ => self.raw_path.clone(),
+        }
+    }
+
+    /// Resource template of the route key, None for the `$default` route
+    pub fn resource(&self) -> Option<String> {
+        self.route_key
+            .split_once(' ')
+            .map(|(_, resource)| resource.to_string())
+    }
+}
+
+/// Proxy request in either payload format. The v2 one is tried first, v1
+/// requests never have the `rawPath` it requires.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum ApiGatewayRequest {
+    V2(ApiGatewayV2Request),
+    V1(ApiGatewayV1Request),
+}
+impl ApiGatewayRequest {
+    pub fn format(&self) -> PayloadFormat {
+        match self {
+            ApiGatewayRequest::V1(_) => PayloadFormat::V1,
+            ApiGatewayRequest::V2(_) => PayloadFormat::V2,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ApiGatewayV1Response {
+    #[serde(rename(serialize = "statusCode"))]
+    pub status_code: u16,
+    pub headers: HashMap<String, String>,
+    pub body: String,
+    #[serde(rename(serialize = "isBase64Encoded"))]
+    pub is_base64_encoded: bool,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ApiGatewayV2Response {
+    #[serde(rename(serialize = "statusCode"))]
+    pub status_code: u16,
+    pub headers: HashMap<String, String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cookies: Vec<String>,
+    pub body: String,
+    #[serde(rename(serialize = "isBase64Encoded"))]
+    pub is_base64_encoded: bool,
+}
+
+/// Proxy response in the payload format of the request it answers
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ApiGatewayResponse {
+    V1(ApiGatewayV1Response),
+    V2(ApiGatewayV2Response),
+}
+impl ApiGatewayResponse {
+    pub fn new(format: PayloadFormat, response: Response) -> Self {
+        match format {
+            PayloadFormat::V1 => ApiGatewayResponse::V1(ApiGatewayV1Response {
+                status_code: response.status_code,
+                headers: response.headers.as_map(),
+                body: response.body,
+                is_base64_encoded: false,
+            }),
+            PayloadFormat::V2 => ApiGatewayResponse::V2(ApiGatewayV2Response {
+                status_code: response.status_code,
+                headers: response.headers.as_map(),
+                cookies: Vec::new(),
+                body: response.body,
+                is_base64_encoded: false,
+            }),
+        }
+    }
+
+    pub fn status_code(&self) -> u16 {
+        match self {
+            ApiGatewayResponse::V1(response) => response.status_code,
+            ApiGatewayResponse::V2(response) => response.status_code,
+        }
+    }
+
+    pub fn body(&self) -> &str {
+        match self {
+            ApiGatewayResponse::V1(response) => &response.body,
+            ApiGatewayResponse::V2(response) => &response.body,
+        }
+    }
+}
+
+#[cfg(test)]
+mod api_gateway_tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fixture(name: &str) -> ApiGatewayRequest {
+        let content = match name {
+            "rest-v1" => include_str!("../../fixtures/api-gateway/rest-v1-project-stats.json"),
+            "http-v2" => include_str!("../../fixtures/api-gateway/http-v2-project-stats.json"),
+            "http-v2-body" => include_str!("../../fixtures/api-gateway/http-v2-users-post.json"),
+            _ => panic!("Unknown fixture: {}", name),
+        };
+        serde_json::from_str(content).unwrap()
+    }
+
+    #[test]
+    fn parses_rest_v1_requests() {
+        let request = match fixture("rest-v1") {
+            ApiGatewayRequest::V1(request) => request,
+            other => panic!("Unexpected request: {:?}", other),
+        };
+        assert_eq!(request.http_method, "GET");
+        assert_eq!(
+            request.path,
+            "/projects/0a6a5e2c-3f1b-4c41-9a43-6f7c2c1a9b10/stats"
+        );
+        assert_eq!(
+            request.resource.as_deref(),
+            Some("/projects/{project}/stats")
+        );
+        assert_eq!(request.query_string_parameters.unwrap()["window"], "7d");
+        assert_eq!(request.request_context.stage.as_deref(), Some("v1"));
+        assert_eq!(request.body, None);
+    }
+
+    #[test]
+    fn parses_http_v2_requests() {
+        let request = match fixture("http-v2") {
+            ApiGatewayRequest::V2(request) => request,
+            other => panic!("Unexpected request: {:?}", other),
+        };
+        assert_eq!(request.request_context.http.method, "GET");
+        // The stage is not part of the route path
+        assert_eq!(
+            request.path(),
+            "/projects/0a6a5e2c-3f1b-4c41-9a43-6f7c2c1a9b10/stats"
+        );
+        assert_eq!(
+            request.resource().as_deref(),
+            Some("/projects/{project}/stats")
+        );
+        assert_eq!(request.cookies.unwrap(), vec!["session=abc"]);
+
+        let request = match fixture("http-v2-body") {
+            ApiGatewayRequest::V2(request) => request,
+            other => panic!("Unexpected request: {:?}", other),
+        };
+        assert_eq!(request.path(), "/users");
+        assert_eq!(request.resource(), None);
+        assert!(request.is_base64_encoded);
+    }
+
+    #[test]
+    fn v1_payloads_of_http_apis_are_v1() {
+        let request: ApiGatewayRequest = serde_json::from_value(json!({
+            "version": "1.0",
+            "httpMethod": "DELETE",
+            "path": "/",
+        }))
+        .unwrap();
+        assert_eq!(request.format(), PayloadFormat::V1);
+        assert!(serde_json::from_value::<ApiGatewayRequest>(json!({ "path": "/" })).is_err());
+    }
+
+    #[test]
+    fn responds_in_the_request_format() {
+        let response = Response::json(json!({ "message": "ok" }), 201);
+        let v1 = json!(ApiGatewayResponse::new(PayloadFormat::V1, response.clone()));
+        assert_eq!(v1["statusCode"], 201);
+        assert_eq!(v1["headers"]["Content-Type"], "application/json");
+        assert_eq!(v1["body"], "{\"message\":\"ok\"}");
+        assert_eq!(v1["isBase64Encoded"], false);
+
+        let v2 = ApiGatewayResponse::new(PayloadFormat::V2, response);
+        assert_eq!(v2.status_code(), 201);
+        assert_eq!(v2.body(), "{\"message\":\"ok\"}");
+        assert!(json!(v2).get("cookies").is_none());
+    }
+}
@@ -0,0 +1,106 @@
+use aws_sdk_dynamodb::model::AttributeValue;
+use error_stack::Context;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+use super::common::AsDynamoDBAttributeValue;
+
+/// File of an artifact, with the SHA-256 of its content
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ArtifactFile {
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+    #[serde(rename(serialize = "contentType"))]
+    pub content_type: String,
+}
+
+/// Every file of an artifact, kept as a JSON object next to the artifact as
+/// it can outgrow a DynamoDB item
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ArtifactManifest {
+    pub bucket: String,
+    pub key: String,
+    #[serde(rename(serialize = "fileCount"))]
+    pub file_count: u64,
+    #[serde(rename(serialize = "totalSize"))]
+    pub total_size: u64,
+    pub files: Vec<ArtifactFile>,
+}
+
+/// Artifact of a deployment, with the location and checksum of its manifest
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ArtifactSummary {
+    pub bucket: String,
+    pub key: String,
+    #[serde(rename(serialize = "fileCount"))]
+    pub file_count: u64,
+    #[serde(rename(serialize = "totalSize"))]
+    pub total_size: u64,
+    /// Deployments described before manifests were moved out of the table have none
+    #[serde(rename(serialize = "manifestKey"))]
+    pub manifest_key: Option<String>,
+    /// SHA-256 of the manifest object
+    pub checksum: Option<String>,
+}
+impl ArtifactSummary {
+    pub fn location(&self) -> String {
+        format!("s3://{}/{}", self.bucket, self.key)
+    }
+}
+impl AsDynamoDBAttributeValue for ArtifactSummary {
+    fn as_hashmap(&self) -> HashMap<String, AttributeValue> {
+        let mut map: HashMap<String, AttributeValue> = HashMap::new();
+        map.insert(
+            "bucket".to_string(),
+            AttributeValue::S(self.bucket.to_owned()),
+        );
+        map.insert("key".to_string(), AttributeValue::S(self.key.to_owned()));
+        map.insert(
+            "file_count".to_string(),
+            AttributeValue::N(format!("{}", self.file_count)),
+        );
+        map.insert(
+            "total_size".to_string(),
+            AttributeValue::N(format!("{}", self.total_size)),
+        );
+        self.manifest_key.as_ref().and_then(|manifest_key| {
+            map.insert(
+                "manifest_key".to_string(),
+                AttributeValue::S(manifest_key.to_owned()),
+            )
+        });
+        self.checksum.as_ref().and_then(|checksum| {
+            map.insert(
+                "checksum".to_string(),
+                AttributeValue::S(checksum.to_owned()),
+            )
+        });
+
+        map
+    }
+
+    fn as_attr(&self) -> AttributeValue {
+        AttributeValue::M(self.as_hashmap())
+    }
+}
+
+/* Artifact Error */
+#[derive(Debug)]
+pub struct ArtifactError {
+    pub msg: String,
+}
+impl ArtifactError {
+    pub fn new(message: &str) -> Self {
+        Self {
+            msg: String::from(message),
+        }
+    }
+}
+impl fmt::Display for ArtifactError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str(format!("Artifact error: {}", self.msg).as_str())
+    }
+}
+impl Context for ArtifactError {}
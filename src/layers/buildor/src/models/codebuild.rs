@@ -1,4 +1,7 @@
-use aws_sdk_codebuild::{model::Build, output::StartBuildOutput};
+use aws_sdk_codebuild::{
+    model::{Build, ComputeType},
+    output::StartBuildOutput,
+};
 use aws_sdk_dynamodb::model::AttributeValue;
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -7,6 +10,9 @@ use std::str::FromStr;
 
 use super::common::AsDynamoDBAttributeValue;
 
+/// Build variable holding the uuid of the deployment that started the build
+pub const DEPLOYMENT_UUID_VARIABLE: &str = "DEPLOYMENT_UUID";
+
 #[derive(Debug, PartialEq)]
 pub enum BuildPhase {
     Queued,          // "QUEUED"
@@ -155,6 +161,63 @@ impl fmt::Display for ProjectDeploymentPhase {
     }
 }
 
+/// Compute sizes a project can request
+pub const COMPUTE_SIZES: &[&str] = &["small", "medium", "large"];
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ComputeSize {
+    Small,  // "small"
+    Medium, // "medium"
+    Large,  // "large"
+}
+impl ComputeSize {
+    fn rank(&self) -> u8 {
+        match self {
+            ComputeSize::Small => 0,
+            ComputeSize::Medium => 1,
+            ComputeSize::Large => 2,
+        }
+    }
+
+    /// Same size or `max` when it is bigger than `max`
+    pub fn capped(&self, max: ComputeSize) -> ComputeSize {
+        if self.rank() > max.rank() {
+            max
+        } else {
+            *self
+        }
+    }
+
+    pub fn compute_type(&self) -> ComputeType {
+        match self {
+            ComputeSize::Small => ComputeType::BuildGeneral1Small,
+            ComputeSize::Medium => ComputeType::BuildGeneral1Medium,
+            ComputeSize::Large => ComputeType::BuildGeneral1Large,
+        }
+    }
+}
+impl FromStr for ComputeSize {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<ComputeSize, ()> {
+        match input.to_lowercase() {
+            small if small == ComputeSize::Small.to_string() => Ok(ComputeSize::Small),
+            medium if medium == ComputeSize::Medium.to_string() => Ok(ComputeSize::Medium),
+            large if large == ComputeSize::Large.to_string() => Ok(ComputeSize::Large),
+            _ => Err(()),
+        }
+    }
+}
+impl fmt::Display for ComputeSize {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ComputeSize::Small => fmt.write_str("small"),
+            ComputeSize::Medium => fmt.write_str("medium"),
+            ComputeSize::Large => fmt.write_str("large"),
+        }
+    }
+}
+
 pub enum BuildObject {
     Build(Build),
     Builds(Option<Vec<Build>>),
@@ -176,6 +239,11 @@ pub struct BuildInfo {
     pub current_phase: Option<String>, // TODO: rename to build_phase
     #[serde(rename(serialize = "buildStatus"))]
     pub build_status: Option<String>, // TODO: rename to build_phase_status
+    #[serde(rename(serialize = "logsUrl"))]
+    pub logs_url: Option<String>,
+    #[serde(rename(serialize = "computeType"))]
+    pub compute_type: Option<String>,
+    pub image: Option<String>,
 }
 impl AsDynamoDBAttributeValue for BuildInfo {
     fn as_hashmap(&self) -> HashMap<String, AttributeValue> {
@@ -205,6 +273,21 @@ impl AsDynamoDBAttributeValue for BuildInfo {
             "build_status".to_string(),
             AttributeValue::S(self.build_status.to_owned().unwrap_or("-".to_string())),
         );
+        if let Some(logs_url) = self.logs_url.as_ref() {
+            map.insert(
+                "logs_url".to_string(),
+                AttributeValue::S(logs_url.to_owned()),
+            );
+        }
+        if let Some(compute_type) = self.compute_type.as_ref() {
+            map.insert(
+                "compute_type".to_string(),
+                AttributeValue::S(compute_type.to_owned()),
+            );
+        }
+        if let Some(image) = self.image.as_ref() {
+            map.insert("image".to_string(), AttributeValue::S(image.to_owned()));
+        }
 
         map
     }
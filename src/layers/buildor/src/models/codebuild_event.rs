@@ -0,0 +1,531 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use error_stack::{Context, Report};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+
+use super::codebuild::{BuildPhase, BuildPhaseStatus, DEPLOYMENT_UUID_VARIABLE};
+use super::outbox::PhaseDuration;
+use super::project_deployment::ProjectDeploymentStatus;
+
+pub const DETAIL_TYPE_BUILD_PHASE_CHANGE: &str = "CodeBuild Build Phase Change";
+pub const DETAIL_TYPE_BUILD_STATE_CHANGE: &str = "CodeBuild Build State Change";
+
+/// Format of the timestamps inside CodeBuild event details, e.g. `Sep 1, 2017 4:12:29 PM`
+const CODEBUILD_TIME_FORMAT: &str = "%b %d, %Y %I:%M:%S %p";
+
+/// Parses the timestamps CodeBuild writes inside event details, which are UTC
+/// but not rfc3339
+pub fn parse_codebuild_time(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.split_whitespace().collect::<Vec<&str>>().join(" ");
+    NaiveDateTime::parse_from_str(&value, CODEBUILD_TIME_FORMAT)
+        .ok()
+        .map(|timestamp| DateTime::<Utc>::from_utc(timestamp, Utc))
+}
+
+/// EventBridge envelope of every CodeBuild event
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CodeBuildEvent<D> {
+    pub id: String,
+    #[serde(rename = "detail-type")]
+    pub detail_type: String,
+    pub source: String,
+    pub time: DateTime<Utc>,
+    #[serde(default)]
+    pub resources: Vec<String>,
+    pub detail: D,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BuildPhaseChangeDetail {
+    #[serde(rename = "build-id")]
+    pub build_id: String,
+    #[serde(rename = "project-name")]
+    pub project_name: String,
+    #[serde(rename = "completed-phase")]
+    pub completed_phase: String,
+    #[serde(rename = "completed-phase-status")]
+    pub completed_phase_status: String,
+    #[serde(rename = "completed-phase-duration-seconds")]
+    pub completed_phase_duration_seconds: Option<i64>,
+    #[serde(rename = "completed-phase-context")]
+    pub completed_phase_context: Option<String>,
+    #[serde(rename = "additional-information")]
+    pub additional_information: AdditionalInformation,
+}
+impl BuildPhaseChangeDetail {
+    pub fn build_uuid(&self) -> String {
+        build_uuid(&self.build_id)
+    }
+
+    pub fn completed_phase(&self) -> BuildPhase {
+        BuildPhase::from_str(&self.completed_phase).unwrap()
+    }
+
+    pub fn completed_phase_status(&self) -> BuildPhaseStatus {
+        BuildPhaseStatus::from_str(&self.completed_phase_status).unwrap()
+    }
+
+    /// A failed phase fails the deployment, while finalizing an unfailed build succeeds it
+    pub fn deployment_status(&self) -> Option<ProjectDeploymentStatus> {
+        match self.completed_phase_status() {
+            BuildPhaseStatus::Succeeded
+            | BuildPhaseStatus::InProgress
+            | BuildPhaseStatus::Unknown => match self.completed_phase() {
+                BuildPhase::Finalizing => Some(ProjectDeploymentStatus::Succeeded),
+                _ => None,
+            },
+            _ => Some(ProjectDeploymentStatus::Failed),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BuildStateChangeDetail {
+    #[serde(rename = "build-id")]
+    pub build_id: String,
+    #[serde(rename = "project-name")]
+    pub project_name: String,
+    #[serde(rename = "build-status")]
+    pub build_status: String,
+    #[serde(rename = "current-phase")]
+    pub current_phase: Option<String>,
+    #[serde(rename = "current-phase-context")]
+    pub current_phase_context: Option<String>,
+    #[serde(rename = "additional-information")]
+    pub additional_information: AdditionalInformation,
+}
+impl BuildStateChangeDetail {
+    pub fn build_uuid(&self) -> String {
+        build_uuid(&self.build_id)
+    }
+
+    pub fn build_status(&self) -> BuildPhaseStatus {
+        BuildPhaseStatus::from_str(&self.build_status).unwrap()
+    }
+
+    /// Final status of the deployment, none while the build is still running
+    pub fn deployment_status(&self) -> Option<ProjectDeploymentStatus> {
+        match self.build_status() {
+            BuildPhaseStatus::Succeeded => Some(ProjectDeploymentStatus::Succeeded),
+            BuildPhaseStatus::InProgress | BuildPhaseStatus::Unknown => None,
+            _ => Some(ProjectDeploymentStatus::Failed),
+        }
+    }
+}
+
+/// Uuid of a build from its arn, `arn:aws:codebuild:...:build/<project>:<uuid>`
+fn build_uuid(build_id: &str) -> String {
+    match build_id.rsplit_once(':') {
+        Some((_, uuid)) => uuid.to_string(),
+        None => build_id.to_string(),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AdditionalInformation {
+    /// Usually an integer, sometimes serialized as a float
+    #[serde(rename = "build-number")]
+    pub build_number: Option<f64>,
+    #[serde(rename = "build-start-time")]
+    pub build_start_time: Option<String>,
+    #[serde(rename = "build-complete", default)]
+    pub build_complete: bool,
+    #[serde(rename = "timeout-in-minutes")]
+    pub timeout_in_minutes: Option<i64>,
+    pub initiator: Option<String>,
+    #[serde(rename = "source-version")]
+    pub source_version: Option<String>,
+    #[serde(rename = "resolved-source-version")]
+    pub resolved_source_version: Option<String>,
+    pub artifact: Option<ArtifactInformation>,
+    pub logs: Option<LogsInformation>,
+    pub environment: Option<EnvironmentInformation>,
+    #[serde(default)]
+    pub phases: Vec<PhaseInformation>,
+}
+impl AdditionalInformation {
+    pub fn build_number(&self) -> Option<i64> {
+        self.build_number.map(|number| number as i64)
+    }
+
+    pub fn build_start_time(&self) -> Option<DateTime<Utc>> {
+        self.build_start_time
+            .as_deref()
+            .and_then(parse_codebuild_time)
+    }
+
+    /// Resolved commit of the build source, the requested version otherwise
+    pub fn commit(&self) -> Option<String> {
+        self.resolved_source_version
+            .clone()
+            .or_else(|| self.source_version.clone())
+    }
+
+    pub fn artifact_location(&self) -> Option<&str> {
+        self.artifact
+            .as_ref()
+            .and_then(|artifact| artifact.location.as_deref())
+    }
+
+    /// Deployment that started the build, builds started before the variable
+    /// existed have none
+    pub fn deployment_uuid(&self) -> Option<String> {
+        self.environment.as_ref().and_then(|environment| {
+            environment
+                .environment_variables
+                .iter()
+                .find(|variable| variable.name == DEPLOYMENT_UUID_VARIABLE)
+                .and_then(|variable| variable.value.clone())
+        })
+    }
+
+    /// CloudWatch console link to the build logs
+    pub fn logs_url(&self) -> Option<String> {
+        self.logs.as_ref().and_then(|logs| logs.deep_link.clone())
+    }
+
+    /// First phase that did not succeed, with its status, e.g. `BUILD (FAILED)`
+    pub fn failed_phase(&self) -> Option<String> {
+        self.phases
+            .iter()
+            .find_map(|phase| match phase.phase_status.as_deref() {
+                Some(status) if status != BuildPhaseStatus::Succeeded.to_string() => {
+                    Some(format!("{} ({})", phase.phase_type, status))
+                }
+                _ => None,
+            })
+    }
+
+    /// Phases that reported a duration, the `COMPLETED` one never does
+    pub fn phase_durations(&self) -> Vec<PhaseDuration> {
+        self.phases
+            .iter()
+            .filter_map(|phase| {
+                phase.duration_in_seconds.map(|duration| PhaseDuration {
+                    phase: phase.phase_type.clone(),
+                    status: phase.phase_status.clone(),
+                    duration_seconds: duration,
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArtifactInformation {
+    pub location: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LogsInformation {
+    #[serde(rename = "group-name")]
+    pub group_name: Option<String>,
+    #[serde(rename = "stream-name")]
+    pub stream_name: Option<String>,
+    #[serde(rename = "deep-link")]
+    pub deep_link: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EnvironmentInformation {
+    pub image: Option<String>,
+    #[serde(rename = "privileged-mode")]
+    pub privileged_mode: Option<bool>,
+    #[serde(rename = "compute-type")]
+    pub compute_type: Option<String>,
+    #[serde(rename = "type")]
+    pub environment_type: Option<String>,
+    #[serde(rename = "environment-variables", default)]
+    pub environment_variables: Vec<EnvironmentVariableInformation>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EnvironmentVariableInformation {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub variable_type: Option<String>,
+    pub value: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PhaseInformation {
+    #[serde(rename = "phase-type")]
+    pub phase_type: String,
+    #[serde(rename = "phase-status")]
+    pub phase_status: Option<String>,
+    #[serde(rename = "duration-in-seconds")]
+    pub duration_in_seconds: Option<i64>,
+    #[serde(rename = "start-time")]
+    pub start_time: Option<String>,
+    #[serde(rename = "end-time")]
+    pub end_time: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum CodeBuildEventDetail {
+    BuildPhaseChange(CodeBuildEvent<BuildPhaseChangeDetail>),
+    BuildStateChange(CodeBuildEvent<BuildStateChangeDetail>),
+}
+impl CodeBuildEventDetail {
+    /// Parses a CodeBuild event by its `detail-type`
+    pub fn parse(event: Value) -> Result<CodeBuildEventDetail, Report<CodeBuildEventError>> {
+        let detail_type = match event.get("detail-type").and_then(|value| value.as_str()) {
+            Some(detail_type) => detail_type.to_string(),
+            None => return Err(Report::new(CodeBuildEventError::new("missing detail-type"))),
+        };
+
+        match detail_type.as_str() {
+            DETAIL_TYPE_BUILD_PHASE_CHANGE => match serde_json::from_value(event) {
+                Ok(event) => Ok(CodeBuildEventDetail::BuildPhaseChange(event)),
+                Err(error) => Err(Report::new(CodeBuildEventError::new(&error.to_string()))),
+            },
+            DETAIL_TYPE_BUILD_STATE_CHANGE => match serde_json::from_value(event) {
+                Ok(event) => Ok(CodeBuildEventDetail::BuildStateChange(event)),
+                Err(error) => Err(Report::new(CodeBuildEventError::new(&error.to_string()))),
+            },
+            detail_type => Err(Report::new(CodeBuildEventError::new(&format!(
+                "unsupported detail-type: {}",
+                detail_type
+            )))),
+        }
+    }
+}
+
+/* CodeBuild Event Error */
+#[derive(Debug)]
+pub struct CodeBuildEventError {
+    pub msg: String,
+}
+impl CodeBuildEventError {
+    pub fn new(message: &str) -> Self {
+        Self {
+            msg: String::from(message),
+        }
+    }
+}
+impl fmt::Display for CodeBuildEventError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str(format!("CodeBuild event error: {}", self.msg).as_str())
+    }
+}
+impl Context for CodeBuildEventError {}
+
+#[cfg(test)]
+mod codebuild_event_tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn fixture(name: &str) -> Value {
+        let content = match name {
+            "submitted" => {
+                include_str!("../../fixtures/codebuild/build-phase-change-submitted.json")
+            }
+            "build-failed" => {
+                include_str!("../../fixtures/codebuild/build-phase-change-build-failed.json")
+            }
+            "succeeded" => {
+                include_str!("../../fixtures/codebuild/build-state-change-succeeded.json")
+            }
+            "failed" => include_str!("../../fixtures/codebuild/build-state-change-failed.json"),
+            "missing-build-id" => {
+                include_str!("../../fixtures/codebuild/build-phase-change-missing-build-id.json")
+            }
+            _ => panic!("Unknown fixture: {}", name),
+        };
+        serde_json::from_str(content).unwrap()
+    }
+
+    #[test]
+    fn parses_codebuild_times() {
+        assert_eq!(
+            parse_codebuild_time("Sep 1, 2022 4:12:29 PM"),
+            Some(Utc.ymd(2022, 9, 1).and_hms(16, 12, 29))
+        );
+        assert_eq!(
+            parse_codebuild_time("Dec 12, 2022  11:02:03 AM"),
+            Some(Utc.ymd(2022, 12, 12).and_hms(11, 2, 3))
+        );
+        assert_eq!(parse_codebuild_time("2022-09-01T16:12:29Z"), None);
+    }
+
+    #[test]
+    fn parses_submitted_phase_change() {
+        let event = match CodeBuildEventDetail::parse(fixture("submitted")).unwrap() {
+            CodeBuildEventDetail::BuildPhaseChange(event) => event,
+            other => panic!("Unexpected event: {:?}", other),
+        };
+
+        assert_eq!(event.id, "1f0f4d62-7d1c-4b53-9b3a-2d3c7b5e3a01");
+        assert_eq!(event.time, Utc.ymd(2022, 9, 1).and_hms(16, 12, 31));
+        assert_eq!(
+            event.detail.build_uuid(),
+            "8745a7a9-c340-456a-9166-edf953571bef"
+        );
+        assert_eq!(event.detail.project_name, "App-Building-SPAs");
+        assert_eq!(event.detail.completed_phase(), BuildPhase::Submitted);
+        assert_eq!(
+            event.detail.completed_phase_status(),
+            BuildPhaseStatus::Succeeded
+        );
+        let info = &event.detail.additional_information;
+        assert_eq!(info.build_number(), Some(12));
+        assert_eq!(
+            info.build_start_time(),
+            Some(Utc.ymd(2022, 9, 1).and_hms(16, 12, 29))
+        );
+        assert_eq!(info.commit(), Some("main".to_string()));
+        assert_eq!(info.artifact_location(), None);
+        assert_eq!(info.phases.len(), 2);
+    }
+
+    #[test]
+    fn parses_failed_phase_change() {
+        let event = match CodeBuildEventDetail::parse(fixture("build-failed")).unwrap() {
+            CodeBuildEventDetail::BuildPhaseChange(event) => event,
+            other => panic!("Unexpected event: {:?}", other),
+        };
+
+        assert_eq!(event.detail.completed_phase(), BuildPhase::Build);
+        assert_eq!(
+            event.detail.completed_phase_status(),
+            BuildPhaseStatus::Failed
+        );
+        assert_eq!(event.detail.completed_phase_duration_seconds, Some(29));
+        let info = &event.detail.additional_information;
+        assert_eq!(info.build_number(), Some(13));
+        assert_eq!(
+            info.commit(),
+            Some("0123456789abcdef0123456789abcdef01234567".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_state_change() {
+        let event = match CodeBuildEventDetail::parse(fixture("succeeded")).unwrap() {
+            CodeBuildEventDetail::BuildStateChange(event) => event,
+            other => panic!("Unexpected event: {:?}", other),
+        };
+
+        assert_eq!(event.detail.build_status(), BuildPhaseStatus::Succeeded);
+        assert_eq!(event.detail.current_phase, Some("COMPLETED".to_string()));
+        assert!(event.detail.additional_information.build_complete);
+        assert_eq!(
+            event.detail.additional_information.artifact_location(),
+            Some(
+                "arn:aws:s3:::app-deploy-spas-artifacts/8745a7a9-c340-456a-9166-edf953571bef/my-app.zip"
+            )
+        );
+        let info = &event.detail.additional_information;
+        assert!(info
+            .logs_url()
+            .unwrap()
+            .starts_with("https://console.aws.amazon.com/cloudwatch/"));
+        let environment = info.environment.as_ref().unwrap();
+        assert_eq!(
+            environment.compute_type,
+            Some("BUILD_GENERAL1_SMALL".to_string())
+        );
+        assert_eq!(
+            environment.image,
+            Some("aws/codebuild/standard:6.0".to_string())
+        );
+        assert_eq!(
+            info.deployment_uuid(),
+            Some("3f0c1a2e-54c1-4c0d-9d1e-6a1b2c3d4e5f".to_string())
+        );
+        assert_eq!(info.failed_phase(), None);
+        assert_eq!(
+            event.detail.deployment_status(),
+            Some(ProjectDeploymentStatus::Succeeded)
+        );
+    }
+
+    #[test]
+    fn failed_state_change_names_the_failed_phase() {
+        let event = match CodeBuildEventDetail::parse(fixture("failed")).unwrap() {
+            CodeBuildEventDetail::BuildStateChange(event) => event,
+            other => panic!("Unexpected event: {:?}", other),
+        };
+
+        assert_eq!(event.detail.build_status(), BuildPhaseStatus::Failed);
+        assert_eq!(
+            event.detail.deployment_status(),
+            Some(ProjectDeploymentStatus::Failed)
+        );
+        let info = &event.detail.additional_information;
+        assert_eq!(info.failed_phase(), Some("BUILD (FAILED)".to_string()));
+        let phases: Vec<(String, i64)> = info
+            .phase_durations()
+            .into_iter()
+            .map(|phase| (phase.phase, phase.duration_seconds))
+            .collect();
+        assert_eq!(
+            phases,
+            vec![
+                ("SUBMITTED".to_string(), 1),
+                ("BUILD".to_string(), 29),
+                ("FINALIZING".to_string(), 1)
+            ]
+        );
+        assert_eq!(info.logs_url(), None);
+        assert_eq!(info.artifact_location(), None);
+
+        let mut in_progress = event.detail.clone();
+        in_progress.build_status = "IN_PROGRESS".to_string();
+        assert_eq!(in_progress.deployment_status(), None);
+    }
+
+    #[test]
+    fn phase_changes_only_finish_on_failures_and_finalizing() {
+        let event = match CodeBuildEventDetail::parse(fixture("submitted")).unwrap() {
+            CodeBuildEventDetail::BuildPhaseChange(event) => event,
+            other => panic!("Unexpected event: {:?}", other),
+        };
+        let mut detail = event.detail;
+        assert_eq!(detail.deployment_status(), None);
+
+        detail.completed_phase = "FINALIZING".to_string();
+        assert_eq!(
+            detail.deployment_status(),
+            Some(ProjectDeploymentStatus::Succeeded)
+        );
+
+        detail.completed_phase = "BUILD".to_string();
+        detail.completed_phase_status = "TIMED_OUT".to_string();
+        assert_eq!(
+            detail.deployment_status(),
+            Some(ProjectDeploymentStatus::Failed)
+        );
+    }
+
+    #[test]
+    fn fails_on_missing_build_id() {
+        match CodeBuildEventDetail::parse(fixture("missing-build-id")) {
+            Err(error) => assert!(error.to_string().contains("missing field `build-id`")),
+            _ => assert_eq!("", "Should have panicked but it did not"),
+        }
+    }
+
+    #[test]
+    fn fails_on_unsupported_detail_type() {
+        let mut event = fixture("submitted");
+        event["detail-type"] = Value::String("CodeBuild Report State Change".to_string());
+        match CodeBuildEventDetail::parse(event) {
+            Err(error) => assert_eq!(
+                error.to_string(),
+                "CodeBuild event error: unsupported detail-type: CodeBuild Report State Change"
+            ),
+            _ => assert_eq!("", "Should have panicked but it did not"),
+        }
+
+        match CodeBuildEventDetail::parse(serde_json::json!({})) {
+            Err(error) => assert_eq!(
+                error.to_string(),
+                "CodeBuild event error: missing detail-type"
+            ),
+            _ => assert_eq!("", "Should have panicked but it did not"),
+        }
+    }
+}
@@ -5,7 +5,9 @@ use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
 
+use super::api_error::ApiError;
 use super::request::RequestError;
+use super::validation::FieldError;
 
 /* Required Env Var Error */
 #[derive(Debug)]
@@ -31,7 +33,7 @@ impl Context for RequiredEnvVarError {}
 pub struct ExecutionError;
 impl fmt::Display for ExecutionError {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt.write_str(format!("Execution error").as_str())
+        fmt.write_str("Execution error")
     }
 }
 impl Context for ExecutionError {}
@@ -40,30 +42,34 @@ impl Context for ExecutionError {}
 pub struct CommonError;
 impl CommonError {
     pub fn generic(error: String) -> RequestError {
-        RequestError {
-            code: "CME00".to_string(),
-            message: "Error".to_string(),
-            details: error,
-        }
+        ApiError::Generic.error(error)
     }
 
     pub fn schema_compliant(details: String) -> RequestError {
+        ApiError::SchemaCompliant.error(details)
+    }
+
+    pub fn validation(errors: Vec<FieldError>) -> RequestError {
+        let details = format!(
+            "Invalid fields: {}",
+            errors
+                .iter()
+                .map(|error| error.field.as_str())
+                .collect::<Vec<&str>>()
+                .join(", ")
+        );
         RequestError {
-            code: "CME01".to_string(),
-            message: "Schema Compliant Error".to_string(),
-            details,
+            errors,
+            ..ApiError::Validation.error(details)
         }
     }
 
+    pub fn invalid_list_query(details: String) -> RequestError {
+        ApiError::InvalidListQuery.error(details)
+    }
+
     pub fn item_not_found(details: Option<String>) -> RequestError {
-        RequestError {
-            code: "CME02".to_string(),
-            message: "Not Found Error".to_string(),
-            details: match details {
-                Some(details) => details,
-                None => "Item not found".to_string(),
-            },
-        }
+        ApiError::ItemNotFound.error(details.unwrap_or_else(|| "Item not found".to_string()))
     }
 }
 
@@ -0,0 +1,59 @@
+use aws_sdk_dynamodb::model::AttributeValue;
+use chrono::Utc;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use super::common::AsDynamoDBAttributeValue;
+
+/// Event a lambda could not process, kept for inspection and replay
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DeadLetter {
+    pub uuid: String,
+    /// Lambda that rejected the event
+    pub source: String,
+    pub reason: String,
+    /// Raw event, as received
+    pub payload: String,
+    #[serde(rename(serialize = "createdAt"))]
+    pub created_at: String,
+}
+impl DeadLetter {
+    pub fn new(source: &str, reason: String, payload: String) -> Self {
+        Self {
+            uuid: Uuid::new_v4().to_string(),
+            source: source.to_string(),
+            reason,
+            payload,
+            created_at: Utc::now().to_rfc3339(),
+        }
+    }
+}
+impl AsDynamoDBAttributeValue for DeadLetter {
+    fn as_hashmap(&self) -> HashMap<String, AttributeValue> {
+        let mut map: HashMap<String, AttributeValue> = HashMap::new();
+        map.insert("uuid".to_string(), AttributeValue::S(self.uuid.to_owned()));
+        map.insert(
+            "source".to_string(),
+            AttributeValue::S(self.source.to_owned()),
+        );
+        map.insert(
+            "reason".to_string(),
+            AttributeValue::S(self.reason.to_owned()),
+        );
+        map.insert(
+            "payload".to_string(),
+            AttributeValue::S(self.payload.to_owned()),
+        );
+        map.insert(
+            "created_at".to_string(),
+            AttributeValue::S(self.created_at.to_owned()),
+        );
+
+        map
+    }
+
+    fn as_attr(&self) -> AttributeValue {
+        AttributeValue::M(self.as_hashmap())
+    }
+}
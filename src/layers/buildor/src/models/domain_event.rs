@@ -0,0 +1,49 @@
+use serde_derive::Serialize;
+
+use super::project::Project;
+use super::project_deployment::ProjectDeployment;
+use super::user::User;
+
+/// Change of a record, decoded from the table streams. Sinks receive them in
+/// the order of the stream shard and at least once.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", content = "data")]
+pub enum DomainEvent {
+    UserCreated(User),
+    UserUpdated {
+        old: User,
+        new: User,
+    },
+    UserDeleted(User),
+    ProjectCreated(Project),
+    ProjectUpdated {
+        old: Project,
+        new: Project,
+    },
+    ProjectDeleted(Project),
+    DeploymentCreated(ProjectDeployment),
+    DeploymentStatusChanged {
+        deployment: ProjectDeployment,
+        from: String,
+        to: String,
+    },
+    /// Any other deployment change, e.g. build info updates
+    DeploymentUpdated(ProjectDeployment),
+    DeploymentDeleted(ProjectDeployment),
+}
+impl DomainEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DomainEvent::UserCreated(_) => "UserCreated",
+            DomainEvent::UserUpdated { .. } => "UserUpdated",
+            DomainEvent::UserDeleted(_) => "UserDeleted",
+            DomainEvent::ProjectCreated(_) => "ProjectCreated",
+            DomainEvent::ProjectUpdated { .. } => "ProjectUpdated",
+            DomainEvent::ProjectDeleted(_) => "ProjectDeleted",
+            DomainEvent::DeploymentCreated(_) => "DeploymentCreated",
+            DomainEvent::DeploymentStatusChanged { .. } => "DeploymentStatusChanged",
+            DomainEvent::DeploymentUpdated(_) => "DeploymentUpdated",
+            DomainEvent::DeploymentDeleted(_) => "DeploymentDeleted",
+        }
+    }
+}
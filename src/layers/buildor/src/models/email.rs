@@ -0,0 +1,112 @@
+use error_stack::Context;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::utils::load_env_var;
+
+/// Submission port of most SMTP relays
+pub const DEFAULT_SMTP_PORT: u16 = 587;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SmtpSecurity {
+    StartTls, // "starttls"
+    Tls,      // "tls"
+    None,     // "none", only meant for local relays and tests
+    Unknown,  // "unknown", (custom value used when parsing from/to string/enum)
+}
+impl FromStr for SmtpSecurity {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<SmtpSecurity, ()> {
+        match input.to_lowercase() {
+            starttls if starttls == SmtpSecurity::StartTls.to_string() => {
+                Ok(SmtpSecurity::StartTls)
+            }
+            tls if tls == SmtpSecurity::Tls.to_string() => Ok(SmtpSecurity::Tls),
+            none if none == SmtpSecurity::None.to_string() => Ok(SmtpSecurity::None),
+            _ => Ok(SmtpSecurity::Unknown),
+        }
+    }
+}
+impl fmt::Display for SmtpSecurity {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SmtpSecurity::StartTls => fmt.write_str("starttls"),
+            SmtpSecurity::Tls => fmt.write_str("tls"),
+            SmtpSecurity::None => fmt.write_str("none"),
+            SmtpSecurity::Unknown => fmt.write_str("unknown"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Sender address, `Name <address>` or a bare address
+    pub from: String,
+    pub security: SmtpSecurity,
+}
+impl SmtpConfig {
+    /// Reads the `SMTP_*` env vars, empty values count as missing. Email
+    /// notifications are disabled when `SMTP_HOST` or `SMTP_FROM` is missing,
+    /// unknown security modes fall back to STARTTLS.
+    pub fn from_env() -> Option<Self> {
+        let var = |name: &str| {
+            load_env_var(name, None)
+                .ok()
+                .filter(|value| !value.is_empty())
+        };
+        let host = var("SMTP_HOST")?;
+        let from = var("SMTP_FROM")?;
+        let port = var("SMTP_PORT")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_SMTP_PORT);
+        let security = match var("SMTP_SECURITY") {
+            Some(value) => match SmtpSecurity::from_str(&value).unwrap() {
+                SmtpSecurity::Unknown => SmtpSecurity::StartTls,
+                security => security,
+            },
+            None => SmtpSecurity::StartTls,
+        };
+
+        Some(Self {
+            host,
+            port,
+            username: var("SMTP_USERNAME"),
+            password: var("SMTP_PASSWORD"),
+            from,
+            security,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmailMessage {
+    /// Sent as blind copies, recipients do not see each other addresses
+    pub recipients: Vec<String>,
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+/* Email Error */
+#[derive(Debug)]
+pub struct EmailError {
+    pub msg: String,
+}
+impl EmailError {
+    pub fn new(message: &str) -> Self {
+        Self {
+            msg: String::from(message),
+        }
+    }
+}
+impl fmt::Display for EmailError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str(format!("Email error: {}", self.msg).as_str())
+    }
+}
+impl Context for EmailError {}
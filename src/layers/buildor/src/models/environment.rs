@@ -0,0 +1,283 @@
+use aws_sdk_dynamodb::model::AttributeValue;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::codebuild::DEPLOYMENT_UUID_VARIABLE;
+use super::common::AsDynamoDBAttributeValue;
+use super::request::RequestError;
+use super::validation::{Rule, Validate, Validator};
+
+pub const DEFAULT_ENVIRONMENT_NAME: &str = "production";
+pub const DEFAULT_ENVIRONMENT_BRANCH: &str = "main";
+/// CodeBuild environment variable types resolved when the build starts
+pub const SECRET_TYPES: &[&str] = &["PARAMETER_STORE", "SECRETS_MANAGER"];
+/// Variables every build sets, environment variables and secrets must not override them
+pub const RESERVED_VARIABLE_NAMES: &[&str] = &[
+    DEPLOYMENT_UUID_VARIABLE,
+    "ENVIRONMENT_NAME",
+    "PROJECT_NAME",
+    "REPO_BRANCH",
+    "REPO_URL",
+];
+
+pub fn default_environment_name() -> String {
+    DEFAULT_ENVIRONMENT_NAME.to_string()
+}
+
+/// Variable read from Parameter Store or Secrets Manager by CodeBuild, only the
+/// reference is stored and shown in the build details
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SecretVariable {
+    /// One of `SECRET_TYPES`
+    #[serde(rename = "type")]
+    pub secret_type: String,
+    /// Parameter name or secret id, e.g. `/App/projects/site/NPM_TOKEN`
+    pub value: String,
+}
+impl AsDynamoDBAttributeValue for SecretVariable {
+    fn as_hashmap(&self) -> HashMap<String, AttributeValue> {
+        let mut map: HashMap<String, AttributeValue> = HashMap::new();
+        map.insert(
+            "type".to_string(),
+            AttributeValue::S(self.secret_type.to_owned()),
+        );
+        map.insert(
+            "value".to_string(),
+            AttributeValue::S(self.value.to_owned()),
+        );
+
+        map
+    }
+
+    fn as_attr(&self) -> AttributeValue {
+        AttributeValue::M(self.as_hashmap())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Environment {
+    pub name: String,
+    pub branch: String,
+    /// Plain text variables, visible in the build details
+    pub variables: HashMap<String, String>,
+    pub secrets: HashMap<String, SecretVariable>,
+    #[serde(rename(serialize = "currentDeployment"))]
+    pub current_deployment: String,
+}
+impl Environment {
+    pub fn new(payload: EnvironmentCreatePayload) -> Self {
+        Self {
+            name: payload.name,
+            branch: match payload.branch {
+                Some(value) => value,
+                None => DEFAULT_ENVIRONMENT_BRANCH.to_string(),
+            },
+            variables: payload.variables.unwrap_or_default(),
+            secrets: payload
+                .secrets
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(name, secret)| {
+                    (
+                        name,
+                        SecretVariable {
+                            secret_type: secret.secret_type.to_uppercase(),
+                            value: secret.value,
+                        },
+                    )
+                })
+                .collect(),
+            current_deployment: "-".to_string(),
+        }
+    }
+
+    pub fn defaults() -> Vec<Self> {
+        vec![Environment::new(EnvironmentCreatePayload::default(
+            default_environment_name(),
+        ))]
+    }
+}
+impl AsDynamoDBAttributeValue for Environment {
+    fn as_hashmap(&self) -> HashMap<String, AttributeValue> {
+        let mut map: HashMap<String, AttributeValue> = HashMap::new();
+        map.insert("name".to_string(), AttributeValue::S(self.name.to_owned()));
+        map.insert(
+            "branch".to_string(),
+            AttributeValue::S(self.branch.to_owned()),
+        );
+        map.insert(
+            "variables".to_string(),
+            AttributeValue::M(
+                self.variables
+                    .iter()
+                    .map(|(k, v)| (k.to_owned(), AttributeValue::S(v.to_owned())))
+                    .collect(),
+            ),
+        );
+        map.insert(
+            "secrets".to_string(),
+            AttributeValue::M(
+                self.secrets
+                    .iter()
+                    .map(|(k, v)| (k.to_owned(), v.as_attr()))
+                    .collect(),
+            ),
+        );
+        map.insert(
+            "current_deployment".to_string(),
+            AttributeValue::S(self.current_deployment.to_owned()),
+        );
+
+        map
+    }
+
+    fn as_attr(&self) -> AttributeValue {
+        AttributeValue::M(self.as_hashmap())
+    }
+}
+
+/// Environments are stored as a map keyed by environment name so a single
+/// environment can be addressed in update expressions.
+pub fn environments_as_attr(environments: &[Environment]) -> AttributeValue {
+    AttributeValue::M(
+        environments
+            .iter()
+            .map(|environment| (environment.name.to_owned(), environment.as_attr()))
+            .collect(),
+    )
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EnvironmentCreatePayload {
+    pub name: String,
+    pub branch: Option<String>,
+    pub variables: Option<HashMap<String, String>>,
+    pub secrets: Option<HashMap<String, SecretVariable>>,
+}
+impl EnvironmentCreatePayload {
+    pub fn default(name: String) -> Self {
+        Self {
+            name,
+            branch: None,
+            variables: None,
+            secrets: None,
+        }
+    }
+}
+impl Validate for EnvironmentCreatePayload {
+    fn validate(&self) -> Result<(), RequestError> {
+        let mut validator = Validator::new()
+            .field(
+                "name",
+                Some(&self.name),
+                &[Rule::NotBlank, Rule::MaxLength(64), Rule::Slug],
+            )
+            .field(
+                "branch",
+                self.branch.as_deref(),
+                &[Rule::NotBlank, Rule::MaxLength(255), Rule::Branch],
+            );
+        for name in self.variables.iter().flat_map(|variables| variables.keys()) {
+            validator = validator.check(&format!("variables.{}", name), variable_name(name));
+        }
+
+        for (name, secret) in self.secrets.iter().flat_map(|secrets| secrets.iter()) {
+            validator = validator
+                .check(
+                    &format!("secrets.{}", name),
+                    variable_name(name).and_then(|_| {
+                        match self
+                            .variables
+                            .as_ref()
+                            .is_some_and(|variables| variables.contains_key(name))
+                        {
+                            true => Err("Must not also be a plain text variable".to_string()),
+                            false => Ok(()),
+                        }
+                    }),
+                )
+                .field(
+                    &format!("secrets.{}.type", name),
+                    Some(&secret.secret_type),
+                    &[Rule::OneOf(SECRET_TYPES)],
+                )
+                .field(
+                    &format!("secrets.{}.value", name),
+                    Some(&secret.value),
+                    &[Rule::NotBlank, Rule::MaxLength(2048)],
+                );
+        }
+
+        validator.finish()
+    }
+}
+
+fn variable_name(name: &str) -> Result<(), String> {
+    match is_variable_name(name) {
+        true if RESERVED_VARIABLE_NAMES.contains(&name) => {
+            Err("Must not override a variable set by the build".to_string())
+        }
+        true => Ok(()),
+        false => Err("Must be a valid environment variable name".to_string()),
+    }
+}
+
+/// Letters, digits and underscores, not starting with a digit
+fn is_variable_name(name: &str) -> bool {
+    match name.chars().next() {
+        Some(first) if !first.is_ascii_digit() => {
+            name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod environment_tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fields(payload: serde_json::Value) -> Vec<String> {
+        let payload: EnvironmentCreatePayload = serde_json::from_value(payload).unwrap();
+        match payload.validate() {
+            Ok(_) => vec![],
+            Err(error) => error.errors.into_iter().map(|error| error.field).collect(),
+        }
+    }
+
+    #[test]
+    fn rejects_reserved_variable_names() {
+        assert_eq!(
+            fields(json!({
+                "name": "production",
+                "variables": { "REPO_URL": "https://example.com/other.git", "API_URL": "https://api" },
+                "secrets": { "DEPLOYMENT_UUID": { "type": "PARAMETER_STORE", "value": "/uuid" } },
+            })),
+            vec!["variables.REPO_URL", "secrets.DEPLOYMENT_UUID"]
+        );
+        for name in RESERVED_VARIABLE_NAMES {
+            assert!(variable_name(name).is_err());
+        }
+        assert!(variable_name("repo_url").is_ok());
+    }
+
+    #[test]
+    fn validates_branch_and_secret_type() {
+        assert_eq!(
+            fields(json!({ "name": "production", "branch": "main $(curl evil)" })),
+            vec!["branch"]
+        );
+
+        let payload: EnvironmentCreatePayload = serde_json::from_value(json!({
+            "name": "production",
+            "branch": "release/1.2",
+            "secrets": { "NPM_TOKEN": { "type": "parameter_store", "value": "/npm" } },
+        }))
+        .unwrap();
+        assert!(payload.validate().is_ok());
+        assert_eq!(
+            Environment::new(payload).secrets["NPM_TOKEN"].secret_type,
+            "PARAMETER_STORE"
+        );
+    }
+}
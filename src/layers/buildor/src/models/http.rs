@@ -0,0 +1,53 @@
+use error_stack::Context;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpRequest {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+impl HttpRequest {
+    pub fn json(url: String, body: String) -> Self {
+        Self {
+            url,
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body,
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: String,
+}
+impl HttpResponse {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/* Http Error */
+#[derive(Debug)]
+pub struct HttpError {
+    pub msg: String,
+}
+impl HttpError {
+    pub fn new(message: &str) -> Self {
+        Self {
+            msg: String::from(message),
+        }
+    }
+}
+impl fmt::Display for HttpError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str(format!("Http error: {}", self.msg).as_str())
+    }
+}
+impl Context for HttpError {}
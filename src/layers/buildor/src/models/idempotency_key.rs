@@ -0,0 +1,216 @@
+use aws_sdk_dynamodb::model::AttributeValue;
+use chrono::{DateTime, Duration, Utc};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+use super::api_error::ApiError;
+use super::common::AsDynamoDBAttributeValue;
+use super::request::RequestError;
+use super::response::{Response, ResponseHeaders};
+
+/// Hours a key is remembered, a replay sent later is handled as a new request
+pub const DEFAULT_IDEMPOTENCY_KEY_TTL_HOURS: i64 = 24;
+
+/// Seconds a request holds its key while in progress, longer than the 29
+/// seconds a request can run. A replay takes over a key whose request never
+/// completed nor released it once the lock expired.
+pub const IDEMPOTENCY_KEY_LOCK_SECONDS: i64 = 60;
+
+/// Longest `Idempotency-Key` header value accepted
+pub const IDEMPOTENCY_KEY_MAX_LENGTH: usize = 255;
+
+/// Request sent with an `Idempotency-Key` header, expired by the DynamoDB TTL.
+/// Its response is kept once it succeeded so that replays get it back.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IdempotencyKey {
+    /// Header value scoped to the route, e.g. `POST /deployments#<key>`
+    pub key: String,
+    /// Hash of the request, replays must send the same request
+    pub fingerprint: String,
+    /// Status of the response, none while the request is in progress
+    #[serde(rename(serialize = "statusCode"))]
+    pub status_code: Option<u16>,
+    pub body: Option<String>,
+    /// Epoch seconds until which the request in progress holds the key
+    #[serde(rename(serialize = "lockedUntil"))]
+    pub locked_until: i64,
+    /// Epoch seconds, the table TTL attribute
+    #[serde(rename(serialize = "expiresAt"))]
+    pub expires_at: i64,
+    #[serde(rename(serialize = "createdAt"))]
+    pub created_at: String,
+}
+impl IdempotencyKey {
+    pub fn new(key: String, fingerprint: String, now: DateTime<Utc>, ttl: Duration) -> Self {
+        Self {
+            key,
+            fingerprint,
+            status_code: None,
+            body: None,
+            locked_until: (now + Duration::seconds(IDEMPOTENCY_KEY_LOCK_SECONDS)).timestamp(),
+            expires_at: (now + ttl).timestamp(),
+            created_at: now.to_rfc3339(),
+        }
+    }
+
+    /// Hash of the method, path and body of a request. JSON bodies are
+    /// compared regardless of their formatting.
+    pub fn fingerprint(method: &str, path: &str, body: Option<&str>) -> String {
+        let body = body
+            .map(|body| match serde_json::from_str::<Value>(body) {
+                Ok(value) => value.to_string(),
+                Err(_) => body.to_string(),
+            })
+            .unwrap_or_default();
+        format!(
+            "{:x}",
+            Sha256::digest(format!("{} {}\n{}", method, path, body).as_bytes())
+        )
+    }
+
+    /// DynamoDB deletes expired items up to a few days late
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at <= now.timestamp()
+    }
+
+    /// Whether the request holding the key is still in progress
+    pub fn is_locked(&self, now: DateTime<Utc>) -> bool {
+        self.status_code.is_none() && self.locked_until > now.timestamp()
+    }
+
+    /// Response of the first request, none while it is in progress
+    pub fn response(&self) -> Option<Response> {
+        match (self.status_code, self.body.as_ref()) {
+            (Some(status_code), Some(body)) => Some(Response {
+                status_code,
+                headers: ResponseHeaders::default(),
+                body: body.to_owned(),
+                error: None,
+            }),
+            _ => None,
+        }
+    }
+}
+impl AsDynamoDBAttributeValue for IdempotencyKey {
+    fn as_hashmap(&self) -> HashMap<String, AttributeValue> {
+        let mut map: HashMap<String, AttributeValue> = HashMap::new();
+        map.insert("key".to_string(), AttributeValue::S(self.key.to_owned()));
+        map.insert(
+            "fingerprint".to_string(),
+            AttributeValue::S(self.fingerprint.to_owned()),
+        );
+        self.status_code.and_then(|status_code| {
+            map.insert(
+                "status_code".to_string(),
+                AttributeValue::N(format!("{}", status_code)),
+            )
+        });
+        self.body
+            .as_ref()
+            .and_then(|body| map.insert("body".to_string(), AttributeValue::S(body.to_owned())));
+        map.insert(
+            "locked_until".to_string(),
+            AttributeValue::N(format!("{}", self.locked_until)),
+        );
+        map.insert(
+            "expires_at".to_string(),
+            AttributeValue::N(format!("{}", self.expires_at)),
+        );
+        map.insert(
+            "created_at".to_string(),
+            AttributeValue::S(self.created_at.to_owned()),
+        );
+
+        map
+    }
+
+    fn as_attr(&self) -> AttributeValue {
+        AttributeValue::M(self.as_hashmap())
+    }
+}
+
+pub struct IdempotencyKeyError;
+impl IdempotencyKeyError {
+    pub fn invalid() -> RequestError {
+        ApiError::IdempotencyKeyInvalid.error(format!(
+            "Idempotency-Key header must be between 1 and {} characters",
+            IDEMPOTENCY_KEY_MAX_LENGTH
+        ))
+    }
+
+    pub fn mismatch() -> RequestError {
+        ApiError::IdempotencyKeyMismatch.error(
+            "Idempotency-Key already used with a different request, send a new key".to_string(),
+        )
+    }
+
+    pub fn in_progress() -> RequestError {
+        ApiError::IdempotencyKeyInProgress.error(
+            "A request with this Idempotency-Key is in progress, try again once it finishes"
+                .to_string(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod idempotency_key_tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn fingerprints_requests() {
+        let fingerprint = IdempotencyKey::fingerprint(
+            "POST",
+            "/deployments",
+            Some(r#"{"project_uuid": "abc", "environment": "main"}"#),
+        );
+        assert_eq!(fingerprint.len(), 64);
+        assert_eq!(
+            IdempotencyKey::fingerprint(
+                "POST",
+                "/deployments",
+                Some(r#"{"project_uuid":"abc","environment":"main"}"#),
+            ),
+            fingerprint
+        );
+        assert_ne!(
+            IdempotencyKey::fingerprint(
+                "POST",
+                "/deployments",
+                Some(r#"{"project_uuid": "abc", "environment": "staging"}"#),
+            ),
+            fingerprint
+        );
+        assert_ne!(
+            IdempotencyKey::fingerprint("POST", "/deployments", None),
+            fingerprint
+        );
+    }
+
+    #[test]
+    fn replays_completed_requests_only() {
+        let now = Utc.ymd(2022, 1, 1).and_hms(0, 0, 0);
+        let mut key = IdempotencyKey::new(
+            "POST /deployments#retry-1".to_string(),
+            "fingerprint".to_string(),
+            now,
+            Duration::hours(DEFAULT_IDEMPOTENCY_KEY_TTL_HOURS),
+        );
+        assert_eq!(key.expires_at, now.timestamp() + 24 * 3600);
+        assert!(!key.is_expired(now));
+        assert!(key.is_expired(now + Duration::hours(24)));
+        assert!(key.is_locked(now));
+        assert!(!key.is_locked(now + Duration::seconds(IDEMPOTENCY_KEY_LOCK_SECONDS)));
+        assert!(key.response().is_none());
+        assert!(!key.as_hashmap().contains_key("status_code"));
+
+        key.status_code = Some(201);
+        key.body = Some(r#"{"uuid":"abc"}"#.to_string());
+        assert!(!key.is_locked(now));
+        let response = key.response().unwrap();
+        assert_eq!(response.status_code, 201);
+        assert_eq!(response.body, r#"{"uuid":"abc"}"#);
+    }
+}
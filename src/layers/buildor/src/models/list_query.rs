@@ -0,0 +1,643 @@
+use aws_sdk_dynamodb::{
+    client::fluent_builders::{Query, Scan},
+    model::AttributeValue,
+};
+use log::{self, error};
+use serde::Serialize as Serializable;
+use serde_json::{json, Map, Value};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+
+use super::common::CommonError;
+use super::request::RequestError;
+
+/// Query parameter sorting the items, e.g. `sort=-createdAt` for newest first
+pub const SORT_PARAMETER: &str = "sort";
+
+/// Query parameter listing the fields of the items, e.g. `fields=uuid,name`
+pub const FIELDS_PARAMETER: &str = "fields";
+
+/// How an attribute of the listed items can be used
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FieldKind {
+    Text,   // string attributes, filtered and sorted
+    Number, // number attributes, filtered and sorted
+    Nested, // maps, lists and booleans, only selected
+}
+
+/// Attribute of the items of a list endpoint, named as in the responses
+#[derive(Debug, PartialEq)]
+pub struct ListField {
+    pub name: &'static str,
+    /// Name of the DynamoDB attribute
+    pub attribute: &'static str,
+    pub kind: FieldKind,
+}
+impl ListField {
+    pub const fn text(name: &'static str, attribute: &'static str) -> Self {
+        Self {
+            name,
+            attribute,
+            kind: FieldKind::Text,
+        }
+    }
+
+    pub const fn number(name: &'static str, attribute: &'static str) -> Self {
+        Self {
+            name,
+            attribute,
+            kind: FieldKind::Number,
+        }
+    }
+
+    pub const fn nested(name: &'static str, attribute: &'static str) -> Self {
+        Self {
+            name,
+            attribute,
+            kind: FieldKind::Nested,
+        }
+    }
+
+    fn attribute_value(&self, value: &str) -> AttributeValue {
+        match self.kind {
+            FieldKind::Number => AttributeValue::N(value.to_string()),
+            _ => AttributeValue::S(value.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FilterOperator {
+    Equals,   // "name=value"
+    Contains, // "name~=value", text fields only
+    After,    // "createdAfter=value", compared to "createdAt"
+    Before,   // "createdBefore=value", compared to "createdAt"
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Filter {
+    pub field: &'static ListField,
+    pub operator: FilterOperator,
+    pub value: String,
+}
+impl Filter {
+    fn parse(
+        parameter: &str,
+        value: &str,
+        schema: &'static [ListField],
+    ) -> Result<Self, RequestError> {
+        let (name, operator) = if let Some(name) = parameter.strip_suffix('~') {
+            (name.to_string(), FilterOperator::Contains)
+        } else if let Some(prefix) = parameter.strip_suffix("After") {
+            (format!("{}At", prefix), FilterOperator::After)
+        } else if let Some(prefix) = parameter.strip_suffix("Before") {
+            (format!("{}At", prefix), FilterOperator::Before)
+        } else {
+            (parameter.to_string(), FilterOperator::Equals)
+        };
+        let field = match schema.iter().find(|field| field.name == name) {
+            Some(field) => field,
+            None => {
+                return Err(CommonError::invalid_list_query(format!(
+                    "Unknown query parameter: {}",
+                    parameter
+                )))
+            }
+        };
+
+        match (field.kind, operator) {
+            (FieldKind::Nested, _) => Err(CommonError::invalid_list_query(format!(
+                "Items can not be filtered by {}",
+                field.name
+            ))),
+            (FieldKind::Number, FilterOperator::Contains) => Err(CommonError::invalid_list_query(
+                format!("{} is a number", field.name),
+            )),
+            (FieldKind::Number, _) if value.parse::<f64>().is_err() => Err(
+                CommonError::invalid_list_query(format!("{} must be a number", parameter)),
+            ),
+            _ => Ok(Self {
+                field,
+                operator,
+                value: value.to_string(),
+            }),
+        }
+    }
+
+    fn condition(&self, name: &str, value: &str) -> String {
+        match self.operator {
+            FilterOperator::Equals => format!("{} = {}", name, value),
+            FilterOperator::Contains => format!("contains({}, {})", name, value),
+            FilterOperator::After => format!("{} > {}", name, value),
+            FilterOperator::Before => format!("{} < {}", name, value),
+        }
+    }
+
+    fn matches(&self, item: &Value) -> bool {
+        let value = &item[self.field.name];
+        let ordering = match self.field.kind {
+            FieldKind::Text => value.as_str().map(|value| value.cmp(self.value.as_str())),
+            _ => value
+                .as_f64()
+                .zip(self.value.parse::<f64>().ok())
+                .and_then(|(value, expected)| value.partial_cmp(&expected)),
+        };
+
+        match self.operator {
+            FilterOperator::Equals => ordering == Some(Ordering::Equal),
+            FilterOperator::Contains => value
+                .as_str()
+                .is_some_and(|value| value.contains(self.value.as_str())),
+            FilterOperator::After => ordering == Some(Ordering::Greater),
+            FilterOperator::Before => ordering == Some(Ordering::Less),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Sort {
+    pub field: &'static ListField,
+    pub descending: bool,
+}
+impl Sort {
+    fn parse(value: &str, schema: &'static [ListField]) -> Result<Self, RequestError> {
+        let (name, descending) = match value.strip_prefix('-') {
+            Some(name) => (name, true),
+            None => (value.strip_prefix('+').unwrap_or(value), false),
+        };
+        match schema.iter().find(|field| field.name == name) {
+            Some(field) if field.kind != FieldKind::Nested => Ok(Self { field, descending }),
+            Some(field) => Err(CommonError::invalid_list_query(format!(
+                "Items can not be sorted by {}",
+                field.name
+            ))),
+            None => Err(CommonError::invalid_list_query(format!(
+                "Unknown sort field: {}",
+                name
+            ))),
+        }
+    }
+
+    /// Missing values come last whatever the direction
+    fn compare(&self, a: &Value, b: &Value) -> Ordering {
+        let (a, b) = (&a[self.field.name], &b[self.field.name]);
+        let ordering = match (a, b) {
+            (Value::Null, Value::Null) => return Ordering::Equal,
+            (Value::Null, _) => return Ordering::Greater,
+            (_, Value::Null) => return Ordering::Less,
+            (Value::Number(a), Value::Number(b)) => a
+                .as_f64()
+                .partial_cmp(&b.as_f64())
+                .unwrap_or(Ordering::Equal),
+            _ => a.as_str().cmp(&b.as_str()),
+        };
+
+        match self.descending {
+            true => ordering.reverse(),
+            false => ordering,
+        }
+    }
+}
+
+/// DynamoDB expressions reading only the items and attributes a list asks for
+#[derive(Debug, PartialEq, Default)]
+pub struct ListExpressions {
+    pub filter_expression: Option<String>,
+    pub projection_expression: Option<String>,
+    pub attribute_names: HashMap<String, String>,
+    pub attribute_values: HashMap<String, AttributeValue>,
+}
+
+/// Filters, sort and fields of a list endpoint, read from its query string
+#[derive(Debug, PartialEq, Default)]
+pub struct ListQuery {
+    pub filters: Vec<Filter>,
+    pub sort: Option<Sort>,
+    pub fields: Option<Vec<&'static ListField>>,
+}
+impl ListQuery {
+    /// Reads the query parameters of a list endpoint whose items have the
+    /// `schema` fields, the `reserved` parameters are left to the endpoint
+    pub fn parse(
+        parameters: &HashMap<String, String>,
+        schema: &'static [ListField],
+        reserved: &[&str],
+    ) -> Result<Self, RequestError> {
+        let mut query = ListQuery::default();
+        let mut names: Vec<&String> = parameters
+            .keys()
+            .filter(|name| !reserved.contains(&name.as_str()) && !parameters[*name].is_empty())
+            .collect();
+        names.sort();
+
+        for name in names {
+            let value = parameters[name].as_str();
+            match name.as_str() {
+                SORT_PARAMETER => query.sort = Some(Sort::parse(value, schema)?),
+                FIELDS_PARAMETER => {
+                    let mut fields = Vec::new();
+                    for name in value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|name| !name.is_empty())
+                    {
+                        match schema.iter().find(|field| field.name == name) {
+                            Some(field) if !fields.contains(&field) => fields.push(field),
+                            Some(_) => {}
+                            None => {
+                                return Err(CommonError::invalid_list_query(format!(
+                                    "Unknown field: {}",
+                                    name
+                                )))
+                            }
+                        }
+                    }
+                    if fields.is_empty() {
+                        return Err(CommonError::invalid_list_query(
+                            "fields must name at least one field".to_string(),
+                        ));
+                    }
+                    query.fields = Some(fields);
+                }
+                _ => query.filters.push(Filter::parse(name, value, schema)?),
+            }
+        }
+
+        Ok(query)
+    }
+
+    /// Whether the fields asked can be read straight from the stored
+    /// attributes, nested ones need the whole item to be parsed
+    pub fn is_projected(&self) -> bool {
+        match &self.fields {
+            Some(fields) => self
+                .projected_fields(fields)
+                .iter()
+                .all(|field| field.kind != FieldKind::Nested),
+            None => false,
+        }
+    }
+
+    fn projected_fields(&self, fields: &[&'static ListField]) -> Vec<&'static ListField> {
+        let mut projected = fields.to_vec();
+        if let Some(sort) = &self.sort {
+            if !projected.contains(&sort.field) {
+                projected.push(sort.field);
+            }
+        }
+        projected
+    }
+
+    pub fn expressions(&self) -> ListExpressions {
+        let mut expressions = ListExpressions::default();
+
+        let mut conditions = Vec::new();
+        for (index, filter) in self.filters.iter().enumerate() {
+            let name = format!("#list_{}", filter.field.attribute);
+            let value = format!(":list_{}", index);
+            conditions.push(filter.condition(&name, &value));
+            expressions
+                .attribute_names
+                .insert(name, filter.field.attribute.to_string());
+            expressions
+                .attribute_values
+                .insert(value, filter.field.attribute_value(&filter.value));
+        }
+        if !conditions.is_empty() {
+            expressions.filter_expression = Some(conditions.join(" AND "));
+        }
+
+        if let (true, Some(fields)) = (self.is_projected(), &self.fields) {
+            let mut projection = Vec::new();
+            for field in self.projected_fields(fields) {
+                let name = format!("#list_{}", field.attribute);
+                projection.push(name.clone());
+                expressions
+                    .attribute_names
+                    .insert(name, field.attribute.to_string());
+            }
+            expressions.projection_expression = Some(projection.join(", "));
+        }
+
+        expressions
+    }
+
+    /// Reads only the matching items, and their fields asked when possible
+    pub fn scan(&self, tx: Scan) -> Scan {
+        let expressions = self.expressions();
+        let mut tx = tx
+            .set_filter_expression(expressions.filter_expression)
+            .set_projection_expression(expressions.projection_expression);
+        for (name, attribute) in expressions.attribute_names {
+            tx = tx.expression_attribute_names(name, attribute);
+        }
+        for (name, value) in expressions.attribute_values {
+            tx = tx.expression_attribute_values(name, value);
+        }
+        tx
+    }
+
+    /// Same as `scan`, for queries of an index
+    pub fn query(&self, tx: Query) -> Query {
+        let expressions = self.expressions();
+        let mut tx = tx
+            .set_filter_expression(expressions.filter_expression)
+            .set_projection_expression(expressions.projection_expression);
+        for (name, attribute) in expressions.attribute_names {
+            tx = tx.expression_attribute_names(name, attribute);
+        }
+        for (name, value) in expressions.attribute_values {
+            tx = tx.expression_attribute_values(name, value);
+        }
+        tx
+    }
+
+    /// Response items of the items read with `scan` or `query`, already
+    /// filtered by DynamoDB
+    pub fn render<T, E, P>(
+        &self,
+        items: Vec<HashMap<String, AttributeValue>>,
+        parse: P,
+    ) -> Vec<Value>
+    where
+        T: Serializable,
+        E: fmt::Display,
+        P: Fn(HashMap<String, AttributeValue>) -> Result<T, E>,
+    {
+        let values = match (self.is_projected(), &self.fields) {
+            (true, Some(fields)) => {
+                let fields = self.projected_fields(fields);
+                items
+                    .iter()
+                    .map(|item| {
+                        let mut value = Map::new();
+                        for field in fields.iter() {
+                            value.insert(
+                                field.name.to_string(),
+                                attribute_as_value(item.get(field.attribute)),
+                            );
+                        }
+                        Value::Object(value)
+                    })
+                    .collect()
+            }
+            _ => items
+                .into_iter()
+                .filter_map(|item| match parse(item) {
+                    Ok(parsed) => Some(json!(parsed)),
+                    Err(error) => {
+                        error!(
+                            "ListQuery::render - parse error (skip from result): {}",
+                            error
+                        );
+                        None
+                    }
+                })
+                .collect(),
+        };
+
+        self.finish(values)
+    }
+
+    /// Response items of items that were not read from DynamoDB, filtered here
+    pub fn apply<T: Serializable>(&self, items: Vec<T>) -> Vec<Value> {
+        let values = items
+            .into_iter()
+            .map(|item| json!(item))
+            .filter(|value| self.filters.iter().all(|filter| filter.matches(value)))
+            .collect();
+
+        self.finish(values)
+    }
+
+    fn finish(&self, mut values: Vec<Value>) -> Vec<Value> {
+        if let Some(sort) = &self.sort {
+            values.sort_by(|a, b| sort.compare(a, b));
+        }
+
+        match &self.fields {
+            Some(fields) => values
+                .into_iter()
+                .map(|value| {
+                    let mut selected = Map::new();
+                    for field in fields {
+                        selected.insert(field.name.to_string(), value[field.name].clone());
+                    }
+                    Value::Object(selected)
+                })
+                .collect(),
+            None => values,
+        }
+    }
+}
+
+/// Text and number attributes as in responses, missing ones are null
+fn attribute_as_value(value: Option<&AttributeValue>) -> Value {
+    match value {
+        Some(AttributeValue::S(value)) => json!(value),
+        Some(AttributeValue::N(value)) => match value.parse::<i64>() {
+            Ok(number) => json!(number),
+            Err(_) => value
+                .parse::<f64>()
+                .map_or(Value::Null, |number| json!(number)),
+        },
+        _ => Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod list_query_tests {
+    use super::*;
+    use serde_derive::Serialize;
+
+    const FIELDS: &[ListField] = &[
+        ListField::text("uuid", "uuid"),
+        ListField::text("name", "name"),
+        ListField::number("attempts", "attempts"),
+        ListField::nested("commands", "commands"),
+        ListField::text("createdAt", "created_at"),
+    ];
+
+    #[derive(Serialize)]
+    struct Item {
+        uuid: &'static str,
+        name: &'static str,
+        attempts: i32,
+        commands: Vec<&'static str>,
+        #[serde(rename(serialize = "createdAt"))]
+        created_at: &'static str,
+    }
+
+    fn items() -> Vec<Item> {
+        vec![
+            Item {
+                uuid: "a",
+                name: "landing",
+                attempts: 3,
+                commands: vec!["npm ci"],
+                created_at: "2022-01-02T00:00:00+00:00",
+            },
+            Item {
+                uuid: "b",
+                name: "docs",
+                attempts: 1,
+                commands: vec![],
+                created_at: "2022-01-03T00:00:00+00:00",
+            },
+            Item {
+                uuid: "c",
+                name: "landing-v2",
+                attempts: 2,
+                commands: vec![],
+                created_at: "2022-01-01T00:00:00+00:00",
+            },
+        ]
+    }
+
+    fn parse(parameters: &[(&str, &str)]) -> Result<ListQuery, RequestError> {
+        let parameters: HashMap<String, String> = parameters
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        ListQuery::parse(&parameters, FIELDS, &["email"])
+    }
+
+    #[test]
+    fn parses_filters_sort_and_fields() {
+        let query = parse(&[
+            ("name~", "landing"),
+            ("createdAfter", "2022-01-01"),
+            ("sort", "-createdAt"),
+            ("fields", "uuid, name"),
+            ("email", "john@example.com"),
+            ("attempts", ""),
+        ])
+        .unwrap();
+        assert_eq!(
+            query
+                .filters
+                .iter()
+                .map(|filter| (filter.field.name, filter.operator))
+                .collect::<Vec<_>>(),
+            vec![
+                ("createdAt", FilterOperator::After),
+                ("name", FilterOperator::Contains),
+            ]
+        );
+        assert_eq!(query.sort.as_ref().unwrap().field.name, "createdAt");
+        assert!(query.sort.as_ref().unwrap().descending);
+        assert_eq!(
+            query
+                .fields
+                .as_ref()
+                .unwrap()
+                .iter()
+                .map(|field| field.name)
+                .collect::<Vec<_>>(),
+            vec!["uuid", "name"]
+        );
+    }
+
+    #[test]
+    fn refuses_unknown_or_unsupported_parameters() {
+        for parameters in [
+            vec![("owner", "abc")],
+            vec![("commands", "npm ci")],
+            vec![("attempts~", "1")],
+            vec![("attempts", "many")],
+            vec![("sort", "commands")],
+            vec![("sort", "-size")],
+            vec![("fields", "uuid,secret")],
+            vec![("fields", ",")],
+        ] {
+            let error = parse(&parameters).unwrap_err();
+            assert_eq!(error.status, 400, "{:?}", parameters);
+        }
+    }
+
+    #[test]
+    fn builds_expressions() {
+        let query = parse(&[
+            ("attempts", "2"),
+            ("name~", "landing"),
+            ("sort", "createdAt"),
+            ("fields", "uuid,name"),
+        ])
+        .unwrap();
+        assert!(query.is_projected());
+        let expressions = query.expressions();
+        assert_eq!(
+            expressions.filter_expression.as_deref(),
+            Some("#list_attempts = :list_0 AND contains(#list_name, :list_1)")
+        );
+        assert_eq!(
+            expressions.projection_expression.as_deref(),
+            Some("#list_uuid, #list_name, #list_created_at")
+        );
+        assert_eq!(
+            expressions.attribute_values[":list_0"],
+            AttributeValue::N("2".to_string())
+        );
+        assert_eq!(
+            expressions.attribute_names["#list_created_at"],
+            "created_at"
+        );
+
+        // Nested fields are only known once the whole item is parsed
+        let query = parse(&[("fields", "uuid,commands")]).unwrap();
+        assert!(!query.is_projected());
+        assert_eq!(query.expressions(), ListExpressions::default());
+    }
+
+    #[test]
+    fn applies_filters_sort_and_fields() {
+        let query = parse(&[
+            ("name~", "landing"),
+            ("sort", "-createdAt"),
+            ("fields", "uuid"),
+        ])
+        .unwrap();
+        assert_eq!(
+            query.apply(items()),
+            vec![json!({ "uuid": "a" }), json!({ "uuid": "c" })]
+        );
+
+        let query = parse(&[("attemptsAfter", "1")]);
+        assert!(query.is_err());
+        let query = parse(&[("createdBefore", "2022-01-03"), ("sort", "attempts")]).unwrap();
+        assert_eq!(
+            query
+                .apply(items())
+                .iter()
+                .map(|item| item["uuid"].clone())
+                .collect::<Vec<_>>(),
+            vec![json!("c"), json!("a")]
+        );
+        assert_eq!(ListQuery::default().apply(items()).len(), 3);
+    }
+
+    #[test]
+    fn renders_projected_items() {
+        let query = parse(&[("sort", "-attempts"), ("fields", "name")]).unwrap();
+        let items = vec![("landing", "3"), ("docs", "5")]
+            .into_iter()
+            .map(|(name, attempts)| {
+                HashMap::from([
+                    ("name".to_string(), AttributeValue::S(name.to_string())),
+                    (
+                        "attempts".to_string(),
+                        AttributeValue::N(attempts.to_string()),
+                    ),
+                ])
+            })
+            .collect();
+        let parse = |_: HashMap<String, AttributeValue>| -> Result<Value, String> {
+            Err("items are not parsed when projected".to_string())
+        };
+        assert_eq!(
+            query.render(items, parse),
+            vec![json!({ "name": "docs" }), json!({ "name": "landing" })]
+        );
+    }
+}
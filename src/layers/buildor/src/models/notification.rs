@@ -0,0 +1,184 @@
+use aws_sdk_dynamodb::model::AttributeValue;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use super::common::AsDynamoDBAttributeValue;
+use super::project_deployment::ProjectDeployment;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum NotificationProvider {
+    Slack,   // "slack"
+    Discord, // "discord"
+    Email,   // "email"
+    Unknown, // "unknown", (custom value used when parsing from/to string/enum)
+}
+impl FromStr for NotificationProvider {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<NotificationProvider, ()> {
+        match input.to_lowercase() {
+            slack if slack == NotificationProvider::Slack.to_string() => {
+                Ok(NotificationProvider::Slack)
+            }
+            discord if discord == NotificationProvider::Discord.to_string() => {
+                Ok(NotificationProvider::Discord)
+            }
+            email if email == NotificationProvider::Email.to_string() => {
+                Ok(NotificationProvider::Email)
+            }
+            _ => Ok(NotificationProvider::Unknown),
+        }
+    }
+}
+impl fmt::Display for NotificationProvider {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotificationProvider::Slack => fmt.write_str("slack"),
+            NotificationProvider::Discord => fmt.write_str("discord"),
+            NotificationProvider::Email => fmt.write_str("email"),
+            NotificationProvider::Unknown => fmt.write_str("unknown"),
+        }
+    }
+}
+
+/// Channel a project posts its deployment results to. Chat channels use their
+/// incoming-webhook url, email channels a `mailto:` url listing the addresses
+/// that opted in besides the project owner
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NotificationChannel {
+    pub provider: String,
+    pub url: String,
+}
+impl NotificationChannel {
+    pub fn provider(&self) -> NotificationProvider {
+        NotificationProvider::from_str(&self.provider).unwrap()
+    }
+
+    /// Returns the reason the channel can not be used
+    pub fn validate(&self) -> Result<(), String> {
+        match self.provider() {
+            NotificationProvider::Unknown => {
+                return Err(format!(
+                    "Unknown notification provider: {}, expected slack, discord or email",
+                    self.provider
+                ))
+            }
+            NotificationProvider::Email => {
+                if !self.url.starts_with("mailto:") {
+                    return Err(format!(
+                        "Email notification url must use mailto: {}",
+                        self.url
+                    ));
+                }
+                return match self
+                    .addresses()
+                    .iter()
+                    .find(|address| !address.contains('@'))
+                {
+                    Some(address) => Err(format!("Invalid email address: {}", address)),
+                    None => Ok(()),
+                };
+            }
+            _ => (),
+        }
+        if !self.url.starts_with("https://") {
+            return Err(format!("Notification url must use https: {}", self.url));
+        }
+
+        Ok(())
+    }
+
+    /// Addresses of a `mailto:` url, empty for chat channels
+    pub fn addresses(&self) -> Vec<String> {
+        match self.url.strip_prefix("mailto:") {
+            Some(addresses) => addresses
+                .split(',')
+                .map(|address| address.trim().to_string())
+                .filter(|address| !address.is_empty())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+impl AsDynamoDBAttributeValue for NotificationChannel {
+    fn as_hashmap(&self) -> HashMap<String, AttributeValue> {
+        let mut map: HashMap<String, AttributeValue> = HashMap::new();
+        map.insert(
+            "provider".to_string(),
+            AttributeValue::S(self.provider.to_owned()),
+        );
+        map.insert("url".to_string(), AttributeValue::S(self.url.to_owned()));
+
+        map
+    }
+
+    fn as_attr(&self) -> AttributeValue {
+        AttributeValue::M(self.as_hashmap())
+    }
+}
+
+pub fn notification_channels_as_attr(channels: &[NotificationChannel]) -> AttributeValue {
+    AttributeValue::L(channels.iter().map(|channel| channel.as_attr()).collect())
+}
+
+/// Result of a deployment as shown in chat messages
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeploymentNotification {
+    pub project: String,
+    pub environment: String,
+    pub deployment: String,
+    pub status: String,
+    /// Build phase the deployment failed in, with its status
+    pub failed_phase: Option<String>,
+    pub duration_seconds: Option<i64>,
+    pub commit: Option<String>,
+}
+impl DeploymentNotification {
+    pub fn new(
+        deployment: &ProjectDeployment,
+        failed_phase: Option<String>,
+        duration_seconds: Option<i64>,
+        commit: Option<String>,
+    ) -> Self {
+        Self {
+            project: deployment.project.name.clone(),
+            environment: deployment.environment.clone(),
+            deployment: deployment.uuid.clone(),
+            status: deployment.status.clone(),
+            failed_phase,
+            duration_seconds,
+            commit,
+        }
+    }
+
+    pub fn title(&self) -> String {
+        format!(
+            "{} deployment to {} {}",
+            self.project,
+            self.environment,
+            self.status.to_lowercase().replace('_', " ")
+        )
+    }
+
+    /// Duration as `1m 05s`, "unknown" when the build times are missing
+    pub fn duration(&self) -> String {
+        match self.duration_seconds {
+            Some(seconds) if seconds >= 60 => format!("{}m {:02}s", seconds / 60, seconds % 60),
+            Some(seconds) => format!("{}s", seconds.max(0)),
+            None => "unknown".to_string(),
+        }
+    }
+
+    /// Abbreviated commit hash, "unknown" when the source version is missing
+    pub fn commit(&self) -> String {
+        match self.commit.as_deref() {
+            Some(commit) if commit.len() > 7 && commit.chars().all(|c| c.is_ascii_hexdigit()) => {
+                commit[..7].to_string()
+            }
+            Some(commit) => commit.to_string(),
+            None => "unknown".to_string(),
+        }
+    }
+}
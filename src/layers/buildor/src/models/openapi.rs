@@ -0,0 +1,757 @@
+use serde_json::{json, Map, Value};
+
+use super::api_error::{ApiError, PROBLEM_CONTENT_TYPE};
+use super::environment::SECRET_TYPES;
+use super::list_query::{FieldKind, ListField, FIELDS_PARAMETER, SORT_PARAMETER};
+use super::webhook::WebhookEvent;
+
+pub const OPENAPI_VERSION: &str = "3.1.0";
+
+/// Documented body of a successful response
+#[derive(Debug, Clone, Copy)]
+pub enum ResponseBody {
+    Schema(&'static str), // component schema
+    List(&'static str),   // `ResponseGenericList` of a component schema
+    Empty,
+}
+
+/// Documentation of a route, its path parameters come from the route template
+#[derive(Debug, Clone, Copy)]
+pub struct Operation {
+    pub id: &'static str,
+    pub summary: &'static str,
+    /// Name and description of the query parameters
+    pub query: &'static [(&'static str, &'static str)],
+    /// Name and description of the request headers
+    pub headers: &'static [(&'static str, &'static str)],
+    /// Fields of the listed items, documented as filter, sort and fields
+    /// query parameters
+    pub list: Option<&'static [ListField]>,
+    pub body: Option<&'static str>,
+    pub status: u16,
+    pub response: ResponseBody,
+}
+impl Operation {
+    /// Operation object of the route, `method` lowercased
+    pub fn as_value(&self, method: &str, template: &str) -> Value {
+        let mut parameters: Vec<Value> = template
+            .split('/')
+            .filter_map(|segment| segment.strip_prefix('{')?.strip_suffix('}'))
+            .map(|name| json!({ "name": name, "in": "path", "required": true, "schema": string() }))
+            .collect();
+        parameters.extend(self.query.iter().map(|(name, description)| {
+            json!({ "name": name, "in": "query", "description": description, "schema": string() })
+        }));
+        parameters.extend(self.list.iter().flat_map(|fields| list_parameters(fields)));
+        parameters.extend(self.headers.iter().map(|(name, description)| {
+            json!({ "name": name, "in": "header", "description": description, "schema": string() })
+        }));
+
+        let response = match self.response {
+            ResponseBody::Schema(name) => json!({
+                "description": self.summary,
+                "content": { "application/json": { "schema": reference(name) } },
+            }),
+            ResponseBody::List(name) => json!({
+                "description": self.summary,
+                "content": { "application/json": { "schema": list(name) } },
+            }),
+            ResponseBody::Empty => json!({ "description": self.summary }),
+        };
+        let mut operation = json!({
+            "operationId": self.id,
+            "summary": self.summary,
+            "parameters": parameters,
+            "responses": {
+                self.status.to_string(): response,
+                "default": {
+                    "description": "Error, listed at /errors",
+                    "content": {
+                        "application/json": { "schema": reference("Error") },
+                        PROBLEM_CONTENT_TYPE: { "schema": reference("ProblemDetails") },
+                    },
+                },
+            },
+        });
+        // GET responses are tagged, writes check the tag the client read
+        if method == "get" {
+            operation["responses"][self.status.to_string()]["headers"] =
+                json!({ "ETag": { "schema": string() } });
+        }
+        let conditional = match method {
+            "get" => Some(("If-None-Match", "304", "Not Modified")),
+            "patch" | "delete" => Some((
+                "If-Match",
+                "412",
+                "Precondition Failed, the resource changed since it was read",
+            )),
+            _ => None,
+        };
+        if let Some((header, status, description)) = conditional {
+            operation["parameters"]
+                .as_array_mut()
+                .unwrap()
+                .push(json!({ "name": header, "in": "header", "schema": string() }));
+            operation["responses"][status] = json!({ "description": description });
+        }
+        if let Some(body) = self.body {
+            operation["requestBody"] = json!({
+                "required": true,
+                "content": { "application/json": { "schema": reference(body) } },
+            });
+        }
+
+        operation
+    }
+}
+
+/// Filter, sort and fields query parameters of a list endpoint
+fn list_parameters(fields: &[ListField]) -> Vec<Value> {
+    let parameter = |name: String, description: String| json!({ "name": name, "in": "query", "description": description, "schema": string() });
+
+    let mut parameters = Vec::new();
+    for field in fields
+        .iter()
+        .filter(|field| field.kind != FieldKind::Nested)
+    {
+        parameters.push(parameter(
+            field.name.to_string(),
+            format!("Only the items whose {} is this value", field.name),
+        ));
+        if field.kind != FieldKind::Text {
+            continue;
+        }
+        parameters.push(parameter(
+            format!("{}~", field.name),
+            format!("Only the items whose {} contains this value", field.name),
+        ));
+        if let Some(prefix) = field.name.strip_suffix("At") {
+            parameters.push(parameter(
+                format!("{}After", prefix),
+                format!("Only the items whose {} is after this date", field.name),
+            ));
+            parameters.push(parameter(
+                format!("{}Before", prefix),
+                format!("Only the items whose {} is before this date", field.name),
+            ));
+        }
+    }
+    parameters.push(parameter(
+        SORT_PARAMETER.to_string(),
+        "Field the items are sorted by, descending when prefixed with -".to_string(),
+    ));
+    parameters.push(parameter(
+        FIELDS_PARAMETER.to_string(),
+        "Comma separated fields of the items, every field when missing".to_string(),
+    ));
+
+    parameters
+}
+
+fn string() -> Value {
+    json!({ "type": "string" })
+}
+
+fn integer() -> Value {
+    json!({ "type": "integer" })
+}
+
+fn boolean() -> Value {
+    json!({ "type": "boolean" })
+}
+
+fn nullable(kind: &str) -> Value {
+    json!({ "type": [kind, "null"] })
+}
+
+fn one_of(values: &[&str]) -> Value {
+    json!({ "type": "string", "enum": values })
+}
+
+fn array(items: Value) -> Value {
+    json!({ "type": "array", "items": items })
+}
+
+fn map(values: Value) -> Value {
+    json!({ "type": "object", "additionalProperties": values })
+}
+
+pub fn reference(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn nullable_reference(name: &str) -> Value {
+    json!({ "oneOf": [reference(name), { "type": "null" }] })
+}
+
+fn list(name: &str) -> Value {
+    object(
+        &[("items", array(reference(name))), ("count", integer())],
+        &["items", "count"],
+    )
+}
+
+fn object(properties: &[(&str, Value)], required: &[&str]) -> Value {
+    let properties: Map<String, Value> = properties
+        .iter()
+        .map(|(name, schema)| (name.to_string(), schema.clone()))
+        .collect();
+    json!({ "type": "object", "properties": properties, "required": required })
+}
+
+/// Request payloads are read with the field names, no rename applies to them
+fn payload(properties: &[(&str, Value)], required: &[&str], example: Value) -> Value {
+    let mut schema = object(properties, required);
+    schema["examples"] = json!([example]);
+    schema
+}
+
+/// Component schemas of the API. Responses use the serialized names of the
+/// models, request payloads the field names.
+pub fn schemas() -> Map<String, Value> {
+    let statuses = &[
+        "QUEUED",
+        "IN_PROGRESS",
+        "SUCCEEDED",
+        "FAILED",
+        "SUPERSEDED",
+        "EXPIRED",
+    ];
+    let compute_sizes = &["small", "medium", "large"];
+    let providers = &["slack", "discord", "email"];
+    let events: Vec<String> = WebhookEvent::all()
+        .iter()
+        .map(|event| event.to_string())
+        .collect();
+    let events: Vec<&str> = events.iter().map(String::as_str).collect();
+    let codes: Vec<&str> = ApiError::ALL.iter().map(|error| error.code()).collect();
+
+    let mut schemas = Map::new();
+    let mut add = |name: &str, schema: Value| schemas.insert(name.to_string(), schema);
+
+    // Responses
+    add("Root", object(&[("message", string())], &["message"]));
+    add(
+        "User",
+        object(
+            &[
+                ("uuid", string()),
+                ("firstName", string()),
+                ("lastName", string()),
+                ("email", string()),
+                ("updatedAt", string()),
+                ("createdAt", string()),
+            ],
+            &[
+                "uuid",
+                "firstName",
+                "lastName",
+                "email",
+                "updatedAt",
+                "createdAt",
+            ],
+        ),
+    );
+    add(
+        "Commands",
+        object(
+            &[("preBuild", array(string())), ("build", array(string()))],
+            &["preBuild", "build"],
+        ),
+    );
+    add(
+        "SecretVariable",
+        object(
+            &[("type", one_of(SECRET_TYPES)), ("value", string())],
+            &["type", "value"],
+        ),
+    );
+    add(
+        "Environment",
+        object(
+            &[
+                ("name", string()),
+                ("branch", string()),
+                ("variables", map(string())),
+                ("secrets", map(reference("SecretVariable"))),
+                ("currentDeployment", string()),
+            ],
+            &[
+                "name",
+                "branch",
+                "variables",
+                "secrets",
+                "currentDeployment",
+            ],
+        ),
+    );
+    add(
+        "RetentionPolicy",
+        object(
+            &[("keepLast", integer()), ("expireAfterDays", integer())],
+            &["keepLast", "expireAfterDays"],
+        ),
+    );
+    add(
+        "NotificationChannel",
+        object(
+            &[("provider", one_of(providers)), ("url", string())],
+            &["provider", "url"],
+        ),
+    );
+    add(
+        "Project",
+        object(
+            &[
+                ("uuid", string()),
+                ("name", string()),
+                ("repository", string()),
+                ("owner", string()),
+                ("commands", reference("Commands")),
+                ("outputFolder", string()),
+                ("environments", array(reference("Environment"))),
+                ("buildTimeoutMinutes", integer()),
+                ("computeSize", one_of(compute_sizes)),
+                ("retention", reference("RetentionPolicy")),
+                ("notifications", array(reference("NotificationChannel"))),
+                ("lastPublished", string()),
+                ("updatedAt", string()),
+                ("createdAt", string()),
+            ],
+            &[
+                "uuid",
+                "name",
+                "repository",
+                "owner",
+                "commands",
+                "outputFolder",
+                "environments",
+                "buildTimeoutMinutes",
+                "computeSize",
+                "retention",
+                "notifications",
+                "lastPublished",
+                "updatedAt",
+                "createdAt",
+            ],
+        ),
+    );
+    add(
+        "BuildInfo",
+        object(
+            &[
+                ("uuid", string()),
+                ("buildNumber", nullable("integer")),
+                ("startTime", nullable("integer")),
+                ("endTime", nullable("integer")),
+                ("deploymentPhase", nullable("string")),
+                ("currentPhase", nullable("string")),
+                ("buildStatus", nullable("string")),
+                ("logsUrl", nullable("string")),
+                ("computeType", nullable("string")),
+                ("image", nullable("string")),
+            ],
+            &["uuid"],
+        ),
+    );
+    add(
+        "ArtifactSummary",
+        object(
+            &[
+                ("bucket", string()),
+                ("key", string()),
+                ("fileCount", integer()),
+                ("totalSize", integer()),
+                ("manifestKey", nullable("string")),
+                ("checksum", nullable("string")),
+            ],
+            &["bucket", "key", "fileCount", "totalSize"],
+        ),
+    );
+    add(
+        "ProjectDeployment",
+        object(
+            &[
+                ("uuid", string()),
+                ("project", reference("Project")),
+                ("environment", string()),
+                ("status", one_of(statuses)),
+                ("build", nullable_reference("BuildInfo")),
+                ("artifact", nullable_reference("ArtifactSummary")),
+                ("pinned", boolean()),
+                ("updatedAt", string()),
+                ("createdAt", string()),
+            ],
+            &[
+                "uuid",
+                "project",
+                "environment",
+                "status",
+                "build",
+                "artifact",
+                "pinned",
+                "updatedAt",
+                "createdAt",
+            ],
+        ),
+    );
+    add(
+        "ProjectDeploymentWait",
+        object(
+            &[
+                ("changed", boolean()),
+                ("deployment", reference("ProjectDeployment")),
+            ],
+            &["changed", "deployment"],
+        ),
+    );
+    add(
+        "DurationSummary",
+        object(
+            &[
+                ("count", integer()),
+                ("meanSeconds", json!({ "type": "number" })),
+                ("p95Seconds", nullable("integer")),
+            ],
+            &["count", "meanSeconds", "p95Seconds"],
+        ),
+    );
+    add(
+        "PhaseStats",
+        object(
+            &[
+                ("phase", string()),
+                ("count", integer()),
+                ("meanSeconds", json!({ "type": "number" })),
+                ("p95Seconds", nullable("integer")),
+            ],
+            &["phase", "count", "meanSeconds", "p95Seconds"],
+        ),
+    );
+    add(
+        "ProjectStats",
+        object(
+            &[
+                ("project", string()),
+                ("window", string()),
+                ("from", string()),
+                ("to", string()),
+                ("builds", integer()),
+                ("succeeded", integer()),
+                ("failed", integer()),
+                ("successRate", nullable("number")),
+                ("duration", nullable_reference("DurationSummary")),
+                ("phases", array(reference("PhaseStats"))),
+                ("mostCommonFailingPhase", nullable("string")),
+                ("meanTimeToRecoverySeconds", nullable("number")),
+                ("recoveries", integer()),
+            ],
+            &[
+                "project",
+                "window",
+                "from",
+                "to",
+                "builds",
+                "succeeded",
+                "failed",
+                "successRate",
+                "duration",
+                "phases",
+                "mostCommonFailingPhase",
+                "meanTimeToRecoverySeconds",
+                "recoveries",
+            ],
+        ),
+    );
+    add(
+        "Webhook",
+        object(
+            &[
+                ("uuid", string()),
+                ("projectUuid", string()),
+                ("userUuid", string()),
+                ("url", string()),
+                ("events", array(one_of(&events))),
+                // Only sent once, in the creation response
+                ("secret", string()),
+                ("updatedAt", string()),
+                ("createdAt", string()),
+            ],
+            &[
+                "uuid",
+                "projectUuid",
+                "userUuid",
+                "url",
+                "events",
+                "updatedAt",
+                "createdAt",
+            ],
+        ),
+    );
+    add(
+        "WebhookDelivery",
+        object(
+            &[
+                ("uuid", string()),
+                ("webhookUuid", string()),
+                ("event", one_of(&events)),
+                ("payload", string()),
+                (
+                    "status",
+                    one_of(&["PENDING", "SUCCEEDED", "RETRYING", "FAILED"]),
+                ),
+                ("attempts", integer()),
+                ("responseStatus", nullable("integer")),
+                ("lastError", nullable("string")),
+                ("nextAttemptAt", string()),
+                ("updatedAt", string()),
+                ("createdAt", string()),
+            ],
+            &[
+                "uuid",
+                "webhookUuid",
+                "event",
+                "payload",
+                "status",
+                "attempts",
+                "responseStatus",
+                "lastError",
+                "nextAttemptAt",
+                "updatedAt",
+                "createdAt",
+            ],
+        ),
+    );
+    add(
+        "ApiErrorEntry",
+        object(
+            &[
+                ("code", one_of(&codes)),
+                ("status", integer()),
+                ("message", string()),
+                ("retryable", boolean()),
+            ],
+            &["code", "status", "message", "retryable"],
+        ),
+    );
+    add(
+        "FieldError",
+        object(
+            &[
+                ("field", string()),
+                ("code", string()),
+                ("message", string()),
+            ],
+            &["field", "code", "message"],
+        ),
+    );
+    add(
+        "Error",
+        object(
+            &[
+                ("code", one_of(&codes)),
+                ("message", string()),
+                ("details", string()),
+                ("retryable", boolean()),
+                ("errors", array(reference("FieldError"))),
+            ],
+            &["code", "message", "details", "retryable"],
+        ),
+    );
+    add(
+        "ProblemDetails",
+        object(
+            &[
+                ("type", string()),
+                ("title", string()),
+                ("status", integer()),
+                ("detail", string()),
+                ("instance", string()),
+                ("code", one_of(&codes)),
+                ("retryable", boolean()),
+                ("errors", array(reference("FieldError"))),
+            ],
+            &[
+                "type",
+                "title",
+                "status",
+                "detail",
+                "instance",
+                "code",
+                "retryable",
+            ],
+        ),
+    );
+
+    // Request payloads
+    add(
+        "UserCreatePayload",
+        payload(
+            &[
+                ("fname", string()),
+                ("lname", string()),
+                ("email", string()),
+            ],
+            &["fname", "lname"],
+            json!({ "fname": "John", "lname": "Doe", "email": "john@example.com" }),
+        ),
+    );
+    add(
+        "UserUpdatePayload",
+        payload(
+            &[
+                ("fname", string()),
+                ("lname", string()),
+                ("email", string()),
+            ],
+            &[],
+            json!({ "fname": "John", "lname": "Doe", "email": "john.doe@example.com" }),
+        ),
+    );
+    add(
+        "CommandsPayload",
+        payload(
+            &[("pre_build", array(string())), ("build", array(string()))],
+            &["pre_build", "build"],
+            json!({ "pre_build": ["npm ci"], "build": ["npm run build"] }),
+        ),
+    );
+    add(
+        "RetentionPolicyPayload",
+        payload(
+            &[("keep_last", integer()), ("expire_after_days", integer())],
+            &[],
+            json!({ "keep_last": 10, "expire_after_days": 7 }),
+        ),
+    );
+    add(
+        "EnvironmentCreatePayload",
+        payload(
+            &[
+                ("name", string()),
+                ("branch", string()),
+                ("variables", map(string())),
+                ("secrets", map(reference("SecretVariable"))),
+            ],
+            &["name"],
+            json!({ "name": "staging", "branch": "develop", "variables": { "API_URL": "https://staging.example.com" }, "secrets": { "NPM_TOKEN": { "type": "PARAMETER_STORE", "value": "/App/projects/site/NPM_TOKEN" } } }),
+        ),
+    );
+    let project_properties = [
+        ("name", string()),
+        ("repository", string()),
+        ("owner", string()),
+        ("commands", reference("CommandsPayload")),
+        ("output_folder", string()),
+        ("environments", array(reference("EnvironmentCreatePayload"))),
+        ("build_timeout_minutes", integer()),
+        ("compute_size", one_of(compute_sizes)),
+        ("retention", reference("RetentionPolicyPayload")),
+        ("notifications", array(reference("NotificationChannel"))),
+    ];
+    let project_example = json!({
+        "name": "site",
+        "repository": "https://github.com/example/site.git",
+        "owner": "0a6a5e2c-3f1b-4c41-9a43-6f7c2c1a9b10",
+        "commands": { "pre_build": ["npm ci"], "build": ["npm run build"] },
+        "output_folder": "public",
+        "environments": [{ "name": "staging", "branch": "develop", "variables": { "API_URL": "https://staging.example.com" }, "secrets": { "NPM_TOKEN": { "type": "PARAMETER_STORE", "value": "/App/projects/site/NPM_TOKEN" } } }],
+        "build_timeout_minutes": 30,
+        "compute_size": "medium",
+        "retention": { "keep_last": 10, "expire_after_days": 7 },
+        "notifications": [{ "provider": "slack", "url": "https://hooks.slack.com/services/T0/B0/X" }],
+    });
+    add(
+        "ProjectCreatePayload",
+        payload(
+            &project_properties,
+            &["name", "repository"],
+            project_example.clone(),
+        ),
+    );
+    let mut update_example = project_example;
+    update_example
+        .as_object_mut()
+        .unwrap()
+        .remove("environments");
+    add(
+        "ProjectUpdatePayload",
+        payload(
+            &project_properties
+                .iter()
+                .filter(|(name, _)| *name != "environments")
+                .cloned()
+                .collect::<Vec<_>>(),
+            &[],
+            update_example,
+        ),
+    );
+    add(
+        "ProjectDeploymentCreatePayload",
+        payload(
+            &[("project_uuid", string()), ("environment", string())],
+            &["project_uuid"],
+            json!({ "project_uuid": "0a6a5e2c-3f1b-4c41-9a43-6f7c2c1a9b10", "environment": "staging" }),
+        ),
+    );
+    add(
+        "ProjectDeploymentPatchPayload",
+        payload(
+            &[("pinned", boolean())],
+            &["pinned"],
+            json!({ "pinned": true }),
+        ),
+    );
+    add(
+        "WebhookCreatePayload",
+        payload(
+            &[
+                ("project_uuid", string()),
+                ("user_uuid", string()),
+                ("url", string()),
+                ("events", array(one_of(&events))),
+                ("secret", string()),
+            ],
+            &["url"],
+            json!({
+                "project_uuid": "0a6a5e2c-3f1b-4c41-9a43-6f7c2c1a9b10",
+                "user_uuid": "5c1d3a2e-8b4f-4e6a-9c7d-2f1e0b9a8c7d",
+                "url": "https://example.com/hook",
+                "events": ["deployment.succeeded"],
+                "secret": "whsec_example",
+            }),
+        ),
+    );
+
+    schemas
+}
+
+/// OpenAPI document of the documented routes, given by method and template
+pub fn document(operations: Vec<(String, &str, Operation)>) -> Value {
+    let mut paths = Map::new();
+    for (method, template, operation) in operations {
+        let path = paths
+            .entry(template.to_string())
+            .or_insert_with(|| json!({}));
+        let method = method.to_lowercase();
+        path[&method] = operation.as_value(&method, template);
+    }
+
+    json!({
+        "openapi": OPENAPI_VERSION,
+        "info": {
+            "title": "Buildor API",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+        "components": {
+            "schemas": schemas(),
+            "securitySchemes": {
+                "apiKey": { "type": "apiKey", "in": "header", "name": "X-Api-Key" },
+                "bearer": { "type": "http", "scheme": "bearer" },
+            },
+        },
+        // Only enforced when API keys are configured
+        "security": [{ "apiKey": [] }, { "bearer": [] }],
+    })
+}
@@ -0,0 +1,275 @@
+use aws_sdk_dynamodb::model::AttributeValue;
+use chrono::{DateTime, Duration, Utc};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use super::common::AsDynamoDBAttributeValue;
+use super::notification::DeploymentNotification;
+use super::project_deployment::{ProjectDeployment, ProjectDeploymentStatus};
+use super::webhook::{RetryPolicy, WebhookEvent};
+
+/// Seconds the scheduled relay leaves new entries to the stream triggered one
+pub const OUTBOX_GRACE_PERIOD_SECONDS: i64 = 60;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OutboxStatus {
+    Pending,   // "PENDING"
+    Published, // "PUBLISHED"
+    Failed,    // "FAILED"
+    Unknown,   // "UNKNOWN", (custom value used when parsing from/to string/enum)
+}
+impl FromStr for OutboxStatus {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<OutboxStatus, ()> {
+        match String::from(input) {
+            pending if pending == OutboxStatus::Pending.to_string() => Ok(OutboxStatus::Pending),
+            published if published == OutboxStatus::Published.to_string() => {
+                Ok(OutboxStatus::Published)
+            }
+            failed if failed == OutboxStatus::Failed.to_string() => Ok(OutboxStatus::Failed),
+            _ => Ok(OutboxStatus::Unknown),
+        }
+    }
+}
+impl fmt::Display for OutboxStatus {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutboxStatus::Pending => fmt.write_str("PENDING"),
+            OutboxStatus::Published => fmt.write_str("PUBLISHED"),
+            OutboxStatus::Failed => fmt.write_str("FAILED"),
+            OutboxStatus::Unknown => fmt.write_str("UNKNOWN"),
+        }
+    }
+}
+
+/// Time a build spent in one of its phases
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PhaseDuration {
+    pub phase: String,
+    pub status: Option<String>,
+    pub duration_seconds: i64,
+}
+
+/// Build details reported along with a final status, empty for other transitions
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct BuildOutcome {
+    /// Whether the build was stopped by hand
+    pub stopped: bool,
+    pub failed_phase: Option<String>,
+    pub duration_seconds: Option<i64>,
+    pub commit: Option<String>,
+    /// Phases completed so far, entries written before phases were reported have none
+    #[serde(default)]
+    pub phases: Vec<PhaseDuration>,
+}
+
+/// Side effect of a deployment change, written in the same transaction as the
+/// change itself
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum OutboxEvent {
+    DeploymentCreated {
+        deployment: String,
+    },
+    DeploymentStatusChanged {
+        deployment: String,
+        status: String,
+        #[serde(default)]
+        outcome: BuildOutcome,
+    },
+}
+impl OutboxEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutboxEvent::DeploymentCreated { .. } => "DeploymentCreated",
+            OutboxEvent::DeploymentStatusChanged { .. } => "DeploymentStatusChanged",
+        }
+    }
+
+    /// Uuid of the deployment the event is about
+    pub fn deployment(&self) -> &str {
+        match self {
+            OutboxEvent::DeploymentCreated { deployment } => deployment,
+            OutboxEvent::DeploymentStatusChanged { deployment, .. } => deployment,
+        }
+    }
+
+    /// Status the deployment reached, None for other events
+    pub fn status(&self) -> Option<ProjectDeploymentStatus> {
+        match self {
+            OutboxEvent::DeploymentStatusChanged { status, .. } => {
+                Some(ProjectDeploymentStatus::from_str(status).unwrap())
+            }
+            _ => None,
+        }
+    }
+
+    /// Event announced to the webhooks, if any
+    pub fn webhook_event(&self) -> Option<WebhookEvent> {
+        match self {
+            OutboxEvent::DeploymentStatusChanged { outcome, .. } => {
+                WebhookEvent::from_status(self.status().unwrap(), outcome.stopped)
+            }
+            _ => None,
+        }
+    }
+
+    /// Chat and email notification, only sent for finished deployments
+    pub fn notification(&self, deployment: &ProjectDeployment) -> Option<DeploymentNotification> {
+        match (self, self.status()) {
+            (
+                OutboxEvent::DeploymentStatusChanged { outcome, .. },
+                Some(ProjectDeploymentStatus::Succeeded),
+            ) => Some(DeploymentNotification::new(
+                deployment,
+                None,
+                outcome.duration_seconds,
+                outcome.commit.clone(),
+            )),
+            (
+                OutboxEvent::DeploymentStatusChanged { outcome, .. },
+                Some(ProjectDeploymentStatus::Failed),
+            ) => Some(DeploymentNotification::new(
+                deployment,
+                outcome.failed_phase.clone(),
+                outcome.duration_seconds,
+                outcome.commit.clone(),
+            )),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OutboxEntry {
+    pub uuid: String,
+    #[serde(rename(serialize = "eventType"))]
+    pub event_type: String,
+    /// Deployment the event is about
+    pub aggregate: String,
+    /// Event serialized as json
+    pub payload: String,
+    pub status: String,
+    pub attempts: i32,
+    #[serde(rename(serialize = "lastError"))]
+    pub last_error: Option<String>,
+    /// Pending entries sort by this value, "-" when there is nothing left to do
+    #[serde(rename(serialize = "nextAttemptAt"))]
+    pub next_attempt_at: String,
+    #[serde(rename(serialize = "updatedAt"))]
+    pub updated_at: String,
+    #[serde(rename(serialize = "createdAt"))]
+    pub created_at: String,
+}
+impl OutboxEntry {
+    pub fn new(event: &OutboxEvent, now: DateTime<Utc>) -> Self {
+        let timestamp = now.to_rfc3339();
+        Self {
+            uuid: Uuid::new_v4().to_string(),
+            event_type: event.name().to_string(),
+            aggregate: event.deployment().to_string(),
+            payload: serde_json::to_string(event).unwrap(),
+            status: OutboxStatus::Pending.to_string(),
+            attempts: 0,
+            last_error: None,
+            next_attempt_at: (now + Duration::seconds(OUTBOX_GRACE_PERIOD_SECONDS)).to_rfc3339(),
+            updated_at: timestamp.clone(),
+            created_at: timestamp,
+        }
+    }
+
+    pub fn status(&self) -> OutboxStatus {
+        OutboxStatus::from_str(&self.status).unwrap()
+    }
+
+    pub fn event(&self) -> Result<OutboxEvent, String> {
+        serde_json::from_str(&self.payload).map_err(|error| error.to_string())
+    }
+
+    /// Records the outcome of a publication attempt
+    pub fn attempted(
+        &mut self,
+        outcome: Result<(), String>,
+        retry: &RetryPolicy,
+        now: DateTime<Utc>,
+    ) {
+        self.attempts += 1;
+        self.updated_at = now.to_rfc3339();
+        match outcome {
+            Ok(()) => {
+                self.status = OutboxStatus::Published.to_string();
+                self.last_error = None;
+                self.next_attempt_at = "-".to_string();
+            }
+            Err(error) => match retry.next_delay(self.attempts) {
+                Some(delay) => {
+                    self.last_error = Some(error);
+                    self.next_attempt_at = (now + delay).to_rfc3339();
+                }
+                None => self.abandon(error, now),
+            },
+        }
+    }
+
+    /// Gives up on an entry that can never be published
+    pub fn abandon(&mut self, error: String, now: DateTime<Utc>) {
+        self.status = OutboxStatus::Failed.to_string();
+        self.last_error = Some(error);
+        self.next_attempt_at = "-".to_string();
+        self.updated_at = now.to_rfc3339();
+    }
+}
+impl AsDynamoDBAttributeValue for OutboxEntry {
+    fn as_hashmap(&self) -> HashMap<String, AttributeValue> {
+        let mut map: HashMap<String, AttributeValue> = HashMap::new();
+        map.insert("uuid".to_string(), AttributeValue::S(self.uuid.to_owned()));
+        map.insert(
+            "event_type".to_string(),
+            AttributeValue::S(self.event_type.to_owned()),
+        );
+        map.insert(
+            "aggregate".to_string(),
+            AttributeValue::S(self.aggregate.to_owned()),
+        );
+        map.insert(
+            "payload".to_string(),
+            AttributeValue::S(self.payload.to_owned()),
+        );
+        map.insert(
+            "status".to_string(),
+            AttributeValue::S(self.status.to_owned()),
+        );
+        map.insert(
+            "attempts".to_string(),
+            AttributeValue::N(format!("{}", self.attempts)),
+        );
+        if let Some(error) = self.last_error.as_ref() {
+            map.insert(
+                "last_error".to_string(),
+                AttributeValue::S(error.to_owned()),
+            );
+        }
+        map.insert(
+            "next_attempt_at".to_string(),
+            AttributeValue::S(self.next_attempt_at.to_owned()),
+        );
+        map.insert(
+            "updated_at".to_string(),
+            AttributeValue::S(self.updated_at.to_owned()),
+        );
+        map.insert(
+            "created_at".to_string(),
+            AttributeValue::S(self.created_at.to_owned()),
+        );
+
+        map
+    }
+
+    fn as_attr(&self) -> AttributeValue {
+        AttributeValue::M(self.as_hashmap())
+    }
+}
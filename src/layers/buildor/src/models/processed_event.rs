@@ -0,0 +1,78 @@
+use aws_sdk_dynamodb::model::AttributeValue;
+use chrono::{DateTime, Duration, Utc};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::common::AsDynamoDBAttributeValue;
+
+/// Hours an event id is remembered, longer than the EventBridge retry window
+pub const DEFAULT_PROCESSED_EVENT_TTL_HOURS: i64 = 48;
+
+/// Seconds a lambda holds an event while processing it, longer than the 30
+/// seconds the processing lambda can run. A retry takes over an event whose
+/// lambda never completed nor released it once the lock expired.
+pub const PROCESSED_EVENT_LOCK_SECONDS: i64 = 60;
+
+/// Event claimed by a lambda, expired by the DynamoDB TTL
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProcessedEvent {
+    pub id: String,
+    pub source: String,
+    /// Whether every side effect of the event was applied, false while it is
+    /// being processed
+    pub done: bool,
+    /// Epoch seconds until which the lambda processing the event holds it
+    #[serde(rename(serialize = "lockedUntil"))]
+    pub locked_until: i64,
+    /// Epoch seconds, the table TTL attribute
+    #[serde(rename(serialize = "expiresAt"))]
+    pub expires_at: i64,
+    #[serde(rename(serialize = "createdAt"))]
+    pub created_at: String,
+}
+impl ProcessedEvent {
+    pub fn new(id: String, source: String, now: DateTime<Utc>, ttl: Duration) -> Self {
+        Self {
+            id,
+            source,
+            done: false,
+            locked_until: (now + Duration::seconds(PROCESSED_EVENT_LOCK_SECONDS)).timestamp(),
+            expires_at: (now + ttl).timestamp(),
+            created_at: now.to_rfc3339(),
+        }
+    }
+
+    /// Whether the lambda processing the event still holds it
+    pub fn is_locked(&self, now: DateTime<Utc>) -> bool {
+        !self.done && self.locked_until > now.timestamp()
+    }
+}
+impl AsDynamoDBAttributeValue for ProcessedEvent {
+    fn as_hashmap(&self) -> HashMap<String, AttributeValue> {
+        let mut map: HashMap<String, AttributeValue> = HashMap::new();
+        map.insert("id".to_string(), AttributeValue::S(self.id.to_owned()));
+        map.insert(
+            "source".to_string(),
+            AttributeValue::S(self.source.to_owned()),
+        );
+        map.insert("done".to_string(), AttributeValue::Bool(self.done));
+        map.insert(
+            "locked_until".to_string(),
+            AttributeValue::N(format!("{}", self.locked_until)),
+        );
+        map.insert(
+            "expires_at".to_string(),
+            AttributeValue::N(format!("{}", self.expires_at)),
+        );
+        map.insert(
+            "created_at".to_string(),
+            AttributeValue::S(self.created_at.to_owned()),
+        );
+
+        map
+    }
+
+    fn as_attr(&self) -> AttributeValue {
+        AttributeValue::M(self.as_hashmap())
+    }
+}
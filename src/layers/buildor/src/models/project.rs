@@ -2,20 +2,59 @@ use aws_sdk_dynamodb::model::AttributeValue;
 use chrono::Utc;
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::str::FromStr;
 use uuid::Uuid;
 
+use super::api_error::ApiError;
+use super::codebuild::{ComputeSize, COMPUTE_SIZES};
 use super::commands::Commands;
 use super::common::AsDynamoDBAttributeValue;
+use super::environment::{environments_as_attr, Environment, EnvironmentCreatePayload};
+use super::list_query::ListField;
+use super::notification::{notification_channels_as_attr, NotificationChannel};
 use super::request::RequestError;
+use super::retention::RetentionPolicy;
+use super::validation::{Rule, Validate, Validator};
+
+/// CodeBuild default build timeout
+pub const DEFAULT_BUILD_TIMEOUT_MINUTES: i32 = 60;
+
+/// Fields the projects can be filtered, sorted and listed by
+pub const PROJECT_LIST_FIELDS: &[ListField] = &[
+    ListField::text("uuid", "uuid"),
+    ListField::text("name", "name"),
+    ListField::text("repository", "repository"),
+    ListField::text("owner", "owner"),
+    ListField::nested("commands", "commands"),
+    ListField::text("outputFolder", "output_folder"),
+    ListField::nested("environments", "environments"),
+    ListField::number("buildTimeoutMinutes", "build_timeout_minutes"),
+    ListField::text("computeSize", "compute_size"),
+    ListField::nested("retention", "retention"),
+    ListField::nested("notifications", "notifications"),
+    ListField::text("lastPublished", "last_published"),
+    ListField::text("updatedAt", "updated_at"),
+    ListField::text("createdAt", "created_at"),
+];
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Project {
     pub uuid: String,
     pub name: String,
     pub repository: String,
+    /// User the project belongs to, "-" when unknown
+    pub owner: String,
     pub commands: Commands,
     #[serde(rename(serialize = "outputFolder"))]
     pub output_folder: String,
+    pub environments: Vec<Environment>,
+    #[serde(rename(serialize = "buildTimeoutMinutes"))]
+    pub build_timeout_minutes: i32,
+    #[serde(rename(serialize = "computeSize"))]
+    pub compute_size: String,
+    pub retention: RetentionPolicy,
+    /// Chat channels deployment results are posted to
+    pub notifications: Vec<NotificationChannel>,
     #[serde(rename(serialize = "lastPublished"))]
     pub last_published: String,
     #[serde(rename(serialize = "updatedAt"))]
@@ -28,20 +67,188 @@ pub struct Project {
 pub struct ProjectCreatePayload {
     pub name: String,
     pub repository: String,
+    pub owner: Option<String>,
     pub commands: Option<Commands>,
     #[serde(rename(serialize = "outputFolder"))]
     pub output_folder: Option<String>,
+    pub environments: Option<Vec<EnvironmentCreatePayload>>,
+    #[serde(rename(serialize = "buildTimeoutMinutes"))]
+    pub build_timeout_minutes: Option<i32>,
+    #[serde(rename(serialize = "computeSize"))]
+    pub compute_size: Option<String>,
+    pub retention: Option<RetentionPolicy>,
+    pub notifications: Option<Vec<NotificationChannel>>,
 }
 impl ProjectCreatePayload {
     pub fn default(name: String, repository: String) -> Self {
         Self {
             name,
             repository,
+            owner: None,
             commands: None,
             output_folder: None,
+            environments: None,
+            build_timeout_minutes: None,
+            compute_size: None,
+            retention: None,
+            notifications: None,
         }
     }
 }
+impl Validate for ProjectCreatePayload {
+    fn validate(&self) -> Result<(), RequestError> {
+        let mut validator = Validator::new()
+            .field(
+                "name",
+                Some(&self.name),
+                &[Rule::NotBlank, Rule::MaxLength(100)],
+            )
+            .field(
+                "repository",
+                Some(&self.repository),
+                &[Rule::NotBlank, Rule::Url(&["https", "http"])],
+            )
+            .field("owner", self.owner.as_deref(), &[Rule::Uuid])
+            .field(
+                "output_folder",
+                self.output_folder.as_deref(),
+                &[Rule::MaxLength(255), Rule::RelativePath],
+            )
+            .number(
+                "build_timeout_minutes",
+                self.build_timeout_minutes.map(i64::from),
+                &[Rule::Range(5, 480)],
+            )
+            .field(
+                "compute_size",
+                self.compute_size.as_deref(),
+                &[Rule::OneOf(COMPUTE_SIZES)],
+            )
+            .each(
+                "environments",
+                self.environments.as_deref().unwrap_or_default(),
+            );
+        for (index, channel) in self.notifications.iter().flatten().enumerate() {
+            validator = validator.check(&format!("notifications[{}]", index), channel.validate());
+        }
+
+        validator.finish()
+    }
+}
+
+/// Fields of a project that can be edited, environments are left out as they
+/// hold the current deployment of each one
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ProjectUpdatePayload {
+    pub name: Option<String>,
+    pub repository: Option<String>,
+    pub owner: Option<String>,
+    pub commands: Option<Commands>,
+    #[serde(rename(serialize = "outputFolder"))]
+    pub output_folder: Option<String>,
+    #[serde(rename(serialize = "buildTimeoutMinutes"))]
+    pub build_timeout_minutes: Option<i32>,
+    #[serde(rename(serialize = "computeSize"))]
+    pub compute_size: Option<String>,
+    pub retention: Option<RetentionPolicy>,
+    pub notifications: Option<Vec<NotificationChannel>>,
+}
+impl Validate for ProjectUpdatePayload {
+    fn validate(&self) -> Result<(), RequestError> {
+        let mut validator = Validator::new()
+            .field(
+                "name",
+                self.name.as_deref(),
+                &[Rule::NotBlank, Rule::MaxLength(100)],
+            )
+            .field(
+                "repository",
+                self.repository.as_deref(),
+                &[Rule::NotBlank, Rule::Url(&["https", "http"])],
+            )
+            .field("owner", self.owner.as_deref(), &[Rule::Uuid])
+            .field(
+                "output_folder",
+                self.output_folder.as_deref(),
+                &[Rule::MaxLength(255), Rule::RelativePath],
+            )
+            .number(
+                "build_timeout_minutes",
+                self.build_timeout_minutes.map(i64::from),
+                &[Rule::Range(5, 480)],
+            )
+            .field(
+                "compute_size",
+                self.compute_size.as_deref(),
+                &[Rule::OneOf(COMPUTE_SIZES)],
+            );
+        for (index, channel) in self.notifications.iter().flatten().enumerate() {
+            validator = validator.check(&format!("notifications[{}]", index), channel.validate());
+        }
+
+        validator.finish()
+    }
+}
+impl AsDynamoDBAttributeValue for ProjectUpdatePayload {
+    fn as_hashmap(&self) -> HashMap<String, AttributeValue> {
+        let mut map: HashMap<String, AttributeValue> = HashMap::new();
+        self.name
+            .as_ref()
+            .and_then(|name| map.insert("name".to_string(), AttributeValue::S(name.to_owned())));
+        self.repository.as_ref().and_then(|repository| {
+            map.insert(
+                "repository".to_string(),
+                AttributeValue::S(repository.to_owned()),
+            )
+        });
+        self.owner
+            .as_ref()
+            .and_then(|owner| map.insert("owner".to_string(), AttributeValue::S(owner.to_owned())));
+        self.commands
+            .as_ref()
+            .and_then(|commands| map.insert("commands".to_string(), commands.as_attr()));
+        self.output_folder.as_ref().and_then(|output_folder| {
+            map.insert(
+                "output_folder".to_string(),
+                AttributeValue::S(output_folder.to_owned()),
+            )
+        });
+        self.build_timeout_minutes.and_then(|minutes| {
+            map.insert(
+                "build_timeout_minutes".to_string(),
+                AttributeValue::N(format!("{}", minutes)),
+            )
+        });
+        // Payloads are validated, unknown sizes are left out
+        self.compute_size
+            .as_deref()
+            .and_then(|compute_size| ComputeSize::from_str(compute_size).ok())
+            .and_then(|compute_size| {
+                map.insert(
+                    "compute_size".to_string(),
+                    AttributeValue::S(compute_size.to_string()),
+                )
+            });
+        self.retention.as_ref().and_then(|retention| {
+            map.insert(
+                "retention".to_string(),
+                RetentionPolicy::new(retention.keep_last, retention.expire_after_days).as_attr(),
+            )
+        });
+        self.notifications.as_ref().and_then(|notifications| {
+            map.insert(
+                "notifications".to_string(),
+                notification_channels_as_attr(notifications),
+            )
+        });
+
+        map
+    }
+
+    fn as_attr(&self) -> AttributeValue {
+        AttributeValue::M(self.as_hashmap())
+    }
+}
 
 impl Project {
     pub fn new(payload: ProjectCreatePayload) -> Self {
@@ -50,6 +257,7 @@ impl Project {
             uuid: Uuid::new_v4().to_string(),
             name: payload.name,
             repository: payload.repository,
+            owner: payload.owner.unwrap_or_else(|| "-".to_string()),
             commands: match payload.commands {
                 Some(value) => value,
                 None => Commands::defaults(),
@@ -58,11 +266,38 @@ impl Project {
                 Some(value) => value,
                 None => "dist".to_string(),
             },
+            environments: match payload.environments {
+                Some(values) if !values.is_empty() => {
+                    values.into_iter().map(Environment::new).collect()
+                }
+                _ => Environment::defaults(),
+            },
+            build_timeout_minutes: payload
+                .build_timeout_minutes
+                .unwrap_or(DEFAULT_BUILD_TIMEOUT_MINUTES),
+            // Payloads are validated, unknown sizes use the default
+            compute_size: payload
+                .compute_size
+                .as_deref()
+                .and_then(|value| ComputeSize::from_str(value).ok())
+                .unwrap_or(ComputeSize::Small)
+                .to_string(),
+            retention: match payload.retention {
+                Some(value) => RetentionPolicy::new(value.keep_last, value.expire_after_days),
+                None => RetentionPolicy::defaults(),
+            },
+            notifications: payload.notifications.unwrap_or_default(),
             last_published: "-".to_string(),
             updated_at: timestamp.clone(),
             created_at: timestamp,
         }
     }
+
+    pub fn environment(&self, name: &str) -> Option<&Environment> {
+        self.environments
+            .iter()
+            .find(|environment| environment.name == name)
+    }
 }
 
 impl AsDynamoDBAttributeValue for Project {
@@ -74,11 +309,32 @@ impl AsDynamoDBAttributeValue for Project {
             "repository".to_string(),
             AttributeValue::S(self.repository.to_owned()),
         );
+        map.insert(
+            "owner".to_string(),
+            AttributeValue::S(self.owner.to_owned()),
+        );
         map.insert("commands".to_string(), self.commands.as_attr());
         map.insert(
             "output_folder".to_string(),
             AttributeValue::S(self.output_folder.to_owned()),
         );
+        map.insert(
+            "environments".to_string(),
+            environments_as_attr(&self.environments),
+        );
+        map.insert(
+            "build_timeout_minutes".to_string(),
+            AttributeValue::N(format!("{}", self.build_timeout_minutes)),
+        );
+        map.insert(
+            "compute_size".to_string(),
+            AttributeValue::S(self.compute_size.to_owned()),
+        );
+        map.insert("retention".to_string(), self.retention.as_attr());
+        map.insert(
+            "notifications".to_string(),
+            notification_channels_as_attr(&self.notifications),
+        );
         map.insert(
             "last_published".to_string(),
             AttributeValue::S(self.last_published.to_owned()),
@@ -103,10 +359,29 @@ impl AsDynamoDBAttributeValue for Project {
 pub struct ProjectError;
 impl ProjectError {
     pub fn creation_failed() -> RequestError {
-        RequestError {
-            code: "PJE00".to_string(),
-            message: "Create Project Error".to_string(),
-            details: "Project creation failed, try again".to_string(),
-        }
+        ApiError::ProjectCreationFailed.error("Project creation failed, try again".to_string())
+    }
+
+    pub fn invalid_payload(details: String) -> RequestError {
+        ApiError::ProjectInvalidPayload.error(details)
+    }
+
+    pub fn update_failed() -> RequestError {
+        ApiError::ProjectUpdateFailed.error("Project update failed, try again".to_string())
+    }
+
+    pub fn delete_failed() -> RequestError {
+        ApiError::ProjectDeleteFailed.error("Project deletion failed, try again".to_string())
+    }
+
+    pub fn delete_blocked(deployments: usize) -> RequestError {
+        ApiError::ProjectDeleteBlocked.error(format!(
+            "Project has {} deployment(s) queued or in progress, try again once they finish",
+            deployments
+        ))
+    }
+
+    pub fn invalid_stats_window(details: String) -> RequestError {
+        ApiError::ProjectInvalidStatsWindow.error(details)
     }
 }
@@ -2,39 +2,231 @@ use aws_sdk_dynamodb::model::AttributeValue;
 use chrono::Utc;
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
 
+use super::api_error::ApiError;
+use super::artifact::ArtifactSummary;
 use super::common::AsDynamoDBAttributeValue;
+use super::environment::default_environment_name;
 use super::request::RequestError;
+use super::validation::{Rule, Validate, Validator};
 use super::{codebuild::BuildInfo, project::Project};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ProjectDeploymentStatus {
+    Queued,     // "QUEUED"
+    InProgress, // "IN_PROGRESS"
+    Succeeded,  // "SUCCEEDED"
+    Failed,     // "FAILED"
+    Superseded, // "SUPERSEDED"
+    Expired,    // "EXPIRED"
+    Unknown,    // "UNKNOWN", (custom value used when parsing from/to string/enum)
+}
+impl ProjectDeploymentStatus {
+    /// Terminal statuses are never picked up by the queue again, only the
+    /// retention policy moves them to `Expired`
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            ProjectDeploymentStatus::Succeeded
+                | ProjectDeploymentStatus::Failed
+                | ProjectDeploymentStatus::Superseded
+                | ProjectDeploymentStatus::Expired
+        )
+    }
+}
+impl FromStr for ProjectDeploymentStatus {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<ProjectDeploymentStatus, ()> {
+        match String::from(input) {
+            queued if queued == ProjectDeploymentStatus::Queued.to_string() => {
+                Ok(ProjectDeploymentStatus::Queued)
+            }
+            in_progress if in_progress == ProjectDeploymentStatus::InProgress.to_string() => {
+                Ok(ProjectDeploymentStatus::InProgress)
+            }
+            succeeded if succeeded == ProjectDeploymentStatus::Succeeded.to_string() => {
+                Ok(ProjectDeploymentStatus::Succeeded)
+            }
+            failed if failed == ProjectDeploymentStatus::Failed.to_string() => {
+                Ok(ProjectDeploymentStatus::Failed)
+            }
+            superseded if superseded == ProjectDeploymentStatus::Superseded.to_string() => {
+                Ok(ProjectDeploymentStatus::Superseded)
+            }
+            expired if expired == ProjectDeploymentStatus::Expired.to_string() => {
+                Ok(ProjectDeploymentStatus::Expired)
+            }
+            _ => Ok(ProjectDeploymentStatus::Unknown),
+        }
+    }
+}
+impl fmt::Display for ProjectDeploymentStatus {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProjectDeploymentStatus::Queued => fmt.write_str("QUEUED"),
+            ProjectDeploymentStatus::InProgress => fmt.write_str("IN_PROGRESS"),
+            ProjectDeploymentStatus::Succeeded => fmt.write_str("SUCCEEDED"),
+            ProjectDeploymentStatus::Failed => fmt.write_str("FAILED"),
+            ProjectDeploymentStatus::Superseded => fmt.write_str("SUPERSEDED"),
+            ProjectDeploymentStatus::Expired => fmt.write_str("EXPIRED"),
+            ProjectDeploymentStatus::Unknown => fmt.write_str("UNKNOWN"),
+        }
+    }
+}
+
+/// Longest wait of the wait endpoint, API Gateway gives up on integrations
+/// after 29 seconds
+pub const MAX_WAIT_TIMEOUT_SECONDS: u64 = 25;
+pub const DEFAULT_WAIT_TIMEOUT_SECONDS: u64 = 25;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum WaitUntil {
+    Change,   // "change"
+    Terminal, // "terminal"
+    Unknown,  // "unknown", (custom value used when parsing from/to string/enum)
+}
+impl FromStr for WaitUntil {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<WaitUntil, ()> {
+        match input.to_lowercase() {
+            change if change == WaitUntil::Change.to_string() => Ok(WaitUntil::Change),
+            terminal if terminal == WaitUntil::Terminal.to_string() => Ok(WaitUntil::Terminal),
+            _ => Ok(WaitUntil::Unknown),
+        }
+    }
+}
+impl fmt::Display for WaitUntil {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WaitUntil::Change => fmt.write_str("change"),
+            WaitUntil::Terminal => fmt.write_str("terminal"),
+            WaitUntil::Unknown => fmt.write_str("unknown"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct WaitOptions {
+    pub until: WaitUntil,
+    pub timeout_seconds: u64,
+}
+impl WaitOptions {
+    /// Options of the `until` and `timeout` query parameters, waits for any
+    /// status change by default. Returns the reason invalid values are rejected.
+    pub fn parse(until: Option<String>, timeout: Option<String>) -> Result<Self, String> {
+        let until = match until {
+            Some(value) => match WaitUntil::from_str(&value).unwrap() {
+                WaitUntil::Unknown => {
+                    return Err(format!(
+                        "Unknown until value: {}, expected change or terminal",
+                        value
+                    ))
+                }
+                until => until,
+            },
+            None => WaitUntil::Change,
+        };
+        let timeout_seconds = match timeout {
+            Some(value) => match value.parse::<u64>() {
+                Ok(seconds) if (1..=MAX_WAIT_TIMEOUT_SECONDS).contains(&seconds) => seconds,
+                _ => {
+                    return Err(format!(
+                        "Timeout must be between 1 and {} seconds: {}",
+                        MAX_WAIT_TIMEOUT_SECONDS, value
+                    ))
+                }
+            },
+            None => DEFAULT_WAIT_TIMEOUT_SECONDS,
+        };
+
+        Ok(Self {
+            until,
+            timeout_seconds,
+        })
+    }
+}
+
+/// Result of waiting on a deployment, `changed` tells whether its status
+/// changed while waiting
+#[derive(Serialize, Debug, Clone)]
+pub struct ProjectDeploymentWait {
+    pub changed: bool,
+    pub deployment: ProjectDeployment,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ProjectDeployment {
     pub uuid: String,
     pub project: Project,
-    pub build: BuildInfo,
+    pub environment: String,
+    pub status: String,
+    pub build: Option<BuildInfo>,
+    pub artifact: Option<ArtifactSummary>,
+    /// Pinned deployments are never expired by the retention policy
+    pub pinned: bool,
     #[serde(rename(serialize = "updatedAt"))]
     pub updated_at: String,
     #[serde(rename(serialize = "createdAt"))]
     pub created_at: String,
 }
 impl ProjectDeployment {
-    pub fn new(project: Project, build: BuildInfo) -> Self {
+    pub fn new(payload: ProjectDeploymentCreatePayload) -> Self {
         let timestamp = Utc::now().to_rfc3339().to_string();
+        let status = match payload.build {
+            Some(_) => ProjectDeploymentStatus::InProgress,
+            None => ProjectDeploymentStatus::Queued,
+        };
         Self {
-            uuid: build.uuid.clone(),
-            project,
-            build,
+            uuid: Uuid::new_v4().to_string(),
+            project: payload.project,
+            environment: payload.environment,
+            status: status.to_string(),
+            build: payload.build,
+            artifact: None,
+            pinned: false,
             updated_at: timestamp.clone(),
             created_at: timestamp,
         }
     }
+
+    pub fn status(&self) -> ProjectDeploymentStatus {
+        ProjectDeploymentStatus::from_str(&self.status).unwrap()
+    }
 }
 impl AsDynamoDBAttributeValue for ProjectDeployment {
     fn as_hashmap(&self) -> HashMap<String, AttributeValue> {
         let mut map: HashMap<String, AttributeValue> = HashMap::new();
         map.insert("uuid".to_string(), AttributeValue::S(self.uuid.to_owned()));
         map.insert("project".to_string(), self.project.as_attr());
-        map.insert("build".to_string(), self.build.as_attr());
+        map.insert(
+            "environment".to_string(),
+            AttributeValue::S(self.environment.to_owned()),
+        );
+        map.insert(
+            "status".to_string(),
+            AttributeValue::S(self.status.to_owned()),
+        );
+        // Top level copies of nested values used as index keys
+        map.insert(
+            "project_uuid".to_string(),
+            AttributeValue::S(self.project.uuid.to_owned()),
+        );
+        if let Some(build) = self.build.as_ref() {
+            map.insert("build".to_string(), build.as_attr());
+            map.insert(
+                "build_uuid".to_string(),
+                AttributeValue::S(build.uuid.to_owned()),
+            );
+        }
+        if let Some(artifact) = self.artifact.as_ref() {
+            map.insert("artifact".to_string(), artifact.as_attr());
+        }
+        map.insert("pinned".to_string(), AttributeValue::Bool(self.pinned));
         map.insert(
             "updated_at".to_string(),
             AttributeValue::S(self.updated_at.to_owned()),
@@ -55,18 +247,37 @@ impl AsDynamoDBAttributeValue for ProjectDeployment {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ProjectDeploymentCreatePayload {
     pub project: Project,
-    pub build: BuildInfo,
+    pub environment: String,
+    /// Deployments without a build yet are queued
+    pub build: Option<BuildInfo>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ProjectDeploymentCreatePayloadRequest {
     pub project_uuid: String,
+    #[serde(default = "default_environment_name")]
+    pub environment: String,
+}
+impl Validate for ProjectDeploymentCreatePayloadRequest {
+    fn validate(&self) -> Result<(), RequestError> {
+        Validator::new()
+            .field("project_uuid", Some(&self.project_uuid), &[Rule::Uuid])
+            .field(
+                "environment",
+                Some(&self.environment),
+                &[Rule::NotBlank, Rule::MaxLength(64)],
+            )
+            .finish()
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ProjectDeploymentUpdatePayload {
     pub project: Option<Project>,
+    pub status: Option<String>,
     pub build: Option<BuildInfo>,
+    pub artifact: Option<ArtifactSummary>,
+    pub pinned: Option<bool>,
 }
 impl AsDynamoDBAttributeValue for ProjectDeploymentUpdatePayload {
     fn as_hashmap(&self) -> HashMap<String, AttributeValue> {
@@ -74,9 +285,21 @@ impl AsDynamoDBAttributeValue for ProjectDeploymentUpdatePayload {
         self.project
             .as_ref()
             .and_then(|project| map.insert("project".to_string(), project.as_attr()));
-        self.build
+        self.status.as_ref().and_then(|status| {
+            map.insert("status".to_string(), AttributeValue::S(status.to_owned()))
+        });
+        if let Some(build) = self.build.as_ref() {
+            map.insert("build".to_string(), build.as_attr());
+            map.insert(
+                "build_uuid".to_string(),
+                AttributeValue::S(build.uuid.to_owned()),
+            );
+        }
+        self.artifact
             .as_ref()
-            .and_then(|build| map.insert("build".to_string(), build.as_attr()));
+            .and_then(|artifact| map.insert("artifact".to_string(), artifact.as_attr()));
+        self.pinned
+            .and_then(|pinned| map.insert("pinned".to_string(), AttributeValue::Bool(pinned)));
 
         map
     }
@@ -86,13 +309,32 @@ impl AsDynamoDBAttributeValue for ProjectDeploymentUpdatePayload {
     }
 }
 
+/// Fields of a deployment that can be edited
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProjectDeploymentPatchPayload {
+    /// Pinned deployments are kept by the retention policy
+    pub pinned: bool,
+}
+
 pub struct ProjectDeploymentError;
 impl ProjectDeploymentError {
     pub fn creation_failed() -> RequestError {
-        RequestError {
-            code: "PDE00".to_string(),
-            message: "Create Project Deployment Error".to_string(),
-            details: "Record creation failed but build has been triggered (probably)".to_string(),
-        }
+        ApiError::ProjectDeploymentCreationFailed.error(
+            "Project deployment could not be created, no build has been triggered".to_string(),
+        )
+    }
+
+    pub fn queue_failed() -> RequestError {
+        ApiError::ProjectDeploymentQueueFailed
+            .error("Project deployment could not be queued, try again".to_string())
+    }
+
+    pub fn invalid_wait(details: String) -> RequestError {
+        ApiError::ProjectDeploymentInvalidWait.error(details)
+    }
+
+    pub fn update_failed() -> RequestError {
+        ApiError::ProjectDeploymentUpdateFailed
+            .error("Project deployment update failed, try again".to_string())
     }
 }
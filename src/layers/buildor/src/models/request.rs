@@ -5,7 +5,9 @@ use serde_derive::Serialize;
 use serde_json::Value;
 use std::fmt;
 
+use crate::models::api_error::ApiError;
 use crate::models::common::CommonError;
+use crate::models::validation::FieldError;
 
 pub struct Request;
 impl Request {
@@ -23,9 +25,19 @@ impl Request {
         }
     }
 
+    /// Query string parameters are optional, missing and empty values are `None`
+    pub fn query_parameter(key: &str, event: &Value) -> Option<String> {
+        event
+            .get("queryStringParameters")
+            .and_then(|params| params.get(key))
+            .and_then(|value| value.as_str())
+            .filter(|value| !value.is_empty())
+            .map(|value| value.to_string())
+    }
+
     pub fn body<'a, T: Deserializable<'a>>(body: &'a Value) -> Result<T, RequestError> {
         let body_str: &'a str = body.as_str().unwrap();
-        match serde_json::from_str::<T>(&body_str) {
+        match serde_json::from_str::<T>(body_str) {
             Ok(valid) => Ok(valid),
             Err(err) => {
                 println!("Body payload not compliant: {}", err);
@@ -38,35 +50,37 @@ impl Request {
 }
 
 /* Request Error */
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone, PartialEq)]
 pub struct RequestError {
     pub code: String,
     pub message: String,
     pub details: String,
+    pub retryable: bool,
+    /// Violated fields of validation errors
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+    /// HTTP status the error is sent with
+    #[serde(skip)]
+    pub status: u16,
 }
 impl RequestError {
-    pub fn new(code: String, message: String, details: String) -> Self {
+    pub fn new(error: ApiError, details: String) -> Self {
         Self {
-            code,
-            message,
+            code: error.code().to_string(),
+            message: error.message().to_string(),
             details,
+            retryable: error.retryable(),
+            errors: Vec::new(),
+            status: error.status(),
         }
     }
 
     pub fn internal() -> Self {
-        Self {
-            code: "ISE00".to_string(),
-            message: "Internal Server Error".to_string(),
-            details: "Something wrong happened, try again later".to_string(),
-        }
+        ApiError::Internal.error("Something wrong happened, try again later".to_string())
     }
 
     pub fn path_parameter(param: String) -> Self {
-        Self {
-            code: "GRE100".to_string(),
-            message: "Request Error".to_string(),
-            details: format!("Path parameter error {}", param),
-        }
+        ApiError::PathParameter.error(format!("Path parameter error {}", param))
     }
 }
 
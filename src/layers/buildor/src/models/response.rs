@@ -1,8 +1,13 @@
 use serde::Serialize as Serializable;
 use serde_derive::Serialize;
 use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 
-#[derive(Serialize, Debug)]
+use super::api_error::{ProblemDetails, PROBLEM_CONTENT_TYPE};
+use super::request::RequestError;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
 pub struct ResponseHeaders {
     #[serde(rename(serialize = "Content-Type"))]
     pub content_type: String,
@@ -24,44 +29,148 @@ pub struct ResponseHeaders {
 
     #[serde(rename(serialize = "Access-Control-Expose-Headers"))]
     pub access_control_expose_headers: String,
+
+    #[serde(rename(serialize = "ETag"), skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
 }
 
-impl ResponseHeaders {
-    pub fn default() -> Self {
+impl Default for ResponseHeaders {
+    fn default() -> Self {
         ResponseHeaders {
             content_type: "application/json".to_string(),
             access_control_allow_origin: "*".to_string(),
             access_control_allow_credentials: "false".to_string(),
             x_requested_with: "*".to_string(),
-            access_control_allow_headers: "Accept,Content-Type,Authorization,X-Amz-Date,X-Api-Key,X-Amz-User-Agent,X-Requested-With,X-Amz-Security-Token".to_string(),
+            access_control_allow_headers: "Accept,Content-Type,Authorization,X-Amz-Date,X-Api-Key,X-Amz-User-Agent,X-Requested-With,X-Amz-Security-Token,If-Match,If-None-Match,Idempotency-Key".to_string(),
             access_control_allow_methods: "OPTIONS,HEAD,GET,POST,PUT,PATCH,DELETE".to_string(),
-            access_control_expose_headers: "Authorization,X-Requested-With".to_string(),
+            access_control_expose_headers: "Authorization,X-Requested-With,ETag".to_string(),
+            etag: None,
+        }
+    }
+}
+
+impl ResponseHeaders {
+    /// Header names and values, as API Gateway responses expect them
+    pub fn as_map(&self) -> HashMap<String, String> {
+        match json!(self) {
+            Value::Object(headers) => headers
+                .into_iter()
+                .filter_map(|(name, value)| value.as_str().map(|value| (name, value.to_string())))
+                .collect(),
+            _ => HashMap::new(),
         }
     }
 }
 
-#[derive(Serialize, Debug)]
+/// Strong entity tag of a JSON representation, the same one `Response::json`
+/// bodies of the value are tagged with
+pub fn etag<T: Serializable>(body: T) -> String {
+    etag_of(&json!(body).to_string())
+}
+
+fn etag_of(body: &str) -> String {
+    let digest = format!("{:x}", Sha256::digest(body.as_bytes()));
+    format!("\"{}\"", &digest[..32])
+}
+
+/// Whether an `If-Match` or `If-None-Match` header value lists the tag, weak
+/// tags compare equal to strong ones
+pub fn etag_matches(header: &str, etag: &str) -> bool {
+    header.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.trim_start_matches("W/") == etag.trim_start_matches("W/")
+    })
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
 pub struct Response {
     #[serde(rename(serialize = "statusCode"))]
     pub status_code: u16,
     pub headers: ResponseHeaders,
     pub body: String,
+    /// Error the body was built from, kept to render it as problem details
+    #[serde(skip)]
+    pub error: Option<RequestError>,
 }
 
 impl Response {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new<T: Serializable>(body: T, code: u16) -> Value {
         json!(Response {
             status_code: code,
             headers: ResponseHeaders::default(),
             body: json!(body).to_string(),
+            error: None,
         })
     }
 
+    /// Typed response with a JSON body, converted to the payload format of the request
+    pub fn json<T: Serializable>(body: T, code: u16) -> Self {
+        Response {
+            status_code: code,
+            headers: ResponseHeaders::default(),
+            body: json!(body).to_string(),
+            error: None,
+        }
+    }
+
+    /// Tags the response with the entity tag of its body
+    pub fn with_etag(mut self) -> Self {
+        self.headers.etag = Some(etag_of(&self.body));
+        self
+    }
+
+    /// Answer to a conditional GET whose representation did not change
+    pub fn not_modified(etag: String) -> Self {
+        Response {
+            status_code: 304,
+            headers: ResponseHeaders {
+                etag: Some(etag),
+                ..ResponseHeaders::default()
+            },
+            body: String::from(""),
+            error: None,
+        }
+    }
+
+    /// Response without body, e.g. once an item is deleted
+    pub fn no_content() -> Self {
+        Response {
+            status_code: 204,
+            headers: ResponseHeaders::default(),
+            body: String::from(""),
+            error: None,
+        }
+    }
+
+    /// Error response, sent with the status of the error
+    pub fn error(error: RequestError) -> Self {
+        Response {
+            status_code: error.status,
+            headers: ResponseHeaders::default(),
+            body: json!(error).to_string(),
+            error: Some(error),
+        }
+    }
+
+    /// RFC 7807 rendering of an error response, `instance` is the request path
+    pub fn problem(error: RequestError, instance: &str) -> Self {
+        Response {
+            status_code: error.status,
+            headers: ResponseHeaders {
+                content_type: PROBLEM_CONTENT_TYPE.to_string(),
+                ..ResponseHeaders::default()
+            },
+            body: json!(ProblemDetails::new(&error, instance)).to_string(),
+            error: Some(error),
+        }
+    }
+
     pub fn ok() -> Value {
         json!(Response {
             status_code: 204,
             headers: ResponseHeaders::default(),
             body: String::from(""),
+            error: None,
         })
     }
 }
@@ -0,0 +1,91 @@
+use aws_sdk_dynamodb::model::AttributeValue;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::common::AsDynamoDBAttributeValue;
+
+/// Successful deployments always kept per project, regardless of their age
+pub const DEFAULT_RETENTION_KEEP_LAST: i32 = 5;
+/// Age after which deployments not otherwise kept are expired
+pub const DEFAULT_RETENTION_EXPIRE_AFTER_DAYS: i32 = 30;
+
+fn default_keep_last() -> i32 {
+    DEFAULT_RETENTION_KEEP_LAST
+}
+
+fn default_expire_after_days() -> i32 {
+    DEFAULT_RETENTION_EXPIRE_AFTER_DAYS
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RetentionPolicy {
+    #[serde(rename(serialize = "keepLast"), default = "default_keep_last")]
+    pub keep_last: i32,
+    #[serde(
+        rename(serialize = "expireAfterDays"),
+        default = "default_expire_after_days"
+    )]
+    pub expire_after_days: i32,
+}
+impl RetentionPolicy {
+    pub fn new(keep_last: i32, expire_after_days: i32) -> Self {
+        Self {
+            keep_last: keep_last.max(0),
+            expire_after_days: expire_after_days.max(0),
+        }
+    }
+
+    pub fn defaults() -> Self {
+        Self::new(
+            DEFAULT_RETENTION_KEEP_LAST,
+            DEFAULT_RETENTION_EXPIRE_AFTER_DAYS,
+        )
+    }
+}
+impl AsDynamoDBAttributeValue for RetentionPolicy {
+    fn as_hashmap(&self) -> HashMap<String, AttributeValue> {
+        let mut map: HashMap<String, AttributeValue> = HashMap::new();
+        map.insert(
+            "keep_last".to_string(),
+            AttributeValue::N(format!("{}", self.keep_last)),
+        );
+        map.insert(
+            "expire_after_days".to_string(),
+            AttributeValue::N(format!("{}", self.expire_after_days)),
+        );
+
+        map
+    }
+
+    fn as_attr(&self) -> AttributeValue {
+        AttributeValue::M(self.as_hashmap())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExpiredDeployment {
+    pub uuid: String,
+    pub environment: String,
+    pub status: String,
+    #[serde(rename(serialize = "createdAt"))]
+    pub created_at: String,
+    /// Location of the deleted artifact, if the deployment had one
+    pub artifact: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProjectRetentionReport {
+    pub project: String,
+    /// The project was deleted, every deployment it left is expired
+    pub deleted: bool,
+    pub retention: RetentionPolicy,
+    pub kept: usize,
+    pub expired: Vec<ExpiredDeployment>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RetentionReport {
+    #[serde(rename(serialize = "dryRun"))]
+    pub dry_run: bool,
+    pub projects: Vec<ProjectRetentionReport>,
+}
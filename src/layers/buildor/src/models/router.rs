@@ -0,0 +1,237 @@
+use serde::Deserialize as Deserializable;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use super::api_error::ApiError;
+use super::api_gateway::{ApiGatewayRequest, PayloadFormat};
+use super::common::CommonError;
+use super::idempotency_key::{IdempotencyKeyError, IDEMPOTENCY_KEY_MAX_LENGTH};
+use super::list_query::{ListField, ListQuery};
+use super::request::RequestError;
+use super::response::etag_matches;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum HttpMethod {
+    Get,     // "GET"
+    Post,    // "POST"
+    Put,     // "PUT"
+    Patch,   // "PATCH"
+    Delete,  // "DELETE"
+    Head,    // "HEAD"
+    Options, // "OPTIONS"
+    Any,     // "ANY", routes matching every method
+    Unknown, // "UNKNOWN", (custom value used when parsing from/to string/enum)
+}
+impl FromStr for HttpMethod {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<HttpMethod, ()> {
+        match input.to_uppercase() {
+            get if get == HttpMethod::Get.to_string() => Ok(HttpMethod::Get),
+            post if post == HttpMethod::Post.to_string() => Ok(HttpMethod::Post),
+            put if put == HttpMethod::Put.to_string() => Ok(HttpMethod::Put),
+            patch if patch == HttpMethod::Patch.to_string() => Ok(HttpMethod::Patch),
+            delete if delete == HttpMethod::Delete.to_string() => Ok(HttpMethod::Delete),
+            head if head == HttpMethod::Head.to_string() => Ok(HttpMethod::Head),
+            options if options == HttpMethod::Options.to_string() => Ok(HttpMethod::Options),
+            any if any == HttpMethod::Any.to_string() => Ok(HttpMethod::Any),
+            _ => Ok(HttpMethod::Unknown),
+        }
+    }
+}
+impl fmt::Display for HttpMethod {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpMethod::Get => fmt.write_str("GET"),
+            HttpMethod::Post => fmt.write_str("POST"),
+            HttpMethod::Put => fmt.write_str("PUT"),
+            HttpMethod::Patch => fmt.write_str("PATCH"),
+            HttpMethod::Delete => fmt.write_str("DELETE"),
+            HttpMethod::Head => fmt.write_str("HEAD"),
+            HttpMethod::Options => fmt.write_str("OPTIONS"),
+            HttpMethod::Any => fmt.write_str("ANY"),
+            HttpMethod::Unknown => fmt.write_str("UNKNOWN"),
+        }
+    }
+}
+impl HttpMethod {
+    /// Whether a route registered for this method serves `method`
+    pub fn serves(&self, method: HttpMethod) -> bool {
+        *self == HttpMethod::Any || *self == method
+    }
+}
+
+/// API Gateway proxy request as seen by the routes, whatever its payload
+/// format. Path parameters are the ones of the matched route template, header
+/// names are lowercase.
+#[derive(Debug, Clone)]
+pub struct RouteRequest {
+    pub format: PayloadFormat,
+    pub method: HttpMethod,
+    /// Request path, without the router base path nor the stage
+    pub path: String,
+    /// Template of the matched route, e.g. `/projects/{project}/stats`
+    pub resource: String,
+    pub path_parameters: HashMap<String, String>,
+    pub query_parameters: HashMap<String, String>,
+    pub headers: HashMap<String, String>,
+    pub cookies: Vec<String>,
+    /// Decoded body, base64 encoded bodies included
+    pub body: Option<String>,
+    pub request_id: Option<String>,
+    pub source_ip: Option<String>,
+}
+impl From<ApiGatewayRequest> for RouteRequest {
+    fn from(request: ApiGatewayRequest) -> Self {
+        let format = request.format();
+        match request {
+            ApiGatewayRequest::V1(request) => Self {
+                format,
+                method: HttpMethod::from_str(&request.http_method).unwrap(),
+                path: request.path,
+                resource: request.resource.unwrap_or_default(),
+                path_parameters: request.path_parameters.unwrap_or_default(),
+                query_parameters: request.query_string_parameters.unwrap_or_default(),
+                headers: lowercase_keys(request.headers.unwrap_or_default()),
+                cookies: Vec::new(),
+                body: decode_body(request.body, request.is_base64_encoded),
+                request_id: request.request_context.request_id,
+                source_ip: request.request_context.identity.source_ip,
+            },
+            ApiGatewayRequest::V2(request) => {
+                let path = request.path();
+                let resource = request.resource().unwrap_or_default();
+                Self {
+                    format,
+                    method: HttpMethod::from_str(&request.request_context.http.method).unwrap(),
+                    path,
+                    resource,
+                    path_parameters: request.path_parameters.unwrap_or_default(),
+                    query_parameters: request.query_string_parameters.unwrap_or_default(),
+                    headers: lowercase_keys(request.headers.unwrap_or_default()),
+                    cookies: request.cookies.unwrap_or_default(),
+                    body: decode_body(request.body, request.is_base64_encoded),
+                    request_id: request.request_context.request_id,
+                    source_ip: request.request_context.http.source_ip,
+                }
+            }
+        }
+    }
+}
+impl RouteRequest {
+    pub fn path_parameter(&self, key: &str) -> Result<String, RequestError> {
+        match self.path_parameters.get(key) {
+            Some(value) if !value.is_empty() => Ok(value.clone()),
+            _ => Err(RequestError::path_parameter(key.to_string())),
+        }
+    }
+
+    /// Query string parameters are optional, missing and empty values are `None`
+    pub fn query_parameter(&self, key: &str) -> Option<String> {
+        self.query_parameters
+            .get(key)
+            .filter(|value| !value.is_empty())
+            .cloned()
+    }
+
+    /// Filters, sort and fields asked to a list endpoint, see `ListQuery::parse`
+    pub fn list_query(
+        &self,
+        schema: &'static [ListField],
+        reserved: &[&str],
+    ) -> Result<ListQuery, RequestError> {
+        ListQuery::parse(&self.query_parameters, schema, reserved)
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_lowercase())
+            .map(|value| value.as_str())
+    }
+
+    /// Checks the `If-Match` header against the current entity tag of the
+    /// resource, requests without the header always match
+    pub fn if_match(&self, etag: &str) -> Result<(), RequestError> {
+        match self.header("if-match") {
+            Some(header) if !etag_matches(header, etag) => Err(RouterError::precondition_failed()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Value of the `Idempotency-Key` header, none when the request was not
+    /// sent with one
+    pub fn idempotency_key(&self) -> Result<Option<String>, RequestError> {
+        match self.header("idempotency-key").map(str::trim) {
+            Some(key) if key.is_empty() || key.len() > IDEMPOTENCY_KEY_MAX_LENGTH => {
+                Err(IdempotencyKeyError::invalid())
+            }
+            Some(key) => Ok(Some(key.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    pub fn body<'a, T: Deserializable<'a>>(&'a self) -> Result<T, RequestError> {
+        let body = match self.body.as_deref() {
+            Some(body) => body,
+            None => {
+                return Err(CommonError::schema_compliant(
+                    "Body payload not compliant: missing body".to_string(),
+                ))
+            }
+        };
+        serde_json::from_str::<T>(body).map_err(|err| {
+            CommonError::schema_compliant(format!("Body payload not compliant: {}", err))
+        })
+    }
+}
+
+fn lowercase_keys(map: HashMap<String, String>) -> HashMap<String, String> {
+    map.into_iter()
+        .map(|(key, value)| (key.to_lowercase(), value))
+        .collect()
+}
+
+/// Binary media types reach the lambda base64 encoded, undecodable bodies are
+/// kept as is for the payload parsing to report them
+fn decode_body(body: Option<String>, is_base64_encoded: bool) -> Option<String> {
+    match (body, is_base64_encoded) {
+        (Some(body), true) => match base64::decode(&body)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+        {
+            Some(decoded) => Some(decoded),
+            None => Some(body),
+        },
+        (body, _) => body,
+    }
+}
+
+/* Router Error */
+pub struct RouterError;
+impl RouterError {
+    pub fn route_not_found(path: &str) -> RequestError {
+        ApiError::RouteNotFound.error(format!("No route matches {}", path))
+    }
+
+    pub fn method_not_allowed(method: HttpMethod, allowed: &[HttpMethod]) -> RequestError {
+        ApiError::MethodNotAllowed.error(format!(
+            "Method {} not allowed, expected one of {}",
+            method,
+            allowed
+                .iter()
+                .map(|method| method.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        ))
+    }
+
+    pub fn unauthorized() -> RequestError {
+        ApiError::Unauthorized.error("Missing or invalid API key".to_string())
+    }
+
+    pub fn precondition_failed() -> RequestError {
+        ApiError::PreconditionFailed
+            .error("The resource changed since it was read, get it again and retry".to_string())
+    }
+}
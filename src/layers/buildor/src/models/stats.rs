@@ -0,0 +1,496 @@
+use chrono::{DateTime, Duration, Utc};
+use serde_derive::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+use super::codebuild::{BuildPhase, BuildPhaseStatus};
+use super::outbox::{BuildOutcome, PhaseDuration};
+use super::project_deployment::ProjectDeploymentStatus;
+
+pub const DEFAULT_STATS_WINDOW_DAYS: i64 = 30;
+pub const MAX_STATS_WINDOW_DAYS: i64 = 365;
+
+/// Upper bounds of the duration histogram buckets, in seconds. CodeBuild stops
+/// builds after 8 hours, longer durations fall in an extra overflow bucket.
+pub const DURATION_BUCKETS_SECONDS: [i64; 16] = [
+    30, 60, 120, 180, 300, 450, 600, 900, 1200, 1800, 2700, 3600, 5400, 7200, 14400, 28800,
+];
+
+/// Day a build is counted in, sortable as a string
+pub fn stats_day(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%d").to_string()
+}
+
+/// Number of days the statistics cover, e.g. `30d`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct StatsWindow {
+    pub days: i64,
+}
+impl StatsWindow {
+    /// Parses the `window` query parameter, returning the reason it is invalid
+    pub fn parse(window: Option<String>) -> Result<Self, String> {
+        let window = match window {
+            Some(window) => window,
+            None => {
+                return Ok(Self {
+                    days: DEFAULT_STATS_WINDOW_DAYS,
+                })
+            }
+        };
+        let days = match window.strip_suffix('d').map(|days| days.parse::<i64>()) {
+            Some(Ok(days)) => days,
+            _ => return Err(format!("Invalid window \"{}\", expected e.g. 30d", window)),
+        };
+        if !(1..=MAX_STATS_WINDOW_DAYS).contains(&days) {
+            return Err(format!(
+                "Window must be between 1d and {}d",
+                MAX_STATS_WINDOW_DAYS
+            ));
+        }
+
+        Ok(Self { days })
+    }
+
+    /// First and last day of the window ending at `now`, both included
+    pub fn range(&self, now: DateTime<Utc>) -> (String, String) {
+        (
+            stats_day(now - Duration::days(self.days - 1)),
+            stats_day(now),
+        )
+    }
+}
+impl fmt::Display for StatsWindow {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str(format!("{}d", self.days).as_str())
+    }
+}
+
+/// Mean and 95th percentile of a duration
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DurationSummary {
+    pub count: i64,
+    #[serde(rename(serialize = "meanSeconds"))]
+    pub mean_seconds: f64,
+    /// Upper bound of the histogram bucket holding the 95th percentile
+    #[serde(rename(serialize = "p95Seconds"))]
+    pub p95_seconds: Option<i64>,
+}
+
+/// Samples, total and histogram of a duration
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DurationCounters {
+    pub count: i64,
+    pub sum: i64,
+    /// One counter per bucket of `DURATION_BUCKETS_SECONDS` plus the overflow one
+    pub histogram: Vec<i64>,
+}
+impl DurationCounters {
+    pub fn bucket(seconds: i64) -> usize {
+        DURATION_BUCKETS_SECONDS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(DURATION_BUCKETS_SECONDS.len())
+    }
+
+    pub fn add_to_bucket(&mut self, bucket: usize, count: i64) {
+        if bucket > DURATION_BUCKETS_SECONDS.len() {
+            return;
+        }
+        if self.histogram.len() <= bucket {
+            self.histogram.resize(bucket + 1, 0);
+        }
+        self.histogram[bucket] += count;
+    }
+
+    pub fn merge(&mut self, other: &DurationCounters) {
+        self.count += other.count;
+        self.sum += other.sum;
+        for (bucket, count) in other.histogram.iter().enumerate() {
+            self.add_to_bucket(bucket, *count);
+        }
+    }
+
+    /// Smallest bucket bound under which `percentile` of the samples fall,
+    /// None for the overflow bucket or without histogram
+    pub fn percentile(&self, percentile: f64) -> Option<i64> {
+        let total: i64 = self.histogram.iter().sum();
+        if total == 0 {
+            return None;
+        }
+        let rank = (total as f64 * percentile).ceil() as i64;
+        let mut seen = 0;
+        for (bucket, count) in self.histogram.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return DURATION_BUCKETS_SECONDS.get(bucket).copied();
+            }
+        }
+
+        None
+    }
+
+    pub fn summary(&self) -> Option<DurationSummary> {
+        match self.count {
+            0 => None,
+            count => Some(DurationSummary {
+                count,
+                mean_seconds: self.sum as f64 / count as f64,
+                p95_seconds: self.percentile(0.95),
+            }),
+        }
+    }
+}
+
+/// Finished build as counted in the daily statistics
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildSample {
+    pub succeeded: bool,
+    pub duration_seconds: Option<i64>,
+    pub phases: Vec<PhaseDuration>,
+    /// First phase that did not succeed
+    pub failing_phase: Option<String>,
+    /// Time since the first failure of the environment, for the success ending it
+    pub recovery_seconds: Option<i64>,
+}
+impl BuildSample {
+    pub fn new(
+        status: ProjectDeploymentStatus,
+        outcome: &BuildOutcome,
+        recovery_seconds: Option<i64>,
+    ) -> Self {
+        let succeeded = status == ProjectDeploymentStatus::Succeeded;
+        let failing_phase = match succeeded {
+            true => None,
+            false => outcome
+                .phases
+                .iter()
+                .find(|phase| {
+                    phase.status.as_deref() != Some(&BuildPhaseStatus::Succeeded.to_string())
+                })
+                .map(|phase| phase.phase.clone()),
+        };
+
+        Self {
+            succeeded,
+            duration_seconds: outcome.duration_seconds,
+            phases: outcome.phases.clone(),
+            failing_phase,
+            recovery_seconds,
+        }
+    }
+
+    /// Counters of the daily statistics item the sample increments
+    pub fn increments(&self) -> Vec<(String, i64)> {
+        let mut increments = vec![("builds".to_string(), 1)];
+        if let Some(duration) = self.duration_seconds {
+            increments.push(("duration_count".to_string(), 1));
+            increments.push(("duration_sum".to_string(), duration));
+            increments.push((
+                format!("duration_hist_{}", DurationCounters::bucket(duration)),
+                1,
+            ));
+        }
+        for phase in self.phases.iter() {
+            increments.push((format!("phase_count_{}", phase.phase), 1));
+            increments.push((format!("phase_sum_{}", phase.phase), phase.duration_seconds));
+            increments.push((
+                format!(
+                    "phase_hist_{}_{}",
+                    phase.phase,
+                    DurationCounters::bucket(phase.duration_seconds)
+                ),
+                1,
+            ));
+        }
+        increments.append(&mut self.status_increments(1));
+
+        increments
+    }
+
+    /// Counters that depend on the final status, a `sign` of -1 takes them back
+    pub fn status_increments(&self, sign: i64) -> Vec<(String, i64)> {
+        let mut increments = Vec::new();
+        match self.succeeded {
+            true => increments.push(("succeeded".to_string(), sign)),
+            false => increments.push(("failed".to_string(), sign)),
+        }
+        if let Some(phase) = self.failing_phase.as_ref() {
+            increments.push((format!("failed_phase_{}", phase), sign));
+        }
+        if let Some(recovery) = self.recovery_seconds {
+            increments.push(("recovery_count".to_string(), sign));
+            increments.push(("recovery_sum".to_string(), sign * recovery));
+        }
+
+        increments
+    }
+}
+
+/// Final status a deployment was counted with. Build state events may correct
+/// a final status, the deployment then moves to the counters of the new one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CountedBuild {
+    pub deployment_uuid: String,
+    pub status: String,
+    /// Day of the counters the build was added to
+    pub day: String,
+    pub failing_phase: Option<String>,
+    pub recovery_seconds: Option<i64>,
+    /// Start of the incident the build closed, restored if it turns out failed
+    pub failing_since: Option<i64>,
+}
+
+/// Change of the failure incident of an environment
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IncidentChange {
+    /// Opens the incident at the timestamp, unless it already is open
+    Open(i64),
+    /// Closes the incident opened at the timestamp
+    Close(i64),
+}
+
+/// Writes counting a deployment that reached a final status, they are applied
+/// together so a retry never counts the deployment twice
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatsUpdate {
+    pub increments: Vec<(String, i64)>,
+    pub counted: CountedBuild,
+    /// Status the deployment was counted with until now, None the first time
+    pub previous_status: Option<String>,
+    pub incident: Option<IncidentChange>,
+}
+impl StatsUpdate {
+    /// None when the deployment is already counted with `status`
+    pub fn new(
+        deployment_uuid: String,
+        status: ProjectDeploymentStatus,
+        outcome: &BuildOutcome,
+        finished_at: DateTime<Utc>,
+        previous: Option<&CountedBuild>,
+        failing_since: Option<DateTime<Utc>>,
+    ) -> Option<Self> {
+        if previous.is_some_and(|previous| previous.status == status.to_string()) {
+            return None;
+        }
+
+        let (recovery_seconds, incident) = match (status, failing_since) {
+            (ProjectDeploymentStatus::Succeeded, Some(since)) => (
+                Some((finished_at - since).num_seconds().max(0)),
+                Some(IncidentChange::Close(since.timestamp())),
+            ),
+            (ProjectDeploymentStatus::Succeeded, None) => (None, None),
+            // A success corrected to a failure opens again the incident it closed
+            _ => (
+                None,
+                Some(IncidentChange::Open(
+                    previous
+                        .and_then(|previous| previous.failing_since)
+                        .unwrap_or_else(|| finished_at.timestamp()),
+                )),
+            ),
+        };
+        let sample = BuildSample::new(status, outcome, recovery_seconds);
+
+        // Durations and phases do not depend on the status, a correction only
+        // moves the deployment between the status counters of its day
+        let (day, increments) = match previous {
+            Some(previous) => {
+                let counted = BuildSample {
+                    succeeded: previous.status == ProjectDeploymentStatus::Succeeded.to_string(),
+                    duration_seconds: None,
+                    phases: Vec::new(),
+                    failing_phase: previous.failing_phase.clone(),
+                    recovery_seconds: previous.recovery_seconds,
+                };
+                let mut increments = counted.status_increments(-1);
+                increments.append(&mut sample.status_increments(1));
+                (previous.day.clone(), increments)
+            }
+            None => (stats_day(finished_at), sample.increments()),
+        };
+
+        Some(Self {
+            increments,
+            counted: CountedBuild {
+                deployment_uuid,
+                status: status.to_string(),
+                day,
+                failing_phase: sample.failing_phase.clone(),
+                recovery_seconds,
+                failing_since: match incident {
+                    Some(IncidentChange::Close(since)) => Some(since),
+                    _ => None,
+                },
+            },
+            previous_status: previous.map(|previous| previous.status.clone()),
+            incident,
+        })
+    }
+}
+
+/// Counters of a project for one day, incremented by every finished build
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DailyStats {
+    pub project_uuid: String,
+    pub day: String,
+    pub builds: i64,
+    pub succeeded: i64,
+    pub failed: i64,
+    pub duration: DurationCounters,
+    pub phases: BTreeMap<String, DurationCounters>,
+    pub failed_phases: BTreeMap<String, i64>,
+    pub recovery: DurationCounters,
+}
+impl DailyStats {
+    /// Reads the counters written by `BuildSample::increments`, unknown ones are ignored
+    pub fn from_counters(
+        project_uuid: String,
+        day: String,
+        counters: &HashMap<String, i64>,
+    ) -> Self {
+        let mut stats = Self {
+            project_uuid,
+            day,
+            ..Self::default()
+        };
+        for (name, value) in counters.iter() {
+            let value = *value;
+            match name.as_str() {
+                "builds" => stats.builds = value,
+                "succeeded" => stats.succeeded = value,
+                "failed" => stats.failed = value,
+                "duration_count" => stats.duration.count = value,
+                "duration_sum" => stats.duration.sum = value,
+                "recovery_count" => stats.recovery.count = value,
+                "recovery_sum" => stats.recovery.sum = value,
+                name => {
+                    if let Some(bucket) = name.strip_prefix("duration_hist_") {
+                        if let Ok(bucket) = bucket.parse() {
+                            stats.duration.add_to_bucket(bucket, value);
+                        }
+                    } else if let Some(phase) = name.strip_prefix("phase_count_") {
+                        stats.phases.entry(phase.to_string()).or_default().count = value;
+                    } else if let Some(phase) = name.strip_prefix("phase_sum_") {
+                        stats.phases.entry(phase.to_string()).or_default().sum = value;
+                    } else if let Some(phase_bucket) = name.strip_prefix("phase_hist_") {
+                        if let Some((phase, bucket)) = phase_bucket.rsplit_once('_') {
+                            if let Ok(bucket) = bucket.parse() {
+                                stats
+                                    .phases
+                                    .entry(phase.to_string())
+                                    .or_default()
+                                    .add_to_bucket(bucket, value);
+                            }
+                        }
+                    } else if let Some(phase) = name.strip_prefix("failed_phase_") {
+                        stats.failed_phases.insert(phase.to_string(), value);
+                    }
+                }
+            }
+        }
+
+        stats
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PhaseStats {
+    pub phase: String,
+    #[serde(flatten)]
+    pub duration: DurationSummary,
+}
+
+/// Statistics of a project over a window, summed from its daily statistics
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ProjectStats {
+    pub project: String,
+    pub window: String,
+    pub from: String,
+    pub to: String,
+    pub builds: i64,
+    pub succeeded: i64,
+    pub failed: i64,
+    /// Succeeded builds over finished ones, None without builds
+    #[serde(rename(serialize = "successRate"))]
+    pub success_rate: Option<f64>,
+    pub duration: Option<DurationSummary>,
+    /// Phases in build order
+    pub phases: Vec<PhaseStats>,
+    #[serde(rename(serialize = "mostCommonFailingPhase"))]
+    pub most_common_failing_phase: Option<String>,
+    /// Mean time between the first failure of an environment and the next success
+    #[serde(rename(serialize = "meanTimeToRecoverySeconds"))]
+    pub mean_time_to_recovery_seconds: Option<f64>,
+    pub recoveries: i64,
+}
+impl ProjectStats {
+    pub fn aggregate(
+        project: String,
+        window: StatsWindow,
+        now: DateTime<Utc>,
+        days: &[DailyStats],
+    ) -> Self {
+        let (from, to) = window.range(now);
+        let mut total = DailyStats::default();
+        for day in days.iter().filter(|day| day.day >= from && day.day <= to) {
+            total.builds += day.builds;
+            total.succeeded += day.succeeded;
+            total.failed += day.failed;
+            total.duration.merge(&day.duration);
+            total.recovery.merge(&day.recovery);
+            for (phase, counters) in day.phases.iter() {
+                total
+                    .phases
+                    .entry(phase.clone())
+                    .or_default()
+                    .merge(counters);
+            }
+            for (phase, count) in day.failed_phases.iter() {
+                *total.failed_phases.entry(phase.clone()).or_default() += count;
+            }
+        }
+
+        let finished = total.succeeded + total.failed;
+        let mut phases: Vec<PhaseStats> = total
+            .phases
+            .iter()
+            .filter_map(|(phase, counters)| {
+                counters.summary().map(|duration| PhaseStats {
+                    phase: phase.clone(),
+                    duration,
+                })
+            })
+            .collect();
+        phases.sort_by_key(|stats| BuildPhase::from_str(&stats.phase).unwrap() as usize);
+        // Ties go to the earliest phase
+        let most_common_failing_phase = total
+            .failed_phases
+            .iter()
+            .filter(|(_, count)| **count > 0)
+            .max_by_key(|(phase, count)| (**count, -(BuildPhase::from_str(phase).unwrap() as i64)))
+            .map(|(phase, _)| phase.clone());
+
+        Self {
+            project,
+            window: window.to_string(),
+            from,
+            to,
+            builds: total.builds,
+            succeeded: total.succeeded,
+            failed: total.failed,
+            success_rate: match finished {
+                0 => None,
+                finished => Some(total.succeeded as f64 / finished as f64),
+            },
+            duration: total.duration.summary(),
+            phases,
+            most_common_failing_phase,
+            mean_time_to_recovery_seconds: total
+                .recovery
+                .summary()
+                .map(|recovery| recovery.mean_seconds),
+            recoveries: total.recovery.count,
+        }
+    }
+}
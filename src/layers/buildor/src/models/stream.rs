@@ -0,0 +1,250 @@
+use aws_sdk_dynamodb::model::AttributeValue;
+use error_stack::{Context, Report};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StreamEventName {
+    Insert,  // "INSERT"
+    Modify,  // "MODIFY"
+    Remove,  // "REMOVE"
+    Unknown, // "UNKNOWN", (custom value used when parsing from/to string/enum)
+}
+impl FromStr for StreamEventName {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<StreamEventName, ()> {
+        match String::from(input) {
+            insert if insert == StreamEventName::Insert.to_string() => Ok(StreamEventName::Insert),
+            modify if modify == StreamEventName::Modify.to_string() => Ok(StreamEventName::Modify),
+            remove if remove == StreamEventName::Remove.to_string() => Ok(StreamEventName::Remove),
+            _ => Ok(StreamEventName::Unknown),
+        }
+    }
+}
+impl fmt::Display for StreamEventName {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamEventName::Insert => fmt.write_str("INSERT"),
+            StreamEventName::Modify => fmt.write_str("MODIFY"),
+            StreamEventName::Remove => fmt.write_str("REMOVE"),
+            StreamEventName::Unknown => fmt.write_str("UNKNOWN"),
+        }
+    }
+}
+
+/// DynamoDB Streams record as delivered to lambdas, images are decoded into
+/// the attribute values the table parsers expect
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamRecord {
+    pub event_id: String,
+    pub event_name: StreamEventName,
+    /// Name of the table the record comes from
+    pub table_name: String,
+    /// Identifier used to report batch item failures
+    pub sequence_number: String,
+    pub old_image: Option<HashMap<String, AttributeValue>>,
+    pub new_image: Option<HashMap<String, AttributeValue>>,
+}
+impl StreamRecord {
+    pub fn parse(record: &Value) -> Result<StreamRecord, Report<StreamError>> {
+        let event_id = match record.get("eventID").and_then(|value| value.as_str()) {
+            Some(value) => value.to_string(),
+            None => return Err(Report::new(StreamError::new("missing eventID"))),
+        };
+
+        let event_name = match record.get("eventName").and_then(|value| value.as_str()) {
+            Some(value) => StreamEventName::from_str(value).unwrap(),
+            None => return Err(Report::new(StreamError::new("missing eventName"))),
+        };
+
+        let table_name = match record
+            .get("eventSourceARN")
+            .and_then(|value| value.as_str())
+            .and_then(table_name_from_arn)
+        {
+            Some(value) => value,
+            None => return Err(Report::new(StreamError::new("missing eventSourceARN"))),
+        };
+
+        let dynamodb = match record.get("dynamodb") {
+            Some(value) => value,
+            None => return Err(Report::new(StreamError::new("missing dynamodb"))),
+        };
+
+        let sequence_number = match dynamodb
+            .get("SequenceNumber")
+            .and_then(|value| value.as_str())
+        {
+            Some(value) => value.to_string(),
+            None => return Err(Report::new(StreamError::new("missing SequenceNumber"))),
+        };
+
+        let old_image = match dynamodb.get("OldImage") {
+            Some(value) => Some(image_from_json(value)?),
+            None => None,
+        };
+
+        let new_image = match dynamodb.get("NewImage") {
+            Some(value) => Some(image_from_json(value)?),
+            None => None,
+        };
+
+        Ok(StreamRecord {
+            event_id,
+            event_name,
+            table_name,
+            sequence_number,
+            old_image,
+            new_image,
+        })
+    }
+}
+
+/// Table name of a stream arn, `arn:aws:dynamodb:...:table/<name>/stream/<label>`
+fn table_name_from_arn(arn: &str) -> Option<String> {
+    let (_, resource) = arn.split_once(":table/")?;
+    Some(resource.split('/').next()?.to_string())
+}
+
+fn image_from_json(value: &Value) -> Result<HashMap<String, AttributeValue>, Report<StreamError>> {
+    let attributes = match value.as_object() {
+        Some(attributes) => attributes,
+        None => return Err(Report::new(StreamError::new("image is not an object"))),
+    };
+
+    let mut image = HashMap::new();
+    for (name, value) in attributes {
+        image.insert(name.to_string(), attribute_value_from_json(value)?);
+    }
+
+    Ok(image)
+}
+
+/// Decodes the DynamoDB JSON of a stream image, e.g. `{"S": "value"}`. Binary
+/// attributes are not used by any table and are rejected.
+pub fn attribute_value_from_json(value: &Value) -> Result<AttributeValue, Report<StreamError>> {
+    let strings = |value: &Value| -> Result<Vec<String>, Report<StreamError>> {
+        match value.as_array() {
+            Some(items) => Ok(items
+                .iter()
+                .filter_map(|item| item.as_str().map(|item| item.to_string()))
+                .collect()),
+            None => Err(Report::new(StreamError::new("set is not an array"))),
+        }
+    };
+
+    let (kind, inner) = match value.as_object().and_then(|object| object.iter().next()) {
+        Some(attribute) => attribute,
+        None => return Err(Report::new(StreamError::new("empty attribute value"))),
+    };
+    match (kind.as_str(), inner) {
+        ("S", Value::String(value)) => Ok(AttributeValue::S(value.to_string())),
+        ("N", Value::String(value)) => Ok(AttributeValue::N(value.to_string())),
+        ("BOOL", Value::Bool(value)) => Ok(AttributeValue::Bool(*value)),
+        ("NULL", Value::Bool(value)) => Ok(AttributeValue::Null(*value)),
+        ("SS", value) => Ok(AttributeValue::Ss(strings(value)?)),
+        ("NS", value) => Ok(AttributeValue::Ns(strings(value)?)),
+        ("M", value) => Ok(AttributeValue::M(image_from_json(value)?)),
+        ("L", Value::Array(items)) => {
+            let mut values = Vec::new();
+            for item in items {
+                values.push(attribute_value_from_json(item)?);
+            }
+            Ok(AttributeValue::L(values))
+        }
+        (kind, _) => Err(Report::new(StreamError::new(&format!(
+            "unsupported attribute value: {}",
+            kind
+        )))),
+    }
+}
+
+/* Stream Error */
+#[derive(Debug)]
+pub struct StreamError {
+    pub msg: String,
+}
+impl StreamError {
+    pub fn new(message: &str) -> Self {
+        Self {
+            msg: String::from(message),
+        }
+    }
+}
+impl fmt::Display for StreamError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str(format!("Stream error: {}", self.msg).as_str())
+    }
+}
+impl Context for StreamError {}
+
+#[cfg(test)]
+mod stream_tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_stream_records() {
+        let record = json!({
+            "eventID": "c4ca4238a0b923820dcc509a6f75849b",
+            "eventName": "MODIFY",
+            "eventSource": "aws:dynamodb",
+            "eventSourceARN": "arn:aws:dynamodb:us-east-1:123456789012:table/App-Projects/stream/2022-09-01T16:12:29.000",
+            "dynamodb": {
+                "Keys": { "uuid": { "S": "1234" } },
+                "SequenceNumber": "4421584500000000017450439091",
+                "OldImage": {
+                    "uuid": { "S": "1234" },
+                    "build_timeout_minutes": { "N": "15" },
+                    "pinned": { "BOOL": false },
+                    "environments": { "L": [{ "M": { "name": { "S": "production" } } }] },
+                    "tags": { "SS": ["a", "b"] },
+                    "owner": { "NULL": true },
+                },
+                "StreamViewType": "NEW_AND_OLD_IMAGES",
+            },
+        });
+
+        let record = StreamRecord::parse(&record).unwrap();
+        assert_eq!(record.event_name, StreamEventName::Modify);
+        assert_eq!(record.table_name, "App-Projects");
+        assert_eq!(record.sequence_number, "4421584500000000017450439091");
+        assert_eq!(record.new_image, None);
+        let image = record.old_image.unwrap();
+        assert_eq!(image["uuid"], AttributeValue::S("1234".to_string()));
+        assert_eq!(
+            image["build_timeout_minutes"],
+            AttributeValue::N("15".to_string())
+        );
+        assert_eq!(image["pinned"], AttributeValue::Bool(false));
+        assert_eq!(image["owner"], AttributeValue::Null(true));
+        assert_eq!(
+            image["tags"],
+            AttributeValue::Ss(vec!["a".to_string(), "b".to_string()])
+        );
+        let environment = image["environments"].as_l().unwrap()[0].as_m().unwrap();
+        assert_eq!(
+            environment["name"],
+            AttributeValue::S("production".to_string())
+        );
+    }
+
+    #[test]
+    fn fails_on_invalid_records() {
+        match StreamRecord::parse(&json!({ "eventID": "1", "eventName": "INSERT" })) {
+            Err(error) => assert_eq!(error.to_string(), "Stream error: missing eventSourceARN"),
+            _ => assert_eq!("", "Should have panicked but it did not"),
+        }
+
+        match attribute_value_from_json(&json!({ "B": "dGVzdA==" })) {
+            Err(error) => assert_eq!(
+                error.to_string(),
+                "Stream error: unsupported attribute value: B"
+            ),
+            _ => assert_eq!("", "Should have panicked but it did not"),
+        }
+    }
+}
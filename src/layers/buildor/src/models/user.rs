@@ -5,16 +5,37 @@ use chrono::Utc;
 use serde_derive::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use super::api_error::ApiError;
 use super::common::AsDynamoDBAttributeValue;
+use super::list_query::ListField;
 use super::request::RequestError;
+use super::validation::{Rule, Validate, Validator};
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Fields the users can be filtered, sorted and listed by
+pub const USER_LIST_FIELDS: &[ListField] = &[
+    ListField::text("uuid", "uuid"),
+    ListField::text("firstName", "fname"),
+    ListField::text("lastName", "lname"),
+    ListField::text("email", "email"),
+    ListField::text("updatedAt", "updated_at"),
+    ListField::text("createdAt", "created_at"),
+];
+
+/// Emails are compared case insensitively and without surrounding spaces,
+/// they are stored in this form
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct User {
     pub uuid: String,
     #[serde(rename(serialize = "firstName"))]
     pub fname: String,
     #[serde(rename(serialize = "lastName"))]
     pub lname: String,
+    /// Address deployment emails are sent to, "-" when unknown
+    pub email: String,
     #[serde(rename(serialize = "updatedAt"))]
     pub updated_at: String,
     #[serde(rename(serialize = "createdAt"))]
@@ -27,15 +48,138 @@ pub struct UserCreatePayload {
     pub fname: String,
     #[serde(rename(serialize = "lastName"))]
     pub lname: String,
+    pub email: Option<String>,
+}
+impl Validate for UserCreatePayload {
+    fn validate(&self) -> Result<(), RequestError> {
+        Validator::new()
+            .field(
+                "fname",
+                Some(&self.fname),
+                &[Rule::NotBlank, Rule::MaxLength(100)],
+            )
+            .field(
+                "lname",
+                Some(&self.lname),
+                &[Rule::NotBlank, Rule::MaxLength(100)],
+            )
+            .field(
+                "email",
+                self.email.as_deref().map(str::trim),
+                &[Rule::MaxLength(254), Rule::Email],
+            )
+            .finish()
+    }
+}
+
+/// Fields of a user that can be edited, a new email must not belong to
+/// another user
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct UserUpdatePayload {
+    #[serde(rename(serialize = "firstName"))]
+    pub fname: Option<String>,
+    #[serde(rename(serialize = "lastName"))]
+    pub lname: Option<String>,
+    pub email: Option<String>,
+}
+impl Validate for UserUpdatePayload {
+    fn validate(&self) -> Result<(), RequestError> {
+        Validator::new()
+            .field(
+                "fname",
+                self.fname.as_deref(),
+                &[Rule::NotBlank, Rule::MaxLength(100)],
+            )
+            .field(
+                "lname",
+                self.lname.as_deref(),
+                &[Rule::NotBlank, Rule::MaxLength(100)],
+            )
+            .field(
+                "email",
+                self.email.as_deref().map(str::trim),
+                &[Rule::MaxLength(254), Rule::Email],
+            )
+            .finish()
+    }
+}
+impl AsDynamoDBAttributeValue for UserUpdatePayload {
+    fn as_hashmap(&self) -> HashMap<String, AttributeValue> {
+        let mut map: HashMap<String, AttributeValue> = HashMap::new();
+        self.fname
+            .as_ref()
+            .and_then(|fname| map.insert("fname".to_string(), AttributeValue::S(fname.to_owned())));
+        self.lname
+            .as_ref()
+            .and_then(|lname| map.insert("lname".to_string(), AttributeValue::S(lname.to_owned())));
+        self.email.as_ref().and_then(|email| {
+            map.insert(
+                "email".to_string(),
+                AttributeValue::S(normalize_email(email)),
+            )
+        });
+
+        map
+    }
+
+    fn as_attr(&self) -> AttributeValue {
+        AttributeValue::M(self.as_hashmap())
+    }
+}
+
+/// Item of the user emails table, the email is the key so an address can only
+/// belong to one user
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserEmail {
+    pub email: String,
+    pub user_uuid: String,
+}
+impl UserEmail {
+    pub fn new(email: &str, user_uuid: &str) -> Self {
+        Self {
+            email: normalize_email(email),
+            user_uuid: user_uuid.to_string(),
+        }
+    }
+}
+impl AsDynamoDBAttributeValue for UserEmail {
+    fn as_hashmap(&self) -> HashMap<String, AttributeValue> {
+        let mut map: HashMap<String, AttributeValue> = HashMap::new();
+        map.insert(
+            "email".to_string(),
+            AttributeValue::S(self.email.to_owned()),
+        );
+        map.insert(
+            "user_uuid".to_string(),
+            AttributeValue::S(self.user_uuid.to_owned()),
+        );
+
+        map
+    }
+
+    fn as_attr(&self) -> AttributeValue {
+        AttributeValue::M(self.as_hashmap())
+    }
 }
 
 impl User {
+    pub fn email(&self) -> Option<&str> {
+        match self.email.as_str() {
+            "-" => None,
+            email => Some(email),
+        }
+    }
+
     pub fn new(payload: UserCreatePayload) -> Self {
         let timestamp = Utc::now().to_rfc3339().to_string();
         User {
             uuid: Uuid::new_v4().to_string(),
             fname: payload.fname,
             lname: payload.lname,
+            email: match payload.email {
+                Some(email) => normalize_email(&email),
+                None => "-".to_string(),
+            },
             updated_at: timestamp.clone(),
             created_at: timestamp,
         }
@@ -53,6 +197,10 @@ impl AsDynamoDBAttributeValue for User {
             "lname".to_string(),
             AttributeValue::S(self.lname.to_owned()),
         );
+        map.insert(
+            "email".to_string(),
+            AttributeValue::S(self.email.to_owned()),
+        );
         map.insert(
             "updated_at".to_string(),
             AttributeValue::S(self.updated_at.to_owned()),
@@ -73,10 +221,67 @@ impl AsDynamoDBAttributeValue for User {
 pub struct UserError;
 impl UserError {
     pub fn creation_failed() -> RequestError {
-        RequestError {
-            code: "USE00".to_string(),
-            message: "Create User Error".to_string(),
-            details: "User creation failed, try again".to_string(),
-        }
+        ApiError::UserCreationFailed.error("User creation failed, try again".to_string())
+    }
+
+    pub fn email_taken(email: &str) -> RequestError {
+        ApiError::UserEmailTaken.error(format!(
+            "Email {} is already registered to another user",
+            normalize_email(email)
+        ))
+    }
+
+    pub fn update_failed() -> RequestError {
+        ApiError::UserUpdateFailed.error("User update failed, try again".to_string())
+    }
+
+    pub fn delete_failed() -> RequestError {
+        ApiError::UserDeleteFailed.error("User deletion failed, try again".to_string())
+    }
+}
+
+#[cfg(test)]
+mod user_tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn normalizes_emails() {
+        assert_eq!(
+            normalize_email("  John.Doe@Example.COM "),
+            "john.doe@example.com"
+        );
+
+        let payload: UserCreatePayload = serde_json::from_value(
+            json!({ "fname": "John", "lname": "Doe", "email": " John@Example.com" }),
+        )
+        .unwrap();
+        assert!(payload.validate().is_ok());
+        let user = User::new(payload);
+        assert_eq!(user.email(), Some("john@example.com"));
+        assert_eq!(
+            UserEmail::new(&user.email, &user.uuid).as_hashmap()["email"],
+            AttributeValue::S("john@example.com".to_string())
+        );
+    }
+
+    #[test]
+    fn maps_only_the_fields_sent() {
+        let payload: UserUpdatePayload =
+            serde_json::from_value(json!({ "email": "JANE@example.com" })).unwrap();
+        assert!(payload.validate().is_ok());
+        let map = payload.as_hashmap();
+        assert_eq!(map.len(), 1);
+        assert_eq!(
+            map["email"],
+            AttributeValue::S("jane@example.com".to_string())
+        );
+
+        let payload: UserUpdatePayload =
+            serde_json::from_value(json!({ "fname": " ", "email": "jane" })).unwrap();
+        assert_eq!(
+            payload.validate().unwrap_err().details,
+            "Invalid fields: fname, email"
+        );
     }
 }
@@ -0,0 +1,316 @@
+use serde_derive::Serialize;
+use uuid::Uuid;
+
+use super::common::CommonError;
+use super::request::RequestError;
+
+/// Rule a payload field must follow, optional fields are only checked when set
+#[derive(Debug, Clone, Copy)]
+pub enum Rule {
+    NotBlank,                       // "VLE00"
+    MaxLength(usize),               // "VLE01"
+    Url(&'static [&'static str]),   // "VLE02", allowed schemes
+    RelativePath,                   // "VLE03", stays inside the repository
+    Email,                          // "VLE04"
+    Range(i64, i64),                // "VLE05", inclusive bounds, numbers only
+    OneOf(&'static [&'static str]), // "VLE06", case insensitive
+    Uuid,                           // "VLE07"
+    Slug,                           // "VLE08", lowercase letters, digits and dashes
+    Branch,                         // "VLE10", git branch name
+}
+impl Rule {
+    fn check(&self, value: &str) -> Result<(), (&'static str, String)> {
+        let valid = match self {
+            Rule::NotBlank => !value.trim().is_empty(),
+            Rule::MaxLength(max) => value.chars().count() <= *max,
+            Rule::Url(schemes) => schemes.iter().any(|scheme| {
+                value
+                    .strip_prefix(scheme)
+                    .and_then(|rest| rest.strip_prefix("://"))
+                    .map(|host| !host.is_empty() && !host.contains(char::is_whitespace))
+                    .unwrap_or(false)
+            }),
+            Rule::RelativePath => {
+                !value.starts_with('/')
+                    && !value.contains('\\')
+                    && !value.split('/').any(|segment| segment == "..")
+            }
+            Rule::Email => match value.split_once('@') {
+                Some((local, domain)) => {
+                    !local.is_empty()
+                        && !domain.contains('@')
+                        && domain.contains('.')
+                        && !domain.starts_with('.')
+                        && !domain.ends_with('.')
+                        && !value.contains(char::is_whitespace)
+                }
+                None => false,
+            },
+            Rule::Range(_, _) => true,
+            Rule::OneOf(choices) => choices
+                .iter()
+                .any(|choice| choice.eq_ignore_ascii_case(value)),
+            Rule::Uuid => Uuid::parse_str(value).is_ok(),
+            Rule::Slug => {
+                !value.is_empty()
+                    && value
+                        .chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+            }
+            Rule::Branch => is_branch_name(value),
+        };
+
+        match valid {
+            true => Ok(()),
+            false => Err(self.violation()),
+        }
+    }
+
+    fn check_number(&self, value: i64) -> Result<(), (&'static str, String)> {
+        match self {
+            Rule::Range(min, max) if value < *min || value > *max => Err(self.violation()),
+            _ => Ok(()),
+        }
+    }
+
+    fn violation(&self) -> (&'static str, String) {
+        match self {
+            Rule::NotBlank => ("VLE00", "Must not be blank".to_string()),
+            Rule::MaxLength(max) => ("VLE01", format!("Must be at most {} characters", max)),
+            Rule::Url(schemes) => ("VLE02", format!("Must be a {} url", schemes.join(" or "))),
+            Rule::RelativePath => (
+                "VLE03",
+                "Must be a relative path inside the repository".to_string(),
+            ),
+            Rule::Email => ("VLE04", "Must be an email address".to_string()),
+            Rule::Range(min, max) => ("VLE05", format!("Must be between {} and {}", min, max)),
+            Rule::OneOf(choices) => ("VLE06", format!("Must be one of {}", choices.join(", "))),
+            Rule::Uuid => ("VLE07", "Must be a uuid".to_string()),
+            Rule::Slug => (
+                "VLE08",
+                "Must only contain lowercase letters, digits and dashes".to_string(),
+            ),
+            Rule::Branch => ("VLE10", "Must be a valid git branch name".to_string()),
+        }
+    }
+}
+
+/// Rules of `git check-ref-format --branch`, names starting with a dash are
+/// refused too so they can't be read as an option
+fn is_branch_name(value: &str) -> bool {
+    !value.is_empty()
+        && value != "@"
+        && !value.starts_with('-')
+        && !value.ends_with('.')
+        && !value.contains("..")
+        && !value.contains("@{")
+        && !value
+            .chars()
+            .any(|c| c.is_ascii_control() || c.is_whitespace() || "~^:?*[\\".contains(c))
+        && value
+            .split('/')
+            .all(|part| !part.is_empty() && !part.starts_with('.') && !part.ends_with(".lock"))
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub code: String,
+    pub message: String,
+}
+
+/// Collects the violations of every field of a payload, the first violated
+/// rule of a field is the only one reported for it
+#[derive(Debug, Default)]
+pub struct Validator {
+    errors: Vec<FieldError>,
+}
+impl Validator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn field(mut self, field: &str, value: Option<&str>, rules: &[Rule]) -> Self {
+        if let Some(value) = value {
+            if let Some((code, message)) = rules.iter().find_map(|rule| rule.check(value).err()) {
+                self.push(field, code, message);
+            }
+        }
+        self
+    }
+
+    pub fn number(mut self, field: &str, value: Option<i64>, rules: &[Rule]) -> Self {
+        if let Some(value) = value {
+            if let Some((code, message)) =
+                rules.iter().find_map(|rule| rule.check_number(value).err())
+            {
+                self.push(field, code, message);
+            }
+        }
+        self
+    }
+
+    /// Checks of a field that do not fit a rule, the error is the message
+    pub fn check(mut self, field: &str, result: Result<(), String>) -> Self {
+        if let Err(message) = result {
+            self.push(field, "VLE09", message);
+        }
+        self
+    }
+
+    /// Validates the nested payloads of a list field, e.g. `environments[0].name`
+    pub fn each<T: Validate>(mut self, field: &str, items: &[T]) -> Self {
+        for (index, item) in items.iter().enumerate() {
+            if let Err(error) = item.validate() {
+                self.errors
+                    .extend(error.errors.into_iter().map(|error| FieldError {
+                        field: format!("{}[{}].{}", field, index, error.field),
+                        ..error
+                    }));
+            }
+        }
+        self
+    }
+
+    pub fn finish(self) -> Result<(), RequestError> {
+        match self.errors.is_empty() {
+            true => Ok(()),
+            false => Err(CommonError::validation(self.errors)),
+        }
+    }
+
+    fn push(&mut self, field: &str, code: &str, message: String) {
+        self.errors.push(FieldError {
+            field: field.to_string(),
+            code: code.to_string(),
+            message,
+        });
+    }
+}
+
+/// Payload with validation rules, checked once it deserialized
+pub trait Validate {
+    fn validate(&self) -> Result<(), RequestError>;
+}
+
+#[cfg(test)]
+mod validation_tests {
+    use super::*;
+    use crate::models::{
+        project::ProjectCreatePayload, project_deployment::ProjectDeploymentCreatePayloadRequest,
+        user::UserCreatePayload,
+    };
+    use serde_json::json;
+
+    fn codes(result: Result<(), RequestError>) -> Vec<(String, String)> {
+        match result {
+            Ok(_) => vec![],
+            Err(error) => error
+                .errors
+                .into_iter()
+                .map(|error| (error.field, error.code))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn reports_every_violated_field() {
+        let result = Validator::new()
+            .field("name", Some(" "), &[Rule::NotBlank, Rule::MaxLength(1)])
+            .field(
+                "repository",
+                Some("ftp://example.com/repo.git"),
+                &[Rule::Url(&["https"])],
+            )
+            .field("owner", None, &[Rule::Uuid])
+            .number("timeout", Some(2), &[Rule::Range(5, 480)])
+            .check("channel", Err("Unknown provider".to_string()))
+            .finish();
+        assert_eq!(
+            codes(result),
+            vec![
+                ("name".to_string(), "VLE00".to_string()),
+                ("repository".to_string(), "VLE02".to_string()),
+                ("timeout".to_string(), "VLE05".to_string()),
+                ("channel".to_string(), "VLE09".to_string()),
+            ]
+        );
+        assert!(Validator::new()
+            .field("name", Some("site"), &[Rule::NotBlank])
+            .finish()
+            .is_ok());
+    }
+
+    #[test]
+    fn validates_payloads() {
+        let payload: ProjectCreatePayload = serde_json::from_value(json!({
+            "name": "",
+            "repository": "ftp://example.com/site.git",
+            "output_folder": "../../etc",
+            "compute_size": "huge",
+            "environments": [
+                { "name": "staging", "secrets": { "NPM_TOKEN": { "type": "vault", "value": "token" } } },
+                { "name": "Prod", "variables": { "1KEY": "value" } },
+            ],
+        }))
+        .unwrap();
+        assert_eq!(
+            codes(payload.validate()),
+            vec![
+                ("name".to_string(), "VLE00".to_string()),
+                ("repository".to_string(), "VLE02".to_string()),
+                ("output_folder".to_string(), "VLE03".to_string()),
+                ("compute_size".to_string(), "VLE06".to_string()),
+                (
+                    "environments[0].secrets.NPM_TOKEN.type".to_string(),
+                    "VLE06".to_string()
+                ),
+                ("environments[1].name".to_string(), "VLE08".to_string()),
+                (
+                    "environments[1].variables.1KEY".to_string(),
+                    "VLE09".to_string()
+                ),
+            ]
+        );
+
+        let payload: ProjectDeploymentCreatePayloadRequest = serde_json::from_value(
+            json!({ "project_uuid": "0a6a5e2c-3f1b-4c41-9a43-6f7c2c1a9b10" }),
+        )
+        .unwrap();
+        assert!(payload.validate().is_ok());
+
+        let payload: UserCreatePayload =
+            serde_json::from_value(json!({ "fname": "John", "lname": " ", "email": "john" }))
+                .unwrap();
+        let error = payload.validate().unwrap_err();
+        assert_eq!(error.code, "CME03");
+        assert_eq!(error.details, "Invalid fields: lname, email");
+    }
+
+    #[test]
+    fn rules() {
+        let valid = |rule: Rule, value: &str| rule.check(value).is_ok();
+        assert!(valid(Rule::Url(&["https", "http"]), "http://example.com"));
+        assert!(!valid(Rule::Url(&["https"]), "https://"));
+        assert!(valid(Rule::RelativePath, "dist/public"));
+        assert!(!valid(Rule::RelativePath, "../../etc"));
+        assert!(!valid(Rule::RelativePath, "/etc"));
+        assert!(!valid(Rule::RelativePath, "dist/../../etc"));
+        assert!(valid(Rule::Email, "john.doe@example.com"));
+        assert!(!valid(Rule::Email, "john@example"));
+        assert!(!valid(Rule::Email, "john doe@example.com"));
+        assert!(valid(Rule::OneOf(&["small", "large"]), "LARGE"));
+        assert!(valid(Rule::Uuid, "0a6a5e2c-3f1b-4c41-9a43-6f7c2c1a9b10"));
+        assert!(!valid(Rule::Uuid, "project"));
+        assert!(valid(Rule::Slug, "staging-2"));
+        assert!(!valid(Rule::Slug, "Staging"));
+        assert!(valid(Rule::Branch, "feature/login-v2"));
+        assert!(!valid(Rule::Branch, "main; rm -rf /"));
+        assert!(!valid(Rule::Branch, "--upload-pack=touch"));
+        assert!(!valid(Rule::Branch, "feature/"));
+        assert!(!valid(Rule::Branch, "release..1"));
+        assert!(!valid(Rule::Branch, "main.lock"));
+        assert!(!valid(Rule::MaxLength(3), "four"));
+        assert!(Rule::Range(5, 480).check_number(480).is_ok());
+    }
+}
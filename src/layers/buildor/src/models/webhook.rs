@@ -0,0 +1,484 @@
+use aws_sdk_dynamodb::model::AttributeValue;
+use chrono::{DateTime, Duration, Utc};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use super::api_error::ApiError;
+use super::common::AsDynamoDBAttributeValue;
+use super::list_query::ListField;
+use super::project_deployment::ProjectDeploymentStatus;
+use super::request::RequestError;
+use super::validation::{Rule, Validate, Validator};
+
+/// Fields the webhooks can be filtered, sorted and listed by, the secret is
+/// never listed
+pub const WEBHOOK_LIST_FIELDS: &[ListField] = &[
+    ListField::text("uuid", "uuid"),
+    ListField::text("projectUuid", "project_uuid"),
+    ListField::text("userUuid", "user_uuid"),
+    ListField::text("url", "url"),
+    ListField::nested("events", "events"),
+    ListField::text("updatedAt", "updated_at"),
+    ListField::text("createdAt", "created_at"),
+];
+
+/// Fields the webhook deliveries can be filtered, sorted and listed by
+pub const WEBHOOK_DELIVERY_LIST_FIELDS: &[ListField] = &[
+    ListField::text("uuid", "uuid"),
+    ListField::text("webhookUuid", "webhook_uuid"),
+    ListField::text("event", "event"),
+    ListField::text("payload", "payload"),
+    ListField::text("status", "status"),
+    ListField::number("attempts", "attempts"),
+    ListField::number("responseStatus", "response_status"),
+    ListField::text("lastError", "last_error"),
+    ListField::text("nextAttemptAt", "next_attempt_at"),
+    ListField::text("updatedAt", "updated_at"),
+    ListField::text("createdAt", "created_at"),
+];
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum WebhookEvent {
+    DeploymentStarted,   // "deployment.started"
+    DeploymentSucceeded, // "deployment.succeeded"
+    DeploymentFailed,    // "deployment.failed"
+    DeploymentCancelled, // "deployment.cancelled"
+    Unknown,             // "unknown", (custom value used when parsing from/to string/enum)
+}
+impl WebhookEvent {
+    pub fn all() -> Vec<WebhookEvent> {
+        vec![
+            WebhookEvent::DeploymentStarted,
+            WebhookEvent::DeploymentSucceeded,
+            WebhookEvent::DeploymentFailed,
+            WebhookEvent::DeploymentCancelled,
+        ]
+    }
+
+    /// Event announced when a deployment reaches `status`. A failed build that
+    /// was stopped by hand is a cancellation.
+    pub fn from_status(status: ProjectDeploymentStatus, stopped: bool) -> Option<WebhookEvent> {
+        match status {
+            ProjectDeploymentStatus::InProgress => Some(WebhookEvent::DeploymentStarted),
+            ProjectDeploymentStatus::Succeeded => Some(WebhookEvent::DeploymentSucceeded),
+            ProjectDeploymentStatus::Failed if stopped => Some(WebhookEvent::DeploymentCancelled),
+            ProjectDeploymentStatus::Failed => Some(WebhookEvent::DeploymentFailed),
+            _ => None,
+        }
+    }
+}
+impl FromStr for WebhookEvent {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<WebhookEvent, ()> {
+        match WebhookEvent::all()
+            .into_iter()
+            .find(|event| event.to_string() == input)
+        {
+            Some(event) => Ok(event),
+            None => Ok(WebhookEvent::Unknown),
+        }
+    }
+}
+impl fmt::Display for WebhookEvent {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebhookEvent::DeploymentStarted => fmt.write_str("deployment.started"),
+            WebhookEvent::DeploymentSucceeded => fmt.write_str("deployment.succeeded"),
+            WebhookEvent::DeploymentFailed => fmt.write_str("deployment.failed"),
+            WebhookEvent::DeploymentCancelled => fmt.write_str("deployment.cancelled"),
+            WebhookEvent::Unknown => fmt.write_str("unknown"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Webhook {
+    pub uuid: String,
+    /// Project the webhook listens to, "-" for user webhooks
+    #[serde(rename(serialize = "projectUuid"))]
+    pub project_uuid: String,
+    /// Owner whose projects the webhook listens to, "-" for project webhooks
+    #[serde(rename(serialize = "userUuid"))]
+    pub user_uuid: String,
+    pub url: String,
+    pub events: Vec<String>,
+    /// Only returned once, when the webhook is created
+    #[serde(skip_serializing)]
+    pub secret: String,
+    #[serde(rename(serialize = "updatedAt"))]
+    pub updated_at: String,
+    #[serde(rename(serialize = "createdAt"))]
+    pub created_at: String,
+}
+impl Webhook {
+    pub fn new(payload: WebhookCreatePayload) -> Self {
+        let timestamp = Utc::now().to_rfc3339().to_string();
+        Self {
+            uuid: Uuid::new_v4().to_string(),
+            project_uuid: payload.project_uuid.unwrap_or_else(|| "-".to_string()),
+            user_uuid: payload.user_uuid.unwrap_or_else(|| "-".to_string()),
+            url: payload.url,
+            events: match payload.events {
+                Some(events) if !events.is_empty() => events,
+                _ => WebhookEvent::all()
+                    .iter()
+                    .map(|event| event.to_string())
+                    .collect(),
+            },
+            secret: payload
+                .secret
+                .unwrap_or_else(|| format!("whsec_{}", Uuid::new_v4().simple())),
+            updated_at: timestamp.clone(),
+            created_at: timestamp,
+        }
+    }
+
+    pub fn subscribes(&self, event: WebhookEvent) -> bool {
+        self.events.contains(&event.to_string())
+    }
+}
+impl AsDynamoDBAttributeValue for Webhook {
+    fn as_hashmap(&self) -> HashMap<String, AttributeValue> {
+        let mut map: HashMap<String, AttributeValue> = HashMap::new();
+        map.insert("uuid".to_string(), AttributeValue::S(self.uuid.to_owned()));
+        map.insert(
+            "project_uuid".to_string(),
+            AttributeValue::S(self.project_uuid.to_owned()),
+        );
+        map.insert(
+            "user_uuid".to_string(),
+            AttributeValue::S(self.user_uuid.to_owned()),
+        );
+        map.insert("url".to_string(), AttributeValue::S(self.url.to_owned()));
+        map.insert(
+            "events".to_string(),
+            AttributeValue::L(
+                self.events
+                    .iter()
+                    .map(|event| AttributeValue::S(event.to_owned()))
+                    .collect(),
+            ),
+        );
+        map.insert(
+            "secret".to_string(),
+            AttributeValue::S(self.secret.to_owned()),
+        );
+        map.insert(
+            "updated_at".to_string(),
+            AttributeValue::S(self.updated_at.to_owned()),
+        );
+        map.insert(
+            "created_at".to_string(),
+            AttributeValue::S(self.created_at.to_owned()),
+        );
+
+        map
+    }
+
+    fn as_attr(&self) -> AttributeValue {
+        AttributeValue::M(self.as_hashmap())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WebhookCreatePayload {
+    pub project_uuid: Option<String>,
+    pub user_uuid: Option<String>,
+    pub url: String,
+    pub events: Option<Vec<String>>,
+    pub secret: Option<String>,
+}
+impl Validate for WebhookCreatePayload {
+    fn validate(&self) -> Result<(), RequestError> {
+        // Plain http is only accepted for receivers running on the same machine
+        let schemes: &'static [&'static str] = match is_localhost(&self.url) {
+            true => &["https", "http"],
+            false => &["https"],
+        };
+        let mut validator = Validator::new()
+            .check(
+                "project_uuid",
+                match (&self.project_uuid, &self.user_uuid) {
+                    (Some(_), None) | (None, Some(_)) => Ok(()),
+                    _ => Err("Exactly one of project_uuid or user_uuid is required".to_string()),
+                },
+            )
+            .field("project_uuid", self.project_uuid.as_deref(), &[Rule::Uuid])
+            .field("user_uuid", self.user_uuid.as_deref(), &[Rule::Uuid])
+            .field(
+                "url",
+                Some(&self.url),
+                &[Rule::NotBlank, Rule::MaxLength(2048), Rule::Url(schemes)],
+            )
+            .field(
+                "secret",
+                self.secret.as_deref(),
+                &[Rule::NotBlank, Rule::MaxLength(256)],
+            );
+        for (index, event) in self.events.iter().flatten().enumerate() {
+            validator = validator.check(
+                &format!("events[{}]", index),
+                match WebhookEvent::from_str(event).unwrap() {
+                    WebhookEvent::Unknown => Err(format!("Unknown webhook event: {}", event)),
+                    _ => Ok(()),
+                },
+            );
+        }
+
+        validator.finish()
+    }
+}
+
+/// Whether the host of an http(s) url is the loopback interface
+fn is_localhost(url: &str) -> bool {
+    let authority = url
+        .split_once("://")
+        .map(|(_, rest)| rest)
+        .unwrap_or_default()
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or_default();
+    let host = match authority.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next().unwrap_or_default(),
+        None => authority.split(':').next().unwrap_or_default(),
+    };
+    matches!(host, "localhost" | "127.0.0.1" | "::1")
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum WebhookDeliveryStatus {
+    Pending,   // "PENDING"
+    Succeeded, // "SUCCEEDED"
+    Retrying,  // "RETRYING"
+    Failed,    // "FAILED"
+    Unknown,   // "UNKNOWN", (custom value used when parsing from/to string/enum)
+}
+impl FromStr for WebhookDeliveryStatus {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<WebhookDeliveryStatus, ()> {
+        match String::from(input) {
+            pending if pending == WebhookDeliveryStatus::Pending.to_string() => {
+                Ok(WebhookDeliveryStatus::Pending)
+            }
+            succeeded if succeeded == WebhookDeliveryStatus::Succeeded.to_string() => {
+                Ok(WebhookDeliveryStatus::Succeeded)
+            }
+            retrying if retrying == WebhookDeliveryStatus::Retrying.to_string() => {
+                Ok(WebhookDeliveryStatus::Retrying)
+            }
+            failed if failed == WebhookDeliveryStatus::Failed.to_string() => {
+                Ok(WebhookDeliveryStatus::Failed)
+            }
+            _ => Ok(WebhookDeliveryStatus::Unknown),
+        }
+    }
+}
+impl fmt::Display for WebhookDeliveryStatus {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebhookDeliveryStatus::Pending => fmt.write_str("PENDING"),
+            WebhookDeliveryStatus::Succeeded => fmt.write_str("SUCCEEDED"),
+            WebhookDeliveryStatus::Retrying => fmt.write_str("RETRYING"),
+            WebhookDeliveryStatus::Failed => fmt.write_str("FAILED"),
+            WebhookDeliveryStatus::Unknown => fmt.write_str("UNKNOWN"),
+        }
+    }
+}
+
+/// Exponential backoff between delivery attempts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    pub max_attempts: i32,
+    pub base_delay_seconds: i64,
+    pub max_delay_seconds: i64,
+}
+impl RetryPolicy {
+    pub fn new(max_attempts: i32, base_delay_seconds: i64, max_delay_seconds: i64) -> Self {
+        Self {
+            max_attempts,
+            base_delay_seconds,
+            max_delay_seconds,
+        }
+    }
+
+    /// Delay before the next attempt once `attempts` attempts failed, `None`
+    /// when no attempts are left
+    pub fn next_delay(&self, attempts: i32) -> Option<Duration> {
+        if attempts >= self.max_attempts {
+            return None;
+        }
+        let exponent = (attempts - 1).clamp(0, 30) as u32;
+        let delay = self
+            .base_delay_seconds
+            .saturating_mul(2_i64.saturating_pow(exponent))
+            .min(self.max_delay_seconds);
+
+        Some(Duration::seconds(delay))
+    }
+}
+impl Default for RetryPolicy {
+    /// 1m, 2m, 4m, 8m, ... up to an hour between attempts
+    fn default() -> Self {
+        Self::new(8, 60, 3600)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WebhookDelivery {
+    pub uuid: String,
+    #[serde(rename(serialize = "webhookUuid"))]
+    pub webhook_uuid: String,
+    pub event: String,
+    /// Exact body sent, the signature covers it
+    pub payload: String,
+    pub status: String,
+    pub attempts: i32,
+    #[serde(rename(serialize = "responseStatus"))]
+    pub response_status: Option<i32>,
+    #[serde(rename(serialize = "lastError"))]
+    pub last_error: Option<String>,
+    /// Pending retries sort by this value, "-" when there is nothing left to do
+    #[serde(rename(serialize = "nextAttemptAt"))]
+    pub next_attempt_at: String,
+    #[serde(rename(serialize = "updatedAt"))]
+    pub updated_at: String,
+    #[serde(rename(serialize = "createdAt"))]
+    pub created_at: String,
+}
+impl WebhookDelivery {
+    pub fn new(webhook_uuid: String, event: WebhookEvent, payload: String) -> Self {
+        let timestamp = Utc::now().to_rfc3339().to_string();
+        Self {
+            uuid: Uuid::new_v4().to_string(),
+            webhook_uuid,
+            event: event.to_string(),
+            payload,
+            status: WebhookDeliveryStatus::Pending.to_string(),
+            attempts: 0,
+            response_status: None,
+            last_error: None,
+            next_attempt_at: timestamp.clone(),
+            updated_at: timestamp.clone(),
+            created_at: timestamp,
+        }
+    }
+
+    pub fn status(&self) -> WebhookDeliveryStatus {
+        WebhookDeliveryStatus::from_str(&self.status).unwrap()
+    }
+
+    /// Records the outcome of an attempt: the http status of the response, or
+    /// the error when the endpoint could not be reached
+    pub fn attempted(
+        &mut self,
+        outcome: Result<u16, String>,
+        retry: &RetryPolicy,
+        now: DateTime<Utc>,
+    ) {
+        self.attempts += 1;
+        self.updated_at = now.to_rfc3339();
+        let failure = match outcome {
+            Ok(status) => {
+                self.response_status = Some(status as i32);
+                match status {
+                    200..=299 => None,
+                    _ => Some(format!("Endpoint responded with status {}", status)),
+                }
+            }
+            Err(error) => Some(error),
+        };
+
+        match failure {
+            None => {
+                self.status = WebhookDeliveryStatus::Succeeded.to_string();
+                self.last_error = None;
+                self.next_attempt_at = "-".to_string();
+            }
+            Some(error) => {
+                self.last_error = Some(error);
+                match retry.next_delay(self.attempts) {
+                    Some(delay) => {
+                        self.status = WebhookDeliveryStatus::Retrying.to_string();
+                        self.next_attempt_at = (now + delay).to_rfc3339();
+                    }
+                    None => {
+                        self.status = WebhookDeliveryStatus::Failed.to_string();
+                        self.next_attempt_at = "-".to_string();
+                    }
+                }
+            }
+        }
+    }
+}
+impl AsDynamoDBAttributeValue for WebhookDelivery {
+    fn as_hashmap(&self) -> HashMap<String, AttributeValue> {
+        let mut map: HashMap<String, AttributeValue> = HashMap::new();
+        map.insert("uuid".to_string(), AttributeValue::S(self.uuid.to_owned()));
+        map.insert(
+            "webhook_uuid".to_string(),
+            AttributeValue::S(self.webhook_uuid.to_owned()),
+        );
+        map.insert(
+            "event".to_string(),
+            AttributeValue::S(self.event.to_owned()),
+        );
+        map.insert(
+            "payload".to_string(),
+            AttributeValue::S(self.payload.to_owned()),
+        );
+        map.insert(
+            "status".to_string(),
+            AttributeValue::S(self.status.to_owned()),
+        );
+        map.insert(
+            "attempts".to_string(),
+            AttributeValue::N(format!("{}", self.attempts)),
+        );
+        if let Some(status) = self.response_status {
+            map.insert(
+                "response_status".to_string(),
+                AttributeValue::N(format!("{}", status)),
+            );
+        }
+        if let Some(error) = self.last_error.as_ref() {
+            map.insert(
+                "last_error".to_string(),
+                AttributeValue::S(error.to_owned()),
+            );
+        }
+        map.insert(
+            "next_attempt_at".to_string(),
+            AttributeValue::S(self.next_attempt_at.to_owned()),
+        );
+        map.insert(
+            "updated_at".to_string(),
+            AttributeValue::S(self.updated_at.to_owned()),
+        );
+        map.insert(
+            "created_at".to_string(),
+            AttributeValue::S(self.created_at.to_owned()),
+        );
+
+        map
+    }
+
+    fn as_attr(&self) -> AttributeValue {
+        AttributeValue::M(self.as_hashmap())
+    }
+}
+
+pub struct WebhookError;
+impl WebhookError {
+    pub fn creation_failed() -> RequestError {
+        ApiError::WebhookCreationFailed.error("Webhook creation failed, try again".to_string())
+    }
+
+    pub fn invalid_payload(details: String) -> RequestError {
+        ApiError::WebhookInvalidPayload.error(details)
+    }
+}
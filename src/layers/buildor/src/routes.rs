@@ -0,0 +1,168 @@
+pub mod errors;
+pub mod openapi;
+pub mod project_deployments;
+pub mod projects;
+pub mod root;
+pub mod users;
+pub mod webhooks;
+
+use error_stack::{Report, ResultExt};
+use log::info;
+
+use crate::{
+    handlers::router::Router,
+    models::{handlers::HandlerError, router::HttpMethod},
+    utils::load_env_var,
+};
+
+/// Loads an env var of the API, routes only load the ones they use so that a
+/// lambda serving a single route only needs those
+pub fn env_var(name: &str) -> Result<String, Report<HandlerError>> {
+    let value = load_env_var(name, None).change_context(HandlerError::new(name))?;
+    info!("{}: {}", name, value);
+    Ok(value)
+}
+
+/// Router serving every route of the API from a single lambda
+pub fn api() -> Router {
+    Router::api()
+        .route(HttpMethod::Any, root::PATH_ROOT, root::RootAny)
+        .route(HttpMethod::Get, errors::PATH_ERRORS, errors::ErrorsList)
+        .route(HttpMethod::Get, openapi::PATH_OPENAPI, openapi::OpenApiGet)
+        .route(HttpMethod::Post, users::PATH_USERS, users::UsersPost)
+        .route(HttpMethod::Get, users::PATH_USERS, users::UsersList)
+        .route(HttpMethod::Get, users::PATH_USER, users::UserGet)
+        .route(HttpMethod::Patch, users::PATH_USER, users::UserPatch)
+        .route(HttpMethod::Delete, users::PATH_USER, users::UserDelete)
+        .route(
+            HttpMethod::Post,
+            projects::PATH_PROJECTS,
+            projects::ProjectsPost,
+        )
+        .route(
+            HttpMethod::Get,
+            projects::PATH_PROJECTS,
+            projects::ProjectsList,
+        )
+        .route(
+            HttpMethod::Get,
+            projects::PATH_PROJECT,
+            projects::ProjectGet,
+        )
+        .route(
+            HttpMethod::Patch,
+            projects::PATH_PROJECT,
+            projects::ProjectPatch,
+        )
+        .route(
+            HttpMethod::Delete,
+            projects::PATH_PROJECT,
+            projects::ProjectDelete,
+        )
+        .route(
+            HttpMethod::Get,
+            projects::PATH_PROJECT_STATS,
+            projects::ProjectStatsGet,
+        )
+        .route(
+            HttpMethod::Post,
+            project_deployments::PATH_DEPLOYMENTS,
+            project_deployments::ProjectDeploymentsPost,
+        )
+        .route(
+            HttpMethod::Get,
+            project_deployments::PATH_DEPLOYMENT,
+            project_deployments::ProjectDeploymentGet,
+        )
+        .route(
+            HttpMethod::Patch,
+            project_deployments::PATH_DEPLOYMENT,
+            project_deployments::ProjectDeploymentPatch,
+        )
+        .route(
+            HttpMethod::Get,
+            project_deployments::PATH_DEPLOYMENT_WAIT,
+            project_deployments::ProjectDeploymentWait,
+        )
+        .route(
+            HttpMethod::Post,
+            webhooks::PATH_WEBHOOKS,
+            webhooks::WebhooksPost,
+        )
+        .route(
+            HttpMethod::Get,
+            webhooks::PATH_WEBHOOKS,
+            webhooks::WebhooksList,
+        )
+        .route(
+            HttpMethod::Get,
+            webhooks::PATH_WEBHOOK_DELIVERIES,
+            webhooks::WebhookDeliveriesList,
+        )
+}
+
+#[cfg(test)]
+mod routes_tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashSet;
+
+    /// Routes without API keys, authentication fails closed otherwise
+    fn api() -> Router {
+        std::env::set_var("API_AUTH", "disabled");
+        super::api()
+    }
+
+    #[test]
+    fn registers_every_route_once() {
+        let router = api();
+        let routes = router.routes();
+        let unique: HashSet<(String, &str)> = routes
+            .iter()
+            .map(|(method, template)| (method.to_string(), *template))
+            .collect();
+        assert_eq!(routes.len(), 21);
+        assert_eq!(unique.len(), routes.len());
+        assert!(unique.contains(&("GET".to_string(), projects::PATH_PROJECT_STATS)));
+    }
+
+    #[tokio::test]
+    async fn serves_the_root_for_every_method() {
+        let response = api()
+            .dispatch(
+                serde_json::from_value(json!({ "httpMethod": "DELETE", "path": "/" })).unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status_code(), 200);
+    }
+
+    #[tokio::test]
+    async fn rejects_a_deployment_patch_without_pinned() {
+        let response = api()
+            .dispatch(
+                serde_json::from_value(json!({
+                    "httpMethod": "PATCH",
+                    "path": "/deployments/1234",
+                    "body": "{\"pinned\":\"yes\"}",
+                }))
+                .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status_code(), 400);
+    }
+
+    #[tokio::test]
+    async fn lists_the_error_catalog() {
+        let response = api()
+            .dispatch(
+                serde_json::from_value(json!({ "httpMethod": "GET", "path": "/errors" })).unwrap(),
+            )
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_str(response.body()).unwrap();
+        assert_eq!(body["count"], crate::models::api_error::ApiError::ALL.len());
+        assert_eq!(body["items"][0]["code"], "CME00");
+    }
+}
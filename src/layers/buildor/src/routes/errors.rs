@@ -0,0 +1,35 @@
+use async_trait::async_trait;
+use log::{self, info};
+
+use crate::{
+    handlers::router::{Route, RouteResult},
+    models::{
+        api_error::{ApiError, ApiErrorEntry, API_ERROR_LIST_FIELDS},
+        common::ResponseGenericList,
+        response::Response,
+        router::RouteRequest,
+    },
+};
+
+pub const PATH_ERRORS: &str = "/errors";
+
+pub struct ErrorsList;
+#[async_trait]
+impl Route for ErrorsList {
+    async fn handle(&self, request: &RouteRequest) -> RouteResult {
+        let list = match request.list_query(API_ERROR_LIST_FIELDS, &[]) {
+            Ok(list) => list,
+            Err(error) => return Ok(Response::error(error)),
+        };
+
+        info!("ErrorsList::handle - errors: {}", ApiError::ALL.len());
+        let errors: Vec<ApiErrorEntry> = ApiError::ALL
+            .iter()
+            .map(|error| ApiErrorEntry::from(*error))
+            .collect();
+        Ok(Response::json(
+            ResponseGenericList::new(list.apply(errors)),
+            200,
+        ))
+    }
+}
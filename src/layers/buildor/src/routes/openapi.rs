@@ -0,0 +1,637 @@
+use async_trait::async_trait;
+use log::{self, info};
+use serde_json::Value;
+
+use super::{errors, project_deployments, projects, root, users, webhooks};
+use crate::{
+    handlers::router::{Route, RouteResult},
+    models::{
+        api_error::API_ERROR_LIST_FIELDS,
+        openapi::{self, Operation, ResponseBody},
+        project::PROJECT_LIST_FIELDS,
+        response::Response,
+        router::{HttpMethod, RouteRequest},
+        user::USER_LIST_FIELDS,
+        webhook::{WEBHOOK_DELIVERY_LIST_FIELDS, WEBHOOK_LIST_FIELDS},
+    },
+};
+
+pub const PATH_OPENAPI: &str = "/openapi.json";
+
+/// Documentation of a route of the API, none for routes left undocumented
+pub fn operation(method: HttpMethod, template: &str) -> Option<Operation> {
+    let operation = |id, summary, body, status, response| Operation {
+        id,
+        summary,
+        query: &[],
+        headers: &[],
+        list: None,
+        body,
+        status,
+        response,
+    };
+
+    let operation = match (method, template) {
+        (HttpMethod::Any, root::PATH_ROOT) => operation(
+            "RootAny",
+            "API name",
+            None,
+            200,
+            ResponseBody::Schema("Root"),
+        ),
+        (HttpMethod::Get, PATH_OPENAPI) => operation(
+            "OpenApiGet",
+            "OpenAPI document of the API",
+            None,
+            200,
+            ResponseBody::Empty,
+        ),
+        (HttpMethod::Get, errors::PATH_ERRORS) => Operation {
+            list: Some(API_ERROR_LIST_FIELDS),
+            ..operation(
+                "ErrorsList",
+                "List the error catalog",
+                None,
+                200,
+                ResponseBody::List("ApiErrorEntry"),
+            )
+        },
+        (HttpMethod::Post, users::PATH_USERS) => operation(
+            "UsersPost",
+            "Create a user",
+            Some("UserCreatePayload"),
+            200,
+            ResponseBody::Schema("User"),
+        ),
+        (HttpMethod::Get, users::PATH_USERS) => Operation {
+            query: &[("email", "Only the user of this email")],
+            list: Some(USER_LIST_FIELDS),
+            ..operation(
+                "UsersList",
+                "List users",
+                None,
+                200,
+                ResponseBody::List("User"),
+            )
+        },
+        (HttpMethod::Get, users::PATH_USER) => operation(
+            "UserGet",
+            "Get a user",
+            None,
+            200,
+            ResponseBody::Schema("User"),
+        ),
+        (HttpMethod::Patch, users::PATH_USER) => operation(
+            "UserPatch",
+            "Update the fields sent of a user",
+            Some("UserUpdatePayload"),
+            200,
+            ResponseBody::Schema("User"),
+        ),
+        (HttpMethod::Delete, users::PATH_USER) => operation(
+            "UserDelete",
+            "Delete a user",
+            None,
+            204,
+            ResponseBody::Empty,
+        ),
+        (HttpMethod::Post, projects::PATH_PROJECTS) => operation(
+            "ProjectsPost",
+            "Create a project",
+            Some("ProjectCreatePayload"),
+            200,
+            ResponseBody::Schema("Project"),
+        ),
+        (HttpMethod::Get, projects::PATH_PROJECTS) => Operation {
+            list: Some(PROJECT_LIST_FIELDS),
+            ..operation(
+                "ProjectsList",
+                "List projects",
+                None,
+                200,
+                ResponseBody::List("Project"),
+            )
+        },
+        (HttpMethod::Get, projects::PATH_PROJECT) => operation(
+            "ProjectGet",
+            "Get a project",
+            None,
+            200,
+            ResponseBody::Schema("Project"),
+        ),
+        (HttpMethod::Patch, projects::PATH_PROJECT) => operation(
+            "ProjectPatch",
+            "Update the fields sent of a project",
+            Some("ProjectUpdatePayload"),
+            200,
+            ResponseBody::Schema("Project"),
+        ),
+        (HttpMethod::Delete, projects::PATH_PROJECT) => operation(
+            "ProjectDelete",
+            "Delete a project without deployments queued or in progress",
+            None,
+            204,
+            ResponseBody::Empty,
+        ),
+        (HttpMethod::Get, projects::PATH_PROJECT_STATS) => Operation {
+            query: &[("window", "Days summed, e.g. 7d, 30d by default")],
+            ..operation(
+                "ProjectStatsGet",
+                "Deployment statistics of a project",
+                None,
+                200,
+                ResponseBody::Schema("ProjectStats"),
+            )
+        },
+        (HttpMethod::Post, project_deployments::PATH_DEPLOYMENTS) => Operation {
+            headers: &[(
+                "Idempotency-Key",
+                "Replays of the request within 24 hours get the original response back",
+            )],
+            ..operation(
+                "ProjectDeploymentsPost",
+                "Deploy a project environment, 202 when the deployment is queued",
+                Some("ProjectDeploymentCreatePayload"),
+                201,
+                ResponseBody::Schema("ProjectDeployment"),
+            )
+        },
+        (HttpMethod::Get, project_deployments::PATH_DEPLOYMENT) => operation(
+            "ProjectDeploymentGet",
+            "Get a deployment",
+            None,
+            200,
+            ResponseBody::Schema("ProjectDeployment"),
+        ),
+        (HttpMethod::Patch, project_deployments::PATH_DEPLOYMENT) => operation(
+            "ProjectDeploymentPatch",
+            "Pin or unpin a deployment, pinned deployments are never expired",
+            Some("ProjectDeploymentPatchPayload"),
+            200,
+            ResponseBody::Schema("ProjectDeployment"),
+        ),
+        (HttpMethod::Get, project_deployments::PATH_DEPLOYMENT_WAIT) => Operation {
+            query: &[
+                ("until", "change (default) or terminal"),
+                ("timeout", "Seconds to wait, 1 to 25"),
+            ],
+            ..operation(
+                "ProjectDeploymentWait",
+                "Wait for the status of a deployment to change",
+                None,
+                200,
+                ResponseBody::Schema("ProjectDeploymentWait"),
+            )
+        },
+        (HttpMethod::Post, webhooks::PATH_WEBHOOKS) => operation(
+            "WebhooksPost",
+            "Subscribe to deployment events, the response includes the signing secret",
+            Some("WebhookCreatePayload"),
+            201,
+            ResponseBody::Schema("Webhook"),
+        ),
+        (HttpMethod::Get, webhooks::PATH_WEBHOOKS) => Operation {
+            query: &[
+                ("project", "Only the webhooks of this project"),
+                ("user", "Only the webhooks of this user"),
+            ],
+            list: Some(WEBHOOK_LIST_FIELDS),
+            ..operation(
+                "WebhooksList",
+                "List webhooks",
+                None,
+                200,
+                ResponseBody::List("Webhook"),
+            )
+        },
+        (HttpMethod::Get, webhooks::PATH_WEBHOOK_DELIVERIES) => Operation {
+            list: Some(WEBHOOK_DELIVERY_LIST_FIELDS),
+            ..operation(
+                "WebhookDeliveriesList",
+                "List the deliveries of a webhook",
+                None,
+                200,
+                ResponseBody::List("WebhookDelivery"),
+            )
+        },
+        _ => return None,
+    };
+
+    Some(operation)
+}
+
+/// OpenAPI document of every route served by the single lambda router
+pub fn document() -> Value {
+    let router = super::api();
+    let operations = router
+        .routes()
+        .into_iter()
+        .filter_map(|(method, template)| {
+            let operation = operation(method, template)?;
+            // Routes of every method are documented by their GET operation
+            let method = match method {
+                HttpMethod::Any => HttpMethod::Get,
+                method => method,
+            };
+            Some((method.to_string(), template, operation))
+        })
+        .collect();
+
+    openapi::document(operations)
+}
+
+pub struct OpenApiGet;
+#[async_trait]
+impl Route for OpenApiGet {
+    async fn handle(&self, _: &RouteRequest) -> RouteResult {
+        info!("OpenApiGet::handle");
+        Ok(Response::json(document(), 200))
+    }
+}
+
+#[cfg(test)]
+mod openapi_tests {
+    use super::*;
+    use crate::models::{
+        api_error::{ApiError, ApiErrorEntry, ProblemDetails},
+        artifact::ArtifactSummary,
+        codebuild::BuildInfo,
+        common::{AsDynamoDBAttributeValue, CommonError},
+        environment::{EnvironmentCreatePayload, SecretVariable},
+        list_query::{FieldKind, ListField},
+        notification::NotificationChannel,
+        project::{Project, ProjectCreatePayload, ProjectUpdatePayload},
+        project_deployment::{
+            ProjectDeployment, ProjectDeploymentCreatePayload,
+            ProjectDeploymentCreatePayloadRequest, ProjectDeploymentPatchPayload,
+            ProjectDeploymentWait,
+        },
+        retention::RetentionPolicy,
+        stats::{DurationSummary, PhaseStats, ProjectStats},
+        user::{User, UserCreatePayload, UserUpdatePayload},
+        validation::FieldError,
+        webhook::{Webhook, WebhookCreatePayload, WebhookDelivery, WebhookEvent},
+    };
+    use aws_sdk_dynamodb::model::AttributeValue;
+    use serde::{de::DeserializeOwned, Serialize};
+    use serde_json::{json, Map};
+    use std::collections::HashMap;
+
+    fn resolve<'a>(schemas: &'a Map<String, Value>, schema: &'a Value) -> &'a Value {
+        match schema["$ref"].as_str() {
+            Some(reference) => &schemas[reference.trim_start_matches("#/components/schemas/")],
+            None => schema,
+        }
+    }
+
+    /// Differences between a schema and a serialized value: undocumented
+    /// properties, missing required ones and unexpected nulls
+    fn drift(schemas: &Map<String, Value>, schema: &Value, value: &Value, at: &str) -> Vec<String> {
+        let schema = resolve(schemas, schema);
+        if let Some(branches) = schema["oneOf"].as_array() {
+            return match value {
+                Value::Null => vec![],
+                _ => drift(schemas, &branches[0], value, at),
+            };
+        }
+        if value.is_null() {
+            return match schema["type"].as_array() {
+                Some(types) if types.contains(&json!("null")) => vec![],
+                _ => vec![format!("{}: null", at)],
+            };
+        }
+
+        let mut problems = Vec::new();
+        match value {
+            Value::Object(object) => {
+                if let Some(values) = schema.get("additionalProperties") {
+                    for (key, value) in object {
+                        problems.extend(drift(schemas, values, value, &format!("{}.{}", at, key)));
+                    }
+                    return problems;
+                }
+                let properties = schema["properties"].as_object().unwrap();
+                for (key, value) in object {
+                    match properties.get(key) {
+                        Some(property) => problems.extend(drift(
+                            schemas,
+                            property,
+                            value,
+                            &format!("{}.{}", at, key),
+                        )),
+                        None => problems.push(format!("{}.{}: undocumented", at, key)),
+                    }
+                }
+                for required in schema["required"].as_array().unwrap() {
+                    if !object.contains_key(required.as_str().unwrap()) {
+                        problems.push(format!("{}.{}: missing", at, required));
+                    }
+                }
+            }
+            Value::Array(items) => {
+                for (index, item) in items.iter().enumerate() {
+                    problems.extend(drift(
+                        schemas,
+                        &schema["items"],
+                        item,
+                        &format!("{}[{}]", at, index),
+                    ));
+                }
+            }
+            _ => {}
+        }
+        problems
+    }
+
+    /// Every field of the responses is listable, under the stored attribute
+    /// name and with the kind of its value
+    fn assert_list_fields<T: Serialize>(
+        fields: &[ListField],
+        sample: T,
+        attributes: Option<HashMap<String, AttributeValue>>,
+    ) {
+        let sample = json!(sample);
+        let sample = sample.as_object().unwrap();
+        for name in sample.keys() {
+            assert!(
+                fields.iter().any(|field| field.name == name),
+                "{} is not listed",
+                name
+            );
+        }
+        for field in fields {
+            let kind = match &sample[field.name] {
+                Value::String(_) => FieldKind::Text,
+                Value::Number(_) => FieldKind::Number,
+                _ => FieldKind::Nested,
+            };
+            assert_eq!(field.kind, kind, "{}", field.name);
+            if let Some(attributes) = &attributes {
+                assert!(
+                    attributes.contains_key(field.attribute),
+                    "{} is not stored as {}",
+                    field.name,
+                    field.attribute
+                );
+            }
+        }
+    }
+
+    fn assert_response<T: Serialize>(name: &str, sample: T) {
+        let schemas = openapi::schemas();
+        let problems = drift(&schemas, &schemas[name], &json!(sample), name);
+        assert!(problems.is_empty(), "{:?}", problems);
+    }
+
+    /// Key paths of the objects of a value, map keys included
+    fn paths(value: &Value, path: Vec<String>, paths: &mut Vec<Vec<String>>) {
+        let children: Vec<(String, &Value)> = match value {
+            Value::Object(object) => object.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            Value::Array(items) => items
+                .iter()
+                .enumerate()
+                .map(|(index, item)| (index.to_string(), item))
+                .collect(),
+            _ => vec![],
+        };
+        for (key, child) in children {
+            let mut path = path.clone();
+            path.push(key);
+            if value.is_object() {
+                paths.push(path.clone());
+            }
+            self::paths(child, path, paths);
+        }
+    }
+
+    fn nulls(value: &Value, at: &str) -> Vec<String> {
+        match value {
+            Value::Null => vec![at.to_string()],
+            Value::Object(object) => object
+                .iter()
+                .flat_map(|(key, value)| nulls(value, &format!("{}.{}", at, key)))
+                .collect(),
+            Value::Array(items) => items
+                .iter()
+                .enumerate()
+                .flat_map(|(index, item)| nulls(item, &format!("{}[{}]", at, index)))
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    fn remove(value: &mut Value, path: &[String]) {
+        let (last, parents) = path.split_last().unwrap();
+        let mut parent = value;
+        for key in parents {
+            parent = match parent {
+                Value::Array(items) => &mut items[key.parse::<usize>().unwrap()],
+                object => &mut object[key],
+            };
+        }
+        parent.as_object_mut().unwrap().remove(last);
+    }
+
+    /// The example of a payload schema deserializes with every field set, and
+    /// every property of it is read by the payload
+    fn assert_payload<T: DeserializeOwned + Serialize>(name: &str) {
+        let schemas = openapi::schemas();
+        let example = &schemas[name]["examples"][0];
+        let read = |value: &Value| json!(serde_json::from_value::<T>(value.clone()).unwrap());
+
+        let full = read(example);
+        let left_out = nulls(&full, name);
+        assert!(left_out.is_empty(), "fields left out: {:?}", left_out);
+        let mut properties = Vec::new();
+        paths(example, vec![], &mut properties);
+        for path in properties {
+            let mut partial = example.clone();
+            remove(&mut partial, &path);
+            // Properties the payload requires fail without them
+            if serde_json::from_value::<T>(partial.clone()).is_ok() {
+                assert_ne!(read(&partial), full, "{}: {:?} is ignored", name, path);
+            }
+        }
+    }
+
+    #[test]
+    fn documents_every_route() {
+        let router = crate::routes::api();
+        for (method, template) in router.routes() {
+            assert!(
+                operation(method, template).is_some(),
+                "{} {} is not documented",
+                method,
+                template
+            );
+        }
+
+        let document = document();
+        assert_eq!(document["openapi"], "3.1.0");
+        assert_eq!(
+            document["paths"]["/users/{user}"]["patch"]["parameters"][0]["name"],
+            "user"
+        );
+        assert_eq!(
+            document["paths"]["/projects"]["post"]["requestBody"]["content"]["application/json"]
+                ["schema"]["$ref"],
+            "#/components/schemas/ProjectCreatePayload"
+        );
+        assert!(document["paths"]["/"]["get"].is_object());
+
+        // Every referenced schema exists
+        let text = document.to_string();
+        let schemas = document["components"]["schemas"].as_object().unwrap();
+        for reference in text.split("#/components/schemas/").skip(1) {
+            let name = reference.split('"').next().unwrap();
+            assert!(schemas.contains_key(name), "missing schema {}", name);
+        }
+    }
+
+    #[test]
+    fn documents_response_models() {
+        let user = User::new(UserCreatePayload {
+            fname: "John".to_string(),
+            lname: "Doe".to_string(),
+            email: Some("john@example.com".to_string()),
+        });
+        assert_response("User", &user);
+        assert_list_fields(USER_LIST_FIELDS, &user, Some(user.as_hashmap()));
+        assert_response("Root", json!({ "message": "Buildor API" }));
+
+        let project = Project::new(ProjectCreatePayload {
+            environments: Some(vec![EnvironmentCreatePayload {
+                name: "staging".to_string(),
+                branch: Some("develop".to_string()),
+                variables: Some(HashMap::from([("KEY".to_string(), "value".to_string())])),
+                secrets: Some(HashMap::from([(
+                    "TOKEN".to_string(),
+                    SecretVariable {
+                        secret_type: "SECRETS_MANAGER".to_string(),
+                        value: "App/projects/site/TOKEN".to_string(),
+                    },
+                )])),
+            }]),
+            retention: Some(RetentionPolicy::new(5, 7)),
+            notifications: Some(vec![NotificationChannel {
+                provider: "slack".to_string(),
+                url: "https://hooks.slack.com/services/T0/B0/X".to_string(),
+            }]),
+            ..ProjectCreatePayload::default(
+                "site".to_string(),
+                "https://github.com/example/site.git".to_string(),
+            )
+        });
+        assert_response("Project", &project);
+        assert_list_fields(PROJECT_LIST_FIELDS, &project, Some(project.as_hashmap()));
+
+        let mut deployment = ProjectDeployment::new(ProjectDeploymentCreatePayload {
+            project: project.clone(),
+            environment: "staging".to_string(),
+            build: Some(BuildInfo {
+                uuid: "build".to_string(),
+                build_number: Some(1),
+                start_time: Some(1),
+                end_time: Some(2),
+                deployment_phase: Some("BUILD".to_string()),
+                current_phase: Some("COMPLETED".to_string()),
+                build_status: Some("SUCCEEDED".to_string()),
+                logs_url: Some("https://logs".to_string()),
+                compute_type: Some("BUILD_GENERAL1_SMALL".to_string()),
+                image: Some("aws/codebuild/standard:6.0".to_string()),
+            }),
+        });
+        deployment.artifact = Some(ArtifactSummary {
+            bucket: "bucket".to_string(),
+            key: "key".to_string(),
+            file_count: 1,
+            total_size: 10,
+            manifest_key: Some("key.manifest.json".to_string()),
+            checksum: Some("sha".to_string()),
+        });
+        assert_response("ProjectDeployment", &deployment);
+        assert_response(
+            "ProjectDeploymentWait",
+            ProjectDeploymentWait {
+                changed: true,
+                deployment,
+            },
+        );
+
+        let duration = DurationSummary {
+            count: 2,
+            mean_seconds: 60.0,
+            p95_seconds: Some(90),
+        };
+        assert_response(
+            "ProjectStats",
+            ProjectStats {
+                project: project.uuid,
+                window: "7d".to_string(),
+                from: "2022-10-01".to_string(),
+                to: "2022-10-07".to_string(),
+                builds: 2,
+                succeeded: 1,
+                failed: 1,
+                success_rate: Some(0.5),
+                duration: Some(duration.clone()),
+                phases: vec![PhaseStats {
+                    phase: "BUILD".to_string(),
+                    duration,
+                }],
+                most_common_failing_phase: Some("BUILD".to_string()),
+                mean_time_to_recovery_seconds: Some(120.0),
+                recoveries: 1,
+            },
+        );
+
+        let webhook = Webhook::new(WebhookCreatePayload {
+            project_uuid: Some("project".to_string()),
+            user_uuid: None,
+            url: "https://example.com/hook".to_string(),
+            events: None,
+            secret: None,
+        });
+        assert_response("Webhook", &webhook);
+        assert_list_fields(WEBHOOK_LIST_FIELDS, &webhook, Some(webhook.as_hashmap()));
+        let mut delivery = WebhookDelivery::new(
+            webhook.uuid,
+            WebhookEvent::DeploymentSucceeded,
+            "{}".to_string(),
+        );
+        delivery.response_status = Some(500);
+        delivery.last_error = Some("Internal Server Error".to_string());
+        assert_list_fields(
+            WEBHOOK_DELIVERY_LIST_FIELDS,
+            &delivery,
+            Some(delivery.as_hashmap()),
+        );
+        assert_response("WebhookDelivery", delivery);
+
+        assert_response("ApiErrorEntry", ApiErrorEntry::from(ApiError::Validation));
+        assert_list_fields(
+            API_ERROR_LIST_FIELDS,
+            ApiErrorEntry::from(ApiError::Validation),
+            None,
+        );
+        let error = CommonError::validation(vec![FieldError {
+            field: "email".to_string(),
+            code: "VLE04".to_string(),
+            message: "Must be an email address".to_string(),
+        }]);
+        assert_response("Error", &error);
+        assert_response("ProblemDetails", ProblemDetails::new(&error, "/users"));
+    }
+
+    #[test]
+    fn documents_request_payloads() {
+        assert_payload::<UserCreatePayload>("UserCreatePayload");
+        assert_payload::<UserUpdatePayload>("UserUpdatePayload");
+        assert_payload::<ProjectCreatePayload>("ProjectCreatePayload");
+        assert_payload::<ProjectUpdatePayload>("ProjectUpdatePayload");
+        assert_payload::<EnvironmentCreatePayload>("EnvironmentCreatePayload");
+        assert_payload::<ProjectDeploymentCreatePayloadRequest>("ProjectDeploymentCreatePayload");
+        assert_payload::<ProjectDeploymentPatchPayload>("ProjectDeploymentPatchPayload");
+        assert_payload::<WebhookCreatePayload>("WebhookCreatePayload");
+    }
+}
@@ -0,0 +1,353 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use log::{self, error, info};
+use std::time::Duration;
+
+use super::env_var;
+use crate::{
+    handlers::{
+        codebuild::{BuildLimits, CodeBuildHandler},
+        deployment_queue::{ConcurrencyLimits, DeploymentQueue},
+        deployment_wait::{wait_for_deployment, WAIT_POLL_INTERVAL_MILLIS},
+        idempotency_keys::IdempotencyKeysHandler,
+        project_deployments::ProjectDeploymentsHandler,
+        projects::ProjectsHandler,
+        router::{Route, RouteResult},
+    },
+    models::{
+        common::CommonError,
+        handlers::HandlerGet,
+        idempotency_key::{IdempotencyKey, IdempotencyKeyError, DEFAULT_IDEMPOTENCY_KEY_TTL_HOURS},
+        project_deployment::{
+            ProjectDeploymentCreatePayloadRequest, ProjectDeploymentError,
+            ProjectDeploymentPatchPayload, ProjectDeploymentStatus, ProjectDeploymentUpdatePayload,
+            WaitOptions,
+        },
+        response::{etag, Response},
+        router::{RouteRequest, RouterError},
+        validation::Validate,
+    },
+    utils::Clients,
+};
+
+pub const PATH_DEPLOYMENTS: &str = "/deployments";
+pub const PATH_DEPLOYMENT: &str = "/deployments/{deployment}";
+pub const PATH_DEPLOYMENT_WAIT: &str = "/deployments/{deployment}/wait";
+
+pub struct ProjectDeploymentsPost;
+#[async_trait]
+impl Route for ProjectDeploymentsPost {
+    async fn handle(&self, request: &RouteRequest) -> RouteResult {
+        let key = match request.idempotency_key() {
+            Ok(Some(key)) => key,
+            Ok(None) => return self.create(request).await,
+            Err(error) => return Ok(Response::error(error)),
+        };
+        let table_name = env_var("TABLE_NAME_IDEMPOTENCY_KEYS")?;
+
+        // A retried request must not start a second build
+        let now = Utc::now();
+        let claim = IdempotencyKey::new(
+            format!("POST {}#{}", PATH_DEPLOYMENTS, key),
+            IdempotencyKey::fingerprint("POST", &request.path, request.body.as_deref()),
+            now,
+            chrono::Duration::hours(DEFAULT_IDEMPOTENCY_KEY_TTL_HOURS),
+        );
+        let ikh = IdempotencyKeysHandler::new(Clients::dynamodb().await, table_name);
+        match ikh.claim(&claim, now).await? {
+            Some(holder) if holder.fingerprint != claim.fingerprint => {
+                info!("ProjectDeploymentsPost::handle - key used by another request");
+                return Ok(Response::error(IdempotencyKeyError::mismatch()));
+            }
+            Some(holder) => {
+                return match holder.response() {
+                    Some(response) => {
+                        info!("ProjectDeploymentsPost::handle - replay: {}", holder.key);
+                        Ok(response)
+                    }
+                    None => {
+                        info!("ProjectDeploymentsPost::handle - key in progress");
+                        Ok(Response::error(IdempotencyKeyError::in_progress()))
+                    }
+                };
+            }
+            None => {}
+        }
+
+        // Only created deployments are replayed, failures can be retried with the same key
+        let result = self.create(request).await;
+        let stored = match &result {
+            Ok(response) if (200..300).contains(&response.status_code) => {
+                match ikh.complete(&claim, response).await {
+                    Ok(false) => {
+                        error!(
+                            "ProjectDeploymentsPost::handle - key {} taken over before completion",
+                            claim.key
+                        );
+                        Ok(())
+                    }
+                    stored => stored.map(|_| ()),
+                }
+            }
+            _ => ikh.release(&claim).await,
+        };
+        if let Err(error) = stored {
+            error!(
+                "ProjectDeploymentsPost::handle - failed to settle key {}: {}",
+                claim.key, error
+            );
+        }
+
+        result
+    }
+}
+impl ProjectDeploymentsPost {
+    async fn create(&self, request: &RouteRequest) -> RouteResult {
+        let table_name = env_var("TABLE_NAME_DEPLOYMENTS")?;
+        let table_name_projects = env_var("TABLE_NAME_PROJECTS")?;
+        let table_name_outbox = env_var("TABLE_NAME_OUTBOX")?;
+        let table_name_slots = env_var("TABLE_NAME_DEPLOYMENT_SLOTS")?;
+        let codebuild_project_name_building = env_var("CODEBUILD_PROJECT_NAME_BUILDING")?;
+        let codebuild_project_name_deployment = env_var("CODEBUILD_PROJECT_NAME_DEPLOYMENT")?;
+
+        info!("ProjectDeploymentsPost::handle - parse body payload");
+        let body = match request.body::<ProjectDeploymentCreatePayloadRequest>() {
+            Ok(value) => value,
+            Err(error) => return Ok(Response::error(error)),
+        };
+        info!("ProjectDeploymentsPost::handle - body: {:?}", body);
+        if let Err(error) = body.validate() {
+            info!(
+                "ProjectDeploymentsPost::handle - invalid payload: {}",
+                error.details
+            );
+            return Ok(Response::error(error));
+        }
+
+        let ph = ProjectsHandler::new(Clients::dynamodb().await, table_name_projects);
+        let project = match ph.get(body.project_uuid).await {
+            Ok(Some(project)) => project,
+            Ok(None) => {
+                return Ok(Response::error(CommonError::item_not_found(Some(
+                    "Project not found".to_string(),
+                ))))
+            }
+            Err(error) => {
+                error!(
+                    "ProjectDeploymentsPost::handle - failed to get project: {}",
+                    error
+                );
+                return Ok(Response::error(CommonError::item_not_found(None)));
+            }
+        };
+        info!("ProjectDeploymentsPost::handle - project: {:?}", project);
+
+        let environment = match project.environment(&body.environment) {
+            Some(environment) => environment.clone(),
+            None => {
+                return Ok(Response::error(CommonError::item_not_found(Some(format!(
+                    "Project environment \"{}\" not found",
+                    body.environment
+                )))))
+            }
+        };
+        info!(
+            "ProjectDeploymentsPost::handle - environment: {:?}",
+            environment
+        );
+
+        let cbh = CodeBuildHandler::new(
+            Clients::codebuild().await,
+            codebuild_project_name_building,
+            codebuild_project_name_deployment,
+            BuildLimits::from_env(),
+        );
+        let pdh = ProjectDeploymentsHandler::new(Clients::dynamodb().await, table_name)
+            .with_outbox(table_name_outbox)
+            .with_slots(table_name_slots);
+        let limits = ConcurrencyLimits::from_env();
+        info!(
+            "ProjectDeploymentsPost::handle - concurrency limits: {:?}",
+            limits
+        );
+        let queue = DeploymentQueue::new(pdh, ph, cbh, limits);
+
+        match queue.enqueue(project, environment.name).await {
+            Ok(deployment) => {
+                info!(
+                    "ProjectDeploymentsPost::handle - deployment: {:?}",
+                    deployment
+                );
+                match deployment.status() {
+                    ProjectDeploymentStatus::Queued => Ok(Response::json(deployment, 202)),
+                    _ => Ok(Response::json(deployment, 201)),
+                }
+            }
+            Err(error) => {
+                error!(
+                    "ProjectDeploymentsPost::handle - failed to queue project deployment: {}",
+                    error
+                );
+                Ok(Response::error(ProjectDeploymentError::queue_failed()))
+            }
+        }
+    }
+}
+
+pub struct ProjectDeploymentGet;
+#[async_trait]
+impl Route for ProjectDeploymentGet {
+    async fn handle(&self, request: &RouteRequest) -> RouteResult {
+        let table_name = env_var("TABLE_NAME_DEPLOYMENTS")?;
+
+        let deployment_uuid = match request.path_parameter("deployment") {
+            Ok(uuid) => uuid,
+            Err(error) => return Ok(Response::error(error)),
+        };
+        info!("ProjectDeploymentGet::handle - uuid: {}", deployment_uuid);
+
+        let pdh = ProjectDeploymentsHandler::new(Clients::dynamodb().await, table_name);
+        match pdh.get(deployment_uuid).await? {
+            Some(deployment) => Ok(Response::json(deployment, 200)),
+            None => {
+                info!("ProjectDeploymentGet::handle - deployment not found");
+                Ok(Response::error(CommonError::item_not_found(Some(
+                    "Project deployment not found".to_string(),
+                ))))
+            }
+        }
+    }
+}
+
+pub struct ProjectDeploymentPatch;
+#[async_trait]
+impl Route for ProjectDeploymentPatch {
+    async fn handle(&self, request: &RouteRequest) -> RouteResult {
+        let deployment_uuid = match request.path_parameter("deployment") {
+            Ok(uuid) => uuid,
+            Err(error) => return Ok(Response::error(error)),
+        };
+        info!("ProjectDeploymentPatch::handle - uuid: {}", deployment_uuid);
+
+        info!("ProjectDeploymentPatch::handle - parse body payload");
+        let body = match request.body::<ProjectDeploymentPatchPayload>() {
+            Ok(value) => value,
+            Err(error) => return Ok(Response::error(error)),
+        };
+        info!("ProjectDeploymentPatch::handle - body: {:?}", body);
+
+        let table_name = env_var("TABLE_NAME_DEPLOYMENTS")?;
+        let pdh = ProjectDeploymentsHandler::new(Clients::dynamodb().await, table_name);
+        let deployment = match pdh.get(deployment_uuid.clone()).await? {
+            Some(deployment) => deployment,
+            None => {
+                info!("ProjectDeploymentPatch::handle - deployment not found");
+                return Ok(Response::error(CommonError::item_not_found(Some(
+                    "Project deployment not found".to_string(),
+                ))));
+            }
+        };
+        if let Err(error) = request.if_match(&etag(&deployment)) {
+            info!("ProjectDeploymentPatch::handle - stale entity tag");
+            return Ok(Response::error(error));
+        }
+
+        // Only the version the client read may be changed when it sent an entity tag
+        let read_updated_at = request.header("if-match").map(|_| deployment.updated_at);
+        let conditional = read_updated_at.is_some();
+        match pdh
+            .update_if_unmodified(
+                deployment_uuid.clone(),
+                ProjectDeploymentUpdatePayload {
+                    project: None,
+                    status: None,
+                    build: None,
+                    artifact: None,
+                    pinned: Some(body.pinned),
+                },
+                read_updated_at,
+            )
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) if conditional => {
+                info!("ProjectDeploymentPatch::handle - deployment modified or deleted meanwhile");
+                return Ok(Response::error(RouterError::precondition_failed()));
+            }
+            Ok(false) => {
+                info!("ProjectDeploymentPatch::handle - deployment not found");
+                return Ok(Response::error(CommonError::item_not_found(Some(
+                    "Project deployment not found".to_string(),
+                ))));
+            }
+            Err(error) => {
+                error!(
+                    "ProjectDeploymentPatch::handle - failed to update deployment: {}",
+                    error
+                );
+                return Ok(Response::error(ProjectDeploymentError::update_failed()));
+            }
+        }
+
+        match pdh.get(deployment_uuid).await? {
+            Some(deployment) => Ok(Response::json(deployment, 200).with_etag()),
+            None => Ok(Response::error(CommonError::item_not_found(Some(
+                "Project deployment not found".to_string(),
+            )))),
+        }
+    }
+}
+
+pub struct ProjectDeploymentWait;
+#[async_trait]
+impl Route for ProjectDeploymentWait {
+    async fn handle(&self, request: &RouteRequest) -> RouteResult {
+        let table_name = env_var("TABLE_NAME_DEPLOYMENTS")?;
+
+        let deployment_uuid = match request.path_parameter("deployment") {
+            Ok(uuid) => uuid,
+            Err(error) => return Ok(Response::error(error)),
+        };
+        info!("ProjectDeploymentWait::handle - uuid: {}", deployment_uuid);
+
+        let options = match WaitOptions::parse(
+            request.query_parameter("until"),
+            request.query_parameter("timeout"),
+        ) {
+            Ok(options) => options,
+            Err(details) => {
+                error!(
+                    "ProjectDeploymentWait::handle - invalid wait options: {}",
+                    details
+                );
+                return Ok(Response::error(ProjectDeploymentError::invalid_wait(
+                    details,
+                )));
+            }
+        };
+        info!("ProjectDeploymentWait::handle - options: {:?}", options);
+
+        let pdh = ProjectDeploymentsHandler::new(Clients::dynamodb().await, table_name);
+        match wait_for_deployment(
+            || pdh.get(deployment_uuid.clone()),
+            options,
+            Duration::from_millis(WAIT_POLL_INTERVAL_MILLIS),
+        )
+        .await?
+        {
+            Some(wait) => {
+                info!(
+                    "ProjectDeploymentWait::handle - deployment: {}, changed: {}",
+                    wait.deployment.status, wait.changed
+                );
+                Ok(Response::json(wait, 200))
+            }
+            None => {
+                info!("ProjectDeploymentWait::handle - deployment not found");
+                Ok(Response::error(CommonError::item_not_found(Some(
+                    "Project deployment not found".to_string(),
+                ))))
+            }
+        }
+    }
+}
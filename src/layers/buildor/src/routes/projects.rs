@@ -0,0 +1,288 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use log::{self, error, info};
+
+use super::env_var;
+use crate::{
+    handlers::{
+        project_deployments::ProjectDeploymentsHandler,
+        projects::ProjectsHandler,
+        router::{Route, RouteResult},
+        stats::ProjectStatsHandler,
+    },
+    models::{
+        common::{CommonError, ResponseGenericList},
+        handlers::{HandlerCreate, HandlerGet},
+        project::{ProjectCreatePayload, ProjectError, ProjectUpdatePayload, PROJECT_LIST_FIELDS},
+        project_deployment::ProjectDeploymentStatus,
+        response::{etag, Response},
+        router::{RouteRequest, RouterError},
+        stats::{ProjectStats, StatsWindow},
+        validation::Validate,
+    },
+    utils::Clients,
+};
+
+pub const PATH_PROJECTS: &str = "/projects";
+pub const PATH_PROJECT: &str = "/projects/{project}";
+pub const PATH_PROJECT_STATS: &str = "/projects/{project}/stats";
+
+pub struct ProjectsPost;
+#[async_trait]
+impl Route for ProjectsPost {
+    async fn handle(&self, request: &RouteRequest) -> RouteResult {
+        let table_name = env_var("TABLE_NAME_PROJECTS")?;
+
+        info!("ProjectsPost::handle - parse body payload");
+        let body = match request.body::<ProjectCreatePayload>() {
+            Ok(value) => value,
+            Err(error) => return Ok(Response::error(error)),
+        };
+        info!("ProjectsPost::handle - body: {:?}", body);
+        if let Err(error) = body.validate() {
+            info!("ProjectsPost::handle - invalid payload: {}", error.details);
+            return Ok(Response::error(error));
+        }
+
+        let ph = ProjectsHandler::new(Clients::dynamodb().await, table_name);
+        match ph.create(body).await {
+            Ok(project) => Ok(Response::json(project, 200)),
+            Err(error) => {
+                error!("ProjectsPost::handle - failed to create project: {}", error);
+                Ok(Response::error(ProjectError::creation_failed()))
+            }
+        }
+    }
+}
+
+pub struct ProjectsList;
+#[async_trait]
+impl Route for ProjectsList {
+    async fn handle(&self, request: &RouteRequest) -> RouteResult {
+        let table_name = env_var("TABLE_NAME_PROJECTS")?;
+
+        let list = match request.list_query(PROJECT_LIST_FIELDS, &[]) {
+            Ok(list) => list,
+            Err(error) => return Ok(Response::error(error)),
+        };
+
+        let ph = ProjectsHandler::new(Clients::dynamodb().await, table_name);
+        let projects = ph.search(&list).await?;
+        info!("ProjectsList::handle - projects: {}", projects.len());
+        Ok(Response::json(ResponseGenericList::new(projects), 200))
+    }
+}
+
+pub struct ProjectGet;
+#[async_trait]
+impl Route for ProjectGet {
+    async fn handle(&self, request: &RouteRequest) -> RouteResult {
+        let table_name = env_var("TABLE_NAME_PROJECTS")?;
+
+        let project_uuid = match request.path_parameter("project") {
+            Ok(uuid) => uuid,
+            Err(error) => return Ok(Response::error(error)),
+        };
+        info!("ProjectGet::handle - uuid: {}", project_uuid);
+
+        let ph = ProjectsHandler::new(Clients::dynamodb().await, table_name);
+        match ph.get(project_uuid).await? {
+            Some(project) => Ok(Response::json(project, 200)),
+            None => {
+                info!("ProjectGet::handle - project not found");
+                Ok(Response::error(CommonError::item_not_found(Some(
+                    "Project not found".to_string(),
+                ))))
+            }
+        }
+    }
+}
+
+pub struct ProjectPatch;
+#[async_trait]
+impl Route for ProjectPatch {
+    async fn handle(&self, request: &RouteRequest) -> RouteResult {
+        let table_name = env_var("TABLE_NAME_PROJECTS")?;
+
+        let project_uuid = match request.path_parameter("project") {
+            Ok(uuid) => uuid,
+            Err(error) => return Ok(Response::error(error)),
+        };
+        info!("ProjectPatch::handle - uuid: {}", project_uuid);
+
+        info!("ProjectPatch::handle - parse body payload");
+        let body = match request.body::<ProjectUpdatePayload>() {
+            Ok(value) => value,
+            Err(error) => return Ok(Response::error(error)),
+        };
+        info!("ProjectPatch::handle - body: {:?}", body);
+        if let Err(error) = body.validate() {
+            info!("ProjectPatch::handle - invalid payload: {}", error.details);
+            return Ok(Response::error(error));
+        }
+
+        let ph = ProjectsHandler::new(Clients::dynamodb().await, table_name);
+        let project = match ph.get(project_uuid.clone()).await? {
+            Some(project) => project,
+            None => {
+                info!("ProjectPatch::handle - project not found");
+                return Ok(Response::error(CommonError::item_not_found(Some(
+                    "Project not found".to_string(),
+                ))));
+            }
+        };
+        if let Err(error) = request.if_match(&etag(&project)) {
+            info!("ProjectPatch::handle - stale entity tag");
+            return Ok(Response::error(error));
+        }
+
+        // Only the version the client read may be changed when it sent an entity tag
+        let read_updated_at = request.header("if-match").map(|_| project.updated_at);
+        match ph
+            .update_if_unmodified(project_uuid.clone(), body, read_updated_at)
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => {
+                info!("ProjectPatch::handle - project modified or deleted meanwhile");
+                return Ok(Response::error(RouterError::precondition_failed()));
+            }
+            Err(error) => {
+                error!("ProjectPatch::handle - failed to update project: {}", error);
+                return Ok(Response::error(ProjectError::update_failed()));
+            }
+        }
+
+        match ph.get(project_uuid).await? {
+            Some(project) => Ok(Response::json(project, 200).with_etag()),
+            None => Ok(Response::error(CommonError::item_not_found(Some(
+                "Project not found".to_string(),
+            )))),
+        }
+    }
+}
+
+pub struct ProjectDelete;
+#[async_trait]
+impl Route for ProjectDelete {
+    async fn handle(&self, request: &RouteRequest) -> RouteResult {
+        let table_name = env_var("TABLE_NAME_PROJECTS")?;
+        let table_name_deployments = env_var("TABLE_NAME_DEPLOYMENTS")?;
+
+        let project_uuid = match request.path_parameter("project") {
+            Ok(uuid) => uuid,
+            Err(error) => return Ok(Response::error(error)),
+        };
+        info!("ProjectDelete::handle - uuid: {}", project_uuid);
+
+        // Queued deployments would start building a project that no longer
+        // exists. A deployment queued after this check fails when the queue
+        // starts it and finds the project deleted.
+        let pdh = ProjectDeploymentsHandler::new(Clients::dynamodb().await, table_name_deployments);
+        let active = pdh
+            .list_by_project(project_uuid.clone(), None)
+            .await?
+            .iter()
+            .filter(|deployment| {
+                matches!(
+                    deployment.status(),
+                    ProjectDeploymentStatus::Queued | ProjectDeploymentStatus::InProgress
+                )
+            })
+            .count();
+        if active > 0 {
+            info!(
+                "ProjectDelete::handle - deployments queued or in progress: {}",
+                active
+            );
+            return Ok(Response::error(ProjectError::delete_blocked(active)));
+        }
+
+        let ph = ProjectsHandler::new(Clients::dynamodb().await, table_name);
+        let read_updated_at = match request.header("if-match") {
+            Some(_) => {
+                let project = match ph.get(project_uuid.clone()).await? {
+                    Some(project) => project,
+                    None => {
+                        info!("ProjectDelete::handle - project not found");
+                        return Ok(Response::error(CommonError::item_not_found(Some(
+                            "Project not found".to_string(),
+                        ))));
+                    }
+                };
+                if let Err(error) = request.if_match(&etag(&project)) {
+                    info!("ProjectDelete::handle - stale entity tag");
+                    return Ok(Response::error(error));
+                }
+                Some(project.updated_at)
+            }
+            None => None,
+        };
+        let conditional = read_updated_at.is_some();
+        match ph.delete_if_unmodified(project_uuid, read_updated_at).await {
+            Ok(true) => Ok(Response::no_content()),
+            Ok(false) if conditional => {
+                info!("ProjectDelete::handle - project modified or deleted meanwhile");
+                Ok(Response::error(RouterError::precondition_failed()))
+            }
+            Ok(false) => {
+                info!("ProjectDelete::handle - project not found");
+                Ok(Response::error(CommonError::item_not_found(Some(
+                    "Project not found".to_string(),
+                ))))
+            }
+            Err(error) => {
+                error!(
+                    "ProjectDelete::handle - failed to delete project: {}",
+                    error
+                );
+                Ok(Response::error(ProjectError::delete_failed()))
+            }
+        }
+    }
+}
+
+pub struct ProjectStatsGet;
+#[async_trait]
+impl Route for ProjectStatsGet {
+    async fn handle(&self, request: &RouteRequest) -> RouteResult {
+        let table_name = env_var("TABLE_NAME_STATS")?;
+        let table_name_projects = env_var("TABLE_NAME_PROJECTS")?;
+
+        let project_uuid = match request.path_parameter("project") {
+            Ok(uuid) => uuid,
+            Err(error) => return Ok(Response::error(error)),
+        };
+        info!("ProjectStatsGet::handle - uuid: {}", project_uuid);
+
+        let window = match StatsWindow::parse(request.query_parameter("window")) {
+            Ok(window) => window,
+            Err(details) => {
+                error!(
+                    "ProjectStatsGet::handle - invalid stats window: {}",
+                    details
+                );
+                return Ok(Response::error(ProjectError::invalid_stats_window(details)));
+            }
+        };
+        info!("ProjectStatsGet::handle - window: {}", window);
+
+        let ph = ProjectsHandler::new(Clients::dynamodb().await, table_name_projects);
+        if ph.get(project_uuid.clone()).await?.is_none() {
+            info!("ProjectStatsGet::handle - project not found");
+            return Ok(Response::error(CommonError::item_not_found(Some(
+                "Project not found".to_string(),
+            ))));
+        }
+
+        let now = Utc::now();
+        let (from, to) = window.range(now);
+        let sh = ProjectStatsHandler::new(Clients::dynamodb().await, table_name);
+        let days = sh.list(project_uuid.clone(), from, to).await?;
+        info!("ProjectStatsGet::handle - days with builds: {}", days.len());
+        Ok(Response::json(
+            ProjectStats::aggregate(project_uuid, window, now, &days),
+            200,
+        ))
+    }
+}
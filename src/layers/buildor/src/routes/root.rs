@@ -0,0 +1,19 @@
+use async_trait::async_trait;
+use log::{self, info};
+use serde_json::json;
+
+use crate::{
+    handlers::router::{Route, RouteResult},
+    models::{response::Response, router::RouteRequest},
+};
+
+pub const PATH_ROOT: &str = "/";
+
+pub struct RootAny;
+#[async_trait]
+impl Route for RootAny {
+    async fn handle(&self, _: &RouteRequest) -> RouteResult {
+        info!("RootAny::handle");
+        Ok(Response::json(json!({ "message": "Buildor API" }), 200))
+    }
+}
@@ -0,0 +1,232 @@
+use async_trait::async_trait;
+use log::{self, error, info};
+
+use super::env_var;
+use crate::{
+    handlers::{
+        router::{Route, RouteResult},
+        users::UsersHandler,
+    },
+    models::{
+        common::{CommonError, ResponseGenericList},
+        handlers::HandlerGet,
+        response::{etag, Response},
+        router::{RouteRequest, RouterError},
+        user::{UserCreatePayload, UserError, UserUpdatePayload, USER_LIST_FIELDS},
+        validation::Validate,
+    },
+    utils::Clients,
+};
+
+pub const PATH_USERS: &str = "/users";
+pub const PATH_USER: &str = "/users/{user}";
+
+pub struct UsersPost;
+#[async_trait]
+impl Route for UsersPost {
+    async fn handle(&self, request: &RouteRequest) -> RouteResult {
+        let table_name = env_var("TABLE_NAME_USERS")?;
+        let table_name_emails = env_var("TABLE_NAME_USER_EMAILS")?;
+
+        info!("UsersPost::handle - parse body payload");
+        let body = match request.body::<UserCreatePayload>() {
+            Ok(value) => value,
+            Err(error) => return Ok(Response::error(error)),
+        };
+        info!("UsersPost::handle - body: {:?}", body);
+        if let Err(error) = body.validate() {
+            info!("UsersPost::handle - invalid payload: {}", error.details);
+            return Ok(Response::error(error));
+        }
+
+        let email = body.email.clone().unwrap_or_default();
+        let uh =
+            UsersHandler::new(Clients::dynamodb().await, table_name).with_emails(table_name_emails);
+        match uh.create_unique(body).await {
+            Ok(Some(user)) => Ok(Response::json(user, 200)),
+            Ok(None) => {
+                info!("UsersPost::handle - email already registered");
+                Ok(Response::error(UserError::email_taken(&email)))
+            }
+            Err(error) => {
+                error!("UsersPost::handle - failed to create user: {}", error);
+                Ok(Response::error(UserError::creation_failed()))
+            }
+        }
+    }
+}
+
+pub struct UsersList;
+#[async_trait]
+impl Route for UsersList {
+    async fn handle(&self, request: &RouteRequest) -> RouteResult {
+        let table_name = env_var("TABLE_NAME_USERS")?;
+        let table_name_emails = env_var("TABLE_NAME_USER_EMAILS")?;
+
+        let list = match request.list_query(USER_LIST_FIELDS, &["email"]) {
+            Ok(list) => list,
+            Err(error) => return Ok(Response::error(error)),
+        };
+
+        let uh =
+            UsersHandler::new(Clients::dynamodb().await, table_name).with_emails(table_name_emails);
+        let users = match request.query_parameter("email") {
+            Some(email) => {
+                info!("UsersList::handle - email: {}", email);
+                list.apply(uh.find_by_email(&email).await?.into_iter().collect())
+            }
+            None => uh.search(&list).await?,
+        };
+        info!("UsersList::handle - users: {}", users.len());
+        Ok(Response::json(ResponseGenericList::new(users), 200))
+    }
+}
+
+pub struct UserGet;
+#[async_trait]
+impl Route for UserGet {
+    async fn handle(&self, request: &RouteRequest) -> RouteResult {
+        let table_name = env_var("TABLE_NAME_USERS")?;
+
+        let user_uuid = match request.path_parameter("user") {
+            Ok(uuid) => uuid,
+            Err(error) => return Ok(Response::error(error)),
+        };
+        info!("UserGet::handle - uuid: {}", user_uuid);
+
+        let uh = UsersHandler::new(Clients::dynamodb().await, table_name);
+        match uh.get(user_uuid).await? {
+            Some(user) => Ok(Response::json(user, 200)),
+            None => {
+                info!("UserGet::handle - user not found");
+                Ok(Response::error(CommonError::item_not_found(Some(
+                    "User not found".to_string(),
+                ))))
+            }
+        }
+    }
+}
+
+pub struct UserPatch;
+#[async_trait]
+impl Route for UserPatch {
+    async fn handle(&self, request: &RouteRequest) -> RouteResult {
+        let table_name = env_var("TABLE_NAME_USERS")?;
+        let table_name_emails = env_var("TABLE_NAME_USER_EMAILS")?;
+
+        let user_uuid = match request.path_parameter("user") {
+            Ok(uuid) => uuid,
+            Err(error) => return Ok(Response::error(error)),
+        };
+        info!("UserPatch::handle - uuid: {}", user_uuid);
+
+        info!("UserPatch::handle - parse body payload");
+        let body = match request.body::<UserUpdatePayload>() {
+            Ok(value) => value,
+            Err(error) => return Ok(Response::error(error)),
+        };
+        info!("UserPatch::handle - body: {:?}", body);
+        if let Err(error) = body.validate() {
+            info!("UserPatch::handle - invalid payload: {}", error.details);
+            return Ok(Response::error(error));
+        }
+
+        let uh =
+            UsersHandler::new(Clients::dynamodb().await, table_name).with_emails(table_name_emails);
+        let user = match uh.get(user_uuid.clone()).await? {
+            Some(user) => user,
+            None => {
+                info!("UserPatch::handle - user not found");
+                return Ok(Response::error(CommonError::item_not_found(Some(
+                    "User not found".to_string(),
+                ))));
+            }
+        };
+        if let Err(error) = request.if_match(&etag(&user)) {
+            info!("UserPatch::handle - stale entity tag");
+            return Ok(Response::error(error));
+        }
+
+        let email = body.email.clone();
+        let unmodified = request.header("if-match").is_some();
+        match uh.update_unique(&user, body, unmodified).await {
+            Ok(true) => {}
+            Ok(false) => {
+                // Tell an email conflict apart from a concurrent change of the user
+                let owner = match email.as_deref() {
+                    Some(email) => uh.find_by_email(email).await?,
+                    None => None,
+                };
+                if !unmodified || owner.filter(|owner| owner.uuid != user.uuid).is_some() {
+                    info!("UserPatch::handle - email already registered");
+                    return Ok(Response::error(UserError::email_taken(
+                        &email.unwrap_or_default(),
+                    )));
+                }
+                info!("UserPatch::handle - user modified or deleted meanwhile");
+                return Ok(Response::error(RouterError::precondition_failed()));
+            }
+            Err(error) => {
+                error!("UserPatch::handle - failed to update user: {}", error);
+                return Ok(Response::error(UserError::update_failed()));
+            }
+        }
+
+        match uh.get(user_uuid).await? {
+            Some(user) => Ok(Response::json(user, 200).with_etag()),
+            None => Ok(Response::error(CommonError::item_not_found(Some(
+                "User not found".to_string(),
+            )))),
+        }
+    }
+}
+
+pub struct UserDelete;
+#[async_trait]
+impl Route for UserDelete {
+    async fn handle(&self, request: &RouteRequest) -> RouteResult {
+        let table_name = env_var("TABLE_NAME_USERS")?;
+        let table_name_emails = env_var("TABLE_NAME_USER_EMAILS")?;
+
+        let user_uuid = match request.path_parameter("user") {
+            Ok(uuid) => uuid,
+            Err(error) => return Ok(Response::error(error)),
+        };
+        info!("UserDelete::handle - uuid: {}", user_uuid);
+
+        let uh =
+            UsersHandler::new(Clients::dynamodb().await, table_name).with_emails(table_name_emails);
+        let user = match uh.get(user_uuid).await? {
+            Some(user) => user,
+            None => {
+                info!("UserDelete::handle - user not found");
+                return Ok(Response::error(CommonError::item_not_found(Some(
+                    "User not found".to_string(),
+                ))));
+            }
+        };
+        if let Err(error) = request.if_match(&etag(&user)) {
+            info!("UserDelete::handle - stale entity tag");
+            return Ok(Response::error(error));
+        }
+
+        let unmodified = request.header("if-match").is_some();
+        match uh.delete_user(&user, unmodified).await {
+            Ok(true) => Ok(Response::no_content()),
+            Ok(false) if unmodified => {
+                info!("UserDelete::handle - user modified or deleted meanwhile");
+                Ok(Response::error(RouterError::precondition_failed()))
+            }
+            Ok(false) => {
+                info!("UserDelete::handle - user not found");
+                Ok(Response::error(CommonError::item_not_found(Some(
+                    "User not found".to_string(),
+                ))))
+            }
+            Err(error) => {
+                error!("UserDelete::handle - failed to delete user: {}", error);
+                Ok(Response::error(UserError::delete_failed()))
+            }
+        }
+    }
+}
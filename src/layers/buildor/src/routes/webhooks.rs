@@ -0,0 +1,146 @@
+use async_trait::async_trait;
+use log::{self, error, info};
+use serde_json::json;
+
+use super::env_var;
+use crate::{
+    handlers::{
+        projects::ProjectsHandler,
+        router::{Route, RouteResult},
+        users::UsersHandler,
+        webhooks::{WebhookDeliveriesHandler, WebhooksHandler},
+    },
+    models::{
+        common::{CommonError, ResponseGenericList},
+        handlers::{HandlerCreate, HandlerGet},
+        response::Response,
+        router::RouteRequest,
+        validation::Validate,
+        webhook::{
+            WebhookCreatePayload, WebhookError, WEBHOOK_DELIVERY_LIST_FIELDS, WEBHOOK_LIST_FIELDS,
+        },
+    },
+    utils::Clients,
+};
+
+pub const PATH_WEBHOOKS: &str = "/webhooks";
+pub const PATH_WEBHOOK_DELIVERIES: &str = "/webhooks/{webhook}/deliveries";
+
+pub struct WebhooksPost;
+#[async_trait]
+impl Route for WebhooksPost {
+    async fn handle(&self, request: &RouteRequest) -> RouteResult {
+        let table_name = env_var("TABLE_NAME_WEBHOOKS")?;
+
+        info!("WebhooksPost::handle - parse body payload");
+        let body = match request.body::<WebhookCreatePayload>() {
+            Ok(value) => value,
+            Err(error) => return Ok(Response::error(error)),
+        };
+        info!("WebhooksPost::handle - body: {:?}", body);
+        if let Err(error) = body.validate() {
+            info!("WebhooksPost::handle - invalid payload: {}", error.details);
+            return Ok(Response::error(error));
+        }
+
+        info!("WebhooksPost::handle - verify webhook owner exists");
+        let exists = match (body.project_uuid.clone(), body.user_uuid.clone()) {
+            (Some(project_uuid), _) => {
+                let ph = ProjectsHandler::new(
+                    Clients::dynamodb().await,
+                    env_var("TABLE_NAME_PROJECTS")?,
+                );
+                ph.get(project_uuid).await?.is_some()
+            }
+            (None, Some(user_uuid)) => {
+                let uh = UsersHandler::new(Clients::dynamodb().await, env_var("TABLE_NAME_USERS")?);
+                uh.get(user_uuid).await?.is_some()
+            }
+            (None, None) => false,
+        };
+        if !exists {
+            return Ok(Response::error(CommonError::item_not_found(Some(
+                "Webhook project or user not found".to_string(),
+            ))));
+        }
+
+        let wh = WebhooksHandler::new(Clients::dynamodb().await, table_name);
+        match wh.create(body).await {
+            Ok(webhook) => {
+                // The secret is only disclosed once, on creation
+                let mut data = json!(webhook);
+                data["secret"] = json!(webhook.secret);
+                Ok(Response::json(data, 201))
+            }
+            Err(error) => {
+                error!("WebhooksPost::handle - failed to create webhook: {}", error);
+                Ok(Response::error(WebhookError::creation_failed()))
+            }
+        }
+    }
+}
+
+pub struct WebhooksList;
+#[async_trait]
+impl Route for WebhooksList {
+    async fn handle(&self, request: &RouteRequest) -> RouteResult {
+        let table_name = env_var("TABLE_NAME_WEBHOOKS")?;
+
+        let list = match request.list_query(WEBHOOK_LIST_FIELDS, &["project", "user"]) {
+            Ok(list) => list,
+            Err(error) => return Ok(Response::error(error)),
+        };
+
+        let wh = WebhooksHandler::new(Clients::dynamodb().await, table_name);
+        let webhooks = match (
+            request.query_parameter("project"),
+            request.query_parameter("user"),
+        ) {
+            (Some(project_uuid), None) => wh.search_by_project(project_uuid, &list).await?,
+            (None, Some(user_uuid)) => wh.search_by_user(user_uuid, &list).await?,
+            _ => {
+                return Ok(Response::error(WebhookError::invalid_payload(
+                    "Exactly one of the project or user query parameters is required".to_string(),
+                )))
+            }
+        };
+        info!("WebhooksList::handle - webhooks: {}", webhooks.len());
+        Ok(Response::json(ResponseGenericList::new(webhooks), 200))
+    }
+}
+
+pub struct WebhookDeliveriesList;
+#[async_trait]
+impl Route for WebhookDeliveriesList {
+    async fn handle(&self, request: &RouteRequest) -> RouteResult {
+        let table_name = env_var("TABLE_NAME_WEBHOOK_DELIVERIES")?;
+        let table_name_webhooks = env_var("TABLE_NAME_WEBHOOKS")?;
+
+        let webhook_uuid = match request.path_parameter("webhook") {
+            Ok(uuid) => uuid,
+            Err(error) => return Ok(Response::error(error)),
+        };
+        info!("WebhookDeliveriesList::handle - uuid: {}", webhook_uuid);
+
+        let list = match request.list_query(WEBHOOK_DELIVERY_LIST_FIELDS, &[]) {
+            Ok(list) => list,
+            Err(error) => return Ok(Response::error(error)),
+        };
+
+        let wh = WebhooksHandler::new(Clients::dynamodb().await, table_name_webhooks);
+        if wh.get(webhook_uuid.clone()).await?.is_none() {
+            info!("WebhookDeliveriesList::handle - webhook not found");
+            return Ok(Response::error(CommonError::item_not_found(Some(
+                "Webhook not found".to_string(),
+            ))));
+        }
+
+        let wdh = WebhookDeliveriesHandler::new(Clients::dynamodb().await, table_name);
+        let deliveries = wdh.search_by_webhook(webhook_uuid, &list).await?;
+        info!(
+            "WebhookDeliveriesList::handle - deliveries: {}",
+            deliveries.len()
+        );
+        Ok(Response::json(ResponseGenericList::new(deliveries), 200))
+    }
+}
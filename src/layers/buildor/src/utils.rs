@@ -1,6 +1,7 @@
 use aws_config::load_from_env;
 use aws_sdk_codebuild::Client as CodebuildClient;
 use aws_sdk_dynamodb::Client as DynamoClient;
+use aws_sdk_s3::Client as S3Client;
 use error_stack::Report;
 
 use crate::models::common::RequiredEnvVarError;
@@ -33,19 +34,19 @@ mod load_env_var_tests {
 
     #[test]
     #[should_panic(expected = "Missing required env var: Undefined")]
-    fn exception_when_undefined_env_var() -> () {
+    fn exception_when_undefined_env_var() {
         let _ = load_env_var("Undefined", None).unwrap();
     }
 
     #[test]
-    fn returns_default_value() -> () {
+    fn returns_default_value() {
         let default_value = "default_value";
-        let value = load_env_var("Undefined", Some(&default_value)).unwrap();
+        let value = load_env_var("Undefined", Some(default_value)).unwrap();
         assert_eq!(value, default_value);
     }
 
     #[test]
-    fn returns_env_var_value() -> () {
+    fn returns_env_var_value() {
         let _ = load_env_var("USER", None).unwrap();
     }
 }
@@ -81,6 +82,11 @@ impl Clients {
         let config = load_from_env().await; // automatically reads credentials/region/profile env vars
         CodebuildClient::new(&config)
     }
+
+    pub async fn s3() -> S3Client {
+        let config = aws_config::load_from_env().await;
+        S3Client::new(&config)
+    }
 }
 
 #[cfg(test)]
@@ -96,4 +102,45 @@ mod clients_tests {
     async fn resturns_codebuild_client() {
         let _ = Clients::codebuild().await;
     }
+
+    #[tokio::test]
+    async fn resturns_s3_client() {
+        let _ = Clients::s3().await;
+    }
+}
+
+/// Lowercase ASCII alphanumerics separated by single dashes, safe for object keys and urls
+pub fn slugify(value: &str) -> String {
+    let mut slug = String::new();
+    for c in value.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+#[cfg(test)]
+mod slugify_tests {
+    use super::*;
+
+    #[test]
+    fn replaces_unsafe_characters() {
+        assert_eq!(slugify("My App: v2.0"), "my-app-v2-0");
+    }
+
+    #[test]
+    fn trims_and_collapses_separators() {
+        assert_eq!(
+            slugify("  --Buildspace  Solana__Pay-- "),
+            "buildspace-solana-pay"
+        );
+    }
+
+    #[test]
+    fn empty_when_no_alphanumerics() {
+        assert_eq!(slugify(" :/ "), "");
+    }
 }
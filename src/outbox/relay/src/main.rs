@@ -0,0 +1,183 @@
+use chrono::Utc;
+use error_stack::{Report, ResultExt};
+use lambda_runtime::{service_fn, LambdaEvent};
+use log::{self, error, info};
+use serde_json::{json, Value};
+
+use buildor::{
+    handlers::{
+        email::{DeploymentMailer, SmtpSender},
+        http::ReqwestSender,
+        notifications::Notifier,
+        outbox::{
+            NotificationsSink, OutboxEntryParser, OutboxHandler, OutboxRelay, OutboxSink,
+            WebhooksSink,
+        },
+        project_deployments::ProjectDeploymentsHandler,
+        projects::ProjectsHandler,
+        stats::{ProjectStatsHandler, ProjectStatsSink},
+        users::UsersHandler,
+        webhooks::{WebhookDeliveriesHandler, WebhookDispatcher, WebhooksHandler},
+    },
+    models::{
+        common::ExecutionError,
+        email::SmtpConfig,
+        request::RequestError,
+        stream::{StreamEventName, StreamRecord},
+        webhook::RetryPolicy,
+    },
+    utils::{load_env_var, Clients},
+};
+
+#[tokio::main]
+async fn main() -> Result<(), Value> {
+    env_logger::init();
+
+    info!("Creating service fn for handler");
+    let func = service_fn(handler);
+    info!("Executing handler from runtime");
+    let result = lambda_runtime::run(func).await;
+    info!("Evaluating handler result");
+    match result {
+        Ok(res) => {
+            info!("Success");
+            Ok(res)
+        }
+        Err(err) => {
+            error!("Handler exception: {}", err);
+            Err(json!(RequestError::internal()))
+        }
+    }
+}
+
+async fn handler(event: LambdaEvent<Value>) -> Result<Value, Report<ExecutionError>> {
+    info!("Start handler execution");
+
+    info!("Load env vars");
+    #[allow(non_snake_case)]
+    let TABLE_NAME = load_env_var("TABLE_NAME", None).change_context(ExecutionError)?;
+    info!("TABLE_NAME: {}", TABLE_NAME);
+    #[allow(non_snake_case)]
+    let TABLE_NAME_DEPLOYMENTS =
+        load_env_var("TABLE_NAME_DEPLOYMENTS", None).change_context(ExecutionError)?;
+    info!("TABLE_NAME_DEPLOYMENTS: {}", TABLE_NAME_DEPLOYMENTS);
+    #[allow(non_snake_case)]
+    let TABLE_NAME_PROJECTS =
+        load_env_var("TABLE_NAME_PROJECTS", None).change_context(ExecutionError)?;
+    info!("TABLE_NAME_PROJECTS: {}", TABLE_NAME_PROJECTS);
+    #[allow(non_snake_case)]
+    let TABLE_NAME_USERS = load_env_var("TABLE_NAME_USERS", None).change_context(ExecutionError)?;
+    info!("TABLE_NAME_USERS: {}", TABLE_NAME_USERS);
+    #[allow(non_snake_case)]
+    let TABLE_NAME_WEBHOOKS =
+        load_env_var("TABLE_NAME_WEBHOOKS", None).change_context(ExecutionError)?;
+    info!("TABLE_NAME_WEBHOOKS: {}", TABLE_NAME_WEBHOOKS);
+    #[allow(non_snake_case)]
+    let TABLE_NAME_WEBHOOK_DELIVERIES =
+        load_env_var("TABLE_NAME_WEBHOOK_DELIVERIES", None).change_context(ExecutionError)?;
+    info!(
+        "TABLE_NAME_WEBHOOK_DELIVERIES: {}",
+        TABLE_NAME_WEBHOOK_DELIVERIES
+    );
+    #[allow(non_snake_case)]
+    let TABLE_NAME_STATS = load_env_var("TABLE_NAME_STATS", None).change_context(ExecutionError)?;
+    info!("TABLE_NAME_STATS: {}", TABLE_NAME_STATS);
+
+    info!("Parse event and context objects");
+    let (event, context) = event.into_parts();
+    info!("event: {}", event);
+    info!("context: {:?}", context);
+
+    let mailer = match SmtpConfig::from_env().map(SmtpSender::new) {
+        Some(Ok(sender)) => Some(DeploymentMailer::new(
+            ProjectDeploymentsHandler::new(
+                Clients::dynamodb().await,
+                TABLE_NAME_DEPLOYMENTS.clone(),
+            ),
+            UsersHandler::new(Clients::dynamodb().await, TABLE_NAME_USERS),
+            Box::new(sender),
+        )),
+        Some(Err(error)) => {
+            error!("Invalid SMTP configuration: {}", error);
+            None
+        }
+        None => {
+            info!("Email notifications disabled, missing SMTP configuration");
+            None
+        }
+    };
+
+    // New sinks are added here, every one of them receives every entry
+    let sinks: Vec<Box<dyn OutboxSink>> = vec![
+        Box::new(WebhooksSink::new(WebhookDispatcher::new(
+            WebhooksHandler::new(Clients::dynamodb().await, TABLE_NAME_WEBHOOKS),
+            WebhookDeliveriesHandler::new(Clients::dynamodb().await, TABLE_NAME_WEBHOOK_DELIVERIES),
+            Box::new(ReqwestSender::default()),
+            RetryPolicy::default(),
+        ))),
+        Box::new(NotificationsSink::new(
+            ProjectsHandler::new(Clients::dynamodb().await, TABLE_NAME_PROJECTS),
+            Notifier::new(Box::new(ReqwestSender::default())),
+            mailer,
+        )),
+        Box::new(ProjectStatsSink::new(ProjectStatsHandler::new(
+            Clients::dynamodb().await,
+            TABLE_NAME_STATS,
+        ))),
+    ];
+    let relay = OutboxRelay::new(
+        OutboxHandler::new(Clients::dynamodb().await, TABLE_NAME),
+        ProjectDeploymentsHandler::new(Clients::dynamodb().await, TABLE_NAME_DEPLOYMENTS),
+        sinks,
+        RetryPolicy::default(),
+    );
+
+    // Scheduled runs pick up the entries the stream did not publish
+    let records = match event.get("Records").and_then(|value| value.as_array()) {
+        Some(records) => records.to_owned(),
+        None => {
+            let entries = relay
+                .relay_due(Utc::now())
+                .await
+                .change_context(ExecutionError)?;
+            info!("Relayed outbox entries: {}", entries.len());
+            return Ok(json!({
+                "relayed": entries.len(),
+                "published": entries.iter().filter(|entry| entry.status == "PUBLISHED").count(),
+            }));
+        }
+    };
+
+    // Only new entries are published from the stream, the relay's own updates
+    // come back as modifications
+    let mut failures = Vec::new();
+    for record in records.iter() {
+        let record = match StreamRecord::parse(record) {
+            Ok(record) => record,
+            Err(error) => {
+                error!("Invalid stream record (skip): {}", error);
+                continue;
+            }
+        };
+        let image = match (record.event_name, record.new_image) {
+            (StreamEventName::Insert, Some(image)) => image,
+            _ => continue,
+        };
+        let entry = match OutboxEntryParser::parse(image) {
+            Ok(entry) => entry,
+            Err(error) => {
+                error!("Invalid outbox entry (skip): {}", error);
+                continue;
+            }
+        };
+
+        // Storage errors stop the batch, the failed record is retried by the stream
+        if let Err(error) = relay.relay(entry).await {
+            error!("Failed to relay outbox entry: {}", error);
+            failures.push(json!({ "itemIdentifier": record.sequence_number }));
+            break;
+        }
+    }
+
+    Ok(json!({ "batchItemFailures": failures }))
+}
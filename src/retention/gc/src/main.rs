@@ -0,0 +1,92 @@
+use chrono::Utc;
+use error_stack::{Report, ResultExt};
+use lambda_runtime::{service_fn, LambdaEvent};
+use log::{self, error, info};
+use serde_json::{json, Value};
+
+use buildor::{
+    handlers::{
+        artifacts::ArtifactsHandler, project_deployments::ProjectDeploymentsHandler,
+        projects::ProjectsHandler, retention::GarbageCollector, stats::ProjectStatsHandler,
+        webhooks::WebhooksHandler,
+    },
+    models::{common::ExecutionError, request::RequestError},
+    utils::{load_env_var, Clients},
+};
+
+#[tokio::main]
+async fn main() -> Result<(), Value> {
+    env_logger::init();
+
+    info!("Creating service fn for handler");
+    let func = service_fn(handler);
+    info!("Executing handler from runtime");
+    let result = lambda_runtime::run(func).await;
+    info!("Evaluating handler result");
+    match result {
+        Ok(res) => {
+            info!("Success");
+            Ok(res)
+        }
+        Err(err) => {
+            error!("Handler exception: {}", err);
+            Err(json!(RequestError::internal()))
+        }
+    }
+}
+
+async fn handler(event: LambdaEvent<Value>) -> Result<Value, Report<ExecutionError>> {
+    info!("Start handler execution");
+
+    info!("Load env vars");
+    #[allow(non_snake_case)]
+    let TABLE_NAME = load_env_var("TABLE_NAME", None).change_context(ExecutionError)?;
+    info!("TABLE_NAME: {}", TABLE_NAME);
+    #[allow(non_snake_case)]
+    let TABLE_NAME_PROJECTS =
+        load_env_var("TABLE_NAME_PROJECTS", None).change_context(ExecutionError)?;
+    info!("TABLE_NAME_PROJECTS: {}", TABLE_NAME_PROJECTS);
+    #[allow(non_snake_case)]
+    let TABLE_NAME_OUTBOX =
+        load_env_var("TABLE_NAME_OUTBOX", None).change_context(ExecutionError)?;
+    info!("TABLE_NAME_OUTBOX: {}", TABLE_NAME_OUTBOX);
+    #[allow(non_snake_case)]
+    let TABLE_NAME_WEBHOOKS =
+        load_env_var("TABLE_NAME_WEBHOOKS", None).change_context(ExecutionError)?;
+    info!("TABLE_NAME_WEBHOOKS: {}", TABLE_NAME_WEBHOOKS);
+    #[allow(non_snake_case)]
+    let TABLE_NAME_STATS = load_env_var("TABLE_NAME_STATS", None).change_context(ExecutionError)?;
+    info!("TABLE_NAME_STATS: {}", TABLE_NAME_STATS);
+    #[allow(non_snake_case)]
+    let RETENTION_DRY_RUN =
+        load_env_var("RETENTION_DRY_RUN", Some("false")).change_context(ExecutionError)?;
+    info!("RETENTION_DRY_RUN: {}", RETENTION_DRY_RUN);
+
+    info!("Parse event and context objects");
+    let (event, context) = event.into_parts();
+    info!("event: {}", event);
+    info!("context: {:?}", context);
+
+    // Scheduled runs use the env var, manual invocations can ask for a dry run
+    let dry_run = match event.get("dryRun").and_then(|value| value.as_bool()) {
+        Some(value) => value,
+        None => RETENTION_DRY_RUN == "true",
+    };
+    info!("Dry run: {}", dry_run);
+
+    let gc = GarbageCollector::new(
+        ProjectsHandler::new(Clients::dynamodb().await, TABLE_NAME_PROJECTS),
+        ProjectDeploymentsHandler::new(Clients::dynamodb().await, TABLE_NAME)
+            .with_outbox(TABLE_NAME_OUTBOX),
+        ArtifactsHandler::new(Clients::s3().await),
+        WebhooksHandler::new(Clients::dynamodb().await, TABLE_NAME_WEBHOOKS),
+        ProjectStatsHandler::new(Clients::dynamodb().await, TABLE_NAME_STATS),
+    );
+    let report = gc
+        .run(dry_run, Utc::now())
+        .await
+        .change_context(ExecutionError)?;
+    info!("Retention report: {}", json!(report));
+
+    Ok(json!(report))
+}
@@ -0,0 +1,104 @@
+use error_stack::{Report, ResultExt};
+use lambda_runtime::{service_fn, LambdaEvent};
+use log::{self, error, info};
+use serde_json::{json, Value};
+
+use buildor::{
+    handlers::{
+        dead_letters::DeadLettersHandler,
+        domain_events::{DomainEventDecoder, DomainEventsProcessor, LogSink, StreamTables},
+    },
+    models::{common::ExecutionError, dead_letter::DeadLetter, request::RequestError},
+    utils::{load_env_var, Clients},
+};
+
+/// Source of the dead letters of this lambda
+const DEAD_LETTER_SOURCE: &str = "streams-domain-events";
+
+#[tokio::main]
+async fn main() -> Result<(), Value> {
+    env_logger::init();
+
+    info!("Creating service fn for handler");
+    let func = service_fn(handler);
+    info!("Executing handler from runtime");
+    let result = lambda_runtime::run(func).await;
+    info!("Evaluating handler result");
+    match result {
+        Ok(res) => {
+            info!("Success");
+            Ok(res)
+        }
+        Err(err) => {
+            error!("Handler exception: {}", err);
+            Err(json!(RequestError::internal()))
+        }
+    }
+}
+
+async fn handler(event: LambdaEvent<Value>) -> Result<Value, Report<ExecutionError>> {
+    info!("Start handler execution");
+
+    info!("Load env vars");
+    #[allow(non_snake_case)]
+    let TABLE_NAME = load_env_var("TABLE_NAME", None).change_context(ExecutionError)?;
+    info!("TABLE_NAME: {}", TABLE_NAME);
+    #[allow(non_snake_case)]
+    let TABLE_NAME_PROJECTS =
+        load_env_var("TABLE_NAME_PROJECTS", None).change_context(ExecutionError)?;
+    info!("TABLE_NAME_PROJECTS: {}", TABLE_NAME_PROJECTS);
+    #[allow(non_snake_case)]
+    let TABLE_NAME_USERS = load_env_var("TABLE_NAME_USERS", None).change_context(ExecutionError)?;
+    info!("TABLE_NAME_USERS: {}", TABLE_NAME_USERS);
+    #[allow(non_snake_case)]
+    let TABLE_NAME_DEAD_LETTERS =
+        load_env_var("TABLE_NAME_DEAD_LETTERS", None).change_context(ExecutionError)?;
+    info!("TABLE_NAME_DEAD_LETTERS: {}", TABLE_NAME_DEAD_LETTERS);
+
+    info!("Parse event and context objects");
+    let (event, context) = event.into_parts();
+    info!("context: {:?}", context);
+
+    let records = match event.get("Records").and_then(|value| value.as_array()) {
+        Some(records) => records.to_owned(),
+        None => {
+            error!("Missing stream records: {}", event);
+            Vec::new()
+        }
+    };
+
+    // New sinks are added here, every one of them receives every event
+    let processor = DomainEventsProcessor::new(
+        DomainEventDecoder::new(StreamTables {
+            users: TABLE_NAME_USERS,
+            projects: TABLE_NAME_PROJECTS,
+            project_deployments: TABLE_NAME,
+        }),
+        vec![Box::new(LogSink)],
+    );
+    let report = processor.process(&records).await;
+    info!(
+        "Published: {}, ignored: {}, invalid: {}, failure: {:?}",
+        report.published,
+        report.ignored,
+        report.invalid.len(),
+        report.failure
+    );
+
+    // Records that can not be decoded would block the shard, they are kept as
+    // dead letters instead
+    if !report.invalid.is_empty() {
+        let dlh = DeadLettersHandler::new(Clients::dynamodb().await, TABLE_NAME_DEAD_LETTERS);
+        for record in &report.invalid {
+            dlh.save(&DeadLetter::new(
+                DEAD_LETTER_SOURCE,
+                record.reason.clone(),
+                record.payload.clone(),
+            ))
+            .await
+            .change_context(ExecutionError)?;
+        }
+    }
+
+    Ok(report.batch_item_failures())
+}
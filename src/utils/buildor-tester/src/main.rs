@@ -1,5 +1,8 @@
 use buildor::{
-    handlers::{codebuild::CodeBuildHandler, project_deployments::ProjectDeploymentsHandler},
+    handlers::{
+        codebuild::{BuildLimits, CodeBuildHandler},
+        project_deployments::ProjectDeploymentsHandler,
+    },
     models::{
         codebuild::{BuildInfo, BuildPhase, BuildPhaseStatus, ProjectDeploymentPhase},
         commands::Commands,
@@ -10,6 +13,7 @@ use buildor::{
     utils::Clients,
 };
 use log::{self, info};
+use uuid::Uuid;
 
 const CODEBUILD_PROJECT_NAME_BUILDING: &str = "App-Dynamically-Deploy-SPAs";
 const CODEBUILD_PROJECT_NAME_DEPLOYMENT: &str = "CODEBUILD_PROJECT_NAME_DEPLOYMENT"; // TODO: replace with deployment codebuild project name
@@ -24,12 +28,13 @@ async fn main() {
     info!("Initialize Handlers");
     let pdh = ProjectDeploymentsHandler::new(
         Clients::dynamodb().await,
-        TABLE_NAME_PROJECT_DEPLOYMENTS.clone().to_string(),
+        TABLE_NAME_PROJECT_DEPLOYMENTS.to_string(),
     );
     let cbh = CodeBuildHandler::new(
         Clients::codebuild().await,
-        CODEBUILD_PROJECT_NAME_BUILDING.clone().to_string(),
-        CODEBUILD_PROJECT_NAME_DEPLOYMENT.clone().to_string(),
+        CODEBUILD_PROJECT_NAME_BUILDING.to_string(),
+        CODEBUILD_PROJECT_NAME_DEPLOYMENT.to_string(),
+        BuildLimits::from_env(),
     );
 
     // =========================== PAYLOADS ===========================
@@ -37,11 +42,17 @@ async fn main() {
     let project_create_payload = ProjectCreatePayload {
         name: "buildspace-solana-pay".to_string(),
         repository: "https://github.com/ccddan/buildspace-solana-pay.git".to_string(),
+        owner: None,
         commands: Some(Commands::new(
             Some(vec!["npm install".to_string()]),
             Some(vec!["npm run release".to_string()]),
         )),
         output_folder: Some("out".to_string()),
+        environments: None,
+        build_timeout_minutes: Some(15),
+        compute_size: Some("small".to_string()),
+        retention: None,
+        notifications: None,
     };
     let project = Project::new(project_create_payload.clone());
 
@@ -54,6 +65,9 @@ async fn main() {
         deployment_phase: Some(ProjectDeploymentPhase::Building.to_string()),
         current_phase: Some(BuildPhase::Queued.to_string()),
         build_status: Some(BuildPhaseStatus::InProgress.to_string()),
+        logs_url: None,
+        compute_type: None,
+        image: None,
     };
     let mut build_updated = build_info.clone();
     build_updated.build_number = Some(3);
@@ -61,11 +75,15 @@ async fn main() {
     build_updated.end_time = Some(3);
     build_updated.deployment_phase = Some(ProjectDeploymentPhase::Deployment.to_string());
     build_updated.current_phase = Some(BuildPhase::Finalizing.to_string());
+    build_updated.logs_url = Some("https://console.aws.amazon.com/cloudwatch/home".to_string());
 
     // =========================== CODEBUILD ===========================
     info!("====================== CodeBuild ======================");
     info!("Create New Build");
-    let result = cbh.create(&project.clone()).await;
+    let environment = project.environments[0].clone();
+    let result = cbh
+        .create(&Uuid::new_v4().to_string(), &project.clone(), &environment)
+        .await;
     info!("New Build: {:?}", result);
 
     info!("Get Existing Build");
@@ -80,7 +98,8 @@ async fn main() {
     let build = build_info.clone();
     let project_deployment_create_payload = ProjectDeploymentCreatePayload {
         project: project.clone(),
-        build,
+        environment: environment.name.clone(),
+        build: Some(build),
     };
 
     info!("Create New Project Deployment");
@@ -93,7 +112,10 @@ async fn main() {
             result.unwrap().uuid,
             ProjectDeploymentUpdatePayload {
                 project: Some(project.clone()),
+                status: None,
                 build: Some(build_updated.clone()),
+                artifact: None,
+                pinned: None,
             },
         )
         .await;
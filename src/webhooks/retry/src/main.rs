@@ -0,0 +1,74 @@
+use chrono::Utc;
+use error_stack::{Report, ResultExt};
+use lambda_runtime::{service_fn, LambdaEvent};
+use log::{self, error, info};
+use serde_json::{json, Value};
+
+use buildor::{
+    handlers::{
+        http::ReqwestSender,
+        webhooks::{WebhookDeliveriesHandler, WebhookDispatcher, WebhooksHandler},
+    },
+    models::{common::ExecutionError, request::RequestError, webhook::RetryPolicy},
+    utils::{load_env_var, Clients},
+};
+
+#[tokio::main]
+async fn main() -> Result<(), Value> {
+    env_logger::init();
+
+    info!("Creating service fn for handler");
+    let func = service_fn(handler);
+    info!("Executing handler from runtime");
+    let result = lambda_runtime::run(func).await;
+    info!("Evaluating handler result");
+    match result {
+        Ok(res) => {
+            info!("Success");
+            Ok(res)
+        }
+        Err(err) => {
+            error!("Handler exception: {}", err);
+            Err(json!(RequestError::internal()))
+        }
+    }
+}
+
+async fn handler(event: LambdaEvent<Value>) -> Result<Value, Report<ExecutionError>> {
+    info!("Start handler execution");
+
+    info!("Load env vars");
+    #[allow(non_snake_case)]
+    let TABLE_NAME_WEBHOOKS =
+        load_env_var("TABLE_NAME_WEBHOOKS", None).change_context(ExecutionError)?;
+    info!("TABLE_NAME_WEBHOOKS: {}", TABLE_NAME_WEBHOOKS);
+    #[allow(non_snake_case)]
+    let TABLE_NAME_WEBHOOK_DELIVERIES =
+        load_env_var("TABLE_NAME_WEBHOOK_DELIVERIES", None).change_context(ExecutionError)?;
+    info!(
+        "TABLE_NAME_WEBHOOK_DELIVERIES: {}",
+        TABLE_NAME_WEBHOOK_DELIVERIES
+    );
+
+    info!("Parse event and context objects");
+    let (event, context) = event.into_parts();
+    info!("event: {}", event);
+    info!("context: {:?}", context);
+
+    let dispatcher = WebhookDispatcher::new(
+        WebhooksHandler::new(Clients::dynamodb().await, TABLE_NAME_WEBHOOKS),
+        WebhookDeliveriesHandler::new(Clients::dynamodb().await, TABLE_NAME_WEBHOOK_DELIVERIES),
+        Box::new(ReqwestSender::default()),
+        RetryPolicy::default(),
+    );
+    let deliveries = dispatcher
+        .retry_due(Utc::now())
+        .await
+        .change_context(ExecutionError)?;
+    info!("Retried webhook deliveries: {}", deliveries.len());
+
+    Ok(json!({
+        "retried": deliveries.len(),
+        "succeeded": deliveries.iter().filter(|delivery| delivery.status == "SUCCEEDED").count(),
+    }))
+}